@@ -0,0 +1,23 @@
+//! Embed the git commit in the build, when built from a checkout, and generate the gRPC
+//! service when enabled.
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok());
+
+    if let Some(hash) = hash {
+        println!("cargo:rustc-env=P25RX_GIT_HASH={}", hash.trim());
+    }
+
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/p25rx.proto").expect("unable to compile protos");
+}
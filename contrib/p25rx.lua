@@ -0,0 +1,53 @@
+-- Wireshark dissector for messages exported by `p25rx --pcap`.
+--
+-- Copy this file into the Wireshark personal plugins directory (shown under
+-- Help > About Wireshark > Folders) and open the pcapng file as usual.
+
+local p25rx = Proto("p25rx", "P25 messages from p25rx")
+
+local types = {
+    [1] = "Trunking signalling block",
+    [2] = "Link control word",
+}
+
+local f = p25rx.fields
+f.type = ProtoField.uint8("p25rx.type", "Message type", base.DEC, types)
+f.crc_valid = ProtoField.bool("p25rx.crc_valid", "CRC valid", 8, nil, 0x01)
+f.freq = ProtoField.uint32("p25rx.freq", "Frequency (Hz)", base.DEC)
+f.mfg = ProtoField.uint8("p25rx.mfg", "Manufacturer ID", base.HEX)
+f.opcode = ProtoField.string("p25rx.opcode", "Opcode")
+f.payload = ProtoField.bytes("p25rx.payload", "Payload")
+
+function p25rx.dissector(buf, pinfo, tree)
+    if buf:len() < 8 then
+        return 0
+    end
+
+    pinfo.cols.protocol = "P25"
+
+    local t = tree:add(p25rx, buf())
+    local kind = buf(0, 1):uint()
+    local opcode_len = buf(7, 1):uint()
+
+    t:add(f.type, buf(0, 1))
+    t:add(f.crc_valid, buf(1, 1))
+    t:add(f.freq, buf(2, 4))
+    t:add(f.mfg, buf(6, 1))
+
+    local opcode = "Unknown"
+
+    if opcode_len > 0 then
+        opcode = buf(8, opcode_len):string()
+        t:add(f.opcode, buf(8, opcode_len))
+    end
+
+    if buf:len() > 8 + opcode_len then
+        t:add(f.payload, buf(8 + opcode_len))
+    end
+
+    pinfo.cols.info = (types[kind] or "Unknown message") .. ": " .. opcode
+
+    return buf:len()
+end
+
+DissectorTable.get("wtap_encap"):add(wtap.USER0, p25rx)
@@ -0,0 +1,471 @@
+//! Notifications sent to web services on receiver events.
+//!
+//! Each alert rule in the settings file is an object with the following fields:
+//!
+//! - `event`: hook event that triggers the alert, like `call-start`
+//! - `talkgroups`: array of talkgroups, one of which the event must be on (optional)
+//! - `units`: array of units, one of which the event must be by (optional)
+//! - `emergency`: whether the event must be on an emergency call (default false)
+//! - `message`: template of the message, with fields of the event like `{talkGroup}`
+//! - `cooldown`: shortest time (sec) between alerts from the rule (default 0)
+//! - exactly one of `webhook` (URL receiving the event as JSON), `discord` (Discord
+//!   webhook URL), or `pushover` (object with the Pushover `token` and `user` keys)
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::{self, Value};
+
+use crate::hooks::HookEvent;
+
+/// Pushover message API endpoint.
+const PUSHOVER_URL: &str = "https://api.pushover.net/1/messages.json";
+/// Time allowed for each request.
+#[cfg(feature = "alerts")]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Error for every request when built without an HTTP client.
+pub const NO_HTTP: &str = "p25rx was built without HTTP requests (the alerts feature)";
+
+/// Alert rule as written in the settings file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertEntry {
+    /// Name of the event that triggers the alert, like `call-start`.
+    event: String,
+    /// Talkgroups the event must be on, or any if empty.
+    #[serde(default)]
+    talkgroups: Vec<u16>,
+    /// Units the event must be from, or any if empty.
+    #[serde(default)]
+    units: Vec<u32>,
+    /// Whether the event must be on an emergency call.
+    #[serde(default)]
+    emergency: bool,
+    /// Message template, or the default message of the event if not given.
+    #[serde(default)]
+    message: Option<String>,
+    /// Shortest time (sec) between alerts.
+    #[serde(default)]
+    cooldown: f32,
+    /// URL receiving the event and message as JSON.
+    #[serde(default)]
+    webhook: Option<String>,
+    /// Discord webhook URL.
+    #[serde(default)]
+    discord: Option<String>,
+    /// Pushover keys.
+    #[serde(default)]
+    pushover: Option<PushoverKeys>,
+}
+
+/// Pushover application and user keys.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PushoverKeys {
+    /// Application API token.
+    token: String,
+    /// User or group key.
+    user: String,
+}
+
+/// Service that receives an alert.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AlertTarget {
+    /// URL receiving the event and message as JSON.
+    Webhook(String),
+    /// Discord webhook URL.
+    Discord(String),
+    /// Pushover keys.
+    Pushover(PushoverKeys),
+}
+
+/// Sends an alert when matching events occur.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlertRule {
+    /// Event that triggers the alert.
+    pub event: HookEvent,
+    /// Talkgroups the event must be on, or any if empty.
+    pub talkgroups: Vec<u16>,
+    /// Units the event must be from, or any if empty.
+    pub units: Vec<u32>,
+    /// Whether the event must be on an emergency call.
+    pub emergency: bool,
+    /// Message template.
+    pub message: String,
+    /// Shortest time between alerts.
+    pub cooldown: Duration,
+    /// Service receiving the alert.
+    pub target: AlertTarget,
+}
+
+impl AlertRule {
+    /// Check if the given event with the given payload triggers the alert.
+    fn matches(&self, event: HookEvent, payload: &Value) -> bool {
+        let id = |name| field(payload, name).and_then(Value::as_u64);
+
+        event == self.event
+            && (self.talkgroups.is_empty()
+                || id("talkGroup").map_or(false, |tg| self.talkgroups.contains(&(tg as u16))))
+            && (self.units.is_empty()
+                || id("srcUnit")
+                    .or_else(|| id("unit"))
+                    .map_or(false, |u| self.units.contains(&(u as u32))))
+            && (!self.emergency || field(payload, "emergency") == Some(&Value::Bool(true)))
+    }
+}
+
+/// Parse the given alert rule from the settings file.
+pub fn parse_rule(e: AlertEntry) -> Result<AlertRule, String> {
+    let event = HookEvent::from_str(&e.event, false)
+        .map_err(|_| format!("invalid alert event {}", e.event))?;
+
+    let target = match (e.webhook, e.discord, e.pushover) {
+        (Some(url), None, None) => AlertTarget::Webhook(url),
+        (None, Some(url), None) => AlertTarget::Discord(url),
+        (None, None, Some(keys)) => AlertTarget::Pushover(keys),
+        _ => return Err("alert needs exactly one of webhook, discord, or pushover".to_string()),
+    };
+
+    if e.cooldown.is_nan() || e.cooldown < 0.0 {
+        return Err(format!("invalid alert cooldown {}", e.cooldown));
+    }
+
+    Ok(AlertRule {
+        event,
+        talkgroups: e.talkgroups,
+        units: e.units,
+        emergency: e.emergency,
+        message: e
+            .message
+            .unwrap_or_else(|| default_message(event).to_string()),
+        cooldown: Duration::from_secs_f32(e.cooldown),
+        target,
+    })
+}
+
+/// Get the message template used when a rule has none.
+fn default_message(event: HookEvent) -> &'static str {
+    match event {
+        HookEvent::CallStart => "Call on talkgroup {talkGroup}",
+        HookEvent::CallEnd => "Call ended on talkgroup {talkGroup}",
+        HookEvent::Emergency => "Emergency on talkgroup {talkGroup} from unit {srcUnit}",
+        HookEvent::Encrypted => "Encrypted call on talkgroup {talkGroup}",
+        HookEvent::ControlLost => "Lost control channel at {freq} Hz",
+        HookEvent::UnitSeen => "Unit {unit} seen: {activity} on talkgroup {talkGroup}",
+    }
+}
+
+/// Fill in the given template with the fields of the given event and payload.
+///
+/// Fields missing from the payload are written as `unknown`.
+fn render(template: &str, event: HookEvent, payload: &Value) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+
+        match &rest[1..end] {
+            "event" => out.push_str(event.name()),
+            name => match field(payload, name) {
+                Some(&Value::String(ref s)) => out.push_str(s),
+                Some(&Value::Null) | None => out.push_str("unknown"),
+                Some(v) => out.push_str(&v.to_string()),
+            },
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Get the given top-level field of the given payload.
+fn field<'a>(payload: &'a Value, name: &str) -> Option<&'a Value> {
+    payload.as_object().and_then(|fields| fields.get(name))
+}
+
+/// HTTP request that delivers an alert.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(not(feature = "alerts"), allow(dead_code))]
+enum Request {
+    /// POST the contained JSON body to the contained URL.
+    Json(String, String),
+    /// POST the contained form fields to the contained URL.
+    Form(String, Vec<(&'static str, String)>),
+}
+
+impl AlertTarget {
+    /// Build the request delivering the given message about the given event and
+    /// payload.
+    fn request(&self, event: HookEvent, message: String, payload: &Value) -> Request {
+        match *self {
+            AlertTarget::Webhook(ref url) => {
+                let mut body = match *payload {
+                    Value::Object(ref fields) => fields.clone(),
+                    _ => serde_json::Map::new(),
+                };
+
+                body.insert("event".to_string(), Value::String(event.name().to_string()));
+                body.insert("message".to_string(), Value::String(message));
+
+                Request::Json(url.clone(), Value::Object(body).to_string())
+            }
+            AlertTarget::Discord(ref url) => {
+                Request::Json(url.clone(), json!({ "content": message }).to_string())
+            }
+            AlertTarget::Pushover(ref keys) => {
+                let priority = match event {
+                    HookEvent::Emergency | HookEvent::ControlLost => "1",
+                    _ => "0",
+                };
+
+                Request::Form(
+                    PUSHOVER_URL.to_string(),
+                    vec![
+                        ("token", keys.token.clone()),
+                        ("user", keys.user.clone()),
+                        ("title", "p25rx".to_string()),
+                        ("message", message),
+                        ("priority", priority.to_string()),
+                    ],
+                )
+            }
+        }
+    }
+}
+
+/// Sends alerts for events matching a set of rules.
+#[derive(Default)]
+pub struct Alerts {
+    /// Alert rules.
+    rules: Vec<AlertRule>,
+    /// Time each rule last sent an alert, if ever.
+    last: Vec<Option<Instant>>,
+}
+
+impl Alerts {
+    /// Create a new `Alerts` using the given rules.
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        if !rules.is_empty() && !cfg!(feature = "alerts") {
+            warn!("ignoring alert rules: {}", NO_HTTP);
+        }
+
+        Alerts {
+            last: vec![None; rules.len()],
+            rules,
+        }
+    }
+
+    /// Check if any rule is triggered by the given event.
+    pub fn handles(&self, event: HookEvent) -> bool {
+        self.rules.iter().any(|r| r.event == event)
+    }
+
+    /// Send an alert for each rule triggered by the given event and payload, without
+    /// blocking the caller.
+    pub fn run(&mut self, event: HookEvent, payload: &Value) {
+        for req in self.requests(event, payload, Instant::now()) {
+            thread::spawn(move || {
+                if let Err(e) = send(&req) {
+                    warn!("unable to send {} alert: {}", event.name(), e);
+                }
+            });
+        }
+    }
+
+    /// Build the request for each rule triggered by the given event and payload at the
+    /// given time, skipping rules still cooling down.
+    fn requests(&mut self, event: HookEvent, payload: &Value, now: Instant) -> Vec<Request> {
+        let mut reqs = vec![];
+
+        for (rule, last) in self.rules.iter().zip(self.last.iter_mut()) {
+            if !rule.matches(event, payload) {
+                continue;
+            }
+
+            if last.map_or(false, |t| now.duration_since(t) < rule.cooldown) {
+                continue;
+            }
+
+            *last = Some(now);
+
+            let message = render(&rule.message, event, payload);
+            reqs.push(rule.target.request(event, message, payload));
+        }
+
+        reqs
+    }
+}
+
+/// Send the given JSON body in a POST request to the given URL.
+pub fn post_json(url: &str, body: &str) -> Result<(), String> {
+    post(url, "application/json", body)
+}
+
+/// Send the given body with the given content type in a POST request to the given URL.
+#[cfg(feature = "alerts")]
+pub fn post(url: &str, content_type: &str, body: &str) -> Result<(), String> {
+    ureq::post(url)
+        .timeout(REQUEST_TIMEOUT)
+        .set("Content-Type", content_type)
+        .send_string(body)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "alerts"))]
+pub fn post(_: &str, _: &str, _: &str) -> Result<(), String> {
+    Err(NO_HTTP.to_string())
+}
+
+/// Send the given request.
+#[cfg(feature = "alerts")]
+fn send(req: &Request) -> Result<(), String> {
+    match *req {
+        Request::Json(ref url, ref body) => post_json(url, body),
+        Request::Form(ref url, ref fields) => {
+            let fields = fields
+                .iter()
+                .map(|&(k, ref v)| (k, v.as_str()))
+                .collect::<Vec<_>>();
+
+            ureq::post(url)
+                .timeout(REQUEST_TIMEOUT)
+                .send_form(&fields)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[cfg(not(feature = "alerts"))]
+fn send(_: &Request) -> Result<(), String> {
+    Err(NO_HTTP.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rule(json: &str) -> Result<AlertRule, String> {
+        parse_rule(serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        let r = rule(r#"{"event": "emergency", "discord": "https://d", "cooldown": 60}"#).unwrap();
+
+        assert_eq!(r.event, HookEvent::Emergency);
+        assert_eq!(r.target, AlertTarget::Discord("https://d".to_string()));
+        assert_eq!(r.cooldown, Duration::from_secs(60));
+        assert_eq!(r.message, default_message(HookEvent::Emergency));
+
+        assert!(rule(r#"{"event": "emergency"}"#).is_err());
+        assert!(rule(r#"{"event": "bogus", "webhook": "http://w"}"#).is_err());
+        assert!(rule(r#"{"event": "call-end", "webhook": "a", "discord": "b"}"#).is_err());
+        assert!(rule(r#"{"event": "call-end", "webhook": "a", "cooldown": -1}"#).is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        let r = rule(r#"{"event": "call-start", "talkgroups": [101], "webhook": "a"}"#).unwrap();
+
+        assert!(r.matches(HookEvent::CallStart, &json!({"talkGroup": 101})));
+        assert!(!r.matches(HookEvent::CallEnd, &json!({"talkGroup": 101})));
+        assert!(!r.matches(HookEvent::CallStart, &json!({"talkGroup": 102})));
+        assert!(!r.matches(HookEvent::CallStart, &json!({})));
+
+        let r = rule(r#"{"event": "call-end", "units": [42], "emergency": true, "webhook": "a"}"#)
+            .unwrap();
+
+        assert!(r.matches(
+            HookEvent::CallEnd,
+            &json!({"srcUnit": 42, "emergency": true})
+        ));
+        assert!(!r.matches(
+            HookEvent::CallEnd,
+            &json!({"srcUnit": 42, "emergency": false})
+        ));
+        assert!(!r.matches(
+            HookEvent::CallEnd,
+            &json!({"srcUnit": null, "emergency": true})
+        ));
+    }
+
+    #[test]
+    fn test_render() {
+        let payload = json!({"talkGroup": 101, "alias": "Fire Dispatch", "srcUnit": null});
+
+        assert_eq!(
+            render(
+                "{event}: {alias} ({talkGroup}) from {srcUnit}",
+                HookEvent::CallStart,
+                &payload
+            ),
+            "call-start: Fire Dispatch (101) from unknown"
+        );
+        assert_eq!(
+            render("{bogus} {", HookEvent::CallStart, &payload),
+            "unknown {"
+        );
+    }
+
+    #[test]
+    fn test_requests() {
+        let mut alerts = Alerts::new(vec![
+            rule(r#"{"event": "control-lost", "discord": "https://d", "cooldown": 60}"#).unwrap(),
+            rule(r#"{"event": "control-lost", "pushover": {"token": "t", "user": "u"}}"#).unwrap(),
+        ]);
+
+        let payload = json!({"freq": 851012500});
+        let now = Instant::now();
+
+        assert!(alerts.handles(HookEvent::ControlLost));
+        assert!(!alerts.handles(HookEvent::CallStart));
+
+        let reqs = alerts.requests(HookEvent::ControlLost, &payload, now);
+
+        assert_eq!(reqs.len(), 2);
+        assert_eq!(
+            reqs[0],
+            Request::Json(
+                "https://d".to_string(),
+                r#"{"content":"Lost control channel at 851012500 Hz"}"#.to_string()
+            )
+        );
+
+        match reqs[1] {
+            Request::Form(ref url, ref fields) => {
+                assert_eq!(url, PUSHOVER_URL);
+                assert!(fields.contains(&("priority", "1".to_string())));
+            }
+            _ => panic!("expected form request"),
+        }
+
+        // The first rule is still cooling down.
+        let reqs = alerts.requests(
+            HookEvent::ControlLost,
+            &payload,
+            now + Duration::from_secs(1),
+        );
+        assert_eq!(reqs.len(), 1);
+
+        let reqs = alerts.requests(
+            HookEvent::ControlLost,
+            &payload,
+            now + Duration::from_secs(61),
+        );
+        assert_eq!(reqs.len(), 2);
+    }
+}
@@ -0,0 +1,109 @@
+//! Human-readable talkgroup names.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use fnv::FnvBuildHasher;
+
+/// Maps talkgroups to user-assigned names.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TalkgroupAliases(HashMap<u16, String, FnvBuildHasher>);
+
+impl TalkgroupAliases {
+    /// Load aliases from the CSV file at the given path.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("unable to read aliases file {}", path.display()))?;
+
+        parse(&text)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("invalid aliases file {}", path.display()))
+    }
+
+    /// Get the name of the given talkgroup, if any.
+    pub fn get(&self, tg: u16) -> Option<&str> {
+        self.0.get(&tg).map(|s| &s[..])
+    }
+
+    /// Set the name of the given talkgroup.
+    pub fn insert(&mut self, tg: u16, name: String) {
+        self.0.insert(tg, name);
+    }
+
+    /// Add the given aliases, replacing any existing names of the same talkgroups.
+    pub fn extend(&mut self, other: TalkgroupAliases) {
+        self.0.extend(other.0);
+    }
+
+    /// Count the talkgroups added, removed, and renamed relative to the given previous
+    /// aliases.
+    pub fn diff(&self, prev: &TalkgroupAliases) -> (usize, usize, usize) {
+        let added = self.0.keys().filter(|tg| !prev.0.contains_key(tg)).count();
+        let removed = prev.0.keys().filter(|tg| !self.0.contains_key(tg)).count();
+        let renamed = self
+            .0
+            .iter()
+            .filter(|&(tg, name)| prev.0.get(tg).map_or(false, |p| p != name))
+            .count();
+
+        (added, removed, renamed)
+    }
+}
+
+/// Parse aliases from lines like `101,Fire Dispatch`.
+///
+/// Blank lines and lines starting with `#` are ignored.
+pub fn parse(text: &str) -> Result<TalkgroupAliases, String> {
+    let mut aliases = TalkgroupAliases::default();
+
+    for (num, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let err = || format!("line {}: expected TALKGROUP,NAME", num + 1);
+        let (tg, name) = line.split_once(',').ok_or_else(err)?;
+        let tg = tg.trim().parse().map_err(|_| err())?;
+
+        aliases.insert(tg, name.trim().to_string());
+    }
+
+    Ok(aliases)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let a = parse("# talkgroups\n101,Fire Dispatch\n\n 4521 , FireDispatch \n").unwrap();
+
+        assert_eq!(a.get(101), Some("Fire Dispatch"));
+        assert_eq!(a.get(4521), Some("FireDispatch"));
+        assert_eq!(a.get(102), None);
+
+        assert_eq!(parse("101").unwrap_err(), "line 1: expected TALKGROUP,NAME");
+        assert_eq!(
+            parse("\nabc,Name").unwrap_err(),
+            "line 2: expected TALKGROUP,NAME"
+        );
+        assert!(parse("70000,Name").is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let prev = parse("1,A\n2,B\n3,C").unwrap();
+        let mut cur = parse("2,B\n3,D\n4,E\n5,F").unwrap();
+
+        assert_eq!(cur.diff(&prev), (2, 1, 1));
+        assert_eq!(prev.diff(&prev), (0, 0, 0));
+
+        cur.extend(parse("4,G\n6,H").unwrap());
+        assert_eq!(cur.get(4), Some("G"));
+        assert_eq!(cur.get(6), Some("H"));
+        assert_eq!(cur.get(5), Some("F"));
+    }
+}
@@ -1,18 +1,106 @@
 //! Voice frame decoding and audio output.
 
-use std::{io::Write, sync::mpsc::Receiver};
+use std::{
+    ffi::CString,
+    fs::{File, OpenOptions},
+    io::{self, ErrorKind, Write},
+    os::unix::{ffi::OsStrExt, fs::OpenOptionsExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use imbe::{consts::SAMPLES_PER_FRAME, decode::ImbeDecoder, frame::ReceivedFrame};
+use clap::ValueEnum;
+use imbe::{consts::SAMPLES_PER_FRAME, decode::ImbeDecoder};
 use p25::voice::frame::VoiceFrame;
-use slice_cast;
 use slice_mip::MapInPlace;
 
+use crate::{
+    biquad::Biquad,
+    calls::CallKind,
+    consts::AUDIO_SAMPLE_RATE,
+    export::{CallInfo, FrameExport},
+    faults::{Fault, FaultCategory},
+    hub::HubEvent,
+    level::{self, Normalizer},
+    queue::{QueueItem, QueueReceiver, QueueSender},
+    record::CallRecorder,
+    resample::Resampler,
+    squelch::ErrorSquelch,
+    stages::StageMetrics,
+    stereo::Panner,
+    tap::Tap,
+    vocoder::{SoftwareVocoder, Vocoder},
+};
+
+/// Minimum time between attempts to reconnect a disconnected sink.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Maximum size of each write to the output stream (bytes).
+///
+/// This is `PIPE_BUF` on Linux, so each write to a FIFO is atomic.
+const WRITE_BYTES: usize = 4096;
+
+/// Audio held by the output FIFO in low-latency mode (sec).
+pub const LOW_LATENCY_FIFO_SECS: f32 = 0.1;
+
+/// Supported output sample rates (Hz).
+pub const AUDIO_RATES: [&str; 4] = ["8000", "16000", "44100", "48000"];
+
+/// Sample encoding of the audio output.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, ValueEnum)]
+pub enum AudioFormat {
+    /// 32-bit little-endian float.
+    F32le,
+    /// 16-bit little-endian signed integer.
+    S16le,
+}
+
+impl AudioFormat {
+    /// Get the size (bytes) of each encoded sample.
+    pub fn width(self) -> usize {
+        match self {
+            AudioFormat::F32le => 4,
+            AudioFormat::S16le => 2,
+        }
+    }
+
+    /// Append the encoding of the given sample, in the range [-1, 1], to the buffer.
+    fn encode(self, s: f32, buf: &mut Vec<u8>) {
+        match self {
+            AudioFormat::F32le => buf.extend_from_slice(&s.to_le_bytes()),
+            AudioFormat::S16le => {
+                let s = (s.max(-1.0).min(1.0) * i16::MAX as f32) as i16;
+                buf.extend_from_slice(&s.to_le_bytes());
+            }
+        }
+    }
+}
+
 /// Messages for `AudioTask`.
 pub enum AudioEvent {
-    /// A voice frame was received.
-    VoiceFrame(VoiceFrame),
+    /// A voice frame was received, with the time its last sample arrived at the SDR if
+    /// it was received live.
+    VoiceFrame(VoiceFrame, Option<Instant>),
+    /// A new voice transmission is expected with the given talkgroup, frequency, and call
+    /// type.
+    BeginCall(u16, u32, CallKind),
+    /// The source unit of the current voice transmission was identified.
+    UpdateSrcUnit(u32),
     /// The current voice transmission has been terminated.
     EndTransmission,
+    /// Enable or disable call recording.
+    Capture(bool),
+    /// Silence or restore the live audio output.
+    Mute(bool),
+    /// Stop handling events, finishing any recording.
+    Stop,
+}
+
+impl QueueItem for AudioEvent {
+    fn droppable(&self) -> bool {
+        matches!(*self, AudioEvent::VoiceFrame(..))
+    }
 }
 
 /// Decodes voice frames and outputs them to a stream.
@@ -20,28 +108,371 @@ pub struct AudioTask<W: Write> {
     /// Decodes and outputs frames.
     audio: AudioOutput<W>,
     /// Channel for messages.
-    events: Receiver<AudioEvent>,
+    events: QueueReceiver<AudioEvent>,
+    /// Event streaming.
+    hub: QueueSender<HubEvent>,
+    /// Whether the last write to the output succeeded.
+    connected: bool,
+    /// Metadata for the current voice transmission.
+    call: CallInfo,
+    /// Writes undecoded voice frames, if enabled.
+    export: Option<FrameExport>,
+    /// Writes the audio of each call, if enabled.
+    record: Option<CallRecorder>,
+    /// Whether new calls are recorded.
+    capture: bool,
+    /// Streams decoded samples to TCP clients, if enabled.
+    tap: Option<Tap>,
+    /// Processing metrics.
+    stage: Arc<StageMetrics>,
 }
 
 impl<W: Write> AudioTask<W> {
-    /// Create a new `AudioTask` with the given audio output and event channel.
-    pub fn new(audio: AudioOutput<W>, events: Receiver<AudioEvent>) -> Self {
+    /// Create a new `AudioTask` with the given audio output and channels.
+    pub fn new(
+        audio: AudioOutput<W>,
+        events: QueueReceiver<AudioEvent>,
+        hub: QueueSender<HubEvent>,
+    ) -> Self {
         AudioTask {
             audio,
             events,
+            hub,
+            connected: true,
+            call: CallInfo::default(),
+            export: None,
+            record: None,
+            capture: true,
+            tap: None,
+            stage: StageMetrics::new("audio"),
         }
     }
 
-    /// Begin handling events, blocking the current thread.
+    /// Also write each undecoded voice frame, along with its call metadata, to the
+    /// given export.
+    pub fn export(mut self, export: FrameExport) -> Self {
+        self.export = Some(export);
+        self
+    }
+
+    /// Also write the audio of each call to its own file with the given recorder.
+    pub fn record(mut self, record: CallRecorder) -> Self {
+        self.record = Some(record);
+        self
+    }
+
+    /// Also stream decoded samples to the clients of the given tap.
+    pub fn tap(mut self, tap: Tap) -> Self {
+        self.tap = Some(tap);
+        self
+    }
+
+    /// Get the processing metrics of the task.
+    pub fn metrics(&self) -> Arc<StageMetrics> {
+        self.stage.clone()
+    }
+
+    /// Begin handling events, blocking the current thread until stopped.
     pub fn run(&mut self) {
         loop {
-            match self.events.recv().expect("unable to receive audio event") {
-                AudioEvent::VoiceFrame(vf) => self.audio.play(&vf),
+            let event = self.events.recv().expect("unable to receive audio event");
+            let _timer = self.stage.start(self.events.metrics().depth());
+
+            let res = match event {
+                AudioEvent::VoiceFrame(vf, arrived) => {
+                    self.export_frame(&vf);
+
+                    let res = self.audio.play(&vf);
+
+                    if let Some(t) = arrived {
+                        self.report_latency(t);
+                    }
+
+                    self.record_samples();
+                    self.stream_samples();
+                    res
+                }
+                AudioEvent::BeginCall(talkgroup, freq, kind) => {
+                    self.audio.begin_call(talkgroup);
+
+                    if self.capture {
+                        self.update_recording(|r| r.begin(talkgroup, kind));
+                    }
+                    self.call = CallInfo {
+                        freq,
+                        talkgroup,
+                        src_unit: 0,
+                    };
+
+                    continue;
+                }
+                AudioEvent::UpdateSrcUnit(src) => {
+                    self.call.src_unit = src;
+                    continue;
+                }
                 AudioEvent::EndTransmission => {
-                    self.audio.flush();
+                    self.call = CallInfo::default();
+                    self.update_recording(CallRecorder::finish);
+
+                    let res = self.audio.flush();
                     self.audio.reset();
+                    res
+                }
+                AudioEvent::Capture(capture) => {
+                    self.capture = capture;
+
+                    if !capture {
+                        self.update_recording(CallRecorder::finish);
+                    }
+
+                    continue;
+                }
+                AudioEvent::Mute(muted) => {
+                    self.audio.set_muted(muted);
+                    continue;
+                }
+                AudioEvent::Stop => {
+                    self.update_recording(CallRecorder::finish);
+                    self.audio.flush().ok();
+                    return;
+                }
+            };
+
+            self.update_connected(res);
+        }
+    }
+
+    /// Write the given frame to the export, if enabled, disabling the export after a
+    /// failed write.
+    fn export_frame(&mut self, vf: &VoiceFrame) {
+        let res = match self.export {
+            Some(ref mut e) => e.write(&self.call, vf),
+            None => return,
+        };
+
+        if let Err(e) = res {
+            self.report(Fault::warning(
+                FaultCategory::Audio,
+                format!("disabling voice frame export: {}", e),
+            ));
+            self.export = None;
+        }
+    }
+
+    /// Write the latest decoded samples to the current recording, if enabled, disabling
+    /// recording after a failed write.
+    fn record_samples(&mut self) {
+        let res = match self.record {
+            Some(ref mut r) => r.write(self.audio.decoded()),
+            None => return,
+        };
+
+        if let Err(e) = res {
+            self.report(Fault::warning(
+                FaultCategory::Audio,
+                format!("disabling call recording: {}", e),
+            ));
+            self.record = None;
+        }
+    }
+
+    /// Send the latest decoded samples to live audio listeners.
+    fn stream_samples(&mut self) {
+        let mut buf = Vec::with_capacity(SAMPLES_PER_FRAME * 2);
+
+        for &s in self.audio.decoded() {
+            AudioFormat::S16le.encode(s, &mut buf);
+        }
+
+        if let Some(ref mut t) = self.tap {
+            t.send(&buf[..]);
+        }
+
+        self.hub
+            .send(HubEvent::AudioFrame(buf))
+            .expect("unable to send audio frame");
+    }
+
+    /// Report the time since the given arrival of the frame just written.
+    fn report_latency(&mut self, arrived: Instant) {
+        let ms = arrived.elapsed().as_millis().min(u32::MAX as u128) as u32;
+
+        self.hub
+            .send(HubEvent::AudioLatency(ms))
+            .expect("unable to send audio latency");
+    }
+
+    /// Apply the given operation to the recorder, if enabled, reporting any finished
+    /// recording and disabling recording after a failure.
+    fn update_recording<F>(&mut self, op: F)
+    where
+        F: FnOnce(&mut CallRecorder) -> io::Result<Option<(u16, PathBuf)>>,
+    {
+        let res = match self.record {
+            Some(ref mut r) => op(r),
+            None => return,
+        };
+
+        match res {
+            Ok(Some((tg, path))) => {
+                self.hub
+                    .send(HubEvent::CallRecorded(tg, path))
+                    .expect("unable to send call recording");
+            }
+            Ok(None) => {}
+            Err(e) => {
+                self.report(Fault::warning(
+                    FaultCategory::Audio,
+                    format!("disabling call recording: {}", e),
+                ));
+                self.record = None;
+            }
+        }
+    }
+
+    /// Log the given fault and report it to the hub.
+    fn report(&self, fault: Fault) {
+        fault.log();
+        self.hub
+            .send(HubEvent::Fault(fault))
+            .expect("unable to send fault");
+    }
+
+    /// Track the output state based on the result of the latest write.
+    ///
+    /// Audio written while the output is down is dropped, so the rest of the receiver
+    /// keeps running regardless of whether anything is listening.
+    fn update_connected(&mut self, res: io::Result<()>) {
+        match (self.connected, res) {
+            (true, Err(e)) => {
+                self.report(Fault::error(
+                    FaultCategory::Audio,
+                    format!("audio output down: {}", e),
+                ));
+                self.connected = false;
+                self.hub
+                    .send(HubEvent::AudioSinkDown)
+                    .expect("unable to send audio sink state");
+            }
+            (false, Ok(())) => {
+                info!("audio output up");
+                self.connected = true;
+                self.hub
+                    .send(HubEvent::AudioSinkUp)
+                    .expect("unable to send audio sink state");
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Create a FIFO at the given path.
+pub fn create_fifo(path: &Path) -> io::Result<()> {
+    let cpath = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+
+    match unsafe { libc::mkfifo(cpath.as_ptr(), 0o644) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Non-blocking audio output to a FIFO (or file) path that reconnects after failures.
+///
+/// Writes never block the audio task: if the reader isn't keeping up, the frame is
+/// dropped, and if the reader disconnects, the path is periodically reopened until a
+/// new reader appears. `AudioOutput` writes at most `PIPE_BUF` bytes at a time, so
+/// writes are always atomic and sample alignment is preserved.
+pub struct FifoSink {
+    /// Path to open.
+    path: PathBuf,
+    /// Currently open output.
+    file: Option<File>,
+    /// Time of the last connection attempt.
+    attempt: Option<Instant>,
+    /// Capacity (bytes) to set on the FIFO when opened, if not the system default.
+    pipe_size: Option<usize>,
+}
+
+impl FifoSink {
+    /// Create a new `FifoSink` over the given path.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FifoSink {
+            path: path.into(),
+            file: None,
+            attempt: None,
+            pipe_size: None,
+        }
+    }
+
+    /// Shrink the FIFO to hold about the given number of bytes, rounded up by the
+    /// system, so less audio can queue up behind a slow reader.
+    pub fn pipe_size(mut self, bytes: usize) -> Self {
+        self.pipe_size = Some(bytes);
+        self
+    }
+
+    /// Try to open the output if it's not currently open.
+    fn connect(&mut self) -> io::Result<&mut File> {
+        if self.file.is_none() {
+            if let Some(t) = self.attempt {
+                if t.elapsed() < RECONNECT_INTERVAL {
+                    return Err(ErrorKind::NotConnected.into());
                 }
             }
+
+            self.attempt = Some(Instant::now());
+
+            // Opening the write end of a FIFO without a reader fails with ENXIO rather
+            // than blocking.
+            let file = OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&self.path)?;
+
+            if let Some(size) = self.pipe_size {
+                // This is safe because the descriptor stays open for the call.
+                let res = unsafe {
+                    libc::fcntl(file.as_raw_fd(), libc::F_SETPIPE_SZ, size as libc::c_int)
+                };
+
+                if res < 0 {
+                    warn!(
+                        "unable to resize audio fifo: {}",
+                        io::Error::last_os_error()
+                    );
+                }
+            }
+
+            self.file = Some(file);
+        }
+
+        Ok(self.file.as_mut().unwrap())
+    }
+}
+
+impl Write for FifoSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let res = self.connect()?.write(buf);
+
+        match res {
+            Ok(n) => Ok(n),
+            // Drop the samples if the reader isn't keeping up.
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                debug!("dropping {} bytes of audio", buf.len());
+                Ok(buf.len())
+            }
+            Err(e) => {
+                self.file = None;
+                Err(e)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file {
+            Some(ref mut f) => f.flush(),
+            None => Ok(()),
         }
     }
 }
@@ -51,41 +482,247 @@ pub struct AudioOutput<W: Write> {
     /// Stream to write to.
     stream: W,
     /// Voice frame decoder.
-    imbe: ImbeDecoder,
+    vocoder: Box<dyn Vocoder + Send>,
+    /// Whether the last frame was decoded successfully.
+    decoding: bool,
+    /// Output sample encoding.
+    format: AudioFormat,
+    /// Output sample rate (Hz).
+    rate: u32,
+    /// Filters applied to decoded samples, in order.
+    filters: Vec<Biquad>,
+    /// Linear gain applied to decoded samples.
+    gain: f32,
+    /// Mutes frames with too many bit errors, if enabled.
+    squelch: Option<ErrorSquelch>,
+    /// Adjusts decoded samples toward a target loudness, if enabled.
+    normalizer: Option<Normalizer>,
+    /// Places each transmission in the stereo field, if the output is stereo.
+    panner: Option<Panner>,
+    /// Converts decoded samples to the output rate, if it differs from the decoded rate.
+    resampler: Option<Resampler>,
+    /// Latest decoded samples, after filtering and gain.
+    decoded: [f32; SAMPLES_PER_FRAME],
+    /// Samples at the output rate.
+    samples: Vec<f32>,
+    /// Encoded output bytes.
+    buf: Vec<u8>,
+    /// Whether silence is written in place of decoded samples.
+    muted: bool,
 }
 
 impl<W: Write> AudioOutput<W> {
-    /// Create a new `AudioOutput` over the given stream.
-    pub fn new(stream: W) -> Self {
+    /// Create a new `AudioOutput` over the given stream, writing samples with the given
+    /// encoding and rate.
+    pub fn new(stream: W, format: AudioFormat, rate: u32) -> Self {
         AudioOutput {
             stream,
-            imbe: ImbeDecoder::new(),
+            vocoder: Box::new(SoftwareVocoder(ImbeDecoder::new())),
+            decoding: true,
+            format,
+            rate,
+            filters: vec![],
+            gain: 1.0,
+            squelch: None,
+            normalizer: None,
+            panner: None,
+            resampler: if rate == AUDIO_SAMPLE_RATE {
+                None
+            }
+            else {
+                Some(Resampler::new(AUDIO_SAMPLE_RATE, rate))
+            },
+            decoded: [0.0; SAMPLES_PER_FRAME],
+            samples: vec![],
+            buf: vec![],
+            muted: false,
         }
     }
 
+    /// Decode voice frames with the given vocoder rather than the software decoder.
+    pub fn vocoder(mut self, vocoder: Box<dyn Vocoder + Send>) -> Self {
+        self.vocoder = vocoder;
+        self
+    }
+
+    /// Append the given filter to the chain applied to decoded samples, before
+    /// normalization and gain.
+    pub fn filter(mut self, filter: Biquad) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Apply the given gain (dB) to decoded samples.
+    pub fn gain(mut self, db: f32) -> Self {
+        self.gain = level::db_to_amplitude(db);
+        self
+    }
+
+    /// Replace runs of frames with more than the given number of bit errors with
+    /// silence.
+    pub fn squelch(mut self, max_errors: usize) -> Self {
+        self.squelch = Some(ErrorSquelch::new(max_errors));
+        self
+    }
+
+    /// Normalize decoded samples toward the given loudness (dBFS RMS) before applying
+    /// gain.
+    pub fn normalize(mut self, target: f32) -> Self {
+        self.normalizer = Some(Normalizer::new(target));
+        self
+    }
+
+    /// Write interleaved stereo samples, with each transmission positioned by the given
+    /// panner.
+    pub fn stereo(mut self, panner: Panner) -> Self {
+        self.panner = Some(panner);
+        self
+    }
+
+    /// Prepare for a new transmission on the given talkgroup.
+    pub fn begin_call(&mut self, tg: u16) {
+        if let Some(ref mut p) = self.panner {
+            p.begin_call(tg);
+        }
+    }
+
+    /// Write silence in place of decoded samples, keeping the output timing, if the
+    /// given flag is set.
+    ///
+    /// Only the output stream is silenced, and the samples returned by `decoded` are
+    /// unaffected.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
     /// Reinitialize the voice decoder for a new transmission.
     pub fn reset(&mut self) {
-        self.imbe = ImbeDecoder::new();
+        self.vocoder.reset();
+
+        for f in &mut self.filters {
+            f.reset();
+        }
+
+        if let Some(ref mut sq) = self.squelch {
+            sq.reset();
+        }
+
+        if let Some(ref mut n) = self.normalizer {
+            n.reset();
+        }
+
+        if let Some(ref mut r) = self.resampler {
+            r.reset();
+        }
     }
 
     /// Decode and output the given frame.
-    pub fn play(&mut self, frame: &VoiceFrame) {
-        let frame = ReceivedFrame::new(frame.chunks, frame.errors);
-
+    pub fn play(&mut self, frame: &VoiceFrame) -> io::Result<()> {
         let mut samples = [0.0; SAMPLES_PER_FRAME];
-        self.imbe.decode(frame, &mut samples);
+
+        // Corrupted frames are concealed by decoding the last clean frame instead.
+        let frame = match self.squelch {
+            Some(ref mut sq) => sq.select(frame),
+            None => Some(frame),
+        };
+
+        // Substitute silence for frames the vocoder fails to decode, so the output
+        // stream keeps its timing.
+        if let Some(frame) = frame {
+            match (self.decoding, self.vocoder.decode(frame, &mut samples)) {
+                (true, Err(e)) => {
+                    warn!("unable to decode voice frames: {}", e);
+                    self.decoding = false;
+                }
+                (false, Ok(())) => {
+                    info!("voice frame decoding resumed");
+                    self.decoding = true;
+                }
+                _ => {}
+            }
+
+            if !self.decoding {
+                samples = [0.0; SAMPLES_PER_FRAME];
+            }
+        }
 
         // Reduce volume to a generally sane level.
         samples.map_in_place(|&s| s / 8192.0);
 
-        self.stream
-            .write_all(unsafe { slice_cast::cast(&samples[..]) })
-            .expect("unable to write audio samples");
+        if let Some(ref mut sq) = self.squelch {
+            sq.process(&mut samples[..]);
+        }
+
+        for f in &mut self.filters {
+            f.process(&mut samples[..]);
+        }
+
+        if let Some(ref mut n) = self.normalizer {
+            n.process(&mut samples[..]);
+        }
+
+        let gain = self.gain;
+        samples.map_in_place(|&s| s * gain);
+
+        self.decoded = samples;
+
+        self.samples.clear();
+
+        match self.resampler {
+            Some(ref mut r) => r.process(&samples[..], &mut self.samples),
+            None => self.samples.extend_from_slice(&samples[..]),
+        }
+
+        self.write_samples()
+    }
+
+    /// Get the samples decoded from the latest frame, at the decoded sample rate.
+    pub fn decoded(&self) -> &[f32] {
+        &self.decoded[..]
     }
 
-    /// Flush the wrapped stream.
-    pub fn flush(&mut self) {
-        self.stream.write(&[0; 8000]).unwrap();
-        self.stream.flush().expect("unable to flush audio samples")
+    /// Write a short period of silence and flush the wrapped stream.
+    pub fn flush(&mut self) -> io::Result<()> {
+        // Write 250ms of silence in frame-sized chunks so each write stays atomic.
+        let chunk = self.rate as usize * SAMPLES_PER_FRAME / AUDIO_SAMPLE_RATE as usize;
+
+        for _ in 0..self.rate as usize / 4 / chunk {
+            self.samples.clear();
+            self.samples.resize(chunk, 0.0);
+            self.write_samples()?;
+        }
+
+        self.stream.flush()
+    }
+
+    /// Encode and write the buffered output samples.
+    fn write_samples(&mut self) -> io::Result<()> {
+        self.buf.clear();
+
+        if self.muted {
+            self.samples.iter_mut().for_each(|s| *s = 0.0);
+        }
+
+        match self.panner {
+            Some(ref p) => {
+                let (l, r) = p.position().gains();
+
+                for &s in &self.samples {
+                    self.format.encode(s * l, &mut self.buf);
+                    self.format.encode(s * r, &mut self.buf);
+                }
+            }
+            None => {
+                for &s in &self.samples {
+                    self.format.encode(s, &mut self.buf);
+                }
+            }
+        }
+
+        for chunk in self.buf.chunks(WRITE_BYTES) {
+            self.stream.write_all(chunk)?;
+        }
+
+        Ok(())
     }
 }
@@ -0,0 +1,38 @@
+//! Unit authentication signalling on the control channel.
+
+/// Authentication command sent to a unit, demanding it prove its identity.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct AuthCommand {
+    /// WACN of the unit's home system.
+    pub wacn: u32,
+    /// ID of the unit's home system.
+    pub system: u16,
+    /// Unit being authenticated.
+    pub unit: u32,
+}
+
+impl AuthCommand {
+    /// Decode the given trunking packet payload, which holds a 20-bit WACN, 12-bit
+    /// system ID, and 24-bit target unit ID.
+    pub fn new(payload: &[u8]) -> Self {
+        AuthCommand {
+            wacn: (payload[0] as u32) << 12 | (payload[1] as u32) << 4 | payload[2] as u32 >> 4,
+            system: ((payload[2] & 0xF) as u16) << 8 | payload[3] as u16,
+            unit: (payload[4] as u32) << 16 | (payload[5] as u32) << 8 | payload[6] as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_auth_command() {
+        let a = AuthCommand::new(&[0xBE, 0xEE, 0xF3, 0xB2, 0x0D, 0xC0, 0x31, 0x00]);
+
+        assert_eq!(a.wacn, 0xBEEEF);
+        assert_eq!(a.system, 0x3B2);
+        assert_eq!(a.unit, 0x0DC031);
+    }
+}
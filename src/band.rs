@@ -0,0 +1,199 @@
+//! Validation of tuning frequencies.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::consts::{TUNER_MAX_FREQ, TUNER_MIN_FREQ};
+
+/// Frequency ranges (Hz) the receiver is allowed to tune to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BandMask {
+    /// Allowed inclusive ranges, or all tunable frequencies if empty.
+    ranges: Vec<(u32, u32)>,
+}
+
+impl BandMask {
+    /// Create a new `BandMask` allowing only the given inclusive ranges.
+    pub fn new(ranges: Vec<(u32, u32)>) -> Self {
+        BandMask {
+            ranges,
+        }
+    }
+
+    /// Check if the given frequency (Hz) can be tuned and is within the mask.
+    pub fn check(&self, freq: u32) -> Result<(), String> {
+        if !(TUNER_MIN_FREQ..=TUNER_MAX_FREQ).contains(&freq) {
+            return Err(format!(
+                "frequency {} Hz outside tuner range {}-{} Hz",
+                freq, TUNER_MIN_FREQ, TUNER_MAX_FREQ
+            ));
+        }
+
+        if self.ranges.is_empty() || self.ranges.iter().any(|r| (r.0..=r.1).contains(&freq)) {
+            Ok(())
+        }
+        else {
+            Err(format!("frequency {} Hz outside allowed bands", freq))
+        }
+    }
+}
+
+/// Traffic channel the receiver must never tune to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Avoid {
+    /// Channel at the given frequency (Hz).
+    Freq(u32),
+    /// Channel with the given band identifier and channel number.
+    Channel(u8, u16),
+}
+
+impl fmt::Display for Avoid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Avoid::Freq(freq) => write!(f, "{}", freq),
+            Avoid::Channel(id, num) => write!(f, "{}:{}", id, num),
+        }
+    }
+}
+
+/// Parse an avoided channel like `851012500` (Hz) or `1:123` (band identifier and
+/// channel number).
+pub fn parse_avoid(s: &str) -> Result<Avoid, String> {
+    match s.split_once(':') {
+        Some((id, num)) => {
+            let id: u8 = id
+                .parse()
+                .ok()
+                .filter(|&id| id < 16)
+                .ok_or_else(|| format!("invalid band identifier {}", id))?;
+            let num: u16 = num
+                .parse()
+                .ok()
+                .filter(|&num| num < 4096)
+                .ok_or_else(|| format!("invalid channel number {}", num))?;
+
+            Ok(Avoid::Channel(id, num))
+        }
+        None => s
+            .parse()
+            .map(Avoid::Freq)
+            .map_err(|_| format!("invalid frequency {}", s)),
+    }
+}
+
+/// Traffic channels the receiver must not tune to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AvoidList {
+    /// Avoided channels.
+    channels: Vec<Avoid>,
+    /// Channels (Hz) avoided until the paired time.
+    until: Vec<(u32, Instant)>,
+}
+
+impl AvoidList {
+    /// Create a new `AvoidList` avoiding the given channels.
+    pub fn new(channels: Vec<Avoid>) -> Self {
+        AvoidList {
+            channels,
+            until: vec![],
+        }
+    }
+
+    /// Also avoid the channel at the given frequency (Hz) for the given time.
+    pub fn avoid_for(&mut self, freq: u32, time: Duration) {
+        let now = Instant::now();
+
+        self.until.retain(|&(f, t)| f != freq && t > now);
+        self.until.push((freq, now + time));
+    }
+
+    /// Check if the channel with the given band identifier, channel number, and
+    /// frequency (Hz) is avoided.
+    pub fn avoids(&self, id: u8, num: u16, freq: u32) -> bool {
+        let avoided = self.channels.iter().any(|&a| match a {
+            Avoid::Freq(f) => f == freq,
+            Avoid::Channel(i, n) => i == id && n == num,
+        });
+
+        avoided
+            || self
+                .until
+                .iter()
+                .any(|&(f, t)| f == freq && t > Instant::now())
+    }
+}
+
+/// Parse a frequency range like `851000000-869000000` (Hz).
+pub fn parse_range(s: &str) -> Result<(u32, u32), String> {
+    let (lo, hi) = s
+        .split_once('-')
+        .ok_or_else(|| format!("invalid range {}", s))?;
+
+    let lo: u32 = lo
+        .parse()
+        .map_err(|_| format!("invalid frequency {}", lo))?;
+    let hi: u32 = hi
+        .parse()
+        .map_err(|_| format!("invalid frequency {}", hi))?;
+
+    if lo > hi {
+        return Err(format!("empty range {}", s));
+    }
+
+    Ok((lo, hi))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let m = BandMask::default();
+        assert!(m.check(856162500).is_ok());
+        assert!(m.check(1000).is_err());
+        assert!(m.check(3000000000).is_err());
+
+        let m = BandMask::new(vec![(851000000, 869000000), (935000000, 941000000)]);
+        assert!(m.check(851000000).is_ok());
+        assert!(m.check(869000000).is_ok());
+        assert!(m.check(937500000).is_ok());
+        assert!(m.check(870000000).is_err());
+        assert!(m.check(155000000).is_err());
+    }
+
+    #[test]
+    fn test_avoid() {
+        assert_eq!(parse_avoid("851012500"), Ok(Avoid::Freq(851012500)));
+        assert_eq!(parse_avoid("1:123"), Ok(Avoid::Channel(1, 123)));
+        assert!(parse_avoid("16:123").is_err());
+        assert!(parse_avoid("1:4096").is_err());
+        assert!(parse_avoid("ch1").is_err());
+        assert_eq!(Avoid::Channel(1, 123).to_string(), "1:123");
+
+        let l = AvoidList::new(vec![Avoid::Freq(851012500), Avoid::Channel(1, 123)]);
+        assert!(l.avoids(0, 1, 851012500));
+        assert!(l.avoids(1, 123, 852000000));
+        assert!(!l.avoids(1, 124, 852012500));
+        assert!(!AvoidList::default().avoids(1, 123, 851012500));
+
+        let mut l = AvoidList::default();
+        l.avoid_for(851012500, Duration::from_secs(60));
+        l.avoid_for(852000000, Duration::from_secs(0));
+        assert!(l.avoids(0, 1, 851012500));
+        assert!(!l.avoids(0, 2, 852000000));
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            parse_range("851000000-869000000"),
+            Ok((851000000, 869000000))
+        );
+        assert!(parse_range("851000000").is_err());
+        assert!(parse_range("869000000-851000000").is_err());
+        assert!(parse_range("a-b").is_err());
+    }
+}
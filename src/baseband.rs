@@ -0,0 +1,244 @@
+//! Outputs for baseband samples, as with `--write`.
+//!
+//! Each output is a `BasebandSink`, opened from a target like `FILE`, `tcp:HOST:PORT`,
+//! or `zstd:FILE`, and samples are written to them as raw 32-bit little-endian floats.
+//! Writes happen on a thread of their own, behind a bounded queue, so a slow disk or
+//! peer drops chunks of samples rather than stalling decoding. Dropped chunks are
+//! counted as overflows of the queue.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    net::TcpStream,
+};
+
+#[cfg(not(feature = "zstd"))]
+use anyhow::anyhow;
+use anyhow::{Context, Result};
+
+use crate::queue::{QueueItem, QueueReceiver, QueueSender};
+
+/// Destination for chunks of baseband samples.
+pub trait BasebandSink: Send {
+    /// Write the given chunk of samples.
+    fn write(&mut self, samples: &[f32]) -> io::Result<()>;
+
+    /// Write out anything buffered, after the last chunk.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes raw samples to a byte stream.
+pub struct StreamSink<W: Write + Send>(W);
+
+impl<W: Write + Send> StreamSink<W> {
+    /// Create a new `StreamSink` over the given stream.
+    pub fn new(stream: W) -> Self {
+        StreamSink(stream)
+    }
+}
+
+impl<W: Write + Send> BasebandSink for StreamSink<W> {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.0.write_all(unsafe { slice_cast::cast(samples) })
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Writes raw samples to a zstd-compressed file.
+#[cfg(feature = "zstd")]
+pub struct ZstdSink(zstd::Encoder<'static, BufWriter<File>>);
+
+#[cfg(feature = "zstd")]
+impl BasebandSink for ZstdSink {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.0.write_all(unsafe { slice_cast::cast(samples) })
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.0.do_finish()
+    }
+}
+
+/// Open a zstd-compressed file at the given path.
+#[cfg(feature = "zstd")]
+fn open_zstd(path: &str) -> Result<Box<dyn BasebandSink>> {
+    let file = BufWriter::new(File::create(path)?);
+
+    Ok(Box::new(ZstdSink(zstd::Encoder::new(file, 0)?)))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn open_zstd(_: &str) -> Result<Box<dyn BasebandSink>> {
+    Err(anyhow!(
+        "p25rx was built without zstd support (the zstd feature)"
+    ))
+}
+
+/// Open the given output target: `tcp:HOST:PORT` to connect to a TCP listener,
+/// `zstd:FILE` for a compressed file, or a file path.
+pub fn open_sink(target: &str) -> Result<Box<dyn BasebandSink>> {
+    let sink: Box<dyn BasebandSink> = if let Some(addr) = target.strip_prefix("tcp:") {
+        Box::new(StreamSink::new(BufWriter::new(TcpStream::connect(addr)?)))
+    }
+    else if let Some(path) = target.strip_prefix("zstd:") {
+        open_zstd(path)?
+    }
+    else {
+        Box::new(StreamSink::new(BufWriter::new(File::create(target)?)))
+    };
+
+    Ok(sink)
+}
+
+impl QueueItem for Vec<f32> {}
+
+/// Sends copies of chunks to the writer task, dropping them if it's too far behind.
+impl BasebandSink for QueueSender<Vec<f32>> {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.send(samples.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "baseband writer stopped"))
+    }
+}
+
+/// Writes each chunk to several sinks, disabling any that fail.
+#[derive(Default)]
+pub struct SinkSet {
+    /// Sinks still enabled, with the name each is reported by.
+    sinks: Vec<(String, Box<dyn BasebandSink>)>,
+}
+
+impl SinkSet {
+    /// Also write to the given sink, reported by the given name.
+    pub fn add<S: Into<String>>(&mut self, name: S, sink: Box<dyn BasebandSink>) {
+        self.sinks.push((name.into(), sink));
+    }
+
+    /// Open and add the given output target, as described for `open_sink`.
+    pub fn open(&mut self, target: &str) -> Result<()> {
+        let sink = open_sink(target)
+            .with_context(|| format!("unable to open baseband output {}", target))?;
+
+        self.add(target, sink);
+
+        Ok(())
+    }
+
+    /// Check if there are no sinks left.
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+}
+
+impl BasebandSink for SinkSet {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.sinks
+            .retain_mut(|(name, sink)| match sink.write(samples) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("disabling baseband output {}: {}", name, e);
+                    false
+                }
+            });
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        for (name, sink) in &mut self.sinks {
+            if let Err(e) = sink.finish() {
+                warn!("unable to finish baseband output {}: {}", name, e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes chunks of baseband samples received from the receiver task to its sinks.
+pub struct BasebandWriter {
+    /// Outputs to write to.
+    sinks: SinkSet,
+    /// Channel for chunks of samples.
+    events: QueueReceiver<Vec<f32>>,
+}
+
+impl BasebandWriter {
+    /// Create a new `BasebandWriter` writing the chunks received on the given channel
+    /// to the given sinks.
+    pub fn new(sinks: SinkSet, events: QueueReceiver<Vec<f32>>) -> Self {
+        BasebandWriter {
+            sinks,
+            events,
+        }
+    }
+
+    /// Begin writing chunks, blocking the current thread until all senders have hung
+    /// up or every sink has failed.
+    pub fn run(&mut self) {
+        while let Ok(samples) = self.events.recv() {
+            self.sinks.write(&samples[..]).ok();
+
+            if self.sinks.is_empty() {
+                warn!("stopping baseband recording");
+                return;
+            }
+        }
+
+        self.sinks.finish().ok();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::queue::{queue, OverflowPolicy};
+    use std::sync::{Arc, Mutex};
+
+    /// Sink that records chunks into a shared buffer and fails after a limit.
+    struct TestSink(Arc<Mutex<Vec<f32>>>, usize);
+
+    impl BasebandSink for TestSink {
+        fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+            let mut buf = self.0.lock().unwrap();
+
+            if buf.len() + samples.len() > self.1 {
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "full"));
+            }
+
+            buf.extend_from_slice(samples);
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_writer() {
+        let (tx, rx) = queue("write", 3, OverflowPolicy::DropNewest);
+
+        tx.send(vec![1.0, 2.0]).unwrap();
+        tx.send(vec![3.0]).unwrap();
+        tx.send(vec![4.0]).unwrap();
+        // The writer isn't keeping up, so the chunk is dropped.
+        tx.send(vec![5.0]).unwrap();
+        assert_eq!(tx.metrics().overflows(), 1);
+        drop(tx);
+
+        let big = Arc::new(Mutex::new(vec![]));
+        let small = Arc::new(Mutex::new(vec![]));
+
+        let mut sinks = SinkSet::default();
+        sinks.add("big", Box::new(TestSink(big.clone(), 10)));
+        sinks.add("small", Box::new(TestSink(small.clone(), 2)));
+
+        BasebandWriter::new(sinks, rx).run();
+
+        assert_eq!(*big.lock().unwrap(), vec![1.0, 2.0, 3.0, 4.0]);
+        // The failed sink is disabled rather than stopping the others.
+        assert_eq!(*small.lock().unwrap(), vec![1.0, 2.0]);
+    }
+}
@@ -0,0 +1,189 @@
+//! Throughput benchmarking of the signal processing chain.
+
+use std::{
+    f32::consts::PI,
+    time::{Duration, Instant},
+};
+
+use collect_slice::CollectSlice;
+use demod_fm::FmDemod;
+use moving_avg::MovingAverage;
+use num::{complex::Complex32, traits::Zero};
+use p25::message::receiver::MessageReceiver;
+use rtlsdr_iq::IQ;
+use slice_mip::MapInPlace;
+
+use crate::{
+    consts::{BASEBAND_SAMPLE_RATE, BUF_BYTES, BUF_SAMPLES, SDR_SAMPLE_RATE},
+    demod::{self, AVG_LEN, DECIM_FACTOR, FM_DEVIATION},
+    fir,
+    gen::{self, Generator},
+};
+
+/// Generate the given duration (sec) of baseband containing trunking packets.
+pub fn generate(secs: f32) -> Vec<f32> {
+    let len = (secs * BASEBAND_SAMPLE_RATE as f32) as usize;
+    let mut g = Generator::new(0x293, 0.01, 0.0, 0);
+    let mut samples = Vec::with_capacity(len);
+
+    g.tsbk(
+        &[gen::channel_params_update(1, 851006250, 6250)],
+        &mut samples,
+    );
+
+    let mut tg = 0u16;
+
+    while samples.len() < len {
+        tg = tg.wrapping_add(1);
+        g.tsbk(&[gen::group_voice_grant(tg, 0x1000, 1)], &mut samples);
+    }
+
+    samples
+}
+
+/// FM modulate the given baseband into interleaved 8-bit I/Q pairs at the SDR sample
+/// rate.
+pub fn modulate(baseband: &[f32]) -> Vec<u8> {
+    let step = 2.0 * PI * FM_DEVIATION as f32 / SDR_SAMPLE_RATE as f32;
+    let mut phase = 0.0f32;
+    let mut bytes = Vec::with_capacity(baseband.len() * DECIM_FACTOR * 2);
+
+    for &s in baseband {
+        for _ in 0..DECIM_FACTOR {
+            phase = (phase + step * s) % (2.0 * PI);
+            bytes.push((127.5 + 127.0 * phase.cos()) as u8);
+            bytes.push((127.5 + 127.0 * phase.sin()) as u8);
+        }
+    }
+
+    bytes
+}
+
+/// Accumulates wall-clock and CPU time spent in a stage.
+#[derive(Default)]
+struct Stage {
+    /// Total wall-clock time.
+    wall: Duration,
+    /// Total CPU time of the current thread.
+    cpu: Duration,
+    /// Total input samples processed.
+    samples: usize,
+}
+
+impl Stage {
+    /// Run the given closure over the given amount of input samples and record the
+    /// time taken.
+    fn time<T, F: FnOnce() -> T>(&mut self, samples: usize, f: F) -> T {
+        let (wall, cpu) = (Instant::now(), thread_cpu_time());
+        let ret = f();
+
+        self.wall += wall.elapsed();
+        self.cpu += thread_cpu_time() - cpu;
+        self.samples += samples;
+
+        ret
+    }
+
+    /// Print a report line for the stage with the given name and real-time input rate.
+    fn report(&self, name: &str, rate: u32) {
+        let secs = self.wall.as_secs_f64();
+        let sps = self.samples as f64 / secs;
+
+        println!(
+            "{:<10} {:>12} {:>10.3} {:>12.0} {:>8.1} {:>10.1}",
+            name,
+            self.samples,
+            secs,
+            sps,
+            100.0 * self.cpu.as_secs_f64() / secs,
+            sps / rate as f64,
+        );
+    }
+}
+
+/// Retrieve the CPU time consumed by the current thread.
+fn thread_cpu_time() -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts);
+    }
+
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+/// Run each stage of the processing chain over the given baseband and print a report of
+/// the throughput of each.
+///
+/// The I/Q stages run over an FM modulated version of the baseband, and the message
+/// receiver runs over the original baseband.
+pub fn bench(baseband: &[f32]) {
+    let bytes = modulate(baseband);
+
+    let mut decim = demod::decimator();
+    let mut bandpass = demod::bandpass();
+    let mut demod = FmDemod::new(FM_DEVIATION, BASEBAND_SAMPLE_RATE);
+    let mut avg = MovingAverage::new(AVG_LEN);
+    let mut msg = MessageReceiver::new();
+
+    let mut convert_stage = Stage::default();
+    let mut decim_stage = Stage::default();
+    let mut bandpass_stage = Stage::default();
+    let mut demod_stage = Stage::default();
+    let mut avg_stage = Stage::default();
+    let mut recv_stage = Stage::default();
+
+    let mut samples = vec![Complex32::zero(); BUF_SAMPLES];
+    let mut out = vec![0.0; BUF_SAMPLES];
+
+    for chunk in bytes.chunks(BUF_BYTES) {
+        // This is safe because it's transforming an array of N 8-bit words to an array
+        // of N/2 16-bit words.
+        let pairs =
+            unsafe { std::slice::from_raw_parts(chunk.as_ptr() as *const u16, chunk.len() / 2) };
+
+        samples.resize(pairs.len(), Complex32::zero());
+
+        convert_stage.time(pairs.len(), || {
+            pairs.iter().map(|&s| IQ[s]).collect_slice(&mut samples[..]);
+        });
+
+        let len = decim_stage.time(samples.len(), || decim.decim_in_place(&mut samples[..]));
+        samples.truncate(len);
+
+        bandpass_stage.time(len, || samples.map_in_place(|&s| bandpass.feed(s)));
+
+        demod_stage.time(len, || {
+            samples
+                .iter()
+                .map(|&s| demod.feed(s))
+                .collect_slice(&mut out[..len]);
+        });
+
+        avg_stage.time(len, || out[..len].map_in_place(|&s| avg.feed(s)));
+    }
+
+    for chunk in baseband.chunks(BUF_SAMPLES) {
+        recv_stage.time(chunk.len(), || {
+            for &s in chunk {
+                msg.feed(s);
+            }
+        });
+    }
+
+    println!("using {} filter kernel", fir::kernel_name());
+    println!(
+        "{:<10} {:>12} {:>10} {:>12} {:>8} {:>10}",
+        "stage", "samples", "secs", "samples/s", "cpu%", "realtime"
+    );
+
+    convert_stage.report("convert", SDR_SAMPLE_RATE);
+    decim_stage.report("decimate", SDR_SAMPLE_RATE);
+    bandpass_stage.report("bandpass", BASEBAND_SAMPLE_RATE);
+    demod_stage.report("fm-demod", BASEBAND_SAMPLE_RATE);
+    avg_stage.report("average", BASEBAND_SAMPLE_RATE);
+    recv_stage.report("receiver", BASEBAND_SAMPLE_RATE);
+}
@@ -0,0 +1,150 @@
+//! Second-order IIR filters for audio post-processing.
+//!
+//! Coefficients follow the RBJ "Audio EQ Cookbook" designs.
+
+use std::f32::consts::PI;
+
+use crate::consts::AUDIO_SAMPLE_RATE;
+
+/// Q of the high-pass and low-pass designs, giving a maximally flat (Butterworth)
+/// response.
+const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+/// Q of the notch design, giving a stopband around 1/4 octave wide.
+const NOTCH_Q: f32 = 4.0;
+
+/// Second-order IIR filter in transposed direct form II.
+pub struct Biquad {
+    /// Feedforward coefficients, normalized by a0.
+    b: [f32; 3],
+    /// Feedback coefficients a1 and a2, normalized by a0.
+    a: [f32; 2],
+    /// Filter state.
+    z: [f32; 2],
+}
+
+impl Biquad {
+    /// Create a new `Biquad` from the given unnormalized coefficients.
+    fn new(b: [f32; 3], a: [f32; 3]) -> Self {
+        Biquad {
+            b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            a: [a[1] / a[0], a[2] / a[0]],
+            z: [0.0; 2],
+        }
+    }
+
+    /// Compute the cosine and alpha terms for the given center/cutoff frequency (Hz)
+    /// and Q at the decoded audio rate.
+    fn terms(freq: f32, q: f32) -> (f32, f32) {
+        let w = 2.0 * PI * freq / AUDIO_SAMPLE_RATE as f32;
+        (w.cos(), w.sin() / (2.0 * q))
+    }
+
+    /// Create a high-pass filter with the given cutoff frequency (Hz).
+    pub fn highpass(freq: f32) -> Self {
+        let (cos, alpha) = Self::terms(freq, BUTTERWORTH_Q);
+
+        Self::new(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// Create a low-pass filter with the given cutoff frequency (Hz).
+    pub fn lowpass(freq: f32) -> Self {
+        let (cos, alpha) = Self::terms(freq, BUTTERWORTH_Q);
+
+        Self::new(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// Create a notch filter centered on the given frequency (Hz).
+    pub fn notch(freq: f32) -> Self {
+        let (cos, alpha) = Self::terms(freq, NOTCH_Q);
+
+        Self::new(
+            [1.0, -2.0 * cos, 1.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// Filter the given samples in place.
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for s in samples.iter_mut() {
+            let x = *s;
+            let y = self.b[0] * x + self.z[0];
+
+            self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+            self.z[1] = self.b[2] * x - self.a[1] * y;
+
+            *s = y;
+        }
+    }
+
+    /// Clear filter state between transmissions.
+    pub fn reset(&mut self) {
+        self.z = [0.0; 2];
+    }
+}
+
+/// Parse a filter frequency (Hz), which must be below the Nyquist frequency of decoded
+/// audio.
+pub fn parse_freq(s: &str) -> Result<f32, String> {
+    let freq: f32 = s.parse().map_err(|_| format!("invalid frequency {}", s))?;
+    let nyquist = AUDIO_SAMPLE_RATE as f32 / 2.0;
+
+    if freq > 0.0 && freq < nyquist {
+        Ok(freq)
+    }
+    else {
+        Err(format!("frequency must be between 0 and {} Hz", nyquist))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Measure the steady-state gain of the filter for a tone at the given frequency.
+    fn response(mut f: Biquad, freq: f32) -> f32 {
+        let mut samples: Vec<f32> = (0..AUDIO_SAMPLE_RATE)
+            .map(|n| (2.0 * PI * freq * n as f32 / AUDIO_SAMPLE_RATE as f32).sin())
+            .collect();
+
+        f.process(&mut samples[..]);
+
+        // Skip the transient response and convert RMS to peak amplitude.
+        let tail = &samples[4000..];
+        (tail.iter().map(|&s| s * s).sum::<f32>() / tail.len() as f32 * 2.0).sqrt()
+    }
+
+    #[test]
+    fn test_highpass() {
+        assert!(response(Biquad::highpass(300.0), 30.0) < 0.02);
+        assert!((response(Biquad::highpass(300.0), 300.0) - 0.707).abs() < 0.01);
+        assert!((response(Biquad::highpass(300.0), 2000.0) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_lowpass() {
+        assert!((response(Biquad::lowpass(3000.0), 300.0) - 1.0).abs() < 0.01);
+        assert!((response(Biquad::lowpass(3000.0), 3000.0) - 0.707).abs() < 0.01);
+        assert!(response(Biquad::lowpass(1000.0), 3500.0) < 0.1);
+    }
+
+    #[test]
+    fn test_notch() {
+        assert!(response(Biquad::notch(1000.0), 1000.0) < 0.01);
+        assert!((response(Biquad::notch(1000.0), 300.0) - 1.0).abs() < 0.05);
+        assert!((response(Biquad::notch(1000.0), 3000.0) - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_parse_freq() {
+        assert_eq!(parse_freq("300"), Ok(300.0));
+        assert!(parse_freq("0").is_err());
+        assert!(parse_freq("4000").is_err());
+        assert!(parse_freq("abc").is_err());
+    }
+}
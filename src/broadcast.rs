@@ -0,0 +1,95 @@
+//! Fan-out of hub events as JSON datagrams to UDP targets.
+
+use std::{
+    io::{self, ErrorKind},
+    net::{SocketAddr, UdpSocket},
+};
+
+use serde::Serialize;
+
+use crate::eventlog;
+
+/// Hops IPv4 multicast datagrams may take, enough to cross a few local routers.
+const MULTICAST_TTL: u32 = 4;
+
+/// Sends each event as a JSON datagram to every target.
+///
+/// Each datagram holds one object in the same format as the event log. Delivery is
+/// best-effort, so events may be lost or reordered.
+pub struct EventBroadcast {
+    /// Unconnected socket to send from.
+    socket: UdpSocket,
+    /// Unicast, broadcast, or multicast addresses to send to.
+    targets: Vec<SocketAddr>,
+}
+
+impl EventBroadcast {
+    /// Create a new `EventBroadcast` sending to the given addresses, which must all be
+    /// the same IP version.
+    pub fn open(targets: &[SocketAddr]) -> io::Result<Self> {
+        let v6 = targets.iter().any(|t| t.is_ipv6());
+
+        if v6 && targets.iter().any(|t| t.is_ipv4()) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "targets must be all IPv4 or all IPv6",
+            ));
+        }
+
+        let socket = UdpSocket::bind(if v6 { "[::]:0" } else { "0.0.0.0:0" })?;
+
+        if !v6 {
+            socket.set_broadcast(true)?;
+            socket.set_multicast_ttl_v4(MULTICAST_TTL)?;
+        }
+
+        // Never hold up the hub waiting for socket buffer space.
+        socket.set_nonblocking(true)?;
+
+        Ok(EventBroadcast {
+            socket,
+            targets: targets.to_vec(),
+        })
+    }
+
+    /// Send the given event, timestamped with the current time, to every target.
+    pub fn send<T: Serialize>(&self, event: &str, payload: &T) {
+        let msg = match eventlog::encode(event, payload) {
+            Ok(msg) => msg,
+            Err(_) => return,
+        };
+
+        for t in &self.targets {
+            match self.socket.send_to(&msg[..], t) {
+                Ok(_) => {}
+                // Datagrams are best-effort, so full buffers and unreachable targets
+                // only drop this event.
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => debug!("unable to broadcast {} to {}: {}", event, t, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_broadcast() {
+        let rx = UdpSocket::bind("127.0.0.1:0").unwrap();
+        rx.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let b = EventBroadcast::open(&[rx.local_addr().unwrap()]).unwrap();
+        b.send("talkGroup", &101);
+
+        let mut buf = [0; 512];
+        let len = rx.recv(&mut buf[..]).unwrap();
+        let msg: serde_json::Value = serde_json::from_slice(&buf[..len]).unwrap();
+
+        assert_eq!(msg["event"].as_str(), Some("talkGroup"));
+        assert_eq!(msg["payload"].as_u64(), Some(101));
+        assert!(msg["time"].is_u64());
+    }
+}
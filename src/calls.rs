@@ -0,0 +1,641 @@
+//! In-memory history of recent calls.
+
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{Local, TimeZone};
+
+use crate::aliases::TalkgroupAliases;
+
+/// Maximum number of calls kept in the history.
+const HISTORY_CALLS: usize = 1000;
+/// Number of calls returned per page when no limit is given.
+const DEFAULT_LIMIT: usize = 100;
+
+/// Type of a followed call.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum CallKind {
+    /// Call on a talkgroup.
+    #[serde(rename = "group")]
+    Group,
+    /// Private call between two units.
+    #[serde(rename = "unit")]
+    Unit,
+    /// Telephone interconnect call involving a unit.
+    #[serde(rename = "phone")]
+    Phone,
+}
+
+impl Default for CallKind {
+    fn default() -> Self {
+        CallKind::Group
+    }
+}
+
+impl CallKind {
+    /// Get the name of the call type, as used in events.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CallKind::Group => "group",
+            CallKind::Unit => "unit",
+            CallKind::Phone => "phone",
+        }
+    }
+}
+
+/// A unit-to-unit or telephone interconnect call granted on the control channel.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct PrivateCall {
+    /// Type of the call.
+    pub kind: CallKind,
+    /// Unit that placed the call, or the unit on a telephone call.
+    #[serde(rename = "srcUnit")]
+    pub src_unit: u32,
+    /// Unit being called, for unit-to-unit calls.
+    #[serde(rename = "destUnit")]
+    pub dest_unit: Option<u32>,
+    /// Traffic channel frequency (Hz).
+    pub freq: u32,
+}
+
+/// A single followed call.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Call {
+    /// Unique, increasing identifier.
+    id: u64,
+    /// Time (ms since the Unix epoch) the call was followed.
+    start: u64,
+    /// Time (ms since the Unix epoch) the call was left, if it has ended.
+    end: Option<u64>,
+    /// Type of the call.
+    kind: CallKind,
+    /// Talkgroup of the call, or 0 for private calls.
+    #[serde(rename = "talkGroup")]
+    talkgroup: u16,
+    /// Traffic channel frequency (Hz).
+    freq: u32,
+    /// Most recent source unit heard on the call, if any.
+    #[serde(rename = "srcUnit")]
+    src_unit: Option<u32>,
+    /// Unit being called, for unit-to-unit calls.
+    #[serde(rename = "destUnit")]
+    dest_unit: Option<u32>,
+    /// Whether the call was marked as an emergency.
+    emergency: bool,
+    /// Highest signal-to-noise ratio (dB) measured on the call, if the noise floor was
+    /// known.
+    snr: Option<f32>,
+    /// Tags added by scripts.
+    tags: Vec<String>,
+    /// Whether audio of the call was recorded.
+    recorded: bool,
+    /// File containing the recorded audio, if any.
+    #[serde(skip_serializing)]
+    recording: Option<PathBuf>,
+}
+
+impl Call {
+    /// Get the type of the call.
+    pub fn kind(&self) -> CallKind {
+        self.kind
+    }
+
+    /// Get the talkgroup of the call.
+    pub fn talkgroup(&self) -> u16 {
+        self.talkgroup
+    }
+
+    /// Get the time (ms since the Unix epoch) the call was followed.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Get the time (ms since the Unix epoch) the call was left, if it has ended.
+    pub fn end(&self) -> Option<u64> {
+        self.end
+    }
+
+    /// Get the length (ms) of the call, or 0 if it hasn't ended.
+    pub fn airtime(&self) -> u64 {
+        self.end.map_or(0, |end| end.saturating_sub(self.start))
+    }
+
+    /// Describe the call in one line, like
+    /// `13:15:02 TG 4521 "FireDispatch" from unit 901233, 8.2s, 857.2625 MHz`.
+    pub fn summary(&self, aliases: &TalkgroupAliases) -> String {
+        let start = Local.timestamp(
+            (self.start / 1000) as i64,
+            (self.start % 1000) as u32 * 1_000_000,
+        );
+
+        let mut s = start.format("%H:%M:%S").to_string();
+
+        match self.kind {
+            CallKind::Group => {
+                s.push_str(&format!(" TG {}", self.talkgroup));
+
+                if let Some(alias) = aliases.get(self.talkgroup) {
+                    s.push_str(&format!(" \"{}\"", alias));
+                }
+            }
+            CallKind::Unit => s.push_str(" unit call"),
+            CallKind::Phone => s.push_str(" phone call"),
+        }
+
+        if let Some(unit) = self.src_unit {
+            s.push_str(&format!(" from unit {}", unit));
+        }
+
+        if let Some(unit) = self.dest_unit {
+            s.push_str(&format!(" to unit {}", unit));
+        }
+
+        if let Some(end) = self.end {
+            s.push_str(&format!(
+                ", {:.1}s",
+                end.saturating_sub(self.start) as f32 / 1000.0
+            ));
+        }
+
+        s.push_str(&format!(", {:.4} MHz", self.freq as f64 / 1e6));
+
+        s
+    }
+}
+
+/// Criteria for selecting a page of calls.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallQuery {
+    /// Only calls started at or after this time (ms since the Unix epoch).
+    since: Option<u64>,
+    /// Only calls on this talkgroup.
+    talkgroup: Option<u16>,
+    /// Only calls older than the call with this ID, for fetching the next page.
+    before: Option<u64>,
+    /// Only calls with recorded audio.
+    recorded: bool,
+    /// Maximum number of calls to return.
+    limit: usize,
+}
+
+impl Default for CallQuery {
+    fn default() -> Self {
+        CallQuery {
+            since: None,
+            talkgroup: None,
+            before: None,
+            recorded: false,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+/// Parse call selection criteria from a query string like `since=1500000000000&tg=101`.
+pub fn parse_query(query: &str) -> Result<CallQuery, String> {
+    let mut q = CallQuery::default();
+
+    for param in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, val) = param.split_once('=').unwrap_or((param, ""));
+        let err = || format!("invalid {} {}", key, val);
+
+        match key {
+            "since" => q.since = Some(val.parse().map_err(|_| err())?),
+            "tg" => q.talkgroup = Some(val.parse().map_err(|_| err())?),
+            "before" => q.before = Some(val.parse().map_err(|_| err())?),
+            "recorded" => q.recorded = val.parse().map_err(|_| err())?,
+            "limit" => q.limit = val.parse().map_err(|_| err())?,
+            _ => {}
+        }
+    }
+
+    q.limit = q.limit.min(HISTORY_CALLS);
+
+    Ok(q)
+}
+
+/// Parse the call ID from a recorded audio path like `/calls/42/audio`.
+pub fn parse_audio_path(path: &str) -> Option<u64> {
+    path.strip_prefix("/calls/")?
+        .strip_suffix("/audio")?
+        .parse()
+        .ok()
+}
+
+/// A page of calls, newest first.
+#[derive(Serialize)]
+pub struct CallPage<'a> {
+    /// Calls matching the query.
+    calls: Vec<&'a Call>,
+    /// Value of `before` to fetch the next page, if there may be more calls.
+    next: Option<u64>,
+}
+
+/// Tracks recently followed calls.
+pub struct CallHistory {
+    /// Recent calls, oldest first.
+    calls: VecDeque<Call>,
+    /// ID for the next call.
+    next_id: u64,
+}
+
+impl Default for CallHistory {
+    fn default() -> Self {
+        CallHistory {
+            calls: VecDeque::with_capacity(HISTORY_CALLS),
+            next_id: 1,
+        }
+    }
+}
+
+impl CallHistory {
+    /// Record the start of a call on the given talkgroup and frequency at the current time,
+    /// ending any current call and returning it.
+    pub fn begin(&mut self, talkgroup: u16, freq: u32) -> Option<Call> {
+        self.begin_at(now(), talkgroup, freq)
+    }
+
+    fn begin_at(&mut self, time: u64, talkgroup: u16, freq: u32) -> Option<Call> {
+        let prev = self.end_at(time);
+
+        if self.calls.len() == HISTORY_CALLS {
+            self.calls.pop_front();
+        }
+
+        self.calls.push_back(Call {
+            id: self.next_id,
+            start: time,
+            end: None,
+            kind: CallKind::Group,
+            talkgroup,
+            freq,
+            src_unit: None,
+            dest_unit: None,
+            emergency: false,
+            snr: None,
+            tags: vec![],
+            recorded: false,
+            recording: None,
+        });
+
+        self.next_id += 1;
+
+        prev
+    }
+
+    /// Record the start of the given private call at the current time, ending any
+    /// current call and returning it.
+    pub fn begin_private(&mut self, p: &PrivateCall) -> Option<Call> {
+        self.begin_private_at(now(), p)
+    }
+
+    fn begin_private_at(&mut self, time: u64, p: &PrivateCall) -> Option<Call> {
+        let prev = self.begin_at(time, 0, p.freq);
+
+        if let Some(call) = self.current() {
+            call.kind = p.kind;
+            call.src_unit = Some(p.src_unit);
+            call.dest_unit = p.dest_unit;
+        }
+
+        prev
+    }
+
+    /// Record the given source unit as heard on the current call.
+    pub fn update_src_unit(&mut self, unit: u32) {
+        if let Some(call) = self.current() {
+            call.src_unit = Some(unit);
+        }
+    }
+
+    /// Record the given signal-to-noise ratio (dB) as measured on the current call.
+    pub fn record_snr(&mut self, snr: f32) {
+        if let Some(call) = self.current() {
+            call.snr = Some(call.snr.map_or(snr, |s| s.max(snr)));
+        }
+    }
+
+    /// Mark the current call as an emergency, returning it if it wasn't already marked.
+    pub fn mark_emergency(&mut self) -> Option<Call> {
+        let call = self.current().filter(|c| !c.emergency)?;
+        call.emergency = true;
+
+        Some(call.clone())
+    }
+
+    /// Add the given tag to the call with the given ID, returning whether the call was
+    /// found.
+    pub fn tag(&mut self, id: u64, tag: String) -> bool {
+        let call = match self.calls.iter_mut().rev().find(|c| c.id == id) {
+            Some(call) => call,
+            None => return false,
+        };
+
+        if !call.tags.contains(&tag) {
+            call.tags.push(tag);
+        }
+
+        true
+    }
+
+    /// Get the call currently being followed, if any.
+    pub fn following(&self) -> Option<&Call> {
+        self.calls.back().filter(|c| c.end.is_none())
+    }
+
+    /// Record the end of the current call, if any, at the current time, returning it.
+    pub fn end(&mut self) -> Option<Call> {
+        self.end_at(now())
+    }
+
+    fn end_at(&mut self, time: u64) -> Option<Call> {
+        let call = self.current()?;
+        call.end = Some(time);
+
+        Some(call.clone())
+    }
+
+    /// Attach the given audio recording to the latest unrecorded call on the given
+    /// talkgroup, returning the call if found.
+    pub fn attach_recording(&mut self, talkgroup: u16, path: PathBuf) -> Option<&Call> {
+        let call = self
+            .calls
+            .iter_mut()
+            .rev()
+            .find(|c| c.talkgroup == talkgroup && !c.recorded)?;
+
+        call.recorded = true;
+        call.recording = Some(path);
+
+        Some(call)
+    }
+
+    /// Get the file containing the recorded audio of the call with the given ID.
+    pub fn recording(&self, id: u64) -> Option<&Path> {
+        self.calls
+            .iter()
+            .find(|c| c.id == id)
+            .and_then(|c| c.recording.as_deref())
+    }
+
+    /// Get the call currently being followed, if any.
+    fn current(&mut self) -> Option<&mut Call> {
+        self.calls.back_mut().filter(|c| c.end.is_none())
+    }
+
+    /// Select a page of calls, newest first, matching the given query.
+    pub fn query(&self, q: &CallQuery) -> CallPage<'_> {
+        let calls: Vec<&Call> = self
+            .calls
+            .iter()
+            .rev()
+            .filter(|c| q.before.map_or(true, |id| c.id < id))
+            .take_while(|c| q.since.map_or(true, |t| c.start >= t))
+            .filter(|c| q.talkgroup.map_or(true, |tg| c.talkgroup == tg))
+            .filter(|c| !q.recorded || c.recorded)
+            .take(q.limit)
+            .collect();
+
+        let next = if calls.len() == q.limit {
+            calls.last().map(|c| c.id)
+        }
+        else {
+            None
+        };
+
+        CallPage {
+            calls,
+            next,
+        }
+    }
+}
+
+/// Get the current time (ms since the Unix epoch).
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let mut h = CallHistory::default();
+
+        h.begin_at(1000, 101, 851000000);
+        h.update_src_unit(42);
+        h.begin_at(2000, 102, 852000000);
+        h.end_at(3000);
+        h.update_src_unit(43);
+
+        let p = h.query(&CallQuery::default());
+        assert_eq!(p.calls.len(), 2);
+        assert_eq!(p.next, None);
+
+        assert_eq!(p.calls[0].id, 2);
+        assert_eq!(p.calls[0].talkgroup, 102);
+        assert_eq!(p.calls[0].end, Some(3000));
+        assert_eq!(p.calls[0].src_unit, None);
+
+        assert_eq!(p.calls[1].id, 1);
+        assert_eq!(p.calls[1].end, Some(2000));
+        assert_eq!(p.calls[1].src_unit, Some(42));
+
+        for i in 0..HISTORY_CALLS as u64 {
+            h.begin_at(4000 + i, 200, 853000000);
+        }
+
+        assert_eq!(h.calls.len(), HISTORY_CALLS);
+        assert_eq!(h.calls.front().unwrap().talkgroup, 200);
+    }
+
+    #[test]
+    fn test_snr() {
+        let mut h = CallHistory::default();
+
+        h.record_snr(20.0);
+        h.begin_at(1000, 101, 851000000);
+        h.record_snr(12.0);
+        h.record_snr(18.0);
+        h.record_snr(3.0);
+        h.end_at(2000);
+        h.record_snr(30.0);
+
+        assert_eq!(h.calls.len(), 1);
+        assert_eq!(h.calls[0].snr, Some(18.0));
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut h = CallHistory::default();
+        let aliases = crate::aliases::parse("4521,FireDispatch").unwrap();
+
+        assert_eq!(h.begin_at(1000, 4521, 857262500), None);
+        h.update_src_unit(901233);
+
+        let call = h.begin_at(9200, 101, 851000000).unwrap();
+        let s = call.summary(&aliases);
+
+        // The start time depends on the local time zone.
+        assert_eq!(s.len(), 8 + 60);
+        assert!(s.ends_with(" TG 4521 \"FireDispatch\" from unit 901233, 8.2s, 857.2625 MHz"));
+
+        let call = h.end_at(10000).unwrap();
+        assert!(call
+            .summary(&aliases)
+            .ends_with(" TG 101, 0.8s, 851.0000 MHz"));
+
+        assert_eq!(h.end_at(11000), None);
+    }
+
+    #[test]
+    fn test_private() {
+        let mut h = CallHistory::default();
+        let aliases = TalkgroupAliases::default();
+
+        h.begin_private_at(
+            1000,
+            &PrivateCall {
+                kind: CallKind::Unit,
+                src_unit: 901233,
+                dest_unit: Some(901240),
+                freq: 857262500,
+            },
+        );
+
+        let call = h.end_at(2500).unwrap();
+        assert_eq!(call.kind(), CallKind::Unit);
+        assert_eq!(call.talkgroup(), 0);
+        assert!(call
+            .summary(&aliases)
+            .ends_with(" unit call from unit 901233 to unit 901240, 1.5s, 857.2625 MHz"));
+
+        h.begin_private_at(
+            3000,
+            &PrivateCall {
+                kind: CallKind::Phone,
+                src_unit: 901233,
+                dest_unit: None,
+                freq: 857262500,
+            },
+        );
+
+        let call = h.begin_at(4000, 101, 851000000).unwrap();
+        assert_eq!(call.kind(), CallKind::Phone);
+        assert_eq!(call.dest_unit, None);
+        assert_eq!(h.following().map(|c| c.kind()), Some(CallKind::Group));
+    }
+
+    #[test]
+    fn test_emergency() {
+        let mut h = CallHistory::default();
+
+        assert_eq!(h.mark_emergency(), None);
+
+        h.begin_at(1000, 101, 851000000);
+        assert_eq!(h.following().map(|c| c.talkgroup), Some(101));
+        assert_eq!(h.mark_emergency().map(|c| c.emergency), Some(true));
+        assert_eq!(h.mark_emergency(), None);
+
+        h.end_at(2000);
+        assert_eq!(h.following(), None);
+        assert_eq!(h.mark_emergency(), None);
+    }
+
+    #[test]
+    fn test_tag() {
+        let mut h = CallHistory::default();
+
+        h.begin_at(1000, 101, 851000000);
+        h.end_at(2000);
+
+        assert!(h.tag(1, "fire".to_string()));
+        assert!(h.tag(1, "fire".to_string()));
+        assert!(h.tag(1, "mutual aid".to_string()));
+        assert!(!h.tag(2, "fire".to_string()));
+
+        assert_eq!(h.calls[0].tags, vec!["fire", "mutual aid"]);
+    }
+
+    #[test]
+    fn test_recording() {
+        let mut h = CallHistory::default();
+
+        h.begin_at(1000, 101, 851000000);
+        h.begin_at(2000, 102, 851000000);
+        h.begin_at(3000, 101, 851000000);
+        h.end_at(4000);
+
+        h.attach_recording(101, PathBuf::from("a.wav"));
+        h.attach_recording(101, PathBuf::from("b.wav"));
+        h.attach_recording(103, PathBuf::from("c.wav"));
+
+        assert_eq!(h.recording(3), Some(Path::new("a.wav")));
+        assert_eq!(h.recording(1), Some(Path::new("b.wav")));
+        assert_eq!(h.recording(2), None);
+        assert_eq!(h.recording(4), None);
+
+        let p = h.query(&parse_query("recorded=true").unwrap());
+        let ids: Vec<u64> = p.calls.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![3, 1]);
+    }
+
+    #[test]
+    fn test_query() {
+        let mut h = CallHistory::default();
+
+        for i in 0..10 {
+            h.begin_at(i * 1000, 100 + (i % 2) as u16, 851000000);
+        }
+
+        let q = parse_query("limit=3").unwrap();
+        let p = h.query(&q);
+        let ids: Vec<u64> = p.calls.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![10, 9, 8]);
+        assert_eq!(p.next, Some(8));
+
+        let q = parse_query("limit=3&before=8").unwrap();
+        let p = h.query(&q);
+        let ids: Vec<u64> = p.calls.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![7, 6, 5]);
+
+        let q = parse_query("tg=100&since=4000").unwrap();
+        let p = h.query(&q);
+        let ids: Vec<u64> = p.calls.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![9, 7, 5]);
+        assert_eq!(p.next, None);
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(parse_query(""), Ok(CallQuery::default()));
+        assert_eq!(
+            parse_query("tg=101&since=5&before=9&limit=20&x=y"),
+            Ok(CallQuery {
+                since: Some(5),
+                talkgroup: Some(101),
+                before: Some(9),
+                recorded: false,
+                limit: 20,
+            })
+        );
+        assert_eq!(parse_query("limit=100000").unwrap().limit, HISTORY_CALLS);
+        assert!(parse_query("tg=abc").is_err());
+        assert!(parse_query("since").is_err());
+        assert!(parse_query("recorded=yes").is_err());
+    }
+
+    #[test]
+    fn test_parse_audio_path() {
+        assert_eq!(parse_audio_path("/calls/42/audio"), Some(42));
+        assert_eq!(parse_audio_path("/calls/abc/audio"), None);
+        assert_eq!(parse_audio_path("/calls/42"), None);
+        assert_eq!(parse_audio_path("/other/42/audio"), None);
+    }
+}
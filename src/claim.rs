@@ -0,0 +1,244 @@
+//! Claims on RTL-SDRs, so instances running on the same host don't fight over a device.
+//!
+//! Each instance takes an exclusive lock on a file named after the device, and writes its
+//! process ID into it for others to report. The lock is released by the OS when the
+//! instance exits, however it exits, so a claim is never left behind.
+//!
+//! Claims are kept in `/run/lock/p25rx`, shared by every user on the host, or otherwise
+//! in `$XDG_RUNTIME_DIR/p25rx`, private to the user. The shared directory is created by
+//! whichever instance runs first, and instances of other users keep using it as long as
+//! it stays sticky and writable by everyone, like `/tmp`. Since it can be written by
+//! anyone, claim files are never followed through links or written unless they belong to
+//! this user.
+
+use std::{
+    fs::{self, DirBuilder, File, OpenOptions, Permissions},
+    io::{self, Read, Write},
+    os::unix::{
+        fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt},
+        io::AsRawFd,
+    },
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+/// Directory holding the directory of claims shared by every user.
+const SHARED_DIR: &str = "/run/lock";
+
+/// Mode of the directory of claims shared by every user.
+const SHARED_MODE: u32 = 0o1777;
+
+/// Claim on a device, held until dropped.
+pub struct DeviceClaim {
+    /// Locked file, unlocked when closed, if there was anywhere to claim the device.
+    file: Option<File>,
+    /// Whether the process ID was written into the file.
+    written: bool,
+}
+
+impl DeviceClaim {
+    /// Claim the device with the given name, failing if another instance holds it.
+    pub fn acquire(name: &str) -> Result<Self> {
+        let dir = match claim_dir() {
+            Some(dir) => dir,
+            None => {
+                warn!("no directory for device claims, not claiming {}", name);
+
+                return Ok(DeviceClaim {
+                    file: None,
+                    written: false,
+                });
+            }
+        };
+
+        let path = dir.join(file_name(name));
+
+        // A claim left by an instance of another user can only be read, which is still
+        // enough to lock it.
+        let mut file = match open_claim(&path, true) {
+            Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied => open_claim(&path, false),
+            res => res,
+        }
+        .with_context(|| format!("unable to open device claim {}", path.display()))?;
+
+        let meta = file
+            .metadata()
+            .with_context(|| format!("unable to open device claim {}", path.display()))?;
+
+        if !meta.is_file() {
+            bail!("device claim {} is not a regular file", path.display());
+        }
+
+        // This is safe because the descriptor stays open for the call.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            match read_pid(&mut file) {
+                Some(pid) => bail!("device {} is already in use by p25rx (pid {})", name, pid),
+                None => bail!("device {} is already in use by p25rx", name),
+            }
+        }
+
+        // Only a file of this user's own is written, never one linked in from elsewhere.
+        let written = meta.nlink() == 1 && meta.uid() == unsafe { libc::geteuid() };
+
+        if written {
+            file.set_len(0)
+                .and_then(|_| write!(file, "{}", std::process::id()))
+                .with_context(|| format!("unable to write device claim {}", path.display()))?;
+        }
+
+        Ok(DeviceClaim {
+            file: Some(file),
+            written,
+        })
+    }
+}
+
+impl Drop for DeviceClaim {
+    fn drop(&mut self) {
+        // Instances of other users can claim the file next without being able to write
+        // it, so it shouldn't keep naming this one.
+        if let Some(ref file) = self.file {
+            if self.written {
+                file.set_len(0).ok();
+            }
+        }
+    }
+}
+
+/// Get the process ID of the instance holding a claim on the device with the given name,
+/// if any.
+pub fn holder(name: &str) -> Option<u32> {
+    let mut file = open_claim(&claim_dir()?.join(file_name(name)), false).ok()?;
+
+    if !file.metadata().ok()?.is_file() {
+        return None;
+    }
+
+    // This is safe because the descriptor stays open for the call. A shared lock is
+    // released when the file is closed.
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH | libc::LOCK_NB) } == 0 {
+        return None;
+    }
+
+    read_pid(&mut file)
+}
+
+/// Get the directory of claims, creating it if needed, or `None` if there's no directory
+/// that can be trusted.
+fn claim_dir() -> Option<PathBuf> {
+    let shared = Path::new(SHARED_DIR);
+
+    if shared.is_dir() {
+        // Shared by every user like its parent, each only able to remove their own files.
+        if let Some(dir) = trusted_dir(&shared.join("p25rx"), SHARED_MODE) {
+            return Some(dir);
+        }
+    }
+
+    let private = PathBuf::from(std::env::var_os("XDG_RUNTIME_DIR")?);
+
+    trusted_dir(&private.join("p25rx"), 0o700)
+}
+
+/// Create the given directory with the given mode if it doesn't exist, and check that
+/// either only its owner, root or this user, can replace the files in it, or it's shared
+/// by every user, created by an instance of another user.
+fn trusted_dir(dir: &Path, mode: u32) -> Option<PathBuf> {
+    match DirBuilder::new().mode(mode).create(dir) {
+        // Apply the full mode regardless of the umask.
+        Ok(()) => fs::set_permissions(dir, Permissions::from_mode(mode)).ok()?,
+        Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(_) => return None,
+    }
+
+    let meta = fs::symlink_metadata(dir).ok()?;
+    let owner = meta.uid() == 0 || meta.uid() == unsafe { libc::geteuid() };
+    let sticky = meta.mode() & 0o1000 != 0;
+    let shared = meta.mode() & 0o022 != 0;
+    // The owner of a directory shared by everyone can remove any claim in it, which at
+    // worst lets two instances use the same device.
+    let everyone = meta.mode() & 0o7777 == SHARED_MODE;
+
+    if meta.is_dir() && ((owner && (sticky || !shared)) || everyone) {
+        Some(dir.to_path_buf())
+    }
+    else {
+        warn!("not using untrusted claim directory {}", dir.display());
+        None
+    }
+}
+
+/// Get the file name of the claim of the device with the given name.
+fn file_name(name: &str) -> String {
+    // Serial numbers are arbitrary strings.
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            }
+            else {
+                '_'
+            }
+        })
+        .collect::<String>();
+
+    format!("{}.lock", name)
+}
+
+/// Open the given claim file for reading, and for writing if given, creating it in that
+/// case.
+fn open_claim(path: &Path, write: bool) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(write)
+        .create(write)
+        .mode(0o644)
+        // Don't follow links or hang on fifos planted in a shared directory.
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)
+}
+
+/// Read the process ID written into the given claim file.
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut text = String::new();
+    file.read_to_string(&mut text).ok()?;
+
+    text.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_claim() {
+        let name = format!("test-{}", std::process::id());
+        let path = claim_dir().unwrap().join(file_name(&name));
+
+        assert_eq!(holder(&name), None);
+
+        let claim = DeviceClaim::acquire(&name).unwrap();
+        assert_eq!(holder(&name), Some(std::process::id()));
+        assert!(DeviceClaim::acquire(&name).is_err());
+
+        drop(claim);
+        assert_eq!(holder(&name), None);
+        assert!(DeviceClaim::acquire(&name).is_ok());
+
+        fs::remove_file(&path).unwrap();
+
+        // Links planted in place of a claim aren't followed.
+        let dir = TempDir::new("claim");
+        let target = dir.join("target");
+        fs::write(&target, "data").unwrap();
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+
+        assert!(DeviceClaim::acquire(&name).is_err());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "data");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
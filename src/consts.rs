@@ -1,16 +1,42 @@
 //! Constants used throughout the crate.
 
+/// Version of this build.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Git commit this build was made from.
+pub const GIT_HASH: &str = match option_env!("P25RX_GIT_HASH") {
+    Some(hash) => hash,
+    None => "unknown",
+};
+
 /// Number of SDR sample byffers to allocate.
 pub const BUF_COUNT: usize = 1;
 /// Size of each SDR sample buffer (bytes).
 pub const BUF_BYTES: usize = 32768;
 /// Number of samples after transforming byte pairs to complex samples.
 pub const BUF_SAMPLES: usize = BUF_BYTES / 2;
+/// Size of each SDR sample buffer in low-latency mode (bytes), about 8.5ms of samples.
+pub const LOW_LATENCY_BUF_BYTES: usize = 4096;
 
 /// Sample rate for the SDR.
 pub const SDR_SAMPLE_RATE: u32 = 240000;
 /// Downconverted baseband sample rate.
 pub const BASEBAND_SAMPLE_RATE: u32 = 48000;
+/// Sample rate of decoded voice audio.
+pub const AUDIO_SAMPLE_RATE: u32 = 8000;
+
+/// P25 symbol rate (symbols/sec).
+pub const SYMBOL_RATE: u32 = 4800;
+/// Baseband samples per symbol.
+pub const SAMPLES_PER_SYMBOL: usize = (BASEBAND_SAMPLE_RATE / SYMBOL_RATE) as usize;
+/// P25 frame synchronization sequence.
+pub const FRAME_SYNC: u64 = 0x5575F5FF77FF;
+/// Number of bits in the frame sync sequence.
+pub const FRAME_SYNC_BITS: usize = 48;
+
+/// Lowest frequency (Hz) the SDR tuner can receive.
+pub const TUNER_MIN_FREQ: u32 = 24000000;
+/// Highest frequency (Hz) the SDR tuner can receive.
+pub const TUNER_MAX_FREQ: u32 = 1766000000;
 
 #[cfg(test)]
 mod test {
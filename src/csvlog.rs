@@ -0,0 +1,184 @@
+//! Export of decoded trunking and link control messages to CSV files.
+//!
+//! Each message is appended as a row with these columns:
+//!
+//! | Column      | Value                                                  |
+//! |-------------|--------------------------------------------------------|
+//! | `time`      | Receive time (ms since the Unix epoch)                 |
+//! | `freq`      | Receive frequency (Hz), or 0 if unknown                |
+//! | `type`      | `tsbk` or `lc`                                         |
+//! | `opcode`    | Opcode name, or empty if unknown                       |
+//! | `mfg`       | Manufacturer ID, or 0 for link control                 |
+//! | `crcValid`  | Whether the CRC was valid                              |
+//! | `talkGroup` | Talkgroup the message is about, if any                 |
+//! | `unit`      | Unit the message is about, if any                      |
+//! | `payload`   | Message payload as lowercase hex                       |
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use p25::{trunking::tsbk::TsbkFields, voice::control::LinkControlFields};
+
+use crate::{calls, dump::hex, units::Sighting};
+
+/// First row of each file.
+const HEADER: &str = "time,freq,type,opcode,mfg,crcValid,talkGroup,unit,payload";
+
+/// Appends decoded messages as rows of a CSV file.
+pub struct MessageCsv<W: Write> {
+    /// Stream to write into.
+    stream: W,
+}
+
+impl MessageCsv<BufWriter<File>> {
+    /// Open the CSV file at the given path for appending, creating it with a header row
+    /// if it doesn't exist or is empty.
+    pub fn append<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let empty = file.metadata()?.len() == 0;
+
+        MessageCsv::new(BufWriter::new(file), empty)
+    }
+}
+
+impl<W: Write> MessageCsv<W> {
+    /// Create a new `MessageCsv` over the given stream, first writing the header row if
+    /// `header` is true.
+    pub fn new(mut stream: W, header: bool) -> io::Result<Self> {
+        if header {
+            writeln!(stream, "{}", HEADER)?;
+        }
+
+        Ok(MessageCsv {
+            stream,
+        })
+    }
+
+    /// Write the given trunking packet, received on the given frequency (Hz).
+    pub fn write_tsbk(&mut self, tsbk: &TsbkFields, freq: u32) -> io::Result<()> {
+        // Only standard packets can be decoded reliably.
+        let seen = if tsbk.mfg() == 0 && tsbk.crc_valid() {
+            Sighting::from_tsbk(*tsbk)
+        }
+        else {
+            None
+        };
+
+        self.write_row(&Row {
+            time: calls::now(),
+            freq,
+            kind: "tsbk",
+            opcode: tsbk.opcode().map(|o| format!("{:?}", o)),
+            mfg: tsbk.mfg(),
+            crc_valid: tsbk.crc_valid(),
+            seen,
+            payload: tsbk.payload(),
+        })
+    }
+
+    /// Write the given link control word, received on the given frequency (Hz).
+    pub fn write_lc(&mut self, lc: &LinkControlFields, freq: u32) -> io::Result<()> {
+        // Link control words are only emitted after error correction succeeds.
+        self.write_row(&Row {
+            time: calls::now(),
+            freq,
+            kind: "lc",
+            opcode: lc.opcode().map(|o| format!("{:?}", o)),
+            mfg: 0,
+            crc_valid: true,
+            seen: Sighting::from_lc(*lc),
+            payload: lc.payload(),
+        })
+    }
+
+    /// Write the given row, flushing it so the file can be read while it's written.
+    fn write_row(&mut self, r: &Row) -> io::Result<()> {
+        writeln!(
+            self.stream,
+            "{},{},{},{},{},{},{},{},{}",
+            r.time,
+            r.freq,
+            r.kind,
+            r.opcode.as_deref().unwrap_or(""),
+            r.mfg,
+            r.crc_valid,
+            r.seen
+                .and_then(|s| s.talkgroup)
+                .map(|tg| tg.to_string())
+                .unwrap_or_default(),
+            r.seen.map(|s| s.unit.to_string()).unwrap_or_default(),
+            hex(r.payload),
+        )?;
+
+        self.stream.flush()
+    }
+}
+
+/// Fields of a single row.
+struct Row<'a> {
+    /// Receive time (ms since the Unix epoch).
+    time: u64,
+    /// Receive frequency (Hz).
+    freq: u32,
+    /// Type of message.
+    kind: &'static str,
+    /// Decoded opcode name, if known.
+    opcode: Option<String>,
+    /// Manufacturer ID.
+    mfg: u8,
+    /// Whether the CRC was valid.
+    crc_valid: bool,
+    /// Unit activity in the message, if any.
+    seen: Option<Sighting>,
+    /// Message payload.
+    payload: &'a [u8],
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::units::Activity;
+
+    #[test]
+    fn test_rows() {
+        let mut c = MessageCsv::new(vec![], true).unwrap();
+
+        c.write_row(&Row {
+            time: 1500000000000,
+            freq: 851006250,
+            kind: "tsbk",
+            opcode: Some("GroupVoiceGrant".to_string()),
+            mfg: 0,
+            crc_valid: true,
+            seen: Some(Sighting {
+                unit: 901233,
+                activity: Activity::Grant,
+                talkgroup: Some(4521),
+            }),
+            payload: &[0x00, 0x11, 0xA9],
+        })
+        .unwrap();
+
+        c.write_row(&Row {
+            time: 1500000000001,
+            freq: 0,
+            kind: "lc",
+            opcode: None,
+            mfg: 0,
+            crc_valid: true,
+            seen: None,
+            payload: &[0xFF],
+        })
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(c.stream).unwrap(),
+            "time,freq,type,opcode,mfg,crcValid,talkGroup,unit,payload\n\
+             1500000000000,851006250,tsbk,GroupVoiceGrant,0,true,4521,901233,0011a9\n\
+             1500000000001,0,lc,,0,true,,,ff\n"
+        );
+    }
+}
@@ -1,66 +1,152 @@
 //! Demodulation and other signal processing.
 
-use std::{
-    self,
-    sync::mpsc::{Receiver, Sender},
-};
+use std::{self, sync::Arc};
 
 use collect_slice::CollectSlice;
-use demod_fm::FmDemod;
-use mio_extras;
 use moving_avg::MovingAverage;
 use num::{complex::Complex32, traits::Zero};
 use p25_filts::{BandpassFir, DecimFir};
-use pool::{Checkout, Pool};
+use pool::Pool;
 use rtlsdr_iq::IQ;
 use slice_mip::MapInPlace;
-use static_decimate::Decimator;
-use static_fir::FirFilter;
+use static_fir::FirCoefs;
 use throttle::Throttler;
 
 use crate::{
-    consts::{BASEBAND_SAMPLE_RATE, BUF_SAMPLES},
+    consts::{BASEBAND_SAMPLE_RATE, BUF_SAMPLES, SDR_SAMPLE_RATE},
+    design::FilterSpec,
+    discrim::{Discriminator, FmDiscriminator},
+    faults::{Fault, FaultCategory},
+    fir::{ComplexDecimator, ComplexFir},
     hub::HubEvent,
-    recv::RecvEvent,
+    iqcorr::IqCorrector,
+    queue::QueueSender,
+    recv::{BasebandSeq, RecvEvent},
+    ring::RingReader,
+    shift::FreqShift,
+    stages::StageMetrics,
 };
 
+/// Decimation factor from SDR to baseband sample rate.
+pub const DECIM_FACTOR: usize = (SDR_SAMPLE_RATE / BASEBAND_SAMPLE_RATE) as usize;
+/// Assumed maximum FM frequency deviation (Hz).
+pub const FM_DEVIATION: u32 = 5000;
+/// Length of the post-demodulation moving average filter.
+pub const AVG_LEN: usize = 10;
+
+/// Create the decimator from SDR to baseband sample rate.
+pub fn decimator() -> ComplexDecimator {
+    let coefs = DecimFir::coefs().iter().cloned().collect::<Vec<f32>>();
+    ComplexDecimator::new(DECIM_FACTOR, &coefs)
+}
+
+/// Create the channel-select filter.
+pub fn bandpass() -> ComplexFir {
+    let coefs = BandpassFir::coefs().iter().cloned().collect::<Vec<f32>>();
+    ComplexFir::new(&coefs)
+}
+
 /// Demodulates raw I/Q signal to C4FM baseband.
 pub struct DemodTask {
+    /// Removes the DC offset and I/Q imbalance of the SDR, if enabled.
+    iq: Option<IqCorrector>,
+    /// Moves the channel back to the center when the SDR is tuned off it, if enabled.
+    shift: Option<FreqShift>,
     /// Decimates I/Q signal.
-    decim: Decimator<DecimFir>,
+    decim: ComplexDecimator,
     /// Channel-select lowpass filter.
-    bandpass: FirFilter<BandpassFir>,
+    bandpass: ComplexFir,
     /// Moving average filter.
     avg: MovingAverage<f32>,
     /// Demodulates FM signal.
-    demod: FmDemod,
-    /// Channel for receiving I/Q sample chunks.
-    reader: Receiver<Checkout<Vec<u8>>>,
+    demod: Box<dyn FmDiscriminator + Send>,
+    /// Ring for receiving I/Q sample chunks.
+    reader: RingReader,
     /// Channel for the hub.
-    hub: mio_extras::channel::Sender<HubEvent>,
+    hub: QueueSender<HubEvent>,
     /// Channel for sending baseband sample chunks.
-    chan: Sender<RecvEvent>,
+    chan: QueueSender<RecvEvent>,
+    /// Whether demodulating for the diversity receiver.
+    diversity: bool,
+    /// Counts the baseband buffers sent.
+    seq: BasebandSeq,
+    /// Processing metrics.
+    stage: Arc<StageMetrics>,
 }
 
 impl DemodTask {
     /// Create a new `DemodTask` to communicate on the given channels.
     pub fn new(
-        reader: Receiver<Checkout<Vec<u8>>>,
-        hub: mio_extras::channel::Sender<HubEvent>,
-        chan: Sender<RecvEvent>,
+        reader: RingReader,
+        hub: QueueSender<HubEvent>,
+        chan: QueueSender<RecvEvent>,
     ) -> Self {
         DemodTask {
-            decim: Decimator::new(5),
-            bandpass: FirFilter::new(),
-            avg: MovingAverage::new(10),
-            // Assume a 5kHz frequency deviation.
-            demod: FmDemod::new(5000, BASEBAND_SAMPLE_RATE),
+            iq: Some(IqCorrector::new()),
+            shift: None,
+            decim: decimator(),
+            bandpass: bandpass(),
+            avg: MovingAverage::new(AVG_LEN),
+            demod: Discriminator::Polar.create(FM_DEVIATION, BASEBAND_SAMPLE_RATE),
             reader,
             hub,
             chan,
+            diversity: false,
+            seq: BasebandSeq::default(),
+            stage: StageMetrics::new("demod"),
         }
     }
 
+    /// Decimate with a filter designed from the given spec rather than the default.
+    pub fn decim_filter(mut self, spec: FilterSpec) -> Self {
+        self.decim = ComplexDecimator::new(DECIM_FACTOR, &spec.design(SDR_SAMPLE_RATE));
+        self
+    }
+
+    /// Select the channel with a filter designed from the given spec rather than the
+    /// default.
+    pub fn channel_filter(mut self, spec: FilterSpec) -> Self {
+        self.bandpass = ComplexFir::new(&spec.design(BASEBAND_SAMPLE_RATE));
+        self
+    }
+
+    /// Demodulate with the given kind of discriminator rather than the default.
+    pub fn discriminator(mut self, kind: Discriminator) -> Self {
+        self.demod = kind.create(FM_DEVIATION, BASEBAND_SAMPLE_RATE);
+        self
+    }
+
+    /// Pass SDR samples through without correcting their DC offset and I/Q imbalance.
+    pub fn raw_iq(mut self) -> Self {
+        self.iq = None;
+        self
+    }
+
+    /// Shift samples back to the channel from an SDR tuned the given offset (Hz) away
+    /// from it, with no shifting for an offset of 0.
+    pub fn tune_offset(mut self, offset: i32) -> Self {
+        self.shift = (offset != 0).then(|| FreqShift::new(offset, SDR_SAMPLE_RATE));
+        self
+    }
+
+    /// Get the processing metrics of the task.
+    pub fn metrics(&self) -> Arc<StageMetrics> {
+        self.stage.clone()
+    }
+
+    /// Get the count of baseband buffers sent by the task.
+    pub fn baseband_seq(&self) -> BasebandSeq {
+        self.seq.clone()
+    }
+
+    /// Send baseband as the diversity receiver, without reporting signal power or
+    /// dropped buffers, and with processing metrics kept apart from the primary's.
+    pub fn diversity(mut self) -> Self {
+        self.diversity = true;
+        self.stage = StageMetrics::new("diversityDemod");
+        self
+    }
+
     /// Begin demodulating, blocking the current thread.
     pub fn run(&mut self) {
         let mut pool = Pool::with_capacity(16, || vec![0.0; BUF_SAMPLES]);
@@ -68,22 +154,45 @@ impl DemodTask {
 
         // Used to reduce the number of signal level messages sent.
         let mut notifier = Throttler::new(4);
+        // Whether baseband is being dropped because the receiver is behind.
+        let mut starved = false;
 
         loop {
-            let bytes = self.reader.recv().expect("unable to receive sdr samples");
+            // The buffer about to be read is still counted in the ring.
+            let backlog = self.reader.depth().saturating_sub(1);
 
-            // This is safe because it's transforming an array of N 8-bit words to an
-            // array of N/2 16-bit words.
-            let pairs =
-                unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const u16, BUF_SAMPLES) };
+            let (_timer, written) = {
+                // The ring slot is released at the end of this block.
+                let bytes = self.reader.recv();
+                let timer = self.stage.start(backlog);
 
-            // This is safe because it equals the original allocation length.
-            unsafe {
-                samples.set_len(BUF_SAMPLES);
+                // Buffers are smaller than the default in low-latency mode.
+                let len = bytes.len() / 2;
+                assert!(len <= BUF_SAMPLES);
+
+                // This is safe because it's transforming an array of N 8-bit words to an
+                // array of N/2 16-bit words.
+                let pairs =
+                    unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const u16, len) };
+
+                // This is safe because it's no more than the original allocation length.
+                unsafe {
+                    samples.set_len(len);
+                }
+
+                // Transform interleaved byte pairs to complex floating point samples.
+                pairs.iter().map(|&s| IQ[s]).collect_slice(&mut samples[..]);
+
+                (timer, bytes.written())
+            };
+
+            if let Some(ref mut iq) = self.iq {
+                iq.correct(&mut samples[..]);
             }
 
-            // Transform interleaved byte pairs to complex floating point samples.
-            pairs.iter().map(|&s| IQ[s]).collect_slice(&mut samples[..]);
+            if let Some(ref mut shift) = self.shift {
+                shift.shift(&mut samples[..]);
+            }
 
             // Decimate from SDR to baseband sample rate.
             let len = self.decim.decim_in_place(&mut samples[..]);
@@ -96,16 +205,44 @@ impl DemodTask {
             // Apply bandpass filter to attenuate out-of-channel interference.
             samples.map_in_place(|&s| self.bandpass.feed(s));
 
+            // Signal statistics are only reported for the primary receiver.
             notifier.throttle(|| {
+                if self.diversity {
+                    return;
+                }
+
                 // Calculate power assuming a "normalized" resistance.
                 let power = power_dbm(&samples[..], 1.0);
 
+                // The hub only stops by panicking, which is already reported.
+                self.hub.send(HubEvent::UpdateSignalPower(power)).ok();
                 self.hub
-                    .send(HubEvent::UpdateSignalPower(power))
-                    .expect("unable to send signal power");
+                    .send(HubEvent::UpdateDroppedBuffers(self.reader.dropped()))
+                    .ok();
             });
 
-            let mut baseband = pool.checkout().expect("unable to allocate baseband");
+            // Every buffer is still held by the receiver if it's far behind.
+            let mut baseband = match pool.checkout() {
+                Some(b) => {
+                    starved = false;
+                    b
+                }
+                None => {
+                    if !starved {
+                        let fault = Fault::warning(
+                            FaultCategory::Demod,
+                            "receiver is behind, dropping baseband",
+                        );
+
+                        fault.log();
+                        self.hub.send(HubEvent::Fault(fault)).ok();
+
+                        starved = true;
+                    }
+
+                    continue;
+                }
+            };
 
             // This is safe because each input sample produces exactly one output sample.
             unsafe {
@@ -121,9 +258,20 @@ impl DemodTask {
             // Apply averaging filter.
             baseband.map_in_place(|&s| self.avg.feed(s));
 
-            self.chan
-                .send(RecvEvent::Baseband(baseband))
-                .expect("unable to send baseband");
+            let seq = self.seq.next();
+
+            let event = if self.diversity {
+                RecvEvent::DiversityBaseband(baseband, seq)
+            }
+            else {
+                RecvEvent::Baseband(baseband, written, seq)
+            };
+
+            // The receiver only stops when shutting down.
+            if self.chan.send(event).is_err() {
+                info!("receiver stopped, stopping demodulator");
+                return;
+            }
         }
     }
 }
@@ -0,0 +1,183 @@
+//! Design of the low-pass FIR filters used by the demodulator.
+//!
+//! By default the demodulator uses the fixed coefficients from `p25_filts`. Filters can
+//! instead be designed from a spec like `63,6250,0.2`, giving the number of taps, the
+//! cutoff frequency (Hz) where the response is down 6 dB, and the raised-cosine roll-off
+//! of the transition band as a fraction of the cutoff. More taps and a smaller roll-off
+//! give a sharper transition, rejecting more of an adjacent channel, at the cost of more
+//! ringing, which smears symbols into each other.
+
+use std::{f32::consts::PI, fmt};
+
+/// Most taps allowed in a designed filter.
+const MAX_TAPS: usize = 1023;
+
+/// Parameters of a designed low-pass filter.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FilterSpec {
+    /// Number of taps.
+    pub taps: usize,
+    /// Cutoff frequency (Hz).
+    pub cutoff: f32,
+    /// Roll-off factor of the transition band, from 0 to 1.
+    pub rolloff: f32,
+}
+
+impl FilterSpec {
+    /// Compute the coefficients of the filter for the given sample rate (Hz), with unity
+    /// gain at DC.
+    pub fn design(&self, rate: u32) -> Vec<f32> {
+        // Cutoff as a fraction of the sample rate.
+        let fc = self.cutoff / rate as f32;
+        let mid = (self.taps - 1) as f32 / 2.0;
+
+        let mut coefs: Vec<f32> = (0..self.taps)
+            .map(|i| {
+                let t = i as f32 - mid;
+                raised_cosine(t, fc, self.rolloff) * hamming(i, self.taps)
+            })
+            .collect();
+
+        let sum: f32 = coefs.iter().sum();
+
+        for c in &mut coefs {
+            *c /= sum;
+        }
+
+        coefs
+    }
+}
+
+impl fmt::Display for FilterSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{}", self.taps, self.cutoff, self.rolloff)
+    }
+}
+
+/// Parse a filter spec like `63,6250,0.2` or `63,6250`, with no roll-off if not given.
+pub fn parse_spec(s: &str) -> Result<FilterSpec, String> {
+    let parts: Vec<&str> = s.split(',').collect();
+
+    let (taps, cutoff, rolloff) = match parts[..] {
+        [taps, cutoff] => (taps, cutoff, "0"),
+        [taps, cutoff, rolloff] => (taps, cutoff, rolloff),
+        _ => return Err(format!("expected TAPS,CUTOFF[,ROLLOFF] in {}", s)),
+    };
+
+    let taps: usize = taps
+        .parse()
+        .map_err(|_| format!("invalid number of taps {}", taps))?;
+    let cutoff: f32 = cutoff
+        .parse()
+        .map_err(|_| format!("invalid cutoff {}", cutoff))?;
+    let rolloff: f32 = rolloff
+        .parse()
+        .map_err(|_| format!("invalid roll-off {}", rolloff))?;
+
+    if taps == 0 || taps > MAX_TAPS {
+        return Err(format!("number of taps must be from 1 to {}", MAX_TAPS));
+    }
+
+    if cutoff <= 0.0 {
+        return Err("cutoff must be positive".to_string());
+    }
+
+    if !(0.0..=1.0).contains(&rolloff) {
+        return Err("roll-off must be from 0 to 1".to_string());
+    }
+
+    Ok(FilterSpec {
+        taps,
+        cutoff,
+        rolloff,
+    })
+}
+
+/// Impulse response at the given time (samples) of a raised-cosine low-pass filter
+/// with the given cutoff (fraction of the sample rate) and roll-off.
+fn raised_cosine(t: f32, fc: f32, rolloff: f32) -> f32 {
+    let x = 2.0 * fc * t;
+    let sinc = if x == 0.0 {
+        1.0
+    }
+    else {
+        (PI * x).sin() / (PI * x)
+    };
+
+    let denom = 1.0 - (2.0 * rolloff * x).powi(2);
+
+    // The singular points have the limit of the expression instead.
+    let shape = if denom.abs() < 1e-6 {
+        PI / 4.0
+    }
+    else {
+        (PI * rolloff * x).cos() / denom
+    };
+
+    2.0 * fc * sinc * shape
+}
+
+/// Hamming window coefficient for the given tap.
+fn hamming(i: usize, taps: usize) -> f32 {
+    if taps == 1 {
+        return 1.0;
+    }
+
+    0.54 - 0.46 * (2.0 * PI * i as f32 / (taps - 1) as f32).cos()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Magnitude of the response of the given filter at the given frequency (fraction
+    /// of the sample rate).
+    fn response(coefs: &[f32], f: f32) -> f32 {
+        let (re, im) = coefs
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (i, &c)| {
+                let w = 2.0 * PI * f * i as f32;
+                (re + c * w.cos(), im - c * w.sin())
+            });
+
+        (re * re + im * im).sqrt()
+    }
+
+    #[test]
+    fn test_design() {
+        let spec = parse_spec("63,6000,0.2").unwrap();
+        let coefs = spec.design(48000);
+
+        assert_eq!(coefs.len(), 63);
+        assert!((coefs.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+
+        // Linear phase.
+        assert!(coefs
+            .iter()
+            .zip(coefs.iter().rev())
+            .all(|(a, b)| (a - b).abs() < 1e-6));
+
+        assert!((response(&coefs, 0.0) - 1.0).abs() < 1e-5);
+        assert!((response(&coefs, 6000.0 / 48000.0) - 0.5).abs() < 0.05);
+        assert!(response(&coefs, 12000.0 / 48000.0) < 0.01);
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(
+            parse_spec("31,5000"),
+            Ok(FilterSpec {
+                taps: 31,
+                cutoff: 5000.0,
+                rolloff: 0.0,
+            })
+        );
+
+        assert!(parse_spec("31").is_err());
+        assert!(parse_spec("0,5000").is_err());
+        assert!(parse_spec("31,-1").is_err());
+        assert!(parse_spec("31,5000,1.5").is_err());
+        assert!(parse_spec("31,5000,0.2,1").is_err());
+    }
+}
@@ -0,0 +1,375 @@
+//! Capture and replay of sliced dibits.
+//!
+//! Symbol captures hold one dibit per byte, with values 0 to 3 using the standard P25
+//! mapping (+3 is `01`, +1 is `00`, -1 is `10`, -3 is `11`). This is the format of the
+//! dibit files written and read by OP25 and of DSD-FME symbol captures, so the same
+//! symbols can be decoded by each tool.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    baseband::BasebandSink,
+    consts::{FRAME_SYNC, FRAME_SYNC_BITS, SAMPLES_PER_SYMBOL},
+    gen::Generator,
+};
+
+/// Number of symbols in the frame sync sequence.
+const SYNC_SYMBOLS: usize = FRAME_SYNC_BITS / 2;
+/// Number of baseband samples spanned by the frame sync sequence.
+const SYNC_SAMPLES: usize = SYNC_SYMBOLS * SAMPLES_PER_SYMBOL;
+/// Minimum normalized correlation with the frame sync sequence to detect a frame.
+const SYNC_THRESHOLD: f32 = 0.9;
+/// Number of symbols to keep slicing after the last frame sync, two voice frames.
+const LOCK_SYMBOLS: usize = 2 * 864;
+/// Number of idle symbols modulated before replayed dibits.
+const LEAD_SYMBOLS: usize = 100;
+
+/// Decision thresholds derived from the levels of a received frame sync.
+#[derive(Copy, Clone, Debug)]
+struct Thresholds {
+    /// Boundary between positive and negative symbols.
+    center: f32,
+    /// Boundary between +1 and +3 symbols.
+    upper: f32,
+    /// Boundary between -1 and -3 symbols.
+    lower: f32,
+}
+
+impl Thresholds {
+    /// Derive thresholds from the average levels of +3 and -3 symbols.
+    fn new(pos: f32, neg: f32) -> Self {
+        let center = (pos + neg) / 2.0;
+        let half = (pos - neg) / 2.0;
+
+        Thresholds {
+            center,
+            upper: center + half * 2.0 / 3.0,
+            lower: center - half * 2.0 / 3.0,
+        }
+    }
+
+    /// Slice the given sample into a dibit.
+    fn slice(&self, s: f32) -> u8 {
+        if s >= self.center {
+            if s >= self.upper {
+                0b01
+            }
+            else {
+                0b00
+            }
+        }
+        else if s <= self.lower {
+            0b11
+        }
+        else {
+            0b10
+        }
+    }
+}
+
+/// Candidate frame sync, kept until the correlation stops increasing.
+struct SyncPeak {
+    /// Correlation with the frame sync sequence, which peaks at the symbol centers.
+    corr: f32,
+    /// Baseband samples received since the peak.
+    age: usize,
+    /// Thresholds derived from the frame sync.
+    thresh: Thresholds,
+    /// Sliced frame sync symbols.
+    dibits: [u8; SYNC_SYMBOLS],
+}
+
+/// Symbol timing and thresholds locked to the last frame sync.
+struct SymbolLock {
+    /// Current decision thresholds.
+    thresh: Thresholds,
+    /// Baseband samples until the next symbol is sliced.
+    countdown: usize,
+    /// Symbols remaining until the lock is lost.
+    remaining: usize,
+}
+
+/// Recovers dibits from C4FM baseband by locking onto each frame sync.
+///
+/// Slicing starts at the first frame sync and continues until no frame sync has been
+/// seen for `LOCK_SYMBOLS` symbols.
+pub struct DibitSlicer {
+    /// Most recent baseband samples, as a circular buffer.
+    hist: [f32; SYNC_SAMPLES],
+    /// Index of the oldest sample in `hist`.
+    idx: usize,
+    /// Best frame sync candidate, if any.
+    peak: Option<SyncPeak>,
+    /// Current symbol lock, if any.
+    lock: Option<SymbolLock>,
+}
+
+impl Default for DibitSlicer {
+    fn default() -> Self {
+        DibitSlicer {
+            hist: [0.0; SYNC_SAMPLES],
+            idx: 0,
+            peak: None,
+            lock: None,
+        }
+    }
+}
+
+impl DibitSlicer {
+    /// Process the given baseband sample, appending any recovered dibits to `out`.
+    pub fn feed(&mut self, s: f32, out: &mut Vec<u8>) {
+        self.hist[self.idx] = s;
+        self.idx = (self.idx + 1) % SYNC_SAMPLES;
+
+        if let Some(ref mut lock) = self.lock {
+            lock.countdown -= 1;
+
+            if lock.countdown == 0 {
+                out.push(lock.thresh.slice(s));
+
+                lock.countdown = SAMPLES_PER_SYMBOL;
+                lock.remaining -= 1;
+            }
+
+            if lock.remaining == 0 {
+                self.lock = None;
+            }
+        }
+
+        self.detect_sync(out);
+    }
+
+    /// Track the frame sync correlation peak, locking onto it once it has passed.
+    fn detect_sync(&mut self, out: &mut Vec<u8>) {
+        if let Some(cand) = self.correlate() {
+            if self.peak.as_ref().map_or(true, |p| cand.corr > p.corr) {
+                self.peak = Some(cand);
+                return;
+            }
+        }
+
+        let age = match self.peak {
+            Some(ref mut p) => {
+                p.age += 1;
+                p.age
+            }
+            None => return,
+        };
+
+        // Wait half a symbol for a stronger correlation before committing.
+        if age < SAMPLES_PER_SYMBOL / 2 {
+            return;
+        }
+
+        let peak = self.peak.take().unwrap();
+
+        // The frame sync was already sliced if the lock was held through it.
+        if self.lock.is_none() {
+            out.extend_from_slice(&peak.dibits);
+        }
+
+        self.lock = Some(SymbolLock {
+            thresh: peak.thresh,
+            countdown: SAMPLES_PER_SYMBOL - age,
+            remaining: LOCK_SYMBOLS,
+        });
+    }
+
+    /// Correlate the latest symbols with the frame sync sequence, returning a candidate
+    /// if the correlation exceeds the threshold.
+    fn correlate(&self) -> Option<SyncPeak> {
+        let mut levels = [0.0; SYNC_SYMBOLS];
+
+        // The newest sample is the last symbol of the sequence.
+        for (i, level) in levels.iter_mut().enumerate() {
+            let pos = self.idx + (i + 1) * SAMPLES_PER_SYMBOL - 1;
+            *level = self.hist[pos % SYNC_SAMPLES];
+        }
+
+        let mean = levels.iter().sum::<f32>() / SYNC_SYMBOLS as f32;
+        let (mut corr, mut dev) = (0.0, 0.0);
+        let (mut pos, mut npos, mut neg, mut nneg) = (0.0, 0, 0.0, 0);
+
+        for (i, &level) in levels.iter().enumerate() {
+            let d = level - mean;
+            dev += d.abs();
+
+            if sync_positive(i) {
+                corr += d;
+                pos += level;
+                npos += 1;
+            }
+            else {
+                corr -= d;
+                neg += level;
+                nneg += 1;
+            }
+        }
+
+        // The normalized correlation detects the sequence regardless of signal level,
+        // but it's flat across symbol timing, so the peak is found from the unnormalized
+        // correlation.
+        if dev == 0.0 || corr / dev < SYNC_THRESHOLD {
+            return None;
+        }
+
+        let thresh = Thresholds::new(pos / npos as f32, neg / nneg as f32);
+        let mut dibits = [0; SYNC_SYMBOLS];
+
+        for (dibit, &level) in dibits.iter_mut().zip(levels.iter()) {
+            *dibit = thresh.slice(level);
+        }
+
+        Some(SyncPeak {
+            corr,
+            age: 0,
+            thresh,
+            dibits,
+        })
+    }
+}
+
+/// Check if the frame sync symbol at the given index is +3 rather than -3.
+fn sync_positive(idx: usize) -> bool {
+    (FRAME_SYNC >> ((SYNC_SYMBOLS - 1 - idx) * 2)) & 0b11 == 0b01
+}
+
+/// Writes dibits recovered from baseband to a symbol capture file.
+pub struct DibitWriter {
+    /// Recovers dibits from baseband.
+    slicer: DibitSlicer,
+    /// Capture file.
+    file: BufWriter<File>,
+    /// Dibits recovered from the current chunk of baseband.
+    buf: Vec<u8>,
+}
+
+impl DibitWriter {
+    /// Create a symbol capture file at the given path.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(DibitWriter {
+            slicer: DibitSlicer::default(),
+            file: BufWriter::new(File::create(path)?),
+            buf: vec![],
+        })
+    }
+
+    /// Slice the given baseband samples and write out the recovered dibits.
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.buf.clear();
+
+        for &s in samples {
+            self.slicer.feed(s, &mut self.buf);
+        }
+
+        self.file.write_all(&self.buf)
+    }
+}
+
+impl BasebandSink for DibitWriter {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        DibitWriter::write(self, samples)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Remodulate the dibits in the given symbol capture into baseband samples.
+///
+/// The result is ideal C4FM baseband, so decoding it exercises the same receiver as
+/// any other baseband recording.
+pub fn read_capture<R: Read>(stream: &mut R) -> io::Result<Vec<f32>> {
+    let mut dibits = vec![];
+    stream.read_to_end(&mut dibits)?;
+
+    let mut gen = Generator::new(0, 0.0, 0.0, 0);
+    let symbols = LEAD_SYMBOLS + dibits.len();
+    let mut samples = Vec::with_capacity(symbols * SAMPLES_PER_SYMBOL);
+
+    // Let the receiver settle before the first frame sync.
+    gen.idle(LEAD_SYMBOLS, &mut samples);
+    gen.dibits(&dibits, &mut samples);
+
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod test {
+    use p25::message::receiver::{MessageEvent, MessageReceiver};
+
+    use super::*;
+    use crate::gen;
+
+    fn sync_dibits() -> Vec<u8> {
+        (0..SYNC_SYMBOLS)
+            .rev()
+            .map(|i| (FRAME_SYNC >> (i * 2)) as u8 & 0b11)
+            .collect()
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let t = Thresholds::new(0.4, -0.2);
+
+        assert_eq!(t.slice(0.4), 0b01);
+        assert_eq!(t.slice(0.2), 0b00);
+        assert_eq!(t.slice(0.0), 0b10);
+        assert_eq!(t.slice(-0.2), 0b11);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &offset in &[0.0, 300.0] {
+            let mut g = Generator::new(0x293, 0.01, offset, 7);
+            let mut samples = vec![];
+
+            g.idle(100, &mut samples);
+            g.tsbk(&[gen::group_voice_grant(101, 0x1042, 42)], &mut samples);
+            g.idle(100, &mut samples);
+
+            let mut slicer = DibitSlicer::default();
+            let mut dibits = vec![];
+
+            for &s in &samples {
+                slicer.feed(s, &mut dibits);
+            }
+
+            assert_eq!(&dibits[..SYNC_SYMBOLS], &sync_dibits()[..]);
+
+            let samples = read_capture(&mut &dibits[..]).unwrap();
+            let mut recv = MessageReceiver::new();
+            let mut tsbks = 0;
+
+            for &s in &samples {
+                if let Some(MessageEvent::TrunkingControl(t)) = recv.feed(s) {
+                    assert!(t.crc_valid());
+                    tsbks += 1;
+                }
+            }
+
+            assert_eq!(tsbks, 1);
+        }
+    }
+
+    #[test]
+    fn test_idle() {
+        let mut g = Generator::new(0x293, 0.0, 0.0, 0);
+        let mut samples = vec![];
+        g.idle(1000, &mut samples);
+
+        let mut slicer = DibitSlicer::default();
+        let mut dibits = vec![];
+
+        for &s in &samples {
+            slicer.feed(s, &mut dibits);
+        }
+
+        assert!(dibits.is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+//! FM discriminators, which recover the instantaneous frequency of the channel.
+//!
+//! Each gives baseband scaled so the assumed maximum deviation is ±1:
+//!
+//! - `polar`: angle of each sample times the conjugate of the one before it, exact but
+//!   taking an arctangent per sample, and what p25rx has always used
+//! - `atan`: derivative of the phase, computed without an arctangent as
+//!   `(I dQ - Q dI) / (I² + Q²)` over consecutive samples, the cheapest, which overstates
+//!   large deviations slightly (under 1% at the outer C4FM symbols)
+//! - `pll`: frequency of a second-order phase-locked loop tracking the carrier, which
+//!   costs the most and rides through short fades and impulse noise better, though it
+//!   can lose lock on a very weak signal
+
+use std::f32::consts::PI;
+
+use clap::ValueEnum;
+use demod_fm::FmDemod;
+use num::{complex::Complex32, traits::Zero};
+
+/// Noise bandwidth of the PLL loop, as a fraction of the sample rate, wide enough to
+/// follow C4FM symbol transitions.
+const PLL_BANDWIDTH: f32 = 0.1;
+/// Damping factor of the PLL loop.
+const PLL_DAMPING: f32 = 0.707;
+
+/// FM discriminator algorithm.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+pub enum Discriminator {
+    /// Conjugate-product polar discriminator.
+    #[serde(rename = "polar")]
+    Polar,
+    /// Arctangent differentiator.
+    #[serde(rename = "atan")]
+    Atan,
+    /// Phase-locked loop.
+    #[serde(rename = "pll")]
+    Pll,
+}
+
+impl Discriminator {
+    /// Create a discriminator of this kind for the given maximum deviation (Hz) at the
+    /// given sample rate (Hz).
+    pub fn create(self, deviation: u32, rate: u32) -> Box<dyn FmDiscriminator + Send> {
+        match self {
+            Discriminator::Polar => Box::new(FmDemod::new(deviation, rate)),
+            Discriminator::Atan => Box::new(AtanDemod::new(deviation, rate)),
+            Discriminator::Pll => Box::new(PllDemod::new(deviation, rate)),
+        }
+    }
+}
+
+/// Recovers baseband from an FM signal.
+pub trait FmDiscriminator {
+    /// Demodulate the given sample.
+    fn feed(&mut self, s: Complex32) -> f32;
+}
+
+impl FmDiscriminator for FmDemod {
+    fn feed(&mut self, s: Complex32) -> f32 {
+        FmDemod::feed(self, s)
+    }
+}
+
+/// Get the gain scaling a frequency (radians per sample) so the given deviation (Hz) at
+/// the given sample rate (Hz) is 1.
+fn gain(deviation: u32, rate: u32) -> f32 {
+    rate as f32 / (2.0 * PI * deviation as f32)
+}
+
+/// Wrap the given angle (radians) into [-π, π].
+fn wrap(a: f32) -> f32 {
+    if a > PI {
+        a - 2.0 * PI
+    }
+    else if a < -PI {
+        a + 2.0 * PI
+    }
+    else {
+        a
+    }
+}
+
+/// Differentiates the phase of the signal without computing it.
+pub struct AtanDemod {
+    /// Previous sample.
+    prev: Complex32,
+    /// Scales frequency to baseband.
+    gain: f32,
+}
+
+impl AtanDemod {
+    /// Create a new `AtanDemod` for the given maximum deviation (Hz) at the given sample
+    /// rate (Hz).
+    pub fn new(deviation: u32, rate: u32) -> Self {
+        AtanDemod {
+            prev: Complex32::zero(),
+            gain: gain(deviation, rate),
+        }
+    }
+}
+
+impl FmDiscriminator for AtanDemod {
+    fn feed(&mut self, s: Complex32) -> f32 {
+        // Twice the midpoint of and the difference between the samples, giving
+        // 2 tan(dφ/2) for the phase change dφ, which is close to dφ for small changes.
+        let mid = s + self.prev;
+        let diff = s - self.prev;
+        self.prev = s;
+
+        let power = mid.norm_sqr();
+
+        if power == 0.0 {
+            return 0.0;
+        }
+
+        2.0 * (mid.re * diff.im - mid.im * diff.re) / power * self.gain
+    }
+}
+
+/// Tracks the carrier with a second-order phase-locked loop.
+pub struct PllDemod {
+    /// Phase of the loop oscillator (radians).
+    phase: f32,
+    /// Frequency of the loop oscillator (radians per sample).
+    freq: f32,
+    /// Proportional gain of the loop filter.
+    alpha: f32,
+    /// Integral gain of the loop filter.
+    beta: f32,
+    /// Scales frequency to baseband.
+    gain: f32,
+}
+
+impl PllDemod {
+    /// Create a new `PllDemod` for the given maximum deviation (Hz) at the given sample
+    /// rate (Hz).
+    pub fn new(deviation: u32, rate: u32) -> Self {
+        let theta = PLL_BANDWIDTH / (PLL_DAMPING + 0.25 / PLL_DAMPING);
+        let denom = 1.0 + 2.0 * PLL_DAMPING * theta + theta * theta;
+
+        PllDemod {
+            phase: 0.0,
+            freq: 0.0,
+            alpha: 4.0 * PLL_DAMPING * theta / denom,
+            beta: 4.0 * theta * theta / denom,
+            gain: gain(deviation, rate),
+        }
+    }
+}
+
+impl FmDiscriminator for PllDemod {
+    fn feed(&mut self, s: Complex32) -> f32 {
+        if s.is_zero() {
+            return self.freq * self.gain;
+        }
+
+        let err = (s * Complex32::from_polar(&1.0, &-self.phase)).arg();
+
+        // Keep the integrator within the frequencies the loop can represent.
+        self.freq = (self.freq + self.beta * err).clamp(-PI, PI);
+
+        let step = self.freq + self.alpha * err;
+        self.phase = wrap(self.phase + step);
+
+        step * self.gain
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Generate samples of a carrier offset by the given frequency (Hz) at the given
+    /// sample rate (Hz).
+    fn tone(freq: f32, rate: u32, len: usize) -> Vec<Complex32> {
+        (0..len)
+            .map(|i| Complex32::from_polar(&1.0, &(2.0 * PI * freq * i as f32 / rate as f32)))
+            .collect()
+    }
+
+    #[test]
+    fn test_discriminators() {
+        for &kind in Discriminator::value_variants() {
+            let mut d = kind.create(5000, 48000);
+
+            let out: Vec<f32> = tone(2500.0, 48000, 500)
+                .into_iter()
+                .map(|s| d.feed(s))
+                .collect();
+
+            // Every discriminator settles on half deviation.
+            assert!(
+                out[400..].iter().all(|&s| (s - 0.5).abs() < 0.01),
+                "{:?}",
+                kind
+            );
+
+            // Outer C4FM symbol.
+            let out: Vec<f32> = tone(-1800.0, 48000, 500)
+                .into_iter()
+                .map(|s| d.feed(s))
+                .collect();
+
+            assert!(
+                out[400..].iter().all(|&s| (s + 0.36).abs() < 0.01),
+                "{:?}",
+                kind
+            );
+        }
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap(0.5), 0.5);
+        assert!((wrap(1.5 * PI) + 0.5 * PI).abs() < 1e-6);
+        assert!((wrap(-1.5 * PI) - 0.5 * PI).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,47 @@
+//! Text dumps of received control messages, for reporting decoding bugs.
+
+use std::fmt::Debug;
+
+use p25::{trunking::tsbk::TsbkFields, voice::control::LinkControlFields};
+
+/// Describe the given trunking packet, like
+/// `TSBK GroupVoiceGrant mfg=00 crc=ok 000011a90dc031b2`.
+pub fn tsbk(tsbk: &TsbkFields) -> String {
+    format!(
+        "TSBK {} mfg={:02x} crc={} {}",
+        opcode(tsbk.opcode()),
+        tsbk.mfg(),
+        if tsbk.crc_valid() { "ok" } else { "bad" },
+        hex(tsbk.payload())
+    )
+}
+
+/// Describe the given link control word, like `LC GroupVoiceTraffic 00000011a90dc031`.
+pub fn lc(lc: &LinkControlFields) -> String {
+    format!("LC {} {}", opcode(lc.opcode()), hex(lc.payload()))
+}
+
+/// Get the name of the given decoded opcode, or `Unknown` if it wasn't recognized.
+fn opcode<T: Debug>(o: Option<T>) -> String {
+    match o {
+        Some(o) => format!("{:?}", o),
+        None => "Unknown".to_string(),
+    }
+}
+
+/// Format the given bytes as a lowercase hex string.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dump() {
+        assert_eq!(hex(&[0x00, 0x11, 0xA9, 0xFF]), "0011a9ff");
+        assert_eq!(opcode(Some(7u8)), "7");
+        assert_eq!(opcode::<u8>(None), "Unknown");
+    }
+}
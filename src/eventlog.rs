@@ -0,0 +1,113 @@
+//! Append-only log of hub events in JSON Lines format.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use serde_json;
+
+use crate::signals::SighupWatch;
+
+/// Appends each event as a JSON object on its own line.
+///
+/// The log file is reopened after SIGHUP, such as after rotation by logrotate.
+pub struct EventLog {
+    /// Path of the log file.
+    path: PathBuf,
+    /// Currently open log file.
+    file: File,
+    /// Detects requests to reopen the log file.
+    sighup: SighupWatch,
+}
+
+impl EventLog {
+    /// Open the log file at the given path, creating it if it doesn't exist.
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let file = open_append(&path)?;
+
+        Ok(EventLog {
+            path,
+            file,
+            sighup: SighupWatch::default(),
+        })
+    }
+
+    /// Append the given event, timestamped with the current time.
+    pub fn write<T: Serialize>(&mut self, event: &str, payload: &T) -> io::Result<()> {
+        if self.sighup.received() {
+            info!("reopening event log {}", self.path.display());
+            self.file = open_append(&self.path)?;
+        }
+
+        let mut line = encode(event, payload)?;
+        line.push(b'\n');
+
+        // Write each line at once so lines are never interleaved or split by rotation.
+        self.file.write_all(&line)
+    }
+}
+
+/// Encode the given event as a JSON object, timestamped with the current time.
+pub fn encode<T: Serialize>(event: &str, payload: &T) -> io::Result<Vec<u8>> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+
+    serde_json::to_vec(&json!({
+        "time": time,
+        "event": event,
+        "payload": payload,
+    }))
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Open the given file for appending, creating it if it doesn't exist.
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{signals, testutil::TempDir};
+    use std::fs;
+
+    #[test]
+    fn test_eventlog() {
+        let dir = TempDir::new("events");
+        let path = dir.join("events.log");
+        let rotated = dir.join("events.log.1");
+
+        let mut log = EventLog::open(&path).unwrap();
+        log.write("talkGroup", &101).unwrap();
+
+        // Writes continue to the rotated file until a reopen is requested.
+        fs::rename(&path, &rotated).unwrap();
+        log.write("srcUnit", &42).unwrap();
+
+        signals::count_sighup(libc::SIGHUP);
+        log.write("curFreq", &851000000).unwrap();
+
+        let old = fs::read_to_string(&rotated).unwrap();
+        let lines: Vec<serde_json::Value> = old
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"].as_str(), Some("talkGroup"));
+        assert_eq!(lines[0]["payload"].as_u64(), Some(101));
+        assert!(lines[0]["time"].is_u64());
+        assert_eq!(lines[1]["event"].as_str(), Some("srcUnit"));
+
+        let new = fs::read_to_string(&path).unwrap();
+        assert_eq!(new.lines().count(), 1);
+        assert!(new.contains("\"curFreq\""));
+    }
+}
@@ -0,0 +1,167 @@
+//! Export of undecoded voice frames.
+//!
+//! Each voice frame is written as a fixed 30-byte record, with multi-byte fields in
+//! little-endian order:
+//!
+//! | Offset | Size | Field                                         |
+//! |--------|------|-----------------------------------------------|
+//! | 0      | 8    | Receive time (ms since the Unix epoch)        |
+//! | 8      | 4    | Channel frequency (Hz)                        |
+//! | 12     | 2    | Talkgroup (0 if unknown)                      |
+//! | 14     | 4    | Source unit (0 if unknown)                    |
+//! | 18     | 1    | Corrected bit errors (saturating)             |
+//! | 19     | 11   | IMBE frame: vectors u0-u7 packed MSB first    |
+//!
+//! Records are appended to a file, or sent one per datagram to a UDP socket.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    net::UdpSocket,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use p25::voice::frame::VoiceFrame;
+
+/// Size of each exported record (bytes).
+pub const RECORD_BYTES: usize = 30;
+/// Size of a packed IMBE frame (bytes).
+pub const FRAME_BYTES: usize = 11;
+/// Bit width of each IMBE vector u0-u7.
+const VECTOR_BITS: [u32; 8] = [12, 12, 12, 12, 11, 11, 11, 7];
+
+/// Metadata for the current voice transmission.
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+pub struct CallInfo {
+    /// Channel frequency (Hz).
+    pub freq: u32,
+    /// Talkgroup, or 0 if unknown.
+    pub talkgroup: u16,
+    /// Source unit, or 0 if unknown.
+    pub src_unit: u32,
+}
+
+/// Pack the 88 bits of IMBE vectors u0-u7 into bytes, MSB first.
+pub fn pack_frame(chunks: &[u32; 8]) -> [u8; FRAME_BYTES] {
+    let mut bytes = [0; FRAME_BYTES];
+    let mut pos = 0;
+
+    for (&chunk, &bits) in chunks.iter().zip(VECTOR_BITS.iter()) {
+        for b in (0..bits).rev() {
+            if (chunk >> b) & 1 == 1 {
+                bytes[pos / 8] |= 0x80 >> (pos % 8);
+            }
+
+            pos += 1;
+        }
+    }
+
+    bytes
+}
+
+/// Encode a record for the given frame received at the given time (ms).
+fn encode(time: u64, call: &CallInfo, frame: &VoiceFrame) -> [u8; RECORD_BYTES] {
+    let mut rec = [0; RECORD_BYTES];
+
+    rec[0..8].copy_from_slice(&time.to_le_bytes());
+    rec[8..12].copy_from_slice(&call.freq.to_le_bytes());
+    rec[12..14].copy_from_slice(&call.talkgroup.to_le_bytes());
+    rec[14..18].copy_from_slice(&call.src_unit.to_le_bytes());
+    rec[18] = frame.errors.min(255) as u8;
+    rec[19..].copy_from_slice(&pack_frame(&frame.chunks));
+
+    rec
+}
+
+/// Destination for exported records.
+enum ExportSink {
+    /// Append to a file.
+    File(File),
+    /// Send datagrams to a connected socket.
+    Udp(UdpSocket),
+}
+
+/// Writes undecoded voice frames with their call metadata.
+pub struct FrameExport {
+    sink: ExportSink,
+}
+
+impl FrameExport {
+    /// Open the given export target, either `udp:HOST:PORT` or a file path.
+    pub fn open(target: &str) -> io::Result<Self> {
+        let sink = match target.strip_prefix("udp:") {
+            Some(addr) => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.connect(addr)?;
+                ExportSink::Udp(socket)
+            }
+            None => ExportSink::File(OpenOptions::new().create(true).append(true).open(target)?),
+        };
+
+        Ok(FrameExport {
+            sink,
+        })
+    }
+
+    /// Write a record for the given frame, timestamped with the current time.
+    pub fn write(&mut self, call: &CallInfo, frame: &VoiceFrame) -> io::Result<()> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        let rec = encode(time, call, frame);
+
+        match self.sink {
+            ExportSink::File(ref mut f) => f.write_all(&rec[..]),
+            // Datagrams are best-effort, so delivery failures (such as no listener) are
+            // ignored.
+            ExportSink::Udp(ref s) => {
+                s.send(&rec[..]).ok();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Unpack the given bytes into IMBE vectors u0-u7.
+    fn unpack_frame(bytes: &[u8; FRAME_BYTES]) -> [u32; 8] {
+        let mut chunks = [0; 8];
+        let mut pos = 0;
+
+        for (chunk, &bits) in chunks.iter_mut().zip(VECTOR_BITS.iter()) {
+            for _ in 0..bits {
+                *chunk = (*chunk << 1) | ((bytes[pos / 8] >> (7 - pos % 8)) & 1) as u32;
+                pos += 1;
+            }
+        }
+
+        chunks
+    }
+
+    #[test]
+    fn test_pack_frame() {
+        let chunks = [0xFFF, 0, 0xABC, 0x123, 0x7FF, 0, 0x555, 0x7F];
+        let bytes = pack_frame(&chunks);
+
+        assert_eq!(
+            bytes,
+            [0xFF, 0xF0, 0x00, 0xAB, 0xC1, 0x23, 0xFF, 0xE0, 0x02, 0xAA, 0xFF]
+        );
+        assert_eq!(unpack_frame(&bytes), chunks);
+
+        let chunks = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(unpack_frame(&pack_frame(&chunks)), chunks);
+    }
+
+    #[test]
+    fn test_pack_frame_overflow() {
+        // Bits above each vector's width are ignored.
+        let chunks = [0xFFFF_FFFF; 8];
+        assert_eq!(pack_frame(&chunks), [0xFF; FRAME_BYTES]);
+    }
+}
@@ -0,0 +1,98 @@
+//! Runtime faults reported to remote clients.
+//!
+//! Faults the receiver carries on from are otherwise only visible in the log, so each is
+//! also sent to the hub and streamed as an `error` event, tagged with the part of the
+//! receiver it came from and how serious it is, for dashboards to show health from.
+
+/// Part of the receiver a fault came from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum FaultCategory {
+    /// Reading from or controlling the RTL-SDR.
+    #[serde(rename = "sdr")]
+    Sdr,
+    /// Filtering and demodulating samples.
+    #[serde(rename = "demod")]
+    Demod,
+    /// Decoding P25 packets and voice frames.
+    #[serde(rename = "decode")]
+    Decode,
+    /// Recording and playing out audio.
+    #[serde(rename = "audio")]
+    Audio,
+    /// Serving HTTP clients.
+    #[serde(rename = "http")]
+    Http,
+}
+
+/// Seriousness of a fault.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum Severity {
+    /// Something was degraded or dropped, but reception continues.
+    #[serde(rename = "warning")]
+    Warning,
+    /// Something stopped working until the user intervenes or it recovers.
+    #[serde(rename = "error")]
+    Error,
+}
+
+/// Fault hit at runtime.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Fault {
+    /// Part of the receiver the fault came from.
+    pub category: FaultCategory,
+    /// Seriousness of the fault.
+    pub severity: Severity,
+    /// Description of the fault, as logged.
+    pub message: String,
+}
+
+impl Fault {
+    /// Create a new warning in the given category with the given message.
+    pub fn warning<S: Into<String>>(category: FaultCategory, message: S) -> Self {
+        Fault {
+            category,
+            severity: Severity::Warning,
+            message: message.into(),
+        }
+    }
+
+    /// Create a new error in the given category with the given message.
+    pub fn error<S: Into<String>>(category: FaultCategory, message: S) -> Self {
+        Fault {
+            category,
+            severity: Severity::Error,
+            message: message.into(),
+        }
+    }
+
+    /// Write the fault to the log at its severity.
+    pub fn log(&self) {
+        match self.severity {
+            Severity::Warning => warn!("{}", self.message),
+            Severity::Error => error!("{}", self.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fault() {
+        let f = Fault::error(FaultCategory::Sdr, "unable to set frequency");
+
+        assert_eq!(
+            serde_json::to_value(&f).unwrap(),
+            json!({
+                "category": "sdr",
+                "severity": "error",
+                "message": "unable to set frequency",
+            })
+        );
+
+        let f = Fault::warning(FaultCategory::Http, format!("evicting {}", 1));
+        assert_eq!(f.severity, Severity::Warning);
+        assert_eq!(f.message, "evicting 1");
+    }
+}
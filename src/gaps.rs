@@ -0,0 +1,129 @@
+//! Detection of samples lost before reaching the receiver.
+//!
+//! librtlsdr doesn't report when the device overruns its buffer because a transfer
+//! wasn't read in time, and the samples after the gap are delivered as if they followed
+//! on directly, which shows up downstream only as decoding errors. Samples arrive at a
+//! fixed rate, so a gap is detected when fewer samples have been delivered since a
+//! reference point than the time elapsed since then accounts for.
+
+use std::time::{Duration, Instant};
+
+/// Time between moving the reference point, so the drift of the tuner clock doesn't
+/// build up into a false gap.
+const ANCHOR_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Reason samples were lost.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum DropCause {
+    /// The SDR produced samples faster than they were read.
+    #[serde(rename = "overrun")]
+    Overrun,
+    /// Samples were read but the receiver couldn't keep up with them.
+    #[serde(rename = "ring")]
+    Ring,
+}
+
+/// Samples lost at one point in the stream.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct SampleDrop {
+    /// Reason the samples were lost.
+    pub cause: DropCause,
+    /// Estimated number of samples lost.
+    pub samples: u64,
+    /// Number of drops since reading started.
+    pub total: u64,
+}
+
+/// Detects gaps in a stream of samples from their arrival times.
+pub struct GapDetector {
+    /// Sample rate (Hz) of the stream.
+    rate: u32,
+    /// Samples arriving later than expected by more than this many are considered lost.
+    tolerance: u64,
+    /// Time of the reference point, if any samples have arrived.
+    anchor: Option<Instant>,
+    /// Samples arrived since the reference point.
+    received: u64,
+}
+
+impl GapDetector {
+    /// Create a new `GapDetector` for a stream of the given sample rate (Hz), allowing
+    /// samples to arrive up to the given number of samples late.
+    pub fn new(rate: u32, tolerance: u64) -> Self {
+        GapDetector {
+            rate,
+            tolerance,
+            anchor: None,
+            received: 0,
+        }
+    }
+
+    /// Record that the given number of samples arrived at the given time, returning the
+    /// estimated number lost before them, if any.
+    pub fn record(&mut self, now: Instant, samples: usize) -> Option<u64> {
+        let anchor = match self.anchor {
+            Some(a) => a,
+            None => {
+                self.anchor = Some(now);
+                return None;
+            }
+        };
+
+        self.received += samples as u64;
+
+        let elapsed = now.duration_since(anchor);
+        let expected = (elapsed.as_secs_f64() * self.rate as f64) as u64;
+        let missing = expected.saturating_sub(self.received);
+
+        if missing > self.tolerance {
+            self.anchor = Some(now);
+            self.received = 0;
+
+            return Some(missing);
+        }
+
+        // Samples arriving early means the reference point was itself late, and the
+        // earliest arrivals give the best reference.
+        if expected < self.received || elapsed >= ANCHOR_INTERVAL {
+            self.anchor = Some(now);
+            self.received = 0;
+        }
+
+        None
+    }
+
+    /// Start over for a new stream.
+    pub fn reset(&mut self) {
+        self.anchor = None;
+        self.received = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_gaps() {
+        let mut g = GapDetector::new(1000, 100);
+        let start = Instant::now();
+        let ms = |t| start + Duration::from_millis(t);
+
+        assert_eq!(g.record(ms(0), 100), None);
+        assert_eq!(g.record(ms(100), 100), None);
+
+        // Late arrivals within the tolerance are made up by the next buffers.
+        assert_eq!(g.record(ms(280), 100), None);
+        assert_eq!(g.record(ms(300), 100), None);
+        assert_eq!(g.record(ms(400), 100), None);
+
+        assert_eq!(g.record(ms(1000), 100), Some(500));
+        assert_eq!(g.record(ms(1100), 100), None);
+
+        g.reset();
+
+        // The time paused between streams isn't counted.
+        assert_eq!(g.record(ms(5000), 100), None);
+        assert_eq!(g.record(ms(5100), 100), None);
+    }
+}
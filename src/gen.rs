@@ -0,0 +1,584 @@
+//! Synthetic C4FM baseband generation for testing.
+//!
+//! The generator produces the same kind of 48kHz baseband that `DemodTask` outputs
+//! (and `--write` records), so it can be fed directly into a `MessageReceiver`. Frames
+//! are built from scratch (frame sync, BCH-coded NID, trellis-coded and interleaved
+//! TSBKs, Golay/Hamming-coded and scrambled voice frames, status symbols) and optionally
+//! impaired with Gaussian noise and a carrier frequency offset, which appears as a DC
+//! bias after FM demodulation.
+
+use moving_avg::MovingAverage;
+use rand::{
+    distributions::{IndependentSample, Normal},
+    SeedableRng, StdRng,
+};
+
+use crate::consts::{FRAME_SYNC, FRAME_SYNC_BITS, SAMPLES_PER_SYMBOL};
+
+/// Number of data dibits between status symbols.
+const STATUS_PERIOD: usize = 35;
+/// Frequency deviation (Hz) of a +1 symbol.
+const SYMBOL_DEVIATION: f32 = 600.0;
+/// Frequency deviation (Hz) corresponding to a full-scale demodulated sample.
+const MAX_DEVIATION: f32 = 5000.0;
+
+/// Generator polynomial for the BCH(63, 16, 23) NID code.
+const BCH_GEN: u64 = 0o6331141367235453;
+/// Data unit ID for a trunking signalling packet.
+const DUID_TSBK: u8 = 0b0111;
+/// Data unit ID for a terminator without link control.
+const DUID_TDU: u8 = 0b0011;
+/// Data unit ID for a logical link data unit 1, carrying link control.
+const DUID_LDU1: u8 = 0b0101;
+/// Data unit ID for a logical link data unit 2, carrying encryption sync.
+const DUID_LDU2: u8 = 0b1010;
+
+/// Generator polynomial for the Golay(23, 12) code protecting voice chunks 0 to 3.
+const GOLAY_GEN: u32 = 0xC75;
+/// Data bits covered by each parity bit of the Hamming(15, 11) code protecting voice
+/// chunks 4 to 6, from the most significant parity bit.
+const HAMMING_GEN: [u16; 4] = [0b11111110000, 0b11110001110, 0b11001101101, 0b10101011011];
+/// Width (bits) of each chunk of IMBE parameters in a voice frame.
+const CHUNK_BITS: [usize; 8] = [12, 12, 12, 12, 11, 11, 11, 7];
+
+/// Next-state constellation points for the 1/2-rate trellis code, indexed by current
+/// state and then input dibit.
+const TRELLIS: [[u8; 4]; 4] = [[0, 15, 12, 3], [4, 11, 8, 7], [13, 2, 1, 14], [9, 6, 5, 10]];
+
+/// Dibit pair transmitted for each constellation point.
+const CONSTELLATION: [(u8, u8); 16] = [
+    (0b00, 0b10),
+    (0b10, 0b10),
+    (0b01, 0b11),
+    (0b11, 0b11),
+    (0b11, 0b10),
+    (0b01, 0b10),
+    (0b10, 0b11),
+    (0b00, 0b11),
+    (0b11, 0b01),
+    (0b01, 0b01),
+    (0b10, 0b00),
+    (0b00, 0b00),
+    (0b00, 0b01),
+    (0b10, 0b01),
+    (0b01, 0b00),
+    (0b11, 0b00),
+];
+
+/// Constellation point transmitted at each position of an interleaved data block.
+const INTERLEAVE: [usize; 49] = [
+    0, 13, 26, 39, 1, 14, 27, 40, 2, 15, 28, 41, 3, 16, 29, 42, 4, 17, 30, 43, 5, 18, 31, 44, 6,
+    19, 32, 45, 7, 20, 33, 46, 8, 21, 34, 47, 9, 22, 35, 48, 10, 23, 36, 11, 24, 37, 12, 25, 38,
+];
+
+/// Coded voice frame bit transmitted at each position of a run of 12 bits, with each run
+/// starting 2 bits further into the frame.
+const VOICE_INTERLEAVE: [usize; 12] = [0, 24, 48, 72, 96, 120, 25, 1, 73, 49, 121, 97];
+
+/// Generates impaired C4FM baseband containing known messages.
+pub struct Generator {
+    /// Network access code used in each NID.
+    nac: u16,
+    /// Additive noise, if enabled.
+    noise: Option<Normal>,
+    /// DC bias caused by the carrier frequency offset.
+    bias: f32,
+    /// Source of noise.
+    rng: StdRng,
+    /// Models the averaging filter applied by `DemodTask`.
+    avg: MovingAverage<f32>,
+    /// Data dibits since the last status symbol.
+    status: usize,
+}
+
+impl Generator {
+    /// Create a new `Generator` using the given NAC, noise standard deviation, carrier
+    /// frequency offset (Hz), and noise seed.
+    pub fn new(nac: u16, noise: f32, offset: f32, seed: usize) -> Self {
+        Generator {
+            nac,
+            noise: if noise > 0.0 {
+                Some(Normal::new(0.0, noise as f64))
+            }
+            else {
+                None
+            },
+            bias: offset / MAX_DEVIATION,
+            rng: StdRng::from_seed(&[seed][..]),
+            avg: MovingAverage::new(10),
+            status: 0,
+        }
+    }
+
+    /// Generate the given amount of idle (zero-deviation) symbols.
+    pub fn idle(&mut self, symbols: usize, out: &mut Vec<f32>) {
+        for _ in 0..symbols * SAMPLES_PER_SYMBOL {
+            let s = self.impair(0.0);
+            out.push(s);
+        }
+    }
+
+    /// Generate a trunking signalling packet containing the given blocks.
+    ///
+    /// Each block holds the opcode byte (without the last-block flag), the manufacturer
+    /// ID, and the 8-byte payload. The last-block flag and CRC are filled in
+    /// automatically.
+    pub fn tsbk(&mut self, blocks: &[[u8; 10]], out: &mut Vec<f32>) {
+        assert!(!blocks.is_empty() && blocks.len() <= 3);
+
+        self.start_frame(DUID_TSBK, out);
+
+        for (idx, block) in blocks.iter().enumerate() {
+            let mut buf = [0; 12];
+            buf[..10].copy_from_slice(block);
+
+            if idx == blocks.len() - 1 {
+                buf[0] |= 0x80;
+            }
+
+            let crc = crc_ccitt(&buf[..10]);
+            buf[10] = (crc >> 8) as u8;
+            buf[11] = crc as u8;
+
+            for dibit in interleave(&trellis_encode(&buf)) {
+                self.data_dibit(dibit, out);
+            }
+        }
+
+        // Pad out to the next status symbol.
+        while self.status != 0 {
+            self.data_dibit(0, out);
+        }
+    }
+
+    /// Generate a logical link data unit 1 carrying the given voice frames, each given as
+    /// its 8 chunks of IMBE parameters, and an all-zero link control word.
+    pub fn ldu1(&mut self, frames: &[[u32; 8]; 9], out: &mut Vec<f32>) {
+        self.ldu(DUID_LDU1, frames, out);
+    }
+
+    /// Generate a logical link data unit 2 carrying the given voice frames, each given as
+    /// its 8 chunks of IMBE parameters, and an all-zero encryption sync word.
+    pub fn ldu2(&mut self, frames: &[[u32; 8]; 9], out: &mut Vec<f32>) {
+        self.ldu(DUID_LDU2, frames, out);
+    }
+
+    /// Generate a terminator without link control.
+    pub fn tdu(&mut self, out: &mut Vec<f32>) {
+        self.start_frame(DUID_TDU, out);
+
+        for _ in 0..14 {
+            self.data_dibit(0, out);
+        }
+    }
+
+    /// Modulate the given raw dibits, such as from a symbol capture.
+    ///
+    /// Status symbols aren't inserted, since captured dibits already include them.
+    pub fn dibits(&mut self, dibits: &[u8], out: &mut Vec<f32>) {
+        for &dibit in dibits {
+            self.symbol(dibit & 0b11, out);
+        }
+    }
+
+    /// Emit a logical link data unit with the given data unit and voice frames.
+    fn ldu(&mut self, duid: u8, frames: &[[u32; 8]; 9], out: &mut Vec<f32>) {
+        self.start_frame(duid, out);
+
+        for (idx, frame) in frames.iter().enumerate() {
+            for &dibit in voice_encode(frame).iter() {
+                self.data_dibit(dibit, out);
+            }
+
+            // Link control or encryption sync follows frames 2 to 7, and low speed data
+            // follows frame 8. All-zero words encode to all-zero codewords.
+            let extra = match idx {
+                1..=6 => 20,
+                7 => 16,
+                _ => 0,
+            };
+
+            for _ in 0..extra {
+                self.data_dibit(0, out);
+            }
+        }
+    }
+
+    /// Emit the frame sync and NID for a new frame with the given data unit.
+    fn start_frame(&mut self, duid: u8, out: &mut Vec<f32>) {
+        self.status = 0;
+
+        for i in (0..FRAME_SYNC_BITS / 2).rev() {
+            self.data_dibit((FRAME_SYNC >> (i * 2)) as u8 & 0b11, out);
+        }
+
+        let word = bch_encode(self.nac << 4 | duid as u16);
+        let word = word << 1 | (word.count_ones() & 1) as u64;
+
+        for i in (0..32).rev() {
+            self.data_dibit((word >> (i * 2)) as u8 & 0b11, out);
+        }
+    }
+
+    /// Emit the given dibit, inserting status symbols where required.
+    fn data_dibit(&mut self, dibit: u8, out: &mut Vec<f32>) {
+        self.symbol(dibit, out);
+        self.status += 1;
+
+        if self.status == STATUS_PERIOD {
+            self.status = 0;
+            self.symbol(0b01, out);
+        }
+    }
+
+    /// Modulate the given dibit into baseband samples.
+    fn symbol(&mut self, dibit: u8, out: &mut Vec<f32>) {
+        let level = match dibit {
+            0b01 => 3.0,
+            0b00 => 1.0,
+            0b10 => -1.0,
+            0b11 => -3.0,
+            _ => unreachable!(),
+        };
+
+        for _ in 0..SAMPLES_PER_SYMBOL {
+            let s = self.impair(level * SYMBOL_DEVIATION / MAX_DEVIATION);
+            out.push(s);
+        }
+    }
+
+    /// Apply filtering and configured impairments to the given sample.
+    fn impair(&mut self, s: f32) -> f32 {
+        let noise = match self.noise {
+            Some(ref n) => n.ind_sample(&mut self.rng) as f32,
+            None => 0.0,
+        };
+
+        self.avg.feed(s + self.bias + noise)
+    }
+}
+
+/// Build a group voice channel grant block for the given talkgroup, channel, and source
+/// unit.
+pub fn group_voice_grant(tg: u16, ch: u16, src: u32) -> [u8; 10] {
+    [
+        0x00,
+        0x00,
+        0x00,
+        (ch >> 8) as u8,
+        ch as u8,
+        (tg >> 8) as u8,
+        tg as u8,
+        (src >> 16) as u8,
+        (src >> 8) as u8,
+        src as u8,
+    ]
+}
+
+/// Build a channel identifier update block mapping the given channel ID to the given
+/// base frequency (Hz) and channel spacing (Hz).
+pub fn channel_params_update(id: u8, base: u32, spacing: u32) -> [u8; 10] {
+    // 12.5kHz bandwidth and no transmit offset.
+    let bw: u64 = 12500 / 125;
+    let bits = (id as u64 & 0xF) << 60
+        | bw << 51
+        | ((spacing / 125) as u64 & 0x3FF) << 32
+        | (base / 5) as u64;
+
+    let mut buf = [0; 10];
+    buf[0] = 0x3D;
+
+    for (i, b) in buf[2..].iter_mut().enumerate() {
+        *b = (bits >> (56 - i * 8)) as u8;
+    }
+
+    buf
+}
+
+/// Build a voice frame of IMBE parameter chunks holding a known pattern, different for
+/// each given sequence number. The pattern isn't meaningful audio.
+pub fn voice_pattern(seq: usize) -> [u32; 8] {
+    let mut chunks = [0; 8];
+
+    for (i, (chunk, &bits)) in chunks.iter_mut().zip(CHUNK_BITS.iter()).enumerate() {
+        let x = (seq * 8 + i) as u32;
+        *chunk = x.wrapping_mul(0x9E3779B1) >> (32 - bits);
+    }
+
+    chunks
+}
+
+/// Encode the given voice frame chunks into 72 dibits: Golay-coded chunks 0 to 3,
+/// Hamming-coded chunks 4 to 6, and raw chunk 7, scrambled by chunk 0 and interleaved.
+fn voice_encode(chunks: &[u32; 8]) -> [u8; 72] {
+    // Pseudo-random sequence seeded by chunk 0, one bit per step.
+    let mut prng = 16 * (chunks[0] & 0xFFF);
+    let mut scramble = |bits: usize| {
+        (0..bits).fold(0, |buf, _| {
+            prng = (173 * prng + 13849) % 65536;
+            buf << 1 | prng >> 15
+        })
+    };
+
+    let words = [
+        (golay_encode(chunks[0] as u16), 23),
+        (golay_encode(chunks[1] as u16) ^ scramble(23), 23),
+        (golay_encode(chunks[2] as u16) ^ scramble(23), 23),
+        (golay_encode(chunks[3] as u16) ^ scramble(23), 23),
+        (hamming_encode(chunks[4] as u16) as u32 ^ scramble(15), 15),
+        (hamming_encode(chunks[5] as u16) as u32 ^ scramble(15), 15),
+        (hamming_encode(chunks[6] as u16) as u32 ^ scramble(15), 15),
+        (chunks[7] & 0x7F, 7),
+    ];
+
+    let mut bits = [0; 144];
+    let mut pos = 0;
+
+    for &(word, len) in words.iter() {
+        for i in (0..len).rev() {
+            bits[pos] = (word >> i) as u8 & 1;
+            pos += 1;
+        }
+    }
+
+    let mut dibits = [0; 72];
+
+    for (i, dibit) in dibits.iter_mut().enumerate() {
+        let bit = |x: usize| bits[VOICE_INTERLEAVE[x % 12] + x / 12 * 2];
+        *dibit = bit(i * 2) << 1 | bit(i * 2 + 1);
+    }
+
+    dibits
+}
+
+/// Encode the given 12-bit word into a systematic Golay(23, 12) codeword.
+fn golay_encode(data: u16) -> u32 {
+    let shifted = (data as u32 & 0xFFF) << 11;
+    let mut rem = shifted;
+
+    for bit in (11..23).rev() {
+        if rem >> bit & 1 == 1 {
+            rem ^= GOLAY_GEN << (bit - 11);
+        }
+    }
+
+    shifted | rem
+}
+
+/// Encode the given 11-bit word into a systematic Hamming(15, 11) codeword.
+fn hamming_encode(data: u16) -> u16 {
+    let data = data & 0x7FF;
+
+    HAMMING_GEN.iter().fold(data, |word, &gen| {
+        word << 1 | ((data & gen).count_ones() & 1) as u16
+    })
+}
+
+/// Encode the given 16-bit word into a systematic BCH(63, 16, 23) codeword.
+fn bch_encode(data: u16) -> u64 {
+    let shifted = (data as u64) << 47;
+    let mut rem = shifted;
+
+    for bit in (47..63).rev() {
+        if rem >> bit & 1 == 1 {
+            rem ^= BCH_GEN << (bit - 47);
+        }
+    }
+
+    shifted | rem
+}
+
+/// Compute the inverted CRC-CCITT of the given bytes.
+fn crc_ccitt(bytes: &[u8]) -> u16 {
+    !crc_xmodem(bytes)
+}
+
+/// Compute the non-inverted, zero-initialized CRC-CCITT of the given bytes.
+fn crc_xmodem(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |mut crc: u16, &b| {
+        crc ^= (b as u16) << 8;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            }
+            else {
+                crc << 1
+            };
+        }
+
+        crc
+    })
+}
+
+/// Encode the given 12 bytes into 49 constellation points with the 1/2-rate trellis
+/// code.
+fn trellis_encode(bytes: &[u8; 12]) -> [u8; 49] {
+    let mut points = [0; 49];
+    let mut state = 0;
+
+    let dibits = bytes
+        .iter()
+        .flat_map(|&b| (0..4).rev().map(move |i| b >> (i * 2) & 0b11))
+        // Flush dibit.
+        .chain(std::iter::once(0));
+
+    for (point, dibit) in points.iter_mut().zip(dibits) {
+        *point = TRELLIS[state as usize][dibit as usize];
+        state = dibit;
+    }
+
+    points
+}
+
+/// Interleave the given constellation points into a stream of 98 dibits.
+fn interleave(points: &[u8; 49]) -> impl Iterator<Item = u8> + '_ {
+    INTERLEAVE.iter().flat_map(move |&idx| {
+        let (a, b) = CONSTELLATION[points[idx] as usize];
+        std::iter::once(a).chain(std::iter::once(b))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use p25::{
+        message::{
+            nid::DataUnit,
+            receiver::{MessageEvent, MessageReceiver},
+        },
+        trunking::{
+            fields::TalkGroup,
+            tsbk::{self, TsbkOpcode},
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_crc() {
+        assert_eq!(crc_xmodem(b"123456789"), 0x31C3);
+        assert_eq!(crc_ccitt(b"123456789"), 0xCE3C);
+    }
+
+    #[test]
+    fn test_bch() {
+        // Every codeword must be divisible by the generator.
+        for &data in &[0x0000, 0x293, 0xFFFF, 0x1234] {
+            let word = bch_encode(data);
+            assert_eq!(word >> 47, data as u64);
+
+            let mut rem = word;
+
+            for bit in (47..63).rev() {
+                if rem >> bit & 1 == 1 {
+                    rem ^= BCH_GEN << (bit - 47);
+                }
+            }
+
+            assert_eq!(rem, 0);
+        }
+    }
+
+    #[test]
+    fn test_voice_interleave() {
+        let mut seen = [false; 144];
+
+        for x in 0..144 {
+            seen[VOICE_INTERLEAVE[x % 12] + x / 12 * 2] = true;
+        }
+
+        assert!(seen.iter().all(|&s| s));
+    }
+
+    #[test]
+    fn test_golay() {
+        // Every codeword must be divisible by the generator.
+        for &data in &[0x000, 0x001, 0xFFF, 0xA5C] {
+            let word = golay_encode(data);
+            assert_eq!(word >> 11, data as u32);
+
+            let mut rem = word;
+
+            for bit in (11..23).rev() {
+                if rem >> bit & 1 == 1 {
+                    rem ^= GOLAY_GEN << (bit - 11);
+                }
+            }
+
+            assert_eq!(rem, 0);
+        }
+    }
+
+    #[test]
+    fn test_voice() {
+        let frames = (0..18).map(voice_pattern).collect::<Vec<_>>();
+        let mut first = [[0; 8]; 9];
+        let mut second = [[0; 8]; 9];
+        first.copy_from_slice(&frames[..9]);
+        second.copy_from_slice(&frames[9..]);
+
+        let mut g = Generator::new(0x293, 0.0, 0.0, 42);
+        let mut samples = vec![];
+
+        g.idle(100, &mut samples);
+        g.ldu1(&first, &mut samples);
+        g.ldu2(&second, &mut samples);
+        g.tdu(&mut samples);
+        g.idle(100, &mut samples);
+
+        let mut recv = MessageReceiver::new();
+        let mut units = vec![];
+        let mut decoded = vec![];
+
+        for &s in &samples {
+            match recv.feed(s) {
+                Some(MessageEvent::PacketNID(nid)) => units.push(nid.data_unit),
+                Some(MessageEvent::VoiceFrame(vf)) => {
+                    assert_eq!(vf.errors, 0);
+                    decoded.push(vf.chunks);
+                }
+                _ => {}
+            }
+        }
+
+        assert!(matches!(
+            units[..],
+            [
+                DataUnit::VoiceLCFrameGroup,
+                DataUnit::VoiceCCFrameGroup,
+                DataUnit::VoiceSimpleTerminator
+            ]
+        ));
+        assert_eq!(decoded, frames);
+    }
+
+    #[test]
+    fn test_receive() {
+        for &(noise, offset) in &[(0.0, 0.0), (0.02, 0.0), (0.0, 300.0)] {
+            let mut g = Generator::new(0x293, noise, offset, 42);
+            let mut samples = vec![];
+
+            g.idle(100, &mut samples);
+            g.tsbk(&[group_voice_grant(1234, 0x1042, 0xABCDEF)], &mut samples);
+            g.idle(100, &mut samples);
+
+            let mut recv = MessageReceiver::new();
+            let mut grants = vec![];
+
+            for &s in &samples {
+                if let Some(MessageEvent::TrunkingControl(t)) = recv.feed(s) {
+                    assert!(t.crc_valid());
+                    assert!(matches!(t.opcode(), Some(TsbkOpcode::GroupVoiceGrant)));
+                    grants.push(tsbk::GroupVoiceGrant::new(t).talkgroup());
+                }
+            }
+
+            assert_eq!(grants.len(), 1);
+
+            match grants[0] {
+                TalkGroup::Other(tg) => assert_eq!(tg, 1234),
+                _ => panic!("unexpected talkgroup"),
+            }
+        }
+    }
+}
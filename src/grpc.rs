@@ -0,0 +1,368 @@
+//! gRPC service mirroring the HTTP API, for integrators wanting typed messages.
+//!
+//! The service runs on its own thread with an async runtime, and is kept up to date by
+//! a `GrpcBridge` fed every event by the hub. See `proto/p25rx.proto` for the API.
+
+#[cfg(feature = "grpc")]
+pub use self::imp::{serve, GrpcBridge};
+
+#[cfg(not(feature = "grpc"))]
+pub use self::nogrpc::{serve, GrpcBridge};
+
+#[cfg(feature = "grpc")]
+mod imp {
+    use std::{
+        collections::HashSet,
+        net::{SocketAddr, TcpListener},
+        pin::Pin,
+        sync::{mpsc, Arc, Mutex},
+        thread,
+    };
+
+    use anyhow::{Context, Result};
+    use prost_types::{value::Kind, ListValue, Struct};
+    use serde::Serialize;
+    use serde_json::{self, Value};
+    use tokio::sync::broadcast;
+    use tokio_stream::{
+        wrappers::{BroadcastStream, TcpListenerStream},
+        Stream, StreamExt,
+    };
+    use tonic::{transport::Server, Request, Response, Status};
+
+    use super::proto::{
+        receiver_server::{Receiver, ReceiverServer},
+        ConfigChanges, ControlFreq, Empty, Event, Mute, PauseRequest, ReceiverState,
+        SubscribeRequest, TalkGroup, Version,
+    };
+    use crate::{
+        band::BandMask,
+        calls,
+        consts::{GIT_HASH, VERSION},
+        hub::{HubControl, HubEvent, StateEvent},
+        queue::QueueSender,
+        recv::RecvEvent,
+    };
+
+    /// Most events buffered for each subscriber before it's dropped for falling behind.
+    const EVENT_BACKLOG: usize = 1024;
+
+    /// Feeds events and state from the hub to the gRPC service.
+    pub struct GrpcBridge {
+        /// Fans out events to subscribers.
+        events: broadcast::Sender<Event>,
+        /// Latest receiver state.
+        state: Arc<Mutex<ReceiverState>>,
+    }
+
+    impl GrpcBridge {
+        /// Send the given event to subscribers, timestamped with the current time.
+        pub fn send_event<T: Serialize>(&self, event: &str, payload: &T) {
+            if self.events.receiver_count() == 0 {
+                return;
+            }
+
+            let payload = match serde_json::to_value(payload) {
+                Ok(p) => p,
+                Err(_) => return,
+            };
+
+            // This only fails when there are no subscribers left.
+            self.events
+                .send(Event {
+                    event: event.to_string(),
+                    time: calls::now(),
+                    payload: Some(to_proto(&payload)),
+                })
+                .ok();
+        }
+
+        /// Update the receiver state based on the given event.
+        pub fn record(&self, e: &HubEvent) {
+            let mut s = self.state.lock().unwrap();
+
+            match *e {
+                HubEvent::State(StateEvent::UpdateCtlFreq(f)) => s.ctl_freq = f,
+                HubEvent::UpdateCurFreq(f) => s.cur_freq = f,
+                HubEvent::UpdateTalkGroup(tg) => s.talk_group = tg as u32,
+                HubEvent::BeginPrivateCall(_) | HubEvent::EndTransmission => s.talk_group = 0,
+                HubEvent::UpdateSignalPower(p) => s.signal_power = p,
+                HubEvent::ControlLost => s.control_lost = true,
+                HubEvent::ControlRestored => s.control_lost = false,
+                _ => {}
+            }
+        }
+    }
+
+    /// Handles gRPC requests.
+    struct ReceiverService {
+        /// Fans out events to subscribers.
+        events: broadcast::Sender<Event>,
+        /// Latest receiver state.
+        state: Arc<Mutex<ReceiverState>>,
+        /// Channel for communication with RecvTask.
+        recv: QueueSender<RecvEvent>,
+        /// Channel for control actions handled by the hub.
+        hub: QueueSender<HubEvent>,
+        /// Frequencies allowed to be set as the control channel.
+        band: BandMask,
+    }
+
+    type EventStream = Pin<Box<dyn Stream<Item = Result<Event, Status>> + Send>>;
+
+    impl ReceiverService {
+        /// Send the given event to the receiver.
+        fn send_recv(&self, e: RecvEvent) -> Result<Response<Empty>, Status> {
+            self.recv
+                .send(e)
+                .map_err(|_| Status::internal("receiver stopped"))?;
+
+            Ok(Response::new(Empty {}))
+        }
+
+        /// Send the given control action to the hub.
+        fn send_hub(&self, c: HubControl) -> Result<(), Status> {
+            self.hub
+                .send(HubEvent::Control(c))
+                .map_err(|_| Status::internal("hub stopped"))
+        }
+    }
+
+    #[tonic::async_trait]
+    impl Receiver for ReceiverService {
+        type SubscribeStream = EventStream;
+
+        async fn subscribe(
+            &self,
+            req: Request<SubscribeRequest>,
+        ) -> Result<Response<EventStream>, Status> {
+            let filter: HashSet<String> = req.into_inner().events.into_iter().collect();
+
+            let stream = BroadcastStream::new(self.events.subscribe()).filter_map(move |e| {
+                match e {
+                    Ok(e) if filter.is_empty() || filter.contains(&e.event) => Some(Ok(e)),
+                    Ok(_) => None,
+                    // Ends the stream, like evicting a lagging HTTP subscriber.
+                    Err(_) => Some(Err(Status::resource_exhausted("subscriber fell behind"))),
+                }
+            });
+
+            Ok(Response::new(Box::pin(stream)))
+        }
+
+        async fn get_state(&self, _: Request<Empty>) -> Result<Response<ReceiverState>, Status> {
+            Ok(Response::new(self.state.lock().unwrap().clone()))
+        }
+
+        async fn set_control_freq(
+            &self,
+            req: Request<ControlFreq>,
+        ) -> Result<Response<Empty>, Status> {
+            let freq = req.into_inner().freq;
+
+            self.band.check(freq).map_err(Status::invalid_argument)?;
+            self.send_recv(RecvEvent::SetControlFreq(freq))
+        }
+
+        async fn reset_stats(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+            self.send_recv(RecvEvent::ResetStats)
+        }
+
+        async fn hold(&self, req: Request<TalkGroup>) -> Result<Response<Empty>, Status> {
+            let tg = u16::try_from(req.into_inner().talk_group)
+                .map_err(|_| Status::invalid_argument("talkgroup out of range"))?;
+
+            self.send_recv(RecvEvent::Hold(Some(tg)))
+        }
+
+        async fn release(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+            self.send_recv(RecvEvent::Hold(None))
+        }
+
+        async fn set_mute(&self, req: Request<Mute>) -> Result<Response<Empty>, Status> {
+            self.send_hub(HubControl::Mute(req.into_inner().muted))?;
+
+            Ok(Response::new(Empty {}))
+        }
+
+        async fn pause(&self, req: Request<PauseRequest>) -> Result<Response<Empty>, Status> {
+            self.send_recv(RecvEvent::Pause(req.into_inner().release))
+        }
+
+        async fn resume(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+            self.send_recv(RecvEvent::Resume)
+        }
+
+        async fn reload(&self, _: Request<Empty>) -> Result<Response<ConfigChanges>, Status> {
+            let (tx, rx) = mpsc::channel();
+            self.send_hub(HubControl::Reload(tx))?;
+
+            // Loading settings can touch the disk, so wait off the runtime thread.
+            let res = tokio::task::spawn_blocking(move || rx.recv())
+                .await
+                .ok()
+                .and_then(Result::ok)
+                .ok_or_else(|| Status::internal("hub stopped"))?;
+
+            let changes = res.map_err(Status::failed_precondition)?;
+
+            Ok(Response::new(ConfigChanges {
+                changes,
+            }))
+        }
+
+        async fn get_version(&self, _: Request<Empty>) -> Result<Response<Version>, Status> {
+            Ok(Response::new(Version {
+                version: VERSION.to_string(),
+                git: GIT_HASH.to_string(),
+            }))
+        }
+    }
+
+    /// Start serving the gRPC API on the given address from a new thread, sending
+    /// control actions to the given receiver and hub channels and rejecting control
+    /// channels outside the given mask.
+    pub fn serve(
+        addr: SocketAddr,
+        recv: QueueSender<RecvEvent>,
+        hub: QueueSender<HubEvent>,
+        band: BandMask,
+    ) -> Result<GrpcBridge> {
+        let (events, _) = broadcast::channel(EVENT_BACKLOG);
+        let state = Arc::new(Mutex::new(ReceiverState::default()));
+
+        let svc = ReceiverService {
+            events: events.clone(),
+            state: state.clone(),
+            recv,
+            hub,
+            band,
+        };
+
+        // Bind up front so failures are reported at startup.
+        let socket = TcpListener::bind(addr).with_context(|| format!("unable to bind {}", addr))?;
+        socket.set_nonblocking(true)?;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("unable to start async runtime")?;
+
+        thread::Builder::new()
+            .name("grpc".to_string())
+            .spawn(move || {
+                let res = rt.block_on(async move {
+                    let socket = tokio::net::TcpListener::from_std(socket)?;
+
+                    Server::builder()
+                        .add_service(ReceiverServer::new(svc))
+                        .serve_with_incoming(TcpListenerStream::new(socket))
+                        .await?;
+
+                    Ok::<_, anyhow::Error>(())
+                });
+
+                if let Err(e) = res {
+                    warn!("stopped gRPC server: {:#}", e);
+                }
+            })
+            .context("unable to spawn gRPC thread")?;
+
+        Ok(GrpcBridge {
+            events,
+            state,
+        })
+    }
+
+    /// Convert the given JSON value to its protobuf equivalent.
+    fn to_proto(v: &Value) -> prost_types::Value {
+        let kind = match *v {
+            Value::Null => Kind::NullValue(0),
+            Value::Bool(b) => Kind::BoolValue(b),
+            Value::Number(ref n) => Kind::NumberValue(n.as_f64().unwrap_or(0.0)),
+            Value::String(ref s) => Kind::StringValue(s.clone()),
+            Value::Array(ref a) => Kind::ListValue(ListValue {
+                values: a.iter().map(to_proto).collect(),
+            }),
+            Value::Object(ref o) => Kind::StructValue(Struct {
+                fields: o.iter().map(|(k, v)| (k.clone(), to_proto(v))).collect(),
+            }),
+        };
+
+        prost_types::Value {
+            kind: Some(kind),
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn test_to_proto() {
+            let v = to_proto(&json!({
+                "talkGroup": 101,
+                "units": [1, 2],
+                "name": "Fire",
+                "crypto": null,
+            }));
+
+            let fields = match v.kind {
+                Some(Kind::StructValue(s)) => s.fields,
+                _ => panic!("expected struct"),
+            };
+
+            assert_eq!(fields["talkGroup"].kind, Some(Kind::NumberValue(101.0)));
+            assert_eq!(
+                fields["name"].kind,
+                Some(Kind::StringValue("Fire".to_string()))
+            );
+            assert_eq!(fields["crypto"].kind, Some(Kind::NullValue(0)));
+
+            match fields["units"].kind {
+                Some(Kind::ListValue(ref l)) => assert_eq!(l.values.len(), 2),
+                _ => panic!("expected list"),
+            }
+        }
+    }
+}
+
+/// Generated protobuf messages and service.
+#[cfg(feature = "grpc")]
+mod proto {
+    tonic::include_proto!("p25rx");
+}
+
+#[cfg(not(feature = "grpc"))]
+mod nogrpc {
+    use std::net::SocketAddr;
+
+    use anyhow::{anyhow, Result};
+    use serde::Serialize;
+
+    use crate::{band::BandMask, hub::HubEvent, queue::QueueSender, recv::RecvEvent};
+
+    pub fn serve(
+        _: SocketAddr,
+        _: QueueSender<RecvEvent>,
+        _: QueueSender<HubEvent>,
+        _: BandMask,
+    ) -> Result<GrpcBridge> {
+        Err(anyhow!(
+            "p25rx was built without gRPC support (the grpc feature)"
+        ))
+    }
+
+    /// Feeds events and state from the hub to the gRPC service.
+    pub enum GrpcBridge {}
+
+    impl GrpcBridge {
+        pub fn send_event<T: Serialize>(&self, _: &str, _: &T) {
+            match *self {}
+        }
+
+        pub fn record(&self, _: &HubEvent) {
+            match *self {}
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Liveness of the receiver, for container orchestration and uptime monitors.
+//!
+//! The receiver is healthy when samples are arriving from the SDR, trunking packets are
+//! being decoded on the control channel at some minimum rate, and every pipeline task is
+//! still running. Checks that don't apply to the current state, like decoding the control
+//! channel while following a call, are passed.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::recv::RecvState;
+
+/// Default lowest rate (packets/sec) of trunking packets on the control channel.
+pub const DEFAULT_MIN_RATE: f32 = 1.0;
+
+/// Longest time without samples before the SDR is considered stalled.
+const SAMPLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time over which the rate of trunking packets is measured.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// Time after tuning to a control channel before its packet rate is checked.
+const CONTROL_GRACE: Duration = Duration::from_secs(3);
+
+/// Result of the health checks, as reported to clients.
+#[derive(Serialize)]
+pub struct HealthReport {
+    /// Whether every check passed.
+    pub healthy: bool,
+    /// Whether samples are arriving from the SDR.
+    samples: bool,
+    /// Whether the control channel is decoding at the minimum rate.
+    control: bool,
+    /// Rate (packets/sec) of trunking packets decoded on the control channel, if it's
+    /// being checked.
+    #[serde(rename = "controlRate")]
+    control_rate: Option<f32>,
+    /// Whether every pipeline task is running.
+    tasks: bool,
+    /// Names of pipeline tasks that have stopped.
+    stopped: Vec<&'static str>,
+}
+
+/// Tracks the activity checked for health.
+pub struct HealthCheck {
+    /// Lowest rate (packets/sec) of trunking packets on the control channel.
+    min_rate: f32,
+    /// Current state of the receiver.
+    state: RecvState,
+    /// Time the receiver started, or was last tuned to a control channel.
+    since: Instant,
+    /// Time samples were last demodulated, if ever.
+    samples: Option<Instant>,
+    /// Arrival times of trunking packets within the rate window.
+    packets: VecDeque<Instant>,
+}
+
+impl HealthCheck {
+    /// Create a new `HealthCheck` requiring the given rate (packets/sec) of trunking
+    /// packets on the control channel.
+    pub fn new(min_rate: f32) -> Self {
+        HealthCheck {
+            min_rate,
+            state: RecvState::Searching,
+            since: Instant::now(),
+            samples: None,
+            packets: VecDeque::new(),
+        }
+    }
+
+    /// Record that the receiver changed to the given state.
+    pub fn record_state(&mut self, state: RecvState) {
+        // Packets from before a call don't count toward the rate after it.
+        if on_control(state) && !on_control(self.state) {
+            self.since = Instant::now();
+            self.packets.clear();
+        }
+
+        self.state = state;
+    }
+
+    /// Record that samples were demodulated.
+    pub fn record_samples(&mut self) {
+        self.samples = Some(Instant::now());
+    }
+
+    /// Record that a trunking packet was decoded.
+    pub fn record_packet(&mut self) {
+        let now = Instant::now();
+
+        self.packets.push_back(now);
+        self.expire(now);
+    }
+
+    /// Drop packets that arrived before the rate window ending at the given time.
+    fn expire(&mut self, now: Instant) {
+        while let Some(&t) = self.packets.front() {
+            if now.duration_since(t) < RATE_WINDOW {
+                break;
+            }
+
+            self.packets.pop_front();
+        }
+    }
+
+    /// Run the checks, given the names of the pipeline tasks that have stopped.
+    pub fn check(&mut self, stopped: Vec<&'static str>) -> HealthReport {
+        let now = Instant::now();
+        self.expire(now);
+
+        // A paused receiver isn't expected to be receiving anything.
+        let paused = self.state == RecvState::Paused;
+
+        let samples = paused
+            || self
+                .samples
+                .map_or(false, |t| now.duration_since(t) < SAMPLE_TIMEOUT);
+
+        let elapsed = now.duration_since(self.since);
+
+        let control_rate = if on_control(self.state) && elapsed >= CONTROL_GRACE {
+            Some(self.packets.len() as f32 / elapsed.min(RATE_WINDOW).as_secs_f32())
+        }
+        else {
+            None
+        };
+
+        let control = control_rate.map_or(true, |r| r >= self.min_rate);
+        let tasks = stopped.is_empty();
+
+        HealthReport {
+            healthy: samples && control && tasks,
+            samples,
+            control,
+            control_rate,
+            tasks,
+            stopped,
+        }
+    }
+}
+
+/// Check if the receiver is expected to be decoding a control channel in the given state.
+fn on_control(state: RecvState) -> bool {
+    matches!(
+        state,
+        RecvState::Searching | RecvState::LockedControl | RecvState::RecoveringControl
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_health() {
+        let mut h = HealthCheck::new(1.0);
+
+        // No samples yet, but the control channel was only just tuned.
+        let r = h.check(vec![]);
+        assert!(!r.healthy);
+        assert!(!r.samples);
+        assert!(r.control);
+        assert_eq!(r.control_rate, None);
+
+        h.record_samples();
+        assert!(h.check(vec![]).healthy);
+
+        let r = h.check(vec!["audio"]);
+        assert!(!r.healthy);
+        assert!(!r.tasks);
+
+        // Past the grace period without any packets.
+        h.since = Instant::now() - CONTROL_GRACE;
+        let r = h.check(vec![]);
+        assert!(!r.control);
+        assert_eq!(r.control_rate, Some(0.0));
+
+        for _ in 0..10 {
+            h.record_packet();
+        }
+
+        assert!(h.check(vec![]).healthy);
+
+        // Following a call doesn't need the control channel.
+        h.record_state(RecvState::TunedVoice);
+        h.packets.clear();
+        assert!(h.check(vec![]).healthy);
+
+        h.record_state(RecvState::LockedControl);
+        assert!(h.check(vec![]).control);
+
+        h.record_state(RecvState::Paused);
+        h.samples = None;
+        assert!(h.check(vec![]).healthy);
+    }
+}
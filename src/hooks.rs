@@ -0,0 +1,242 @@
+//! External commands run on receiver events.
+//!
+//! Each command is run with `sh -c`, receiving the event payload as a JSON object on
+//! stdin and each top-level field of the payload in an environment variable, like
+//! `P25RX_TALK_GROUP` for `talkGroup`, along with the event name in `P25RX_EVENT`.
+//! Variables with that prefix inherited from p25rx, like the options set through the
+//! environment, are removed so a field missing from the payload is never mistaken for
+//! one of them.
+
+use std::{
+    env,
+    io::Write,
+    process::{Command, Stdio},
+    thread,
+};
+
+use clap::ValueEnum;
+use serde_json::{self, Value};
+
+/// Events that can run a command.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub enum HookEvent {
+    /// Receiver began following a call.
+    CallStart,
+    /// Receiver left a call.
+    CallEnd,
+    /// Current call was marked as an emergency.
+    Emergency,
+    /// Current call was found to be encrypted.
+    Encrypted,
+    /// No trunking packets have been received on the control channel for a while.
+    ControlLost,
+    /// Followed unit was active.
+    UnitSeen,
+}
+
+impl HookEvent {
+    /// Get the name of the event, as used on the command line.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            HookEvent::CallStart => "call-start",
+            HookEvent::CallEnd => "call-end",
+            HookEvent::Emergency => "emergency",
+            HookEvent::Encrypted => "encrypted",
+            HookEvent::ControlLost => "control-lost",
+            HookEvent::UnitSeen => "unit-seen",
+        }
+    }
+}
+
+/// Command run on an event.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hook {
+    /// Event that runs the command.
+    pub event: HookEvent,
+    /// Shell command to run.
+    pub cmd: String,
+}
+
+/// Parse a hook like `call-end:notify-send "call ended"`.
+pub fn parse_hook(s: &str) -> Result<Hook, String> {
+    let (event, cmd) = s
+        .split_once(':')
+        .ok_or_else(|| "expected EVENT:COMMAND".to_string())?;
+
+    let event = HookEvent::from_str(event, false)?;
+
+    if cmd.trim().is_empty() {
+        return Err("empty command".to_string());
+    }
+
+    Ok(Hook {
+        event,
+        cmd: cmd.to_string(),
+    })
+}
+
+/// Runs commands on events without blocking the caller.
+#[derive(Clone, Debug, Default)]
+pub struct Hooks(Vec<Hook>);
+
+impl Hooks {
+    /// Create a new `Hooks` running the given commands.
+    pub fn new(hooks: Vec<Hook>) -> Self {
+        Hooks(hooks)
+    }
+
+    /// Check if any command runs on the given event.
+    pub fn handles(&self, event: HookEvent) -> bool {
+        self.0.iter().any(|h| h.event == event)
+    }
+
+    /// Run each command for the given event with the given payload.
+    pub fn run(&self, event: HookEvent, payload: &Value) {
+        for hook in self.0.iter().filter(|h| h.event == event) {
+            let cmd = hook.cmd.clone();
+            let env = env_vars(event, payload);
+            let input = serde_json::to_string(payload).expect("unable to serialize payload");
+
+            // Each command gets its own thread so slow commands never hold up the hub.
+            thread::spawn(move || {
+                if let Err(e) = run_command(&cmd, env, input.as_bytes()) {
+                    warn!("{} hook failed: {}", event.name(), e);
+                }
+            });
+        }
+    }
+}
+
+/// Run the given shell command with the given environment and stdin, waiting for it to
+/// exit.
+fn run_command(cmd: &str, env: Vec<(String, String)>, input: &[u8]) -> Result<(), String> {
+    let mut command = Command::new("sh");
+
+    for (key, _) in env::vars_os() {
+        if key.to_string_lossy().starts_with("P25RX_") {
+            command.env_remove(key);
+        }
+    }
+
+    let mut child = command
+        .arg("-c")
+        .arg(cmd)
+        .envs(env)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("unable to run {}: {}", cmd, e))?;
+
+    // The command may exit without reading its input.
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).ok();
+    }
+
+    let status = child
+        .wait()
+        .map_err(|e| format!("unable to wait for {}: {}", cmd, e))?;
+
+    if status.success() {
+        Ok(())
+    }
+    else {
+        Err(format!("{} exited with {}", cmd, status))
+    }
+}
+
+/// Build the environment variables describing the given event and payload.
+fn env_vars(event: HookEvent, payload: &Value) -> Vec<(String, String)> {
+    let mut env = vec![("P25RX_EVENT".to_string(), event.name().to_string())];
+
+    let fields = match payload.as_object() {
+        Some(f) => f,
+        None => return env,
+    };
+
+    for (key, val) in fields {
+        let val = match *val {
+            Value::Null => continue,
+            Value::String(ref s) => s.clone(),
+            ref v => v.to_string(),
+        };
+
+        env.push((format!("P25RX_{}", env_name(key)), val));
+    }
+
+    env
+}
+
+/// Convert the given camel case field name to upper snake case.
+fn env_name(key: &str) -> String {
+    let mut name = String::with_capacity(key.len() + 4);
+
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            name.push('_');
+        }
+
+        name.push(c.to_ascii_uppercase());
+    }
+
+    name
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_parse_hook() {
+        assert_eq!(
+            parse_hook("call-end:notify-send 'call: ended'"),
+            Ok(Hook {
+                event: HookEvent::CallEnd,
+                cmd: "notify-send 'call: ended'".to_string(),
+            })
+        );
+        assert!(parse_hook("call-end").is_err());
+        assert!(parse_hook("call-end: ").is_err());
+        assert!(parse_hook("bogus:true").is_err());
+    }
+
+    #[test]
+    fn test_env_vars() {
+        let env = env_vars(
+            HookEvent::CallStart,
+            &json!({
+                "talkGroup": 101,
+                "srcUnit": null,
+                "alias": "Fire Dispatch",
+            }),
+        );
+
+        assert_eq!(
+            env,
+            vec![
+                ("P25RX_EVENT".to_string(), "call-start".to_string()),
+                ("P25RX_ALIAS".to_string(), "Fire Dispatch".to_string()),
+                ("P25RX_TALK_GROUP".to_string(), "101".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_command() {
+        let dir = TempDir::new("hook");
+        let path = dir.join("stdin");
+        let cmd = format!(
+            "cat > {} && test \"$P25RX_EVENT\" = call-end",
+            path.display()
+        );
+
+        run_command(
+            &cmd,
+            vec![("P25RX_EVENT".to_string(), "call-end".to_string())],
+            b"{}",
+        )
+        .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+        assert!(run_command("exit 3", vec![], b"").is_err());
+    }
+}
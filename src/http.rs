@@ -13,29 +13,99 @@ use uhttp_response_header::HeaderLines;
 use uhttp_status::StatusCode;
 use uhttp_version::HttpVersion;
 
+/// Methods allowed in cross-origin requests.
+const CORS_METHODS: &str = "GET, PUT, POST, OPTIONS";
+/// Time (sec) browsers may cache a preflight response.
+const CORS_MAX_AGE: u32 = 86400;
+
+/// Cross-origin resource sharing policy.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CorsPolicy {
+    /// Send no CORS headers, so browsers only allow same-origin requests.
+    Disabled,
+    /// Allow requests from the given origin, or any origin with `*`.
+    Origin(String),
+}
+
+impl CorsPolicy {
+    /// Write the headers allowing cross-origin access to a response.
+    fn write_headers<W: Write>(&self, h: &mut HeaderLines<W>) -> std::io::Result<()> {
+        match *self {
+            CorsPolicy::Disabled => Ok(()),
+            CorsPolicy::Origin(ref origin) => {
+                write!(h.line(), "Access-Control-Allow-Origin: {}", origin)
+            }
+        }
+    }
+}
+
+/// Parse a CORS policy, either `none`, `*`, or a single origin like
+/// `https://example.com`.
+pub fn parse_cors(s: &str) -> Result<CorsPolicy, String> {
+    match s {
+        "none" => Ok(CorsPolicy::Disabled),
+        "*" => Ok(CorsPolicy::Origin(s.to_string())),
+        _ if s.starts_with("http://") || s.starts_with("https://") => {
+            Ok(CorsPolicy::Origin(s.trim_end_matches('/').to_string()))
+        }
+        _ => Err(format!("invalid origin {}", s)),
+    }
+}
+
 /// Send common response headers starting with the given status code.
-pub fn send_status<W: Write>(s: W, st: StatusCode) -> std::io::Result<()> {
-    send_head(&mut HeaderLines::new(s), st)
+pub fn send_status<W: Write>(s: W, cors: &CorsPolicy, st: StatusCode) -> std::io::Result<()> {
+    send_head(&mut HeaderLines::new(s), cors, st)
 }
 
 /// Write common response headers into the given sink.
-pub fn send_head<W: Write>(h: &mut HeaderLines<W>, st: StatusCode) -> std::io::Result<()> {
+pub fn send_head<W: Write>(
+    h: &mut HeaderLines<W>,
+    cors: &CorsPolicy,
+    st: StatusCode,
+) -> std::io::Result<()> {
     write!(h.line(), "{} {}", HttpVersion::from_parts(1, 1), st)?;
     write!(
         h.line(),
         "Date: {}",
         UTC::now().format("%a, %d %b %Y %T %Z")
     )?;
-    write!(h.line(), "Access-Control-Allow-Origin: *")?;
+
+    cors.write_headers(h)
+}
+
+/// Send a response to a CORS preflight request.
+pub fn send_preflight<W: Write>(s: W, cors: &CorsPolicy) -> std::io::Result<()> {
+    let mut h = HeaderLines::new(s);
+
+    send_head(&mut h, cors, StatusCode::Ok)?;
+
+    if let CorsPolicy::Disabled = *cors {
+        return Ok(());
+    }
+
+    write!(h.line(), "Access-Control-Allow-Methods: {}", CORS_METHODS)?;
+    write!(h.line(), "Access-Control-Allow-Headers: Content-Type")?;
+    write!(h.line(), "Access-Control-Max-Age: {}", CORS_MAX_AGE)?;
+    write!(h.line(), "Content-Length: 0")?;
 
     Ok(())
 }
 
 /// Send the given message as a JSON response body.
-pub fn send_json<W: Write, S: Serialize>(mut s: W, msg: S) -> std::io::Result<()> {
+pub fn send_json<W: Write, S: Serialize>(s: W, cors: &CorsPolicy, msg: S) -> std::io::Result<()> {
+    send_json_status(s, cors, StatusCode::Ok, msg)
+}
+
+/// Send the given message as a JSON response body with the given status code.
+pub fn send_json_status<W: Write, S: Serialize>(
+    mut s: W,
+    cors: &CorsPolicy,
+    st: StatusCode,
+    msg: S,
+) -> std::io::Result<()> {
     {
         let mut h = HeaderLines::new(&mut s);
-        send_head(&mut h, StatusCode::Ok)?;
+        send_head(&mut h, cors, st)?;
         write!(h.line(), "Content-Type: application/json")?;
         write!(h.line(), "Transfer-Encoding: chunked")?;
     }
@@ -44,3 +114,187 @@ pub fn send_json<W: Write, S: Serialize>(mut s: W, msg: S) -> std::io::Result<()
 
     serde_json::to_writer(&mut body, &msg).map_err(|_| std::io::ErrorKind::Other.into())
 }
+
+/// Send the given bytes as a response body with the given content type.
+pub fn send_body<W: Write>(
+    mut s: W,
+    cors: &CorsPolicy,
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    {
+        let mut h = HeaderLines::new(&mut s);
+        send_head(&mut h, cors, StatusCode::Ok)?;
+        write!(h.line(), "Content-Type: {}", content_type)?;
+        write!(h.line(), "Content-Length: {}", body.len())?;
+    }
+
+    s.write_all(body)
+}
+
+/// Send the head of a response with the given content type to a request for the given
+/// range of bytes (inclusive), or all bytes if `None`, of a body with the given length.
+///
+/// The body is left for the caller to send.
+pub fn send_range_head<W: Write>(
+    s: W,
+    cors: &CorsPolicy,
+    content_type: &str,
+    len: u64,
+    range: Option<(usize, usize)>,
+) -> std::io::Result<()> {
+    let (st, part) = match range {
+        Some((start, end)) => (StatusCode::PartialContent, (end - start + 1) as u64),
+        None => (StatusCode::Ok, len),
+    };
+
+    let mut h = HeaderLines::new(s);
+    send_head(&mut h, cors, st)?;
+    write!(h.line(), "Content-Type: {}", content_type)?;
+    write!(h.line(), "Content-Length: {}", part)?;
+    write!(h.line(), "Accept-Ranges: bytes")?;
+
+    if let Some((start, end)) = range {
+        write!(h.line(), "Content-Range: bytes {}-{}/{}", start, end, len)?;
+    }
+
+    Ok(())
+}
+
+/// Find the value of the given header in the given raw request head.
+pub fn find_header<'a>(head: &'a [u8], name: &str) -> Option<&'a str> {
+    head.split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        // Skip the request line and stop at the end of the headers.
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| std::str::from_utf8(line).ok())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, val)| val.trim())
+}
+
+/// Progress of receiving a request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RequestProgress {
+    /// More of the request is needed.
+    Partial,
+    /// Head and body have been received.
+    Complete,
+    /// Request can't fit in the allowed size.
+    TooLarge,
+}
+
+/// Check if the given received bytes hold a complete request, including any body given
+/// by `Content-Length`, that fits in the given number of bytes.
+pub fn request_progress(buf: &[u8], max: usize) -> RequestProgress {
+    let head = match buf.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => pos + 4,
+        None if buf.len() >= max => return RequestProgress::TooLarge,
+        None => return RequestProgress::Partial,
+    };
+
+    // An invalid length is left for the request parser to reject.
+    let body = find_header(&buf[..head], "Content-Length")
+        .and_then(|len| len.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    match head.checked_add(body) {
+        Some(len) if len > max => RequestProgress::TooLarge,
+        Some(len) if len > buf.len() => RequestProgress::Partial,
+        Some(_) => RequestProgress::Complete,
+        None => RequestProgress::TooLarge,
+    }
+}
+
+/// Parse a single byte range like `bytes=0-499`, `bytes=500-`, or `bytes=-500` into
+/// inclusive bounds within a body of the given length.
+///
+/// Returns `None` if the range is malformed or can't be satisfied.
+pub fn parse_range(s: &str, len: usize) -> Option<(usize, usize)> {
+    let (start, end) = s.strip_prefix("bytes=")?.split_once('-')?;
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let n: usize = suffix.parse().ok()?;
+            (len.checked_sub(n.min(len))?, len.checked_sub(1)?)
+        }
+        (start, "") => (start.parse().ok()?, len.checked_sub(1)?),
+        (start, end) => {
+            let end: usize = end.parse().ok()?;
+            (start.parse().ok()?, end.min(len.checked_sub(1)?))
+        }
+    };
+
+    if start > end {
+        return None;
+    }
+
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_cors() {
+        assert_eq!(parse_cors("none"), Ok(CorsPolicy::Disabled));
+        assert_eq!(parse_cors("*"), Ok(CorsPolicy::Origin("*".to_string())));
+        assert_eq!(
+            parse_cors("https://example.com/"),
+            Ok(CorsPolicy::Origin("https://example.com".to_string()))
+        );
+        assert_eq!(
+            parse_cors("http://localhost:8080"),
+            Ok(CorsPolicy::Origin("http://localhost:8080".to_string()))
+        );
+        assert!(parse_cors("example.com").is_err());
+    }
+
+    #[test]
+    fn test_find_header() {
+        let head = b"GET /calls/1/audio HTTP/1.1\r\nHost: x\r\nrange: bytes=0-9\r\n\r\nRange: no";
+
+        assert_eq!(find_header(head, "Range"), Some("bytes=0-9"));
+        assert_eq!(find_header(head, "host"), Some("x"));
+        assert_eq!(find_header(head, "Accept"), None);
+        assert_eq!(find_header(b"GET / HTTP/1.1\r\n\r\n\xFF", "Range"), None);
+    }
+
+    #[test]
+    fn test_request_progress() {
+        use self::RequestProgress::*;
+
+        assert_eq!(
+            request_progress(b"GET / HTTP/1.1\r\nHost: x\r\n", 64),
+            Partial
+        );
+        assert_eq!(request_progress(b"GET / HTTP/1.1\r\n\r\n", 64), Complete);
+
+        let put = b"PUT /ctlfreq HTTP/1.1\r\nContent-Length: 4\r\n\r\n";
+        let mut req = put.to_vec();
+        assert_eq!(request_progress(&req, 64), Partial);
+        req.extend_from_slice(b"{}");
+        assert_eq!(request_progress(&req, 64), Partial);
+        req.extend_from_slice(b"  ");
+        assert_eq!(request_progress(&req, 64), Complete);
+        assert_eq!(request_progress(&req, 46), TooLarge);
+
+        assert_eq!(request_progress(&[b'a'; 64], 64), TooLarge);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-499", 1000), Some((0, 499)));
+        assert_eq!(parse_range("bytes=500-", 1000), Some((500, 999)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=-2000", 1000), Some((0, 999)));
+        assert_eq!(parse_range("bytes=900-2000", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=5-1", 1000), None);
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+        assert_eq!(parse_range("bytes=0-", 0), None);
+    }
+}
@@ -2,16 +2,23 @@
 
 use std::{
     self,
+    collections::{HashMap, HashSet},
     convert::TryFrom,
-    io::{ErrorKind, Write},
+    fs::{self, File},
+    io::{ErrorKind, Read, Seek, SeekFrom, Take, Write},
     net::{SocketAddr, TcpStream},
-    os::unix::io::{FromRawFd, IntoRawFd, RawFd},
-    sync::mpsc::{Sender, TryRecvError},
+    os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, TryRecvError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 
 use arrayvec::ArrayVec;
+use fnv::FnvBuildHasher;
 use mio::{event::Event, net::TcpListener, unix::EventedFd, Events, Poll, PollOpt, Ready, Token};
-use mio_extras::channel::Receiver;
 use p25::{
     stats::{CodeStats, Stats},
     trunking::{
@@ -24,7 +31,7 @@ use p25::{
     },
 };
 use serde::Serialize;
-use serde_json;
+use serde_json::{self, Value};
 use uhttp_json_api::{HttpRequest, HttpResult};
 use uhttp_method::Method;
 use uhttp_response_header::HeaderLines;
@@ -33,18 +40,140 @@ use uhttp_status::StatusCode;
 use uhttp_uri::HttpResource;
 use uhttp_version::HttpVersion;
 
-use crate::{http, recv::RecvEvent, talkgroups::GroupCryptoMap};
+use crate::{
+    alerts::Alerts,
+    auth::AuthCommand,
+    band::BandMask,
+    broadcast::EventBroadcast,
+    calls::{self, Call, CallHistory, CallKind, CallQuery, PrivateCall},
+    consts::{AUDIO_SAMPLE_RATE, GIT_HASH, VERSION},
+    discrim::Discriminator,
+    eventlog::EventLog,
+    faults::{Fault, FaultCategory},
+    gaps::SampleDrop,
+    grpc::GrpcBridge,
+    health::{self, HealthCheck},
+    hooks::{HookEvent, Hooks},
+    http::{self, CorsPolicy, RequestProgress},
+    influx::InfluxExport,
+    latency::{LatencyStats, RetuneLatency},
+    lifetime::LifetimeStats,
+    noise::NoiseFloor,
+    profiles,
+    publish::Publisher,
+    queue::{EventedReceiver, QueueItem, QueueMetrics, QueueSender},
+    ratelimit::RateLimiter,
+    record,
+    recv::{FailedTune, RecvEvent, RecvState},
+    responses::Response,
+    rollup::{self, RollupQuery},
+    schedule::Window,
+    scripts::{Action, Scripts},
+    settings::{Settings, SettingsFiles},
+    signals::SighupWatch,
+    sites::SiteTable,
+    stages::StageMetrics,
+    talkgroups::{Decision, GroupCryptoMap},
+    tgstats::{self, TalkgroupStats},
+    tui::LiveView,
+    units::{self, Sighting, UnitQuery, UnitTable, UnitWatch},
+    www,
+};
+
+/// Bundled web dashboard.
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// Longest time to wait for events before checking for SIGHUP.
+const SIGNAL_CHECK: Duration = Duration::from_secs(1);
+/// Time between saves of the unit database and lifetime statistics.
+const UNITS_SAVE: Duration = Duration::from_secs(60);
+/// Time between summaries of recently active talkgroups.
+const STATS_SUMMARY: Duration = Duration::from_secs(60);
+/// Default limit on connections waiting to be handled.
+pub const MAX_PENDING: usize = 64;
+/// Longest time to wait for a client to send its complete request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest request (head and body) that can be handled.
+const REQUEST_BUF: usize = 8192;
+/// Most bytes of events that can wait to be sent to a subscriber before it's dropped.
+const MAX_BACKLOG: usize = 256 * 1024;
+
+/// Most call recordings that can be downloaded at once.
+const MAX_DOWNLOADS: usize = 16;
+
+/// Size of each piece of a call recording read for a download.
+const DOWNLOAD_CHUNK: u64 = 64 * 1024;
+
+/// Longest time a download can go without progress before it's dropped.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// Available routes.
 enum Route {
-    /// Subscribe to SSE stream.
-    Subscribe,
+    /// Get web dashboard.
+    Dashboard,
+    /// Subscribe to SSE stream, optionally receiving only the given event types.
+    Subscribe(Option<EventFilter>),
     /// Get/Set control channel frequency.
     CtlFreq,
     /// Get current known encrypted talkgroups.
     Encrypted,
+    /// Get cumulative statistics of this session and every session.
+    Stats,
     /// Reset stat counters.
     ResetStats,
+    /// Get inter-task queue counters.
+    Queues,
+    /// Get processing metrics of each pipeline stage.
+    Metrics,
+    /// Get retune latency statistics.
+    Latency,
+    /// Get the latest system status broadcasts.
+    System,
+    /// Get the neighboring sites advertised on the control channel.
+    Adjacent,
+    /// Get the active talkgroup selection profile.
+    Profile,
+    /// Get the current activity of the receiver.
+    Status,
+    /// Check whether the receiver is healthy.
+    Health,
+    /// Stop reading samples, closing the SDR if set.
+    Pause(bool),
+    /// Resume reading samples.
+    Resume,
+    /// Get/Set whether the live audio output is silenced.
+    Mute,
+    /// Reload settings and reopen the SDR.
+    Restart,
+    /// Get build version.
+    Version,
+    /// Get effective receiver configuration.
+    Config,
+    /// Reload settings from their files.
+    Reload,
+    /// Get a page of recent calls matching the given query.
+    Calls(CallQuery),
+    /// Get the recorded audio of the call with the given ID.
+    CallAudio(u64),
+    /// Get the known units matching the given query.
+    Units(UnitQuery),
+    /// Get the activity of talkgroups heard since the given time, if any.
+    TalkgroupStats(Option<u64>),
+    /// Get the hourly or daily activity of talkgroups matching the given query.
+    TalkgroupRollup(RollupQuery),
+    /// Listen to live decoded audio in the given format.
+    AudioStream(AudioFormat),
+    /// Get a static file at the given path.
+    Static(String),
+}
+
+/// Encoding of the live audio sent to a listener.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum AudioFormat {
+    /// Endless WAV stream, with the maximum length in its header.
+    Wav,
+    /// Bare big-endian samples, as declared by the `audio/L16` content type.
+    L16,
 }
 
 impl<'a> TryFrom<HttpResource<'a>> for Route {
@@ -52,18 +181,246 @@ impl<'a> TryFrom<HttpResource<'a>> for Route {
 
     fn try_from(r: HttpResource<'a>) -> HttpResult<Self> {
         match r.path {
-            "/subscribe" => Ok(Route::Subscribe),
+            "/" => Ok(Route::Dashboard),
+            "/subscribe" => Ok(Route::Subscribe(r.query.and_then(parse_filter))),
             "/ctlfreq" => Ok(Route::CtlFreq),
             "/encrypted" => Ok(Route::Encrypted),
+            "/stats" => Ok(Route::Stats),
             "/stats/reset" => Ok(Route::ResetStats),
-            _ => Err(StatusCode::NotFound),
+            "/queues" => Ok(Route::Queues),
+            "/metrics" => Ok(Route::Metrics),
+            "/latency" => Ok(Route::Latency),
+            "/system" => Ok(Route::System),
+            "/adjacent" => Ok(Route::Adjacent),
+            "/profile" => Ok(Route::Profile),
+            "/status" => Ok(Route::Status),
+            "/healthz" => Ok(Route::Health),
+            "/pause" => {
+                Ok(Route::Pause(r.query.map_or(false, |q| {
+                    q.split('&').any(|p| p == "release=true")
+                })))
+            }
+            "/resume" => Ok(Route::Resume),
+            "/mute" => Ok(Route::Mute),
+            "/restart" => Ok(Route::Restart),
+            "/version" => Ok(Route::Version),
+            "/config" => Ok(Route::Config),
+            "/reload" => Ok(Route::Reload),
+            "/audio.stream" => Ok(Route::AudioStream(AudioFormat::Wav)),
+            "/audio.pcm" => Ok(Route::AudioStream(AudioFormat::L16)),
+            "/calls" => calls::parse_query(r.query.unwrap_or(""))
+                .map(Route::Calls)
+                .map_err(|_| StatusCode::BadRequest),
+            "/talkgroups/stats" => tgstats::parse_query(r.query.unwrap_or(""))
+                .map(Route::TalkgroupStats)
+                .map_err(|_| StatusCode::BadRequest),
+            "/talkgroups/rollup" => rollup::parse_query(r.query.unwrap_or(""))
+                .map(Route::TalkgroupRollup)
+                .map_err(|_| StatusCode::BadRequest),
+            "/units" => units::parse_query(r.query.unwrap_or(""))
+                .map(Route::Units)
+                .map_err(|_| StatusCode::BadRequest),
+            path => match calls::parse_audio_path(path) {
+                Some(id) => Ok(Route::CallAudio(id)),
+                None => Ok(Route::Static(path.to_string())),
+            },
         }
     }
 }
 
+/// Set of event types sent to a subscriber.
+type EventFilter = HashSet<String, FnvBuildHasher>;
+
+/// Parse the event types from a query string like `events=curFreq,talkGroup`.
+fn parse_filter(query: &str) -> Option<EventFilter> {
+    query
+        .split('&')
+        .filter_map(|param| param.strip_prefix("events="))
+        .next()
+        .map(|events| {
+            events
+                .split(',')
+                .filter(|e| !e.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+}
+
+/// Connection subscribed to receive events.
+struct Streamer {
+    /// Stream to write events into.
+    stream: TcpStream,
+    /// Event types to send, or all types if `None`.
+    filter: Option<EventFilter>,
+    /// Serialized events not yet accepted by the client.
+    out: Vec<u8>,
+}
+
+impl Streamer {
+    /// Create a new `Streamer` writing events of the given types into the given
+    /// non-blocking stream.
+    fn new(stream: TcpStream, filter: Option<EventFilter>) -> Self {
+        Streamer {
+            stream,
+            filter,
+            out: vec![],
+        }
+    }
+
+    /// Write as much pending output as the client accepts without blocking.
+    ///
+    /// Fails if the client disconnected or has fallen too far behind.
+    fn flush(&mut self) -> Result<(), ()> {
+        while !self.out.is_empty() {
+            match self.stream.write(&self.out) {
+                Ok(0) => return Err(()),
+                Ok(n) => {
+                    self.out.drain(..n);
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return Err(()),
+            }
+        }
+
+        if self.lagging() {
+            Err(())
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    /// Queue the given bytes and write as much as the client accepts without blocking.
+    ///
+    /// Fails if the client disconnected or has fallen too far behind.
+    fn write_bytes(&mut self, buf: &[u8]) -> Result<(), ()> {
+        self.out.extend_from_slice(buf);
+        self.flush()
+    }
+
+    /// Check if the client has fallen too far behind to keep.
+    fn lagging(&self) -> bool {
+        self.out.len() > MAX_BACKLOG
+    }
+}
+
+/// Call recording being sent to a client as fast as it accepts it.
+struct Download {
+    /// Non-blocking stream to write the recording into.
+    stream: TcpStream,
+    /// Part of the recording not yet read.
+    file: Take<File>,
+    /// Data read from the recording but not yet accepted by the client.
+    out: Vec<u8>,
+    /// Time the client last accepted any data.
+    progress: Instant,
+}
+
+impl Download {
+    /// Write as much of the recording as the client accepts without blocking.
+    ///
+    /// Return whether the whole recording has been sent, or fail if the client
+    /// disconnected or the recording couldn't be read.
+    fn send(&mut self) -> Result<bool, ()> {
+        loop {
+            if self.out.is_empty() {
+                (&mut self.file)
+                    .take(DOWNLOAD_CHUNK)
+                    .read_to_end(&mut self.out)
+                    .map_err(|_| ())?;
+
+                if self.out.is_empty() {
+                    return Ok(true);
+                }
+            }
+
+            match self.stream.write(&self.out) {
+                Ok(0) => return Err(()),
+                Ok(n) => {
+                    self.out.drain(..n);
+                    self.progress = Instant::now();
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return Err(()),
+            }
+        }
+    }
+}
+
+/// Destination for serialized events.
+trait EventSink {
+    /// Check if the given event type should be sent.
+    fn wants(&self, event: &str) -> bool;
+
+    /// Write the given event.
+    fn write_event<T: Serialize>(&mut self, e: &SerdeEvent<T>) -> Result<(), ()>;
+}
+
+impl EventSink for Streamer {
+    fn wants(&self, event: &str) -> bool {
+        match self.filter {
+            Some(ref f) => f.contains(event),
+            None => true,
+        }
+    }
+
+    fn write_event<T: Serialize>(&mut self, e: &SerdeEvent<T>) -> Result<(), ()> {
+        e.write(&mut self.out)?;
+        self.flush()
+    }
+}
+
+impl EventSink for EventBroadcast {
+    fn wants(&self, _: &str) -> bool {
+        true
+    }
+
+    fn write_event<T: Serialize>(&mut self, e: &SerdeEvent<T>) -> Result<(), ()> {
+        self.send(e.event, &e.payload);
+        Ok(())
+    }
+}
+
+impl EventSink for Publisher {
+    fn wants(&self, _: &str) -> bool {
+        true
+    }
+
+    fn write_event<T: Serialize>(&mut self, e: &SerdeEvent<T>) -> Result<(), ()> {
+        self.send_event(e.event, &e.payload);
+        Ok(())
+    }
+}
+
+impl EventSink for GrpcBridge {
+    fn wants(&self, _: &str) -> bool {
+        true
+    }
+
+    fn write_event<T: Serialize>(&mut self, e: &SerdeEvent<T>) -> Result<(), ()> {
+        self.send_event(e.event, &e.payload);
+        Ok(())
+    }
+}
+
+impl EventSink for EventLog {
+    fn wants(&self, _: &str) -> bool {
+        true
+    }
+
+    fn write_event<T: Serialize>(&mut self, e: &SerdeEvent<T>) -> Result<(), ()> {
+        self.write(e.event, &e.payload).map_err(|err| {
+            warn!("disabling event log: {}", err);
+        })
+    }
+}
+
 const CONNS: usize = 1 << 31;
 const EVENTS: usize = 1 << 30;
 const REQUEST: usize = 1 << 29;
+const DOWNLOAD: usize = 1 << 28;
 
 /// Allow 24 bits for file descriptors
 ///
@@ -79,20 +436,23 @@ const TAG_MASK: usize = !(FD_MASK as usize);
 /// The complications around packing this type into 32-bit `Token`s is to support
 /// platforms with 32-bit `usize`.
 pub enum HubToken {
-    /// Socket connection.
-    Conns,
+    /// Connection on the listening socket with contained index.
+    Conns(usize),
     /// Channel events.
     Events,
     /// Request stream with contained file descriptor.
     Request(RawFd),
+    /// Download stream with contained file descriptor.
+    Download(RawFd),
 }
 
 impl From<HubToken> for Token {
     fn from(tok: HubToken) -> Self {
         Token(match tok {
-            HubToken::Conns => CONNS,
+            HubToken::Conns(idx) => CONNS | idx,
             HubToken::Events => EVENTS,
             HubToken::Request(fd) => REQUEST | fd as usize,
+            HubToken::Download(fd) => DOWNLOAD | fd as usize,
         })
     }
 }
@@ -100,54 +460,158 @@ impl From<HubToken> for Token {
 impl From<Token> for HubToken {
     fn from(tok: Token) -> Self {
         match tok.0 & TAG_MASK {
-            CONNS => HubToken::Conns,
+            CONNS => HubToken::Conns(tok.0 & FD_MASK as usize),
             EVENTS => HubToken::Events,
             REQUEST => HubToken::Request(tok.0 as RawFd & FD_MASK),
+            DOWNLOAD => HubToken::Download(tok.0 as RawFd & FD_MASK),
             _ => panic!("unknown token"),
         }
     }
 }
 
 impl HubToken {
+    pub fn for_conns(idx: usize) -> Self {
+        assert!(idx & !(FD_MASK as usize) == 0);
+        HubToken::Conns(idx)
+    }
+
     pub fn for_request(fd: RawFd) -> Self {
         assert!(fd & !FD_MASK == 0);
         HubToken::Request(fd)
     }
+
+    pub fn for_download(fd: RawFd) -> Self {
+        assert!(fd & !FD_MASK == 0);
+        HubToken::Download(fd)
+    }
 }
 
 /// Handles HTTP requests and broadcasts events to listening subscribers.
 pub struct HubTask {
     /// Tracks pertinent state of other tasks.
     state: State,
-    /// Main socket for HTTP connections.
-    socket: TcpListener,
+    /// Listening sockets for HTTP connections, one for each bind address.
+    sockets: Vec<TcpListener>,
     /// Async event loop.
     events: Poll,
+    /// Time each accepted connection waiting for its complete request was accepted.
+    pending: HashMap<RawFd, Instant, FnvBuildHasher>,
+    /// Most connections that can wait to be handled at once.
+    max_pending: usize,
+    /// Limits requests from each client, if enabled.
+    limiter: Option<RateLimiter>,
+    /// FM discriminator the demodulator uses.
+    discriminator: Discriminator,
     /// Streams subscribed to receive events.
-    streamers: ArrayVec<[TcpStream; 4]>,
+    streamers: ArrayVec<[Streamer; 4]>,
+    /// Total number of subscribers dropped for falling behind.
+    evictions: usize,
+    /// Faults hit by the hub itself, waiting to be streamed.
+    faults: Vec<Fault>,
+    /// Streams receiving live audio, with the format of each.
+    listeners: ArrayVec<[(Streamer, AudioFormat); 4]>,
+    /// Call recordings being downloaded, by the file descriptor of their stream.
+    downloads: HashMap<RawFd, Download, FnvBuildHasher>,
     /// Channel for receiving events.
-    chan: Receiver<HubEvent>,
+    chan: EventedReceiver<HubEvent>,
     /// Channel for communication with RecvTask.
-    recv: Sender<RecvEvent>,
+    recv: QueueSender<RecvEvent>,
+    /// Counters for each inter-task queue.
+    queues: Vec<Arc<QueueMetrics>>,
+    /// Processing metrics of each pipeline stage.
+    stages: Vec<Arc<StageMetrics>>,
+    /// Directory of static files to serve, if any.
+    www: Option<PathBuf>,
+    /// Cross-origin access policy for responses.
+    cors: CorsPolicy,
+    /// Frequencies allowed to be set as the control channel.
+    band: BandMask,
+    /// Effective receiver configuration.
+    config: serde_json::Value,
+    /// Recently followed calls.
+    calls: CallHistory,
+    /// Records every event, if enabled.
+    eventlog: Option<EventLog>,
+    /// Sends every event as a datagram, if enabled.
+    broadcast: Option<EventBroadcast>,
+    /// Publishes every event and decoded audio over ZeroMQ, if enabled.
+    publisher: Option<Publisher>,
+    /// Feeds the gRPC service, if enabled.
+    grpc: Option<GrpcBridge>,
+    /// Terminal view to keep updated, if enabled.
+    view: Option<Arc<Mutex<LiveView>>>,
+    /// Files that settings are reloaded from.
+    files: SettingsFiles,
+    /// Currently applied settings.
+    settings: Settings,
+    /// Detects requests to reload settings.
+    sighup: SighupWatch,
+    /// Whether to log a summary of each call.
+    call_log: bool,
+    /// Whether to log each unit activity.
+    activity_log: bool,
+    /// Commands run on events.
+    hooks: Hooks,
+    /// Scripts run on events.
+    scripts: Scripts,
+    /// Sends alerts on events.
+    alerts: Alerts,
+    /// Reports activity by watched units.
+    units: UnitWatch,
+    /// Registration and affiliation of every unit seen.
+    unit_table: UnitTable,
+    /// File the unit table is saved to, if any.
+    unit_db: Option<PathBuf>,
+    /// Activity of each talkgroup heard.
+    tgstats: TalkgroupStats,
+    /// Totals of this session and previous sessions.
+    lifetime: LifetimeStats,
+    /// Liveness checks.
+    health: HealthCheck,
+    /// File the lifetime statistics are saved to, if any.
+    stats_db: Option<PathBuf>,
+    /// Neighboring sites advertised on the current control channel.
+    sites: SiteTable,
+    /// Time taken to decode voice after following each call.
+    latency: LatencyStats,
+    /// Noise floor estimated from idle traffic channels.
+    noise: NoiseFloor,
+    /// Exports periodic measurements, if enabled.
+    influx: Option<InfluxExport>,
+    /// Name of the active talkgroup selection profile, if any.
+    profile: Option<String>,
+    /// Whether recording is enabled by the record schedule.
+    capture: bool,
+    /// Whether the live audio output is silenced.
+    muted: bool,
 }
 
 impl HubTask {
-    /// Create a new `HubTask` to communicate on the given channels and bind to the given
-    /// socket address.
+    /// Create a new `HubTask` to communicate on the given channels and bind to each of
+    /// the given socket addresses, reporting the given queue counters.
     pub fn new(
-        chan: Receiver<HubEvent>,
-        recv: Sender<RecvEvent>,
-        addr: &SocketAddr,
+        chan: EventedReceiver<HubEvent>,
+        recv: QueueSender<RecvEvent>,
+        addrs: &[SocketAddr],
+        queues: Vec<Arc<QueueMetrics>>,
     ) -> std::io::Result<Self> {
-        let socket = TcpListener::bind(addr)?;
         let events = Poll::new()?;
+        let mut sockets = Vec::with_capacity(addrs.len());
 
-        events.register(
-            &socket,
-            HubToken::Conns.into(),
-            Ready::readable(),
-            PollOpt::edge(),
-        )?;
+        for (idx, addr) in addrs.iter().enumerate() {
+            let socket = TcpListener::bind(addr).map_err(|e| {
+                std::io::Error::new(e.kind(), format!("unable to bind {}: {}", addr, e))
+            })?;
+
+            events.register(
+                &socket,
+                HubToken::for_conns(idx).into(),
+                Ready::readable(),
+                PollOpt::edge(),
+            )?;
+
+            sockets.push(socket);
+        }
         events.register(
             &chan,
             HubToken::Events.into(),
@@ -157,62 +621,440 @@ impl HubTask {
 
         Ok(HubTask {
             state: State::default(),
-            socket,
+            sockets,
             events,
+            pending: HashMap::default(),
+            max_pending: MAX_PENDING,
+            limiter: None,
+            discriminator: Discriminator::Polar,
             streamers: ArrayVec::new(),
+            evictions: 0,
+            faults: Vec::new(),
+            listeners: ArrayVec::new(),
+            downloads: HashMap::default(),
             chan,
             recv,
+            queues,
+            stages: vec![],
+            www: None,
+            cors: CorsPolicy::Origin("*".to_string()),
+            band: BandMask::default(),
+            config: json!({}),
+            calls: CallHistory::default(),
+            eventlog: None,
+            broadcast: None,
+            publisher: None,
+            grpc: None,
+            view: None,
+            files: SettingsFiles::default(),
+            settings: Settings::default(),
+            sighup: SighupWatch::default(),
+            call_log: false,
+            activity_log: false,
+            hooks: Hooks::default(),
+            scripts: Scripts::default(),
+            alerts: Alerts::default(),
+            units: UnitWatch::default(),
+            unit_table: UnitTable::default(),
+            unit_db: None,
+            tgstats: TalkgroupStats::default(),
+            lifetime: LifetimeStats::default(),
+            health: HealthCheck::new(health::DEFAULT_MIN_RATE),
+            stats_db: None,
+            sites: SiteTable::default(),
+            latency: LatencyStats::default(),
+            noise: NoiseFloor::default(),
+            influx: None,
+            profile: None,
+            capture: true,
+            muted: false,
         })
     }
 
+    /// Apply the given cross-origin access policy to responses.
+    pub fn cors(mut self, cors: CorsPolicy) -> Self {
+        self.cors = cors;
+        self
+    }
+
+    /// Reject control channel frequencies outside the given mask.
+    pub fn band(mut self, band: BandMask) -> Self {
+        self.band = band;
+        self
+    }
+
+    /// Report the given receiver configuration to clients.
+    pub fn config(mut self, config: serde_json::Value) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Also append every event to the given log.
+    pub fn eventlog(mut self, log: EventLog) -> Self {
+        self.eventlog = Some(log);
+        self
+    }
+
+    /// Also send every event with the given broadcaster.
+    pub fn broadcast(mut self, b: EventBroadcast) -> Self {
+        self.broadcast = Some(b);
+        self
+    }
+
+    /// Also publish every event and decoded audio with the given publisher.
+    pub fn publisher(mut self, p: Publisher) -> Self {
+        self.publisher = Some(p);
+        self
+    }
+
+    /// Also feed every event to the gRPC service through the given bridge.
+    pub fn grpc(mut self, g: GrpcBridge) -> Self {
+        self.grpc = Some(g);
+        self
+    }
+
+    /// Also keep the given terminal view updated with every event.
+    pub fn view(mut self, view: Arc<Mutex<LiveView>>) -> Self {
+        self.view = Some(view);
+        self
+    }
+
+    /// Use the given settings, which were loaded from the given files.
+    pub fn settings(mut self, files: SettingsFiles, settings: Settings) -> Self {
+        self.files = files;
+        self.alerts = Alerts::new(settings.alerts.clone());
+        self.settings = settings;
+        self
+    }
+
+    /// Log a one-line summary of each call when it ends.
+    pub fn call_log(mut self) -> Self {
+        self.call_log = true;
+        self
+    }
+
+    /// Log each unit activity seen in trunking packets and link control words.
+    pub fn activity_log(mut self) -> Self {
+        self.activity_log = true;
+        self
+    }
+
+    /// Run the given commands on events.
+    pub fn hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Run the given scripts on events, reloading them with the settings.
+    pub fn scripts(mut self, scripts: Scripts) -> Self {
+        self.scripts = scripts;
+        self
+    }
+
+    /// Report activity by the given units.
+    pub fn follow_units(mut self, units: &[u32]) -> Self {
+        self.units = UnitWatch::new(units);
+        self
+    }
+
+    /// Start with the given unit table, periodically saving it to the given file.
+    pub fn unit_db(mut self, path: PathBuf, table: UnitTable) -> Self {
+        self.unit_db = Some(path);
+        self.unit_table = table;
+        self
+    }
+
+    /// Start with the given lifetime statistics, periodically saving them to the given
+    /// file.
+    pub fn stats_db(mut self, path: PathBuf, stats: LifetimeStats) -> Self {
+        self.stats_db = Some(path);
+        self.lifetime = stats;
+        self
+    }
+
+    /// Periodically export measurements with the given exporter.
+    pub fn influx(mut self, influx: InfluxExport) -> Self {
+        self.influx = Some(influx);
+        self
+    }
+
+    /// Report the processing metrics of the given pipeline stages.
+    pub fn stages(mut self, stages: Vec<Arc<StageMetrics>>) -> Self {
+        self.stages = stages;
+        self
+    }
+
+    /// Allow at most the given number of connections to wait to be handled at once.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_pending = max;
+        self
+    }
+
+    /// Report the given FM discriminator as the one in use.
+    pub fn discriminator(mut self, kind: Discriminator) -> Self {
+        self.discriminator = kind;
+        self
+    }
+
+    /// Report unhealthy while trunking packets are decoded on the control channel at
+    /// less than the given rate (packets/sec).
+    pub fn health_rate(mut self, rate: f32) -> Self {
+        self.health = HealthCheck::new(rate);
+        self
+    }
+
+    /// Allow each client IP at most the given number of requests per second.
+    pub fn rate_limit(mut self, rate: f32) -> Self {
+        self.limiter = Some(RateLimiter::new(rate));
+        self
+    }
+
+    /// Serve static files from the given directory for paths not used by the API.
+    pub fn serve_static(mut self, dir: PathBuf) -> Self {
+        self.www = Some(dir);
+        self
+    }
+
     /// Start handling HTTP requests and events, blocking the current thread.
     pub fn run(&mut self) {
         let mut events = Events::with_capacity(32);
+        let mut saved = Instant::now();
+        let mut summarized = (Instant::now(), calls::now());
 
         loop {
             self.events
-                .poll(&mut events, None)
+                .poll(&mut events, Some(SIGNAL_CHECK))
                 .expect("unable to poll events");
 
             for event in events.iter() {
                 self.handle_poll(event);
             }
+
+            if self.sighup.received() {
+                if let Err(e) = self.reload() {
+                    warn!("keeping current settings: {:#}", e);
+                }
+            }
+
+            self.expire_pending();
+            self.expire_downloads();
+            self.flush_streamers();
+            self.stream_faults();
+            self.apply_profile(false);
+            self.apply_schedule();
+
+            // The receiver only reports liveness to systemd as samples arrive.
+            if self.state.recv == RecvState::Paused {
+                self.recv
+                    .send(RecvEvent::Heartbeat)
+                    .expect("unable to send heartbeat");
+            }
+
+            if let Some(ref mut influx) = self.influx {
+                influx.send_due(Instant::now(), calls::now());
+            }
+
+            if saved.elapsed() >= UNITS_SAVE {
+                saved = Instant::now();
+                self.save_units();
+                self.save_stats();
+            }
+
+            if summarized.0.elapsed() >= STATS_SUMMARY {
+                let since = summarized.1;
+                summarized = (Instant::now(), calls::now());
+                self.handle_event(HubEvent::TalkgroupSummary(since));
+            }
         }
     }
 
     /// Handle the given event.
     fn handle_poll(&mut self, e: Event) {
         match e.token().into() {
-            HubToken::Conns => self.handle_conns().expect("unable to handle connection"),
+            HubToken::Conns(idx) => self.handle_conns(idx),
             HubToken::Events => self.handle_chan().expect("unable to handle channel event"),
-            HubToken::Request(fd) => {
-                let stream = unsafe { TcpStream::from_raw_fd(fd.into()) };
+            HubToken::Request(fd) => self.handle_pending(fd),
+            HubToken::Download(fd) => self.handle_download(fd),
+        }
+    }
 
-                self.events
-                    .deregister(&EventedFd(&fd))
-                    .expect("unable to deregister stream");
+    /// Handle newly received data on the pending connection with the given file
+    /// descriptor, handling the request once it's complete.
+    ///
+    /// The data is only peeked at until then, so it's left for the request parser and
+    /// reading it never blocks.
+    fn handle_pending(&mut self, fd: RawFd) {
+        let mut stream = unsafe { TcpStream::from_raw_fd(fd) };
+        let mut buf = [0; REQUEST_BUF];
+
+        let progress = match stream.peek(&mut buf[..]) {
+            // The client closed the connection before finishing its request.
+            Ok(0) => None,
+            Ok(len) => Some(http::request_progress(&buf[..len], REQUEST_BUF)),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => Some(RequestProgress::Partial),
+            Err(_) => None,
+        };
+
+        if let Some(RequestProgress::Partial) = progress {
+            // Keep the connection open until more of the request arrives.
+            stream.into_raw_fd();
+            return;
+        }
 
-                self.handle_stream(stream);
+        self.close_pending(fd);
+
+        match progress {
+            Some(RequestProgress::Complete) => {
+                if stream.set_nonblocking(false).is_ok() {
+                    self.handle_stream(stream);
+                }
+            }
+            Some(RequestProgress::TooLarge) => {
+                http::send_status(&mut stream, &self.cors, StatusCode::PayloadTooLarge).ok();
             }
+            _ => {}
+        }
+    }
+
+    /// Stop waiting for a request on the pending connection with the given file
+    /// descriptor.
+    fn close_pending(&mut self, fd: RawFd) {
+        self.events
+            .deregister(&EventedFd(&fd))
+            .expect("unable to deregister stream");
+
+        self.pending.remove(&fd);
+    }
+
+    /// Close pending connections that haven't sent a complete request in time.
+    fn expire_pending(&mut self) {
+        let expired = self
+            .pending
+            .iter()
+            .filter(|&(_, accepted)| accepted.elapsed() >= READ_TIMEOUT)
+            .map(|(&fd, _)| fd)
+            .collect::<Vec<_>>();
+
+        for fd in expired {
+            self.close_pending(fd);
+
+            let mut stream = unsafe { TcpStream::from_raw_fd(fd) };
+            http::send_status(&mut stream, &self.cors, StatusCode::RequestTimeout).ok();
+        }
+    }
+
+    /// Start sending the given call recording into the given stream, which has been sent
+    /// the response head.
+    fn start_download(&mut self, stream: TcpStream, file: Take<File>) {
+        // The recording is sent as the client accepts it, so a slow client can't stall
+        // the hub.
+        if stream.set_nonblocking(true).is_err() {
+            return;
+        }
+
+        let fd = stream.as_raw_fd();
+        let tok = HubToken::for_download(fd);
+
+        self.events
+            .register(
+                &EventedFd(&fd),
+                tok.into(),
+                Ready::writable(),
+                PollOpt::edge(),
+            )
+            .expect("unable to register stream");
+
+        self.downloads.insert(
+            fd,
+            Download {
+                stream,
+                file,
+                out: vec![],
+                progress: Instant::now(),
+            },
+        );
+
+        self.handle_download(fd);
+    }
+
+    /// Send more of the download on the stream with the given file descriptor, closing
+    /// it once complete or failed.
+    fn handle_download(&mut self, fd: RawFd) {
+        let done = match self.downloads.get_mut(&fd) {
+            Some(d) => !matches!(d.send(), Ok(false)),
+            None => return,
+        };
+
+        if done {
+            self.close_download(fd);
+        }
+    }
+
+    /// Stop sending the download on the stream with the given file descriptor.
+    fn close_download(&mut self, fd: RawFd) {
+        self.events
+            .deregister(&EventedFd(&fd))
+            .expect("unable to deregister stream");
+
+        self.downloads.remove(&fd);
+    }
+
+    /// Close downloads whose clients haven't accepted any data in time.
+    fn expire_downloads(&mut self) {
+        let expired = self
+            .downloads
+            .iter()
+            .filter(|&(_, d)| d.progress.elapsed() >= DOWNLOAD_TIMEOUT)
+            .map(|(&fd, _)| fd)
+            .collect::<Vec<_>>();
+
+        for fd in expired {
+            self.close_download(fd);
         }
     }
 
-    /// Handle pending HTTP connections.
-    fn handle_conns(&mut self) -> Result<(), ()> {
+    /// Handle pending HTTP connections on the listening socket with the given index.
+    fn handle_conns(&mut self, idx: usize) {
         loop {
-            let (stream, _) = match self.socket.accept_std() {
+            let (mut stream, addr) = match self.sockets[idx].accept_std() {
                 Ok(x) => x,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                // Usually out of file descriptors, which clears as connections close.
                 Err(e) => {
-                    return if e.kind() == ErrorKind::WouldBlock {
-                        Ok(())
-                    }
-                    else {
-                        Err(())
-                    }
+                    self.report(Fault::error(
+                        FaultCategory::Http,
+                        format!("unable to accept connection: {}", e),
+                    ));
+
+                    return;
                 }
             };
 
+            if self.pending.len() >= self.max_pending {
+                debug!("rejecting connection from {}: too many connections", addr);
+                http::send_status(&mut stream, &self.cors, StatusCode::TooManyRequests).ok();
+                continue;
+            }
+
+            let limited = match self.limiter {
+                Some(ref mut l) => !l.allow(addr.ip(), Instant::now()),
+                None => false,
+            };
+
+            if limited {
+                debug!("rejecting connection from {}: rate limited", addr);
+                http::send_status(&mut stream, &self.cors, StatusCode::TooManyRequests).ok();
+                continue;
+            }
+
+            // The request is received as it arrives, so a slow client can't stall the hub.
+            if stream.set_nonblocking(true).is_err() {
+                continue;
+            }
+
             let fd = stream.into_raw_fd();
+            self.pending.insert(fd, Instant::now());
+
             let tok = HubToken::for_request(fd);
             let event = EventedFd(&fd);
 
@@ -226,6 +1068,7 @@ impl HubTask {
     fn handle_chan(&mut self) -> Result<(), ()> {
         loop {
             match self.chan.try_recv() {
+                Ok(HubEvent::Control(c)) => self.handle_control(c),
                 Ok(e) => self.handle_event(e),
                 Err(TryRecvError::Disconnected) => return Err(()),
                 Err(TryRecvError::Empty) => return Ok(()),
@@ -233,14 +1076,163 @@ impl HubTask {
         }
     }
 
+    /// Handle the given control action requested by the gRPC service.
+    fn handle_control(&mut self, c: HubControl) {
+        match c {
+            HubControl::Mute(muted) => {
+                if self.set_muted(muted).is_err() {
+                    warn!("unable to mute live audio: receiver stopped");
+                }
+            }
+            HubControl::Reload(reply) => {
+                let res = self.reload().map_err(|e| {
+                    warn!("keeping current settings: {:#}", e);
+                    format!("{:#}", e)
+                });
+
+                // The request may have been cancelled in the meantime.
+                reply.send(res).ok();
+            }
+        }
+    }
+
+    /// Silence or restore the live audio output, failing if the receiver has stopped.
+    fn set_muted(&mut self, muted: bool) -> Result<(), ()> {
+        self.recv.send(RecvEvent::Mute(muted)).map_err(|_| ())?;
+
+        if muted != self.muted {
+            info!("{} live audio", if muted { "muted" } else { "unmuted" });
+
+            self.muted = muted;
+            self.handle_event(HubEvent::MuteChange(muted));
+        }
+
+        Ok(())
+    }
+
     /// Handle the given channel event.
     fn handle_event(&mut self, e: HubEvent) {
-        if let HubEvent::State(sm) = e {
-            self.state.update(sm);
+        if let HubEvent::AudioFrame(ref buf) = e {
+            self.stream_audio(buf);
+            return;
+        }
+
+        if let Some(ref view) = self.view {
+            view.lock().unwrap().update(&e);
+        }
+
+        if let Some(ref mut influx) = self.influx {
+            influx.record(&e);
+        }
+
+        match e {
+            HubEvent::State(sm) => {
+                if let StateEvent::UpdateEncrypted(tg, alg) = sm {
+                    self.trigger(
+                        HookEvent::Encrypted,
+                        &json!({
+                            "talkGroup": tg,
+                            "alg": format!("{:?}", alg),
+                        }),
+                    );
+                }
+
+                if let StateEvent::UpdateCtlFreq(f) = sm {
+                    // Neighbors of another site no longer apply.
+                    if f != self.state.ctlfreq {
+                        self.sites.clear();
+                    }
+                }
+
+                self.state.update(sm);
+            }
+            HubEvent::UpdateCurFreq(f) => self.state.curfreq = f,
+            HubEvent::RecvStateChange(s) => {
+                self.state.recv = s;
+                self.health.record_state(s);
+            }
+            HubEvent::UpdateSignalPower(p) => {
+                self.health.record_samples();
+
+                // The control channel never goes idle, so only traffic channels show the
+                // floor.
+                if self.state.curfreq != self.state.ctlfreq {
+                    self.noise.record(p);
+
+                    if let Some(snr) = self.noise.snr(p) {
+                        self.calls.record_snr(snr);
+                    }
+                }
+            }
+            HubEvent::UpdateTalkGroup(tg) => {
+                let prev = self.calls.begin(tg, self.state.curfreq);
+                self.end_call(prev);
+
+                if let Some(call) = self.calls.following().cloned() {
+                    self.run_call_hooks(HookEvent::CallStart, &call);
+                }
+            }
+            HubEvent::BeginPrivateCall(ref p) => {
+                let prev = self.calls.begin_private(p);
+                self.end_call(prev);
+
+                if let Some(call) = self.calls.following().cloned() {
+                    self.run_call_hooks(HookEvent::CallStart, &call);
+                }
+            }
+            HubEvent::LinkControl(lc) => {
+                self.state.record_lc(lc);
+
+                if let Some(LinkControlOpcode::AdjacentSite) = lc.opcode() {
+                    self.record_adjacent(fields::AdjacentSite::new(lc.payload()));
+                }
+
+                if let Some(LinkControlOpcode::GroupVoiceTraffic) = lc.opcode() {
+                    let lc = control::GroupVoiceTraffic::new(lc);
+                    self.calls.update_src_unit(lc.src_unit());
+
+                    if lc.opts().emergency() {
+                        if let Some(call) = self.calls.mark_emergency() {
+                            warn!("emergency on talkgroup {}", call.talkgroup());
+                            self.run_call_hooks(HookEvent::Emergency, &call);
+                        }
+                    }
+                }
+            }
+            HubEvent::EndTransmission => {
+                let prev = self.calls.end();
+                self.end_call(prev);
+            }
+            HubEvent::ControlLost => self.trigger(
+                HookEvent::ControlLost,
+                &json!({
+                    "freq": self.state.ctlfreq,
+                }),
+            ),
+            HubEvent::TrunkingControl(tsbk) => {
+                self.tgstats.record_tsbk(tsbk, calls::now());
+                self.state.record_tsbk(tsbk);
+                self.health.record_packet();
+
+                if let Some(TsbkOpcode::AdjacentSite) = tsbk.opcode() {
+                    self.record_adjacent(fields::AdjacentSite::new(tsbk.payload()));
+                }
+            }
+            HubEvent::CallRecorded(tg, ref path) => self.attach_recording(tg, path),
+            HubEvent::RetuneLatency(ref l) => self.latency.record(l),
+            HubEvent::AudioLatency(ms) => self.latency.record_audio(ms),
+            HubEvent::UpdateStats(ref s) => self.lifetime.record_decoding(s),
+            HubEvent::UnitSeen(s) => {
+                info!("watched {}", s);
+
+                let payload = serde_json::to_value(&s).expect("unable to serialize sighting");
+                self.trigger(HookEvent::UnitSeen, &payload);
+            }
+            _ => {}
         }
 
         // Holds streamers that are still alive.
-        let mut keep = ArrayVec::<[TcpStream; 4]>::new();
+        let mut keep = ArrayVec::<[Streamer; 4]>::new();
 
         loop {
             let mut s = match self.streamers.pop() {
@@ -248,12 +1240,354 @@ impl HubTask {
                 None => break,
             };
 
-            if let Ok(()) = self.stream_event(&mut s, &e) {
-                keep.push(s);
+            match self.stream_event(&mut s, &e) {
+                Ok(()) => keep.push(s),
+                Err(()) => self.drop_streamer(s, "subscriber"),
             }
         }
 
         self.streamers = keep;
+
+        if let Some(mut log) = self.eventlog.take() {
+            if let Ok(()) = self.stream_event(&mut log, &e) {
+                self.eventlog = Some(log);
+            }
+        }
+
+        if let Some(mut b) = self.broadcast.take() {
+            self.stream_event(&mut b, &e).ok();
+            self.broadcast = Some(b);
+        }
+
+        if let Some(mut p) = self.publisher.take() {
+            self.stream_event(&mut p, &e).ok();
+            self.publisher = Some(p);
+        }
+
+        if let Some(mut g) = self.grpc.take() {
+            g.record(&e);
+            self.stream_event(&mut g, &e).ok();
+            self.grpc = Some(g);
+        }
+
+        self.track_units(&e);
+    }
+
+    /// Send pending output to each subscriber and audio listener, dropping disconnected
+    /// and lagging clients.
+    fn flush_streamers(&mut self) {
+        let mut keep = ArrayVec::<[Streamer; 4]>::new();
+
+        while let Some(mut s) = self.streamers.pop() {
+            match s.flush() {
+                Ok(()) => keep.push(s),
+                Err(()) => self.drop_streamer(s, "subscriber"),
+            }
+        }
+
+        self.streamers = keep;
+
+        let mut keep = ArrayVec::<[(Streamer, AudioFormat); 4]>::new();
+
+        while let Some((mut s, format)) = self.listeners.pop() {
+            match s.flush() {
+                Ok(()) => keep.push((s, format)),
+                Err(()) => self.drop_streamer(s, "audio listener"),
+            }
+        }
+
+        self.listeners = keep;
+    }
+
+    /// Drop the given failed client of the given kind, recording if it was evicted for
+    /// falling behind.
+    fn drop_streamer(&mut self, s: Streamer, kind: &str) {
+        if !s.lagging() {
+            return;
+        }
+
+        self.evictions += 1;
+
+        let msg = match s.stream.peer_addr() {
+            Ok(addr) => format!("evicting {} {}: {} bytes behind", kind, addr, s.out.len()),
+            Err(_) => format!("evicting {}: {} bytes behind", kind, s.out.len()),
+        };
+
+        self.report(Fault::warning(FaultCategory::Http, msg));
+    }
+
+    /// Log the given fault hit by the hub, streaming it once current events are sent.
+    fn report(&mut self, fault: Fault) {
+        fault.log();
+        self.faults.push(fault);
+    }
+
+    /// Stream the faults hit by the hub since the last call.
+    fn stream_faults(&mut self) {
+        // Streaming may evict more subscribers.
+        while !self.faults.is_empty() {
+            for fault in std::mem::take(&mut self.faults) {
+                self.handle_event(HubEvent::Fault(fault));
+            }
+        }
+    }
+
+    /// Save the unit table, if enabled, disabling saves after a failure.
+    fn save_units(&mut self) {
+        let res = match self.unit_db {
+            Some(ref path) => self.unit_table.save(path),
+            None => return,
+        };
+
+        if let Err(e) = res {
+            warn!("disabling unit database: {:#}", e);
+            self.unit_db = None;
+        }
+    }
+
+    /// Save the lifetime statistics, if enabled, disabling saves after a failure.
+    fn save_stats(&mut self) {
+        let res = match self.stats_db {
+            Some(ref path) => self.lifetime.save(path),
+            None => return,
+        };
+
+        if let Err(e) = res {
+            warn!("disabling statistics database: {:#}", e);
+            self.stats_db = None;
+        }
+    }
+
+    /// Record any unit activity in the given event, reporting activity by watched units.
+    fn track_units(&mut self, e: &HubEvent) {
+        let sighting = match *e {
+            HubEvent::TrunkingControl(tsbk) => Sighting::from_tsbk(tsbk),
+            HubEvent::LinkControl(lc) => Sighting::from_lc(lc),
+            _ => None,
+        };
+
+        if let Some(s) = sighting {
+            self.unit_table.record(&s, calls::now());
+
+            if self.activity_log {
+                info!(target: "activity", "{}", s);
+            }
+
+            if self.units.report(s, Instant::now()) {
+                self.handle_event(HubEvent::UnitSeen(s));
+            }
+        }
+    }
+
+    /// Reload settings from their files and apply them, returning a description of each
+    /// change.
+    fn reload(&mut self) -> anyhow::Result<Vec<String>> {
+        let settings = self.files.load()?;
+        let scripts = self.scripts.reload()?;
+        let mut changes = settings.changes(&self.settings);
+
+        if scripts.count() > 0 || self.scripts.count() > 0 {
+            changes.push(format!("reloaded {} scripts", scripts.count()));
+        }
+
+        if changes.is_empty() {
+            info!("reloaded settings: no changes");
+        }
+
+        for change in &changes {
+            info!("reloaded settings: {}", change);
+        }
+
+        if let Some(ref view) = self.view {
+            view.lock().unwrap().set_aliases(settings.aliases.clone());
+        }
+
+        if settings.alerts != self.settings.alerts {
+            self.alerts = Alerts::new(settings.alerts.clone());
+        }
+
+        self.settings = settings;
+        self.scripts = scripts;
+        self.apply_profile(true);
+        self.handle_event(HubEvent::ConfigReload(changes.clone()));
+
+        Ok(changes)
+    }
+
+    /// Apply the talkgroup selection settings of the currently scheduled profile, or the
+    /// top-level settings if none, when the active profile changes or if `force` is
+    /// true.
+    fn apply_profile(&mut self, force: bool) {
+        let active = profiles::active(&self.settings.profiles);
+        let name = active.map(|p| p.name.clone());
+
+        if !force && name == self.profile {
+            return;
+        }
+
+        let talkgroups = active.map_or(&self.settings.talkgroups, |p| &p.talkgroups);
+
+        self.recv
+            .send(RecvEvent::Configure(talkgroups.clone()))
+            .expect("unable to configure talkgroups");
+
+        if name == self.profile {
+            return;
+        }
+
+        match name {
+            Some(ref name) => info!("switched to profile {}", name),
+            None => info!("switched to top-level talkgroup settings"),
+        }
+
+        self.profile = name.clone();
+        self.handle_event(HubEvent::ProfileChange(name));
+    }
+
+    /// Enable or disable recording when entering or leaving the record schedule.
+    fn apply_schedule(&mut self) {
+        let windows = &self.settings.record_schedule;
+        let capture = windows.is_empty() || windows.iter().any(Window::active);
+
+        if capture == self.capture {
+            return;
+        }
+
+        if capture {
+            info!("entered record schedule, resuming recording");
+        }
+        else {
+            info!("left record schedule, pausing recording");
+        }
+
+        self.recv
+            .send(RecvEvent::Capture(capture))
+            .expect("unable to update recording");
+
+        self.capture = capture;
+        self.handle_event(HubEvent::CaptureChange(capture));
+    }
+
+    /// Attach the given recording to its call and embed the call details in the file.
+    fn attach_recording(&mut self, tg: u16, path: &Path) {
+        let call = match self.calls.attach_recording(tg, path.to_path_buf()) {
+            Some(call) => call,
+            None => return,
+        };
+
+        let alias = self.settings.aliases.get(call.talkgroup());
+
+        let title = match (call.kind(), alias) {
+            (CallKind::Group, Some(alias)) => alias.to_string(),
+            (CallKind::Group, None) => format!("TG {}", call.talkgroup()),
+            (kind, _) => format!("{} call", kind.name()),
+        };
+
+        let comment = json!({
+            "call": call,
+            "alias": alias,
+            "rfss": &self.state.rfss,
+            "network": &self.state.network,
+        });
+
+        if let Err(e) = record::tag(path, &title, call.start(), &comment.to_string()) {
+            warn!("unable to tag recording {}: {}", path.display(), e);
+        }
+    }
+
+    /// Log a summary of the given ended call, if enabled, and run its hooks.
+    fn end_call(&mut self, call: Option<Call>) {
+        let call = match call {
+            Some(call) => call,
+            None => return,
+        };
+
+        self.tgstats.record_call(&call);
+        self.lifetime.record_call(&call);
+
+        if self.call_log {
+            // Use a separate target so call lines can be filtered from protocol logging.
+            info!(target: "calls", "{}", call.summary(&self.settings.aliases));
+        }
+
+        self.run_call_hooks(HookEvent::CallEnd, &call);
+    }
+
+    /// Run the hooks of the given event with the given call, along with its talkgroup
+    /// name, as payload.
+    fn run_call_hooks(&mut self, event: HookEvent, call: &Call) {
+        if !self.hooks.handles(event) && !self.scripts.handles(event) && !self.alerts.handles(event)
+        {
+            return;
+        }
+
+        let mut payload = serde_json::to_value(call).expect("unable to serialize call");
+
+        if let Some(alias) = self.settings.aliases.get(call.talkgroup()) {
+            if let Some(fields) = payload.as_object_mut() {
+                fields.insert(
+                    "alias".to_string(),
+                    serde_json::Value::String(alias.to_string()),
+                );
+            }
+        }
+
+        self.trigger(event, &payload);
+    }
+
+    /// Run the hooks, scripts, and alerts of the given event with the given payload,
+    /// applying the actions requested by the scripts.
+    fn trigger(&mut self, event: HookEvent, payload: &serde_json::Value) {
+        self.hooks.run(event, payload);
+        self.alerts.run(event, payload);
+
+        for action in self.scripts.run(event, payload) {
+            match action {
+                Action::Hold(tg) => self
+                    .recv
+                    .send(RecvEvent::Hold(Some(tg)))
+                    .expect("unable to hold talkgroup"),
+                Action::Release => self
+                    .recv
+                    .send(RecvEvent::Hold(None))
+                    .expect("unable to release talkgroup"),
+                Action::Tag(id, tag) => {
+                    if !self.calls.tag(id, tag) {
+                        warn!("unable to tag unknown call {}", id);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Write the given audio samples to each listener, dropping disconnected and lagging
+    /// listeners, and publish them if enabled.
+    fn stream_audio(&mut self, buf: &[u8]) {
+        let mut keep = ArrayVec::<[(Streamer, AudioFormat); 4]>::new();
+        // Samples arrive little-endian.
+        let swapped: Vec<u8> = buf
+            .chunks(2)
+            .flat_map(|c| c.iter().rev())
+            .cloned()
+            .collect();
+
+        while let Some((mut s, format)) = self.listeners.pop() {
+            let res = match format {
+                AudioFormat::Wav => s.write_bytes(buf),
+                AudioFormat::L16 => s.write_bytes(&swapped),
+            };
+
+            match res {
+                Ok(()) => keep.push((s, format)),
+                Err(()) => self.drop_streamer(s, "audio listener"),
+            }
+        }
+
+        self.listeners = keep;
+
+        if let Some(ref p) = self.publisher {
+            p.send_audio(buf);
+        }
     }
 
     /// Handle the given HTTP connection.
@@ -261,13 +1595,13 @@ impl HubTask {
         match self.handle_request(&mut s) {
             Ok(()) => {}
             Err(e) => {
-                http::send_status(&mut s, e).ok();
+                http::send_status(&mut s, &self.cors, e).ok();
             }
         }
     }
 
     fn handle_request(&mut self, s: &mut TcpStream) -> HttpResult<()> {
-        let mut buf = [0; 8192];
+        let mut buf = [0; REQUEST_BUF];
 
         let mut req = HttpRequest::new(s, &mut buf[..])?;
         let (ver, method, route) = req.route()?;
@@ -277,16 +1611,46 @@ impl HubTask {
         }
 
         match (method, route) {
-            (Method::Get, Route::Subscribe) => {
+            (Method::Get, Route::Dashboard) => {
+                http::send_body(
+                    req.into_stream(),
+                    &self.cors,
+                    "text/html; charset=utf-8",
+                    DASHBOARD.as_bytes(),
+                )
+                .ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::Subscribe(filter)) => {
+                if let Ok(mut s) = req.into_stream().try_clone() {
+                    // Check if streamer can be supported before sending response.
+                    if self.streamers.is_full() {
+                        return Err(StatusCode::TooManyRequests);
+                    }
+
+                    if self.start_stream(&mut s).is_ok() && s.set_nonblocking(true).is_ok() {
+                        // This is guaranteed to succeed due to the above check.
+                        self.streamers.push(Streamer::new(s, filter));
+                    }
+
+                    Ok(())
+                }
+                else {
+                    Err(StatusCode::InternalServerError)
+                }
+            }
+            (Method::Get, Route::AudioStream(format)) => {
                 if let Ok(mut s) = req.into_stream().try_clone() {
-                    // Check if streamer can be supported before sending response.
-                    if self.streamers.is_full() {
+                    if self.listeners.is_full() {
                         return Err(StatusCode::TooManyRequests);
                     }
 
-                    if self.start_stream(&mut s).is_ok() {
+                    // Samples are buffered for a listener that can't keep up, like events for
+                    // subscribers.
+                    if self.start_audio(&mut s, format).is_ok() && s.set_nonblocking(true).is_ok() {
                         // This is guaranteed to succeed due to the above check.
-                        self.streamers.push(s);
+                        self.listeners.push((Streamer::new(s, None), format));
                     }
 
                     Ok(())
@@ -298,6 +1662,7 @@ impl HubTask {
             (Method::Get, Route::CtlFreq) => {
                 http::send_json(
                     req.into_stream(),
+                    &self.cors,
                     SerdeCtlFreq {
                         ctlfreq: self.state.ctlfreq,
                     },
@@ -309,7 +1674,19 @@ impl HubTask {
             (Method::Put, Route::CtlFreq) => {
                 let msg: SerdeCtlFreq = req.read_json()?;
 
-                // TODO: verify frequency range.
+                if let Err(e) = self.band.check(msg.ctlfreq) {
+                    http::send_json_status(
+                        req.into_stream(),
+                        &self.cors,
+                        StatusCode::UnprocessableEntity,
+                        json!({
+                            "error": e,
+                        }),
+                    )
+                    .ok();
+
+                    return Ok(());
+                }
 
                 if self
                     .recv
@@ -319,13 +1696,14 @@ impl HubTask {
                     return Err(StatusCode::InternalServerError);
                 }
 
-                http::send_status(req.into_stream(), StatusCode::Ok).ok();
+                http::send_status(req.into_stream(), &self.cors, StatusCode::Ok).ok();
 
                 Ok(())
             }
             (Method::Get, Route::Encrypted) => {
                 http::send_json(
                     req.into_stream(),
+                    &self.cors,
                     json!({
                         "encrypted": &self.state.encrypted,
                     }),
@@ -334,6 +1712,19 @@ impl HubTask {
 
                 Ok(())
             }
+            (Method::Get, Route::Stats) => {
+                http::send_json(
+                    req.into_stream(),
+                    &self.cors,
+                    json!({
+                        "session": self.lifetime.session().summary(),
+                        "lifetime": self.lifetime.lifetime().summary(),
+                    }),
+                )
+                .ok();
+
+                Ok(())
+            }
             (Method::Put, Route::ResetStats) => {
                 self.recv
                     .send(RecvEvent::ResetStats)
@@ -341,12 +1732,336 @@ impl HubTask {
 
                 Ok(())
             }
-            (Method::Options, _) => {
-                let mut h = HeaderLines::new(req.into_stream());
+            (Method::Get, Route::Queues) => {
+                let queues = self
+                    .queues
+                    .iter()
+                    .map(|q| {
+                        json!({
+                            "name": q.name,
+                            "capacity": q.cap,
+                            "policy": format!("{:?}", q.policy),
+                            "depth": q.depth(),
+                            "overflows": q.overflows(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                let subscribers = json!({
+                    "connected": self.streamers.len(),
+                    "backlog": self.streamers.iter().map(|s| s.out.len()).collect::<Vec<_>>(),
+                    "evictions": self.evictions,
+                });
+
+                http::send_json(
+                    req.into_stream(),
+                    &self.cors,
+                    json!({
+                        "queues": queues,
+                        "subscribers": subscribers,
+                    }),
+                )
+                .ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::Metrics) => {
+                let ms = |d: Duration| d.as_secs_f32() * 1000.0;
+
+                let stages = self
+                    .stages
+                    .iter()
+                    .map(|s| {
+                        json!({
+                            "name": s.name,
+                            "items": s.items(),
+                            "meanMs": ms(s.mean()),
+                            "maxMs": ms(s.max()),
+                            "load": s.load(),
+                            "backlog": s.backlog(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                http::send_json(req.into_stream(), &self.cors, json!({ "stages": stages })).ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::Latency) => {
+                http::send_json(req.into_stream(), &self.cors, self.latency).ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::Adjacent) => {
+                http::send_json(req.into_stream(), &self.cors, self.sites.query()).ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::System) => {
+                http::send_json(
+                    req.into_stream(),
+                    &self.cors,
+                    json!({
+                        "rfss": &self.state.rfss,
+                        "network": &self.state.network,
+                        "altControl": &self.state.alt_control,
+                    }),
+                )
+                .ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::Profile) => {
+                http::send_json(
+                    req.into_stream(),
+                    &self.cors,
+                    json!({
+                        "active": &self.profile,
+                        "profiles": self
+                            .settings
+                            .profiles
+                            .iter()
+                            .map(|p| &p.name)
+                            .collect::<Vec<_>>(),
+                    }),
+                )
+                .ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::Health) => {
+                // A task that stopped has dropped its handle on its metrics.
+                let stopped = self
+                    .stages
+                    .iter()
+                    .filter(|s| Arc::strong_count(s) == 1)
+                    .map(|s| s.name)
+                    .collect();
+
+                let report = self.health.check(stopped);
+
+                let status = if report.healthy {
+                    StatusCode::Ok
+                }
+                else {
+                    StatusCode::ServiceUnavailable
+                };
+
+                http::send_json_status(req.into_stream(), &self.cors, status, report).ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::Status) => {
+                let known = |f: u32| if f == std::u32::MAX { None } else { Some(f) };
+
+                http::send_json(
+                    req.into_stream(),
+                    &self.cors,
+                    json!({
+                        "state": self.state.recv,
+                        "ctlFreq": known(self.state.ctlfreq),
+                        "curFreq": known(self.state.curfreq),
+                        "call": self.calls.following(),
+                        "discriminator": self.discriminator,
+                    }),
+                )
+                .ok();
+
+                Ok(())
+            }
+            (Method::Post, Route::Pause(release)) => {
+                if self.recv.send(RecvEvent::Pause(release)).is_err() {
+                    return Err(StatusCode::InternalServerError);
+                }
+
+                http::send_status(req.into_stream(), &self.cors, StatusCode::Ok).ok();
+
+                Ok(())
+            }
+            (Method::Post, Route::Resume) => {
+                if self.recv.send(RecvEvent::Resume).is_err() {
+                    return Err(StatusCode::InternalServerError);
+                }
+
+                http::send_status(req.into_stream(), &self.cors, StatusCode::Ok).ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::Mute) => {
+                http::send_json(
+                    req.into_stream(),
+                    &self.cors,
+                    SerdeMute {
+                        muted: self.muted,
+                    },
+                )
+                .ok();
+
+                Ok(())
+            }
+            (Method::Put, Route::Mute) => {
+                let msg: SerdeMute = req.read_json()?;
+
+                if self.set_muted(msg.muted).is_err() {
+                    return Err(StatusCode::InternalServerError);
+                }
+
+                http::send_status(req.into_stream(), &self.cors, StatusCode::Ok).ok();
+
+                Ok(())
+            }
+            (Method::Post, Route::Restart) => {
+                let stream = req.into_stream();
+
+                // Settings that fail to load are reported without restarting, like
+                // a failed reload.
+                match self.reload() {
+                    Ok(changes) => {
+                        if self.recv.send(RecvEvent::Restart).is_err() {
+                            return Err(StatusCode::InternalServerError);
+                        }
+
+                        http::send_json(stream, &self.cors, json!({ "changes": changes })).ok();
+                    }
+                    Err(e) => {
+                        warn!("not restarting: {:#}", e);
+
+                        http::send_json_status(
+                            stream,
+                            &self.cors,
+                            StatusCode::UnprocessableEntity,
+                            json!({
+                                "error": format!("{:#}", e),
+                            }),
+                        )
+                        .ok();
+                    }
+                }
+
+                Ok(())
+            }
+            (Method::Get, Route::Version) => {
+                http::send_json(
+                    req.into_stream(),
+                    &self.cors,
+                    json!({
+                        "version": VERSION,
+                        "git": GIT_HASH,
+                    }),
+                )
+                .ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::Config) => {
+                http::send_json(req.into_stream(), &self.cors, &self.config).ok();
+
+                Ok(())
+            }
+            (Method::Post, Route::Reload) => {
+                let stream = req.into_stream();
+
+                match self.reload() {
+                    Ok(changes) => {
+                        http::send_json(stream, &self.cors, json!({ "changes": changes })).ok();
+                    }
+                    Err(e) => {
+                        warn!("keeping current settings: {:#}", e);
+
+                        http::send_json_status(
+                            stream,
+                            &self.cors,
+                            StatusCode::UnprocessableEntity,
+                            json!({
+                                "error": format!("{:#}", e),
+                            }),
+                        )
+                        .ok();
+                    }
+                }
+
+                Ok(())
+            }
+            (Method::Get, Route::Calls(q)) => {
+                http::send_json(req.into_stream(), &self.cors, self.calls.query(&q)).ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::TalkgroupStats(since)) => {
+                let page = self.tgstats.query(since, &self.settings.aliases);
+                http::send_json(req.into_stream(), &self.cors, page).ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::TalkgroupRollup(q)) => {
+                http::send_json(req.into_stream(), &self.cors, self.tgstats.rollup(&q)).ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::Units(q)) => {
+                http::send_json(req.into_stream(), &self.cors, self.unit_table.query(&q)).ok();
+
+                Ok(())
+            }
+            (Method::Get, Route::CallAudio(id)) => {
+                if self.downloads.len() >= MAX_DOWNLOADS {
+                    return Err(StatusCode::TooManyRequests);
+                }
+
+                let path = self.calls.recording(id).ok_or(StatusCode::NotFound)?;
+                let mut file = File::open(path).map_err(|_| StatusCode::NotFound)?;
+                let len = file.metadata().map_err(|_| StatusCode::NotFound)?.len();
+
+                // The request head remains in the buffer after the request is parsed.
+                let mut stream = req
+                    .into_stream()
+                    .try_clone()
+                    .map_err(|_| StatusCode::InternalServerError)?;
+
+                let range = match http::find_header(&buf[..], "Range") {
+                    Some(r) => Some(
+                        http::parse_range(r, len as usize)
+                            .ok_or(StatusCode::RangeNotSatisfiable)?,
+                    ),
+                    None => None,
+                };
+
+                // Only the requested range is read, a piece at a time.
+                let (start, part) = match range {
+                    Some((start, end)) => (start as u64, (end - start + 1) as u64),
+                    None => (0, len),
+                };
+
+                file.seek(SeekFrom::Start(start))
+                    .map_err(|_| StatusCode::InternalServerError)?;
+
+                if http::send_range_head(&mut stream, &self.cors, "audio/wav", len, range).is_ok() {
+                    self.start_download(stream, file.take(part));
+                }
+
+                Ok(())
+            }
+            (Method::Get, Route::Static(path)) => {
+                let file = self
+                    .www
+                    .as_ref()
+                    .and_then(|root| www::resolve(root, &path))
+                    .ok_or(StatusCode::NotFound)?;
+
+                let body = fs::read(&file).map_err(|_| StatusCode::NotFound)?;
+
+                http::send_body(
+                    req.into_stream(),
+                    &self.cors,
+                    www::content_type(&file),
+                    &body,
+                )
+                .ok();
 
-                http::send_head(&mut h, StatusCode::Ok).ok();
-                write!(h.line(), "Access-Control-Allow-Methods: GET, PUT").ok();
-                write!(h.line(), "Access-Control-Allow-Headers: Content-Type").ok();
+                Ok(())
+            }
+            (Method::Options, _) => {
+                http::send_preflight(req.into_stream(), &self.cors).ok();
 
                 Ok(())
             }
@@ -358,32 +2073,77 @@ impl HubTask {
     fn start_stream(&self, s: &mut TcpStream) -> std::io::Result<()> {
         let mut h = HeaderLines::new(s);
 
-        http::send_head(&mut h, StatusCode::Ok)?;
+        http::send_head(&mut h, &self.cors, StatusCode::Ok)?;
         write!(h.line(), "Content-Type: text/event-stream")?;
 
         Ok(())
     }
 
-    fn stream_event(&mut self, s: &mut TcpStream, e: &HubEvent) -> Result<(), ()> {
+    /// Send the header of a live audio stream in the given format to the given listener.
+    fn start_audio(&self, s: &mut TcpStream, format: AudioFormat) -> std::io::Result<()> {
+        {
+            let mut h = HeaderLines::new(&mut *s);
+
+            http::send_head(&mut h, &self.cors, StatusCode::Ok)?;
+
+            match format {
+                AudioFormat::Wav => write!(h.line(), "Content-Type: audio/wav")?,
+                AudioFormat::L16 => write!(
+                    h.line(),
+                    "Content-Type: audio/L16;rate={};channels=1",
+                    AUDIO_SAMPLE_RATE
+                )?,
+            }
+        }
+
+        match format {
+            // The stream has no end, so its length is given as the maximum.
+            AudioFormat::Wav => record::write_header(s, std::u32::MAX),
+            AudioFormat::L16 => Ok(()),
+        }
+    }
+
+    fn stream_event<S: EventSink>(&mut self, s: &mut S, e: &HubEvent) -> Result<(), ()> {
         use self::{HubEvent::*, StateEvent::*};
 
         match *e {
-            State(UpdateCtlFreq(f)) => SerdeEvent::new("ctlFreq", f).write(s),
+            State(UpdateCtlFreq(f)) => SerdeEvent::new("ctlFreq", f).send(s),
             State(UpdateChannelParams(_)) => Ok(()),
             State(UpdateEncrypted(..)) => {
-                SerdeEvent::new("updateEncrypted", &self.state.encrypted).write(s)
+                SerdeEvent::new("updateEncrypted", &self.state.encrypted).send(s)
+            }
+            UpdateCurFreq(f) => SerdeEvent::new("curFreq", f).send(s),
+            UpdateTalkGroup(tg) => SerdeEvent::new("talkGroup", tg).send(s),
+            BeginPrivateCall(ref p) => SerdeEvent::new("privateCall", p).send(s),
+            UpdateSignalPower(p) => {
+                SerdeEvent::new("sigPower", p).send(s)?;
+
+                match self.noise.floor() {
+                    Some(floor) => SerdeEvent::new(
+                        "snr",
+                        json!({
+                            "snr": (p - floor).max(0.0),
+                            "noiseFloor": floor,
+                        }),
+                    )
+                    .send(s),
+                    None => Ok(()),
+                }
             }
-            UpdateCurFreq(f) => SerdeEvent::new("curFreq", f).write(s),
-            UpdateTalkGroup(tg) => SerdeEvent::new("talkGroup", tg).write(s),
-            UpdateSignalPower(p) => SerdeEvent::new("sigPower", p).write(s),
+            UpdateDroppedBuffers(n) => SerdeEvent::new("droppedBuffers", n).send(s),
+            DroppedSamples(ref d) => SerdeEvent::new("sampleDrop", d).send(s),
             // If this event has been received, the TSBK is valid with a known opcode.
             TrunkingControl(tsbk) => match tsbk.opcode().unwrap() {
-                TsbkOpcode::RfssStatusBroadcast => {
-                    stream_rfss_status(s, fields::RfssStatusBroadcast::new(tsbk.payload()))
-                }
-                TsbkOpcode::NetworkStatusBroadcast => {
-                    stream_net_status(s, fields::NetworkStatusBroadcast::new(tsbk.payload()))
-                }
+                TsbkOpcode::RfssStatusBroadcast => SerdeEvent::new(
+                    "rfssStatus",
+                    rfss_status(fields::RfssStatusBroadcast::new(tsbk.payload())),
+                )
+                .send(s),
+                TsbkOpcode::NetworkStatusBroadcast => SerdeEvent::new(
+                    "networkStatus",
+                    net_status(fields::NetworkStatusBroadcast::new(tsbk.payload())),
+                )
+                .send(s),
                 TsbkOpcode::AltControlChannel => {
                     self.stream_alt_control(s, fields::AltControlChannel::new(tsbk.payload()))
                 }
@@ -402,7 +2162,7 @@ impl HubTask {
                             "unit": f.dest_unit(),
                         }),
                     )
-                    .write(s)
+                    .send(s)
                 }
                 TsbkOpcode::UnitRegResponse => {
                     let f = tsbk::UnitRegResponse::new(tsbk);
@@ -416,7 +2176,16 @@ impl HubTask {
                             "unitAddr": f.src_addr(),
                         }),
                     )
-                    .write(s)
+                    .send(s)
+                }
+                TsbkOpcode::AckResponse | TsbkOpcode::QueuedResponse | TsbkOpcode::DenyResponse => {
+                    match Response::from_tsbk(tsbk) {
+                        Some(r) => SerdeEvent::new(r.kind.event(), r).send(s),
+                        None => Ok(()),
+                    }
+                }
+                TsbkOpcode::AuthCommand => {
+                    SerdeEvent::new("authCommand", AuthCommand::new(tsbk.payload())).send(s)
                 }
                 TsbkOpcode::UnitDeregAck => {
                     let f = tsbk::UnitDeregAck::new(tsbk);
@@ -429,7 +2198,7 @@ impl HubTask {
                             "unit": f.src_unit(),
                         }),
                     )
-                    .write(s)
+                    .send(s)
                 }
                 _ => Ok(()),
             },
@@ -437,14 +2206,18 @@ impl HubTask {
             LinkControl(lc) => match lc.opcode().unwrap() {
                 LinkControlOpcode::GroupVoiceTraffic => {
                     SerdeEvent::new("srcUnit", control::GroupVoiceTraffic::new(lc).src_unit())
-                        .write(s)
-                }
-                LinkControlOpcode::RfssStatusBroadcast => {
-                    stream_rfss_status(s, fields::RfssStatusBroadcast::new(lc.payload()))
-                }
-                LinkControlOpcode::NetworkStatusBroadcast => {
-                    stream_net_status(s, fields::NetworkStatusBroadcast::new(lc.payload()))
+                        .send(s)
                 }
+                LinkControlOpcode::RfssStatusBroadcast => SerdeEvent::new(
+                    "rfssStatus",
+                    rfss_status(fields::RfssStatusBroadcast::new(lc.payload())),
+                )
+                .send(s),
+                LinkControlOpcode::NetworkStatusBroadcast => SerdeEvent::new(
+                    "networkStatus",
+                    net_status(fields::NetworkStatusBroadcast::new(lc.payload())),
+                )
+                .send(s),
                 LinkControlOpcode::AdjacentSite => {
                     self.stream_adjacent_site(s, fields::AdjacentSite::new(lc.payload()))
                 }
@@ -453,36 +2226,75 @@ impl HubTask {
                 }
                 _ => Ok(()),
             },
-            UpdateStats(stats) => SerdeEvent::new("updateStats", serialize_stats(&stats)).write(s),
+            UpdateStats(stats) => SerdeEvent::new("updateStats", serialize_stats(&stats)).send(s),
+            AudioSinkDown => SerdeEvent::new("audioSinkDown", ()).send(s),
+            AudioSinkUp => SerdeEvent::new("audioSinkUp", ()).send(s),
+            ControlLost => SerdeEvent::new("controlLost", self.state.ctlfreq).send(s),
+            ControlRestored => SerdeEvent::new("controlRestored", self.state.ctlfreq).send(s),
+            ConfigReload(ref changes) => {
+                SerdeEvent::new("configReload", json!({ "changes": changes })).send(s)
+            }
+            UnitSeen(ref sighting) => SerdeEvent::new("unitSeen", sighting).send(s),
+            SelectionDecision(ref d) => SerdeEvent::new("selectionDecision", d).send(s),
+            RetuneLatency(ref l) => SerdeEvent::new("retuneLatency", l).send(s),
+            Fault(ref f) => SerdeEvent::new("error", f).send(s),
+            AvoidedGrant(tg, freq) => SerdeEvent::new(
+                "avoidedGrant",
+                json!({
+                    "talkGroup": tg,
+                    "freq": freq,
+                }),
+            )
+            .send(s),
+            TalkgroupSummary(since) => {
+                let page = self.tgstats.query(Some(since), &self.settings.aliases);
+
+                if page.is_empty() {
+                    return Ok(());
+                }
+
+                SerdeEvent::new("talkgroupStats", page).send(s)
+            }
+            ProfileChange(ref name) => SerdeEvent::new("profile", name).send(s),
+            CaptureChange(capture) => SerdeEvent::new("capture", &capture).send(s),
+            MuteChange(muted) => SerdeEvent::new("mute", &muted).send(s),
+            RecvStateChange(state) => SerdeEvent::new("recvState", state).send(s),
+            VoiceTimeout(ref t) => SerdeEvent::new("voiceTimeout", t).send(s),
+            EndTransmission | CallRecorded(..) | AudioFrame(_) | AudioLatency(_) | Control(_) => {
+                Ok(())
+            }
         }
     }
 
-    fn stream_alt_control(
+    fn stream_alt_control<S: EventSink>(
         &self,
-        mut s: &mut TcpStream,
+        s: &mut S,
         f: fields::AltControlChannel,
     ) -> Result<(), ()> {
-        for &(ch, _) in f.alts().iter() {
-            let freq = match self.state.channels.lookup(ch.id()) {
-                Some(p) => p.rx_freq(ch.number()),
-                None => continue,
-            };
-
-            SerdeEvent::new(
-                "altControl",
-                json!({
-                    "rfss": f.rfss(),
-                    "site": f.site(),
-                    "freq": freq,
-                }),
-            )
-            .write(&mut s)?;
+        for alt in self.state.alt_controls(f) {
+            SerdeEvent::new("altControl", alt).send(s)?;
         }
 
         Ok(())
     }
 
-    fn stream_adjacent_site(&self, s: &mut TcpStream, f: fields::AdjacentSite) -> Result<(), ()> {
+    /// Add the given adjacent site broadcast to the site table.
+    fn record_adjacent(&mut self, f: fields::AdjacentSite) {
+        let ch = f.channel();
+        let freq = self
+            .state
+            .channels
+            .lookup(ch.id())
+            .map(|p| p.rx_freq(ch.number()));
+
+        self.sites.record(f, freq, calls::now());
+    }
+
+    fn stream_adjacent_site<S: EventSink>(
+        &self,
+        s: &mut S,
+        f: fields::AdjacentSite,
+    ) -> Result<(), ()> {
         let ch = f.channel();
 
         let freq = match self.state.channels.lookup(ch.id()) {
@@ -500,7 +2312,7 @@ impl HubTask {
                 "freq": freq,
             }),
         )
-        .write(s)
+        .send(s)
     }
 }
 
@@ -513,14 +2325,88 @@ pub enum HubEvent {
     UpdateCurFreq(u32),
     /// Current talkgroup has changed.
     UpdateTalkGroup(u16),
+    /// Receiver began following the given unit-to-unit or telephone interconnect call.
+    BeginPrivateCall(PrivateCall),
     /// Power of received signal.
     UpdateSignalPower(f32),
+    /// Total SDR sample buffers dropped due to a full ring.
+    UpdateDroppedBuffers(usize),
+    /// Samples were lost between the SDR and the receiver.
+    DroppedSamples(SampleDrop),
     /// Trunking control packet was received.
     TrunkingControl(TsbkFields),
     /// Link control packet was received.
     LinkControl(LinkControlFields),
     /// Updated stat counters.
     UpdateStats(Stats),
+    /// Audio output has stopped accepting samples.
+    AudioSinkDown,
+    /// Audio output has resumed accepting samples.
+    AudioSinkUp,
+    /// Receiver has left the current call, if any.
+    EndTransmission,
+    /// Audio of the latest call on the given talkgroup was recorded to the given file.
+    CallRecorded(u16, PathBuf),
+    /// Decoded voice audio, as 16-bit little-endian mono samples at the decoded rate.
+    AudioFrame(Vec<u8>),
+    /// Audio of a voice frame was written out the given time (ms) after the frame
+    /// arrived at the SDR.
+    AudioLatency(u32),
+    /// Receiver hit the given fault and carried on.
+    Fault(Fault),
+    /// Settings were reloaded with the given changes.
+    ConfigReload(Vec<String>),
+    /// No trunking packets have been received on the control channel for a while.
+    ControlLost,
+    /// Trunking packets are being received on the control channel again.
+    ControlRestored,
+    /// Watched unit was active.
+    UnitSeen(Sighting),
+    /// Receiver chose a talkgroup to follow for the given reason.
+    SelectionDecision(Decision),
+    /// First voice frame of a followed call was decoded after the given delays.
+    RetuneLatency(RetuneLatency),
+    /// Grant for the given talkgroup on the given avoided channel (Hz) was ignored.
+    AvoidedGrant(u16, u32),
+    /// Summarize the talkgroups heard since the given time (ms since the Unix epoch).
+    TalkgroupSummary(u64),
+    /// Given talkgroup selection profile became active, or none for the top-level
+    /// settings.
+    ProfileChange(Option<String>),
+    /// Recording was enabled or disabled by the record schedule.
+    CaptureChange(bool),
+    /// Live audio output was silenced or restored.
+    MuteChange(bool),
+    /// Receiver activity changed.
+    RecvStateChange(RecvState),
+    /// No voice was received after tuning to a traffic channel.
+    VoiceTimeout(FailedTune),
+    /// Control action requested by the gRPC service.
+    Control(HubControl),
+}
+
+impl QueueItem for HubEvent {
+    fn droppable(&self) -> bool {
+        // Periodic measurements are superseded by the next ones.
+        matches!(
+            *self,
+            HubEvent::UpdateSignalPower(_)
+                | HubEvent::UpdateDroppedBuffers(_)
+                | HubEvent::UpdateStats(_)
+                | HubEvent::AudioFrame(_)
+                | HubEvent::AudioLatency(_)
+        )
+    }
+}
+
+/// Control actions requested of the hub.
+#[derive(Clone)]
+pub enum HubControl {
+    /// Silence or restore the live audio output, like `PUT /mute`.
+    Mute(bool),
+    /// Reload the settings files, like `POST /reload`, replying with the changes or the
+    /// error.
+    Reload(mpsc::Sender<Result<Vec<String>, String>>),
 }
 
 /// State update events.
@@ -538,18 +2424,33 @@ pub enum StateEvent {
 pub struct State {
     /// Current control channel frequency.
     ctlfreq: u32,
+    /// Current center frequency.
+    curfreq: u32,
+    /// Current activity of the receiver.
+    recv: RecvState,
     /// Channel parameters for current site.
     channels: ChannelParamsMap,
     /// Known encrypted talkgroups.
     encrypted: GroupCryptoMap,
+    /// Latest RFSS status broadcast on the current control channel.
+    rfss: Option<Value>,
+    /// Latest network status broadcast on the current control channel.
+    network: Option<Value>,
+    /// Secondary control channels in the latest broadcast on the current control channel.
+    alt_control: Vec<Value>,
 }
 
 impl Default for State {
     fn default() -> Self {
         State {
             ctlfreq: std::u32::MAX,
+            curfreq: std::u32::MAX,
+            recv: RecvState::Searching,
             channels: ChannelParamsMap::default(),
             encrypted: GroupCryptoMap::default(),
+            rfss: None,
+            network: None,
+            alt_control: vec![],
         }
     }
 }
@@ -560,7 +2461,16 @@ impl State {
         use self::StateEvent::*;
 
         match e {
-            UpdateCtlFreq(f) => self.ctlfreq = f,
+            UpdateCtlFreq(f) => {
+                // Status broadcasts from another site no longer apply.
+                if f != self.ctlfreq {
+                    self.rfss = None;
+                    self.network = None;
+                    self.alt_control.clear();
+                }
+
+                self.ctlfreq = f;
+            }
             UpdateChannelParams(tsbk) => self
                 .channels
                 .update(&fields::ChannelParamsUpdate::new(tsbk.payload())),
@@ -569,6 +2479,61 @@ impl State {
             }
         }
     }
+
+    /// Keep the given trunking packet if it's a system status broadcast.
+    fn record_tsbk(&mut self, tsbk: TsbkFields) {
+        match tsbk.opcode() {
+            Some(TsbkOpcode::RfssStatusBroadcast) => {
+                let f = fields::RfssStatusBroadcast::new(tsbk.payload());
+                self.rfss = Some(rfss_status(f));
+            }
+            Some(TsbkOpcode::NetworkStatusBroadcast) => {
+                let f = fields::NetworkStatusBroadcast::new(tsbk.payload());
+                self.network = Some(net_status(f));
+            }
+            Some(TsbkOpcode::AltControlChannel) => {
+                let f = fields::AltControlChannel::new(tsbk.payload());
+                self.alt_control = self.alt_controls(f);
+            }
+            _ => {}
+        }
+    }
+
+    /// Keep the given link control word if it's a system status broadcast.
+    fn record_lc(&mut self, lc: LinkControlFields) {
+        match lc.opcode() {
+            Some(LinkControlOpcode::RfssStatusBroadcast) => {
+                let f = fields::RfssStatusBroadcast::new(lc.payload());
+                self.rfss = Some(rfss_status(f));
+            }
+            Some(LinkControlOpcode::NetworkStatusBroadcast) => {
+                let f = fields::NetworkStatusBroadcast::new(lc.payload());
+                self.network = Some(net_status(f));
+            }
+            Some(LinkControlOpcode::AltControlChannel) => {
+                let f = fields::AltControlChannel::new(lc.payload());
+                self.alt_control = self.alt_controls(f);
+            }
+            _ => {}
+        }
+    }
+
+    /// Describe each secondary control channel in the given broadcast whose frequency is
+    /// known.
+    fn alt_controls(&self, f: fields::AltControlChannel) -> Vec<Value> {
+        f.alts()
+            .iter()
+            .filter_map(|&(ch, _)| {
+                let p = self.channels.lookup(ch.id())?;
+
+                Some(json!({
+                    "rfss": f.rfss(),
+                    "site": f.site(),
+                    "freq": p.rx_freq(ch.number()),
+                }))
+            })
+            .collect()
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -576,6 +2541,11 @@ struct SerdeCtlFreq {
     ctlfreq: u32,
 }
 
+#[derive(Deserialize, Serialize)]
+struct SerdeMute {
+    muted: bool,
+}
+
 #[derive(Serialize)]
 struct SerdeEvent<T: Serialize> {
     event: &'static str,
@@ -596,34 +2566,37 @@ impl<T: Serialize> SerdeEvent<T> {
 
         serde_json::to_writer(&mut data, self).map_err(|_| ())
     }
+
+    /// Write the event to the given sink if it accepts this event type.
+    fn send<S: EventSink>(&self, s: &mut S) -> Result<(), ()> {
+        if !s.wants(self.event) {
+            return Ok(());
+        }
+
+        s.write_event(self)
+    }
 }
 
-fn stream_rfss_status(s: &mut TcpStream, f: fields::RfssStatusBroadcast) -> Result<(), ()> {
-    SerdeEvent::new(
-        "rfssStatus",
-        json!({
-            "area": f.area(),
-            "system": f.system(),
-            "rfss": f.rfss(),
-            "site": f.site(),
-        }),
-    )
-    .write(s)
+/// Describe the given RFSS status broadcast.
+fn rfss_status(f: fields::RfssStatusBroadcast) -> Value {
+    json!({
+        "area": f.area(),
+        "system": f.system(),
+        "rfss": f.rfss(),
+        "site": f.site(),
+    })
 }
 
-fn stream_net_status(s: &mut TcpStream, f: fields::NetworkStatusBroadcast) -> Result<(), ()> {
-    SerdeEvent::new(
-        "networkStatus",
-        json!({
-            "area": f.area(),
-            "wacn": f.wacn(),
-            "system": f.system(),
-        }),
-    )
-    .write(s)
+/// Describe the given network status broadcast.
+fn net_status(f: fields::NetworkStatusBroadcast) -> Value {
+    json!({
+        "area": f.area(),
+        "wacn": f.wacn(),
+        "system": f.system(),
+    })
 }
 
-fn serialize_stats(s: &Stats) -> impl Serialize {
+pub fn serialize_stats(s: &Stats) -> impl Serialize {
     json!({
         "bch": serialize_code_stats(&s.bch),
         "cyclic": serialize_code_stats(&s.cyclic),
@@ -648,3 +2621,41 @@ fn serialize_code_stats(s: &CodeStats) -> impl Serialize {
         "fixedSymbols": s.fixed,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_token() {
+        match Token::from(HubToken::for_conns(3)).into() {
+            HubToken::Conns(3) => {}
+            _ => panic!("wrong token"),
+        }
+
+        match Token::from(HubToken::Events).into() {
+            HubToken::Events => {}
+            _ => panic!("wrong token"),
+        }
+
+        match Token::from(HubToken::for_request(42)).into() {
+            HubToken::Request(42) => {}
+            _ => panic!("wrong token"),
+        }
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        let f = parse_filter("events=curFreq,talkGroup").unwrap();
+        assert_eq!(f.len(), 2);
+        assert!(f.contains("curFreq"));
+        assert!(f.contains("talkGroup"));
+
+        let f = parse_filter("x=1&events=sigPower,&y=2").unwrap();
+        assert_eq!(f.len(), 1);
+        assert!(f.contains("sigPower"));
+
+        assert!(parse_filter("events=").unwrap().is_empty());
+        assert!(parse_filter("x=1").is_none());
+    }
+}
@@ -0,0 +1,73 @@
+//! Candidate control channels to try when the current one is lost.
+
+/// Most control channels remembered for a system.
+const MAX_CHANNELS: usize = 8;
+
+/// Control channel frequencies of the current system, in the order they were learned.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlHunt {
+    /// Known control channel frequencies (Hz), starting with the primary.
+    freqs: Vec<u32>,
+}
+
+impl ControlHunt {
+    /// Create a new `ControlHunt` starting with the given primary control channel (Hz).
+    pub fn new(primary: u32) -> Self {
+        ControlHunt {
+            freqs: vec![primary],
+        }
+    }
+
+    /// Remember the given secondary control channel (Hz), returning whether it's new.
+    pub fn add(&mut self, freq: u32) -> bool {
+        if self.freqs.contains(&freq) || self.freqs.len() == MAX_CHANNELS {
+            return false;
+        }
+
+        self.freqs.push(freq);
+
+        true
+    }
+
+    /// Get the control channel to try after the given one, if there are others.
+    pub fn next(&self, cur: u32) -> Option<u32> {
+        if self.freqs.len() < 2 {
+            return None;
+        }
+
+        let idx = self
+            .freqs
+            .iter()
+            .position(|&f| f == cur)
+            .map_or(0, |i| i + 1);
+
+        Some(self.freqs[idx % self.freqs.len()])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hunt() {
+        let mut h = ControlHunt::new(851000000);
+        assert_eq!(h.next(851000000), None);
+
+        assert!(h.add(851250000));
+        assert!(!h.add(851250000));
+        assert!(!h.add(851000000));
+        assert!(h.add(851500000));
+
+        assert_eq!(h.next(851000000), Some(851250000));
+        assert_eq!(h.next(851250000), Some(851500000));
+        assert_eq!(h.next(851500000), Some(851000000));
+        assert_eq!(h.next(852000000), Some(851000000));
+
+        for i in 0..10 {
+            h.add(852000000 + i);
+        }
+
+        assert_eq!(h.freqs.len(), MAX_CHANNELS);
+    }
+}
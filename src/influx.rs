@@ -0,0 +1,262 @@
+//! Periodic export of receiver measurements in InfluxDB line protocol.
+//!
+//! Every interval, a single `p25rx` point tagged with the control channel frequency is
+//! sent with these fields, covering the interval:
+//!
+//! | Field       | Value                                                   |
+//! |-------------|---------------------------------------------------------|
+//! | `power`     | Mean signal power (dB), if measured                     |
+//! | `tsbks`     | Trunking packets received                               |
+//! | `tsbk_rate` | Trunking packets received per second                    |
+//! | `calls`     | Calls followed                                          |
+//! | `retunes`   | Frequency changes                                       |
+//! | `words`     | Error-corrected code words decoded                      |
+//! | `err_words` | Code words that had errors                              |
+//! | `err_rate`  | Fraction of code words that had errors, if any decoded  |
+
+use std::{
+    io,
+    net::UdpSocket,
+    thread,
+    time::{Duration, Instant},
+};
+
+use p25::stats::{CodeStats, Stats};
+
+use crate::{alerts, hub::HubEvent};
+
+/// Destination for exported points.
+enum InfluxSink {
+    /// Send datagrams to a connected socket, as accepted by Telegraf and InfluxDB 1.x.
+    Udp(UdpSocket),
+    /// POST to the given write endpoint URL.
+    Http(String),
+}
+
+/// Measurements accumulated over one interval.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Sample {
+    /// Sum of signal power measurements (dB).
+    power: f32,
+    /// Number of signal power measurements.
+    powers: u32,
+    /// Trunking packets received.
+    tsbks: u64,
+    /// Calls followed.
+    calls: u64,
+    /// Frequency changes.
+    retunes: u64,
+    /// Code words decoded.
+    words: u64,
+    /// Code words that had errors.
+    err_words: u64,
+}
+
+/// Accumulates measurements from hub events and periodically sends them.
+pub struct InfluxExport {
+    /// Where points are sent.
+    sink: InfluxSink,
+    /// Time between points.
+    interval: Duration,
+    /// Start of the current interval.
+    start: Instant,
+    /// Measurements in the current interval.
+    cur: Sample,
+    /// Current control channel frequency (Hz).
+    ctlfreq: u32,
+    /// Current center frequency (Hz).
+    curfreq: u32,
+    /// Cumulative code word and error counts at the last stats update.
+    totals: (u64, u64),
+}
+
+impl InfluxExport {
+    /// Open the given target, either `udp:HOST:PORT` or the URL of an HTTP write
+    /// endpoint, sending a point every given interval.
+    pub fn open(target: &str, interval: Duration) -> io::Result<Self> {
+        let sink = match target.strip_prefix("udp:") {
+            Some(addr) => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.connect(addr)?;
+                InfluxSink::Udp(socket)
+            }
+            None if cfg!(feature = "alerts") => InfluxSink::Http(target.to_string()),
+            None => return Err(io::Error::other(alerts::NO_HTTP)),
+        };
+
+        Ok(InfluxExport {
+            sink,
+            interval,
+            start: Instant::now(),
+            cur: Sample::default(),
+            ctlfreq: 0,
+            curfreq: 0,
+            totals: (0, 0),
+        })
+    }
+
+    /// Update the measurements based on the given event.
+    pub fn record(&mut self, e: &HubEvent) {
+        use crate::hub::StateEvent::UpdateCtlFreq;
+
+        match *e {
+            HubEvent::State(UpdateCtlFreq(f)) => self.ctlfreq = f,
+            HubEvent::UpdateCurFreq(f) => {
+                if f != self.curfreq {
+                    self.cur.retunes += 1;
+                    self.curfreq = f;
+                }
+            }
+            HubEvent::UpdateSignalPower(p) => {
+                self.cur.power += p;
+                self.cur.powers += 1;
+            }
+            HubEvent::TrunkingControl(_) => self.cur.tsbks += 1,
+            HubEvent::UpdateTalkGroup(_) | HubEvent::BeginPrivateCall(_) => self.cur.calls += 1,
+            HubEvent::UpdateStats(ref s) => self.record_totals(code_totals(s)),
+            _ => {}
+        }
+    }
+
+    /// Count the code words decoded since the last update, given the cumulative totals.
+    fn record_totals(&mut self, (words, errs): (u64, u64)) {
+        // Totals restart from zero when the stats are reset.
+        let prev = if words < self.totals.0 {
+            (0, 0)
+        }
+        else {
+            self.totals
+        };
+
+        self.cur.words += words - prev.0;
+        self.cur.err_words += errs.saturating_sub(prev.1);
+        self.totals = (words, errs);
+    }
+
+    /// Send a point for the current interval if it has ended by the given time, stamped
+    /// with the given time (ms since the Unix epoch).
+    pub fn send_due(&mut self, now: Instant, time: u64) {
+        let elapsed = now.saturating_duration_since(self.start);
+
+        if elapsed < self.interval {
+            return;
+        }
+
+        let line = encode(&self.cur, self.ctlfreq, elapsed, time);
+
+        self.start = now;
+        self.cur = Sample::default();
+
+        match self.sink {
+            // Datagrams are best-effort, so delivery failures (such as no listener) are
+            // ignored.
+            InfluxSink::Udp(ref s) => {
+                s.send(line.as_bytes()).ok();
+            }
+            InfluxSink::Http(ref url) => {
+                let url = url.clone();
+
+                thread::spawn(move || {
+                    let res = alerts::post(&url, "text/plain; charset=utf-8", &line);
+
+                    if let Err(e) = res {
+                        warn!("unable to write metrics: {}", e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Sum the decoded and errored code words over all codes in the given stats.
+fn code_totals(s: &Stats) -> (u64, u64) {
+    let codes: [&CodeStats; 12] = [
+        &s.bch,
+        &s.cyclic,
+        &s.golay_std,
+        &s.golay_ext,
+        &s.golay_short,
+        &s.hamming_std,
+        &s.hamming_short,
+        &s.rs_short,
+        &s.rs_med,
+        &s.rs_long,
+        &s.viterbi_dibit,
+        &s.viterbi_tribit,
+    ];
+
+    codes.iter().fold((0, 0), |(words, errs), c| {
+        (words + c.words as u64, errs + c.errs as u64)
+    })
+}
+
+/// Encode the given interval of measurements, which lasted the given time, as a line
+/// stamped with the given time (ms since the Unix epoch).
+fn encode(s: &Sample, ctlfreq: u32, elapsed: Duration, time: u64) -> String {
+    let secs = elapsed.as_secs_f32().max(f32::EPSILON);
+
+    let mut fields = vec![
+        format!("tsbks={}i", s.tsbks),
+        format!("tsbk_rate={}", s.tsbks as f32 / secs),
+        format!("calls={}i", s.calls),
+        format!("retunes={}i", s.retunes),
+        format!("words={}i", s.words),
+        format!("err_words={}i", s.err_words),
+    ];
+
+    if s.powers > 0 {
+        fields.push(format!("power={}", s.power / s.powers as f32));
+    }
+
+    if s.words > 0 {
+        fields.push(format!("err_rate={}", s.err_words as f32 / s.words as f32));
+    }
+
+    format!(
+        "p25rx,ctlfreq={} {} {}\n",
+        ctlfreq,
+        fields.join(","),
+        time * 1_000_000
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let mut s = Sample::default();
+
+        assert_eq!(
+            encode(&s, 851006250, Duration::from_secs(10), 1500000000000),
+            "p25rx,ctlfreq=851006250 tsbks=0i,tsbk_rate=0,calls=0i,retunes=0i,words=0i,\
+             err_words=0i 1500000000000000000\n"
+        );
+
+        s.power = -60.0;
+        s.powers = 2;
+        s.tsbks = 25;
+        s.words = 200;
+        s.err_words = 5;
+
+        assert_eq!(
+            encode(&s, 851006250, Duration::from_secs(10), 1500000000000),
+            "p25rx,ctlfreq=851006250 tsbks=25i,tsbk_rate=2.5,calls=0i,retunes=0i,words=200i,\
+             err_words=5i,power=-30,err_rate=0.025 1500000000000000000\n"
+        );
+    }
+
+    #[test]
+    fn test_totals() {
+        let mut e = InfluxExport::open("udp:127.0.0.1:8089", Duration::from_secs(10)).unwrap();
+
+        e.record_totals((100, 4));
+        e.record_totals((150, 5));
+        assert_eq!((e.cur.words, e.cur.err_words), (150, 5));
+
+        // Stats were reset.
+        e.record_totals((20, 1));
+        assert_eq!((e.cur.words, e.cur.err_words), (170, 6));
+    }
+}
@@ -0,0 +1,148 @@
+//! Correction of the DC offset and I/Q imbalance of the SDR.
+//!
+//! Cheap RTL-SDR dongles add a DC offset to their samples, seen as a spur at the center
+//! of the band, and their I and Q paths differ slightly in gain and phase, which mirrors
+//! each signal to the other side of the center. Both are estimated blindly, assuming that
+//! over a second or so the received signal averages to zero, has equal power in I and Q,
+//! and has nothing correlated between them. The offset is then subtracted, and Q is made
+//! orthogonal to I and scaled to match its power.
+
+use num::{complex::Complex32, traits::Zero};
+
+/// Weight of each buffer in the running estimates, giving a time constant of about 20
+/// buffers.
+const SMOOTHING: f32 = 0.05;
+
+/// Removes the DC offset and I/Q imbalance from SDR samples.
+pub struct IqCorrector {
+    /// Estimated DC offset.
+    dc: Complex32,
+    /// Estimated power of I, after removing the offset.
+    ii: f32,
+    /// Estimated power of Q, after removing the offset.
+    qq: f32,
+    /// Estimated correlation between I and Q, after removing the offset.
+    iq: f32,
+    /// Whether any samples have been estimated from.
+    primed: bool,
+}
+
+impl IqCorrector {
+    /// Create a new `IqCorrector` with no estimates.
+    pub fn new() -> Self {
+        IqCorrector {
+            dc: Complex32::zero(),
+            ii: 0.0,
+            qq: 0.0,
+            iq: 0.0,
+            primed: false,
+        }
+    }
+
+    /// Update the estimates from the given buffer of samples and correct them in place.
+    pub fn correct(&mut self, samples: &mut [Complex32]) {
+        if samples.is_empty() {
+            return;
+        }
+
+        let n = samples.len() as f32;
+        // The first buffer gives the initial estimates.
+        let w = if self.primed { SMOOTHING } else { 1.0 };
+
+        let mean = samples.iter().fold(Complex32::zero(), |sum, &s| sum + s) / n;
+        self.dc = self.dc + (mean - self.dc) * w;
+
+        let dc = self.dc;
+        let (ii, qq, iq) = samples.iter().fold((0.0, 0.0, 0.0), |(ii, qq, iq), &s| {
+            let s = s - dc;
+            (ii + s.re * s.re, qq + s.im * s.im, iq + s.re * s.im)
+        });
+
+        self.ii += (ii / n - self.ii) * w;
+        self.qq += (qq / n - self.qq) * w;
+        self.iq += (iq / n - self.iq) * w;
+        self.primed = true;
+
+        let (leak, scale) = self.coefs();
+
+        for s in samples.iter_mut() {
+            let x = *s - dc;
+            *s = Complex32::new(x.re, (x.im - leak * x.re) * scale);
+        }
+    }
+
+    /// Get the fraction of I leaking into Q, and the scale then matching the power of Q
+    /// to that of I.
+    fn coefs(&self) -> (f32, f32) {
+        if self.ii <= 0.0 {
+            return (0.0, 1.0);
+        }
+
+        let leak = self.iq / self.ii;
+        // Power of Q left after removing the part correlated with I.
+        let rest = self.qq - leak * self.iq;
+
+        if rest <= 0.0 {
+            return (0.0, 1.0);
+        }
+
+        (leak, (self.ii / rest).sqrt())
+    }
+}
+
+impl Default for IqCorrector {
+    fn default() -> Self {
+        IqCorrector::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_correct() {
+        let mut c = IqCorrector::new();
+        // Tone at 1/12 of the sample rate, with the phase kept small for precision.
+        let w = 2.0 * PI / 12.0;
+        let phase = |i: usize| w * (i % 12) as f32;
+        let mut t = 0;
+
+        // Average of the given samples mixed down from the given frequency.
+        let mix = |samples: &[Complex32], start: usize, f: f32| {
+            samples
+                .iter()
+                .enumerate()
+                .fold(Complex32::zero(), |sum, (i, &s)| {
+                    sum + s * Complex32::from_polar(&1.0, &(-f * phase(start + i)))
+                })
+                / samples.len() as f32
+        };
+
+        for _ in 0..100 {
+            // 10% gain error, 0.1 rad phase error, and an offset.
+            let mut samples: Vec<Complex32> = (t..t + 4096)
+                .map(|i| {
+                    let p = phase(i);
+                    Complex32::new(1.1 * p.cos() + 0.2, (p + 0.1).sin() - 0.1)
+                })
+                .collect();
+
+            assert!(mix(&samples, t, -1.0).norm() > 0.05);
+
+            c.correct(&mut samples);
+
+            let signal = mix(&samples, t, 1.0).norm();
+            let image = mix(&samples, t, -1.0).norm();
+            let offset = mix(&samples, t, 0.0).norm();
+
+            t += samples.len();
+
+            if t > 50 * 4096 {
+                assert!(image / signal < 0.01);
+                assert!(offset < 0.01);
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Time taken to start decoding voice after following a call.
+
+use std::time::{Duration, Instant};
+
+/// Latency of following a single call.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct RetuneLatency {
+    /// Talkgroup of the call.
+    #[serde(rename = "talkGroup")]
+    pub talkgroup: u16,
+    /// Time (ms) from the first grant received for the call to its first voice frame,
+    /// if the call was granted while collecting candidates.
+    pub grant: Option<u32>,
+    /// Time (ms) from retuning to the first voice frame.
+    pub retune: u32,
+}
+
+/// Call being followed that hasn't yet produced a voice frame.
+#[derive(Copy, Clone, Debug)]
+pub struct Retune {
+    /// Talkgroup of the call.
+    talkgroup: u16,
+    /// Time the first grant for the call was received, if any.
+    granted: Option<Instant>,
+    /// Time the receiver retuned to the call.
+    tuned: Instant,
+}
+
+impl Retune {
+    /// Create a new `Retune` for a call on the given talkgroup, first granted at the
+    /// given time, tuned at the given time.
+    pub fn new(talkgroup: u16, granted: Option<Instant>, tuned: Instant) -> Self {
+        Retune {
+            talkgroup,
+            granted,
+            tuned,
+        }
+    }
+
+    /// Measure the latency for a first voice frame received at the given time.
+    pub fn finish(self, now: Instant) -> RetuneLatency {
+        RetuneLatency {
+            talkgroup: self.talkgroup,
+            grant: self
+                .granted
+                .map(|t| millis(now.saturating_duration_since(t))),
+            retune: millis(now.saturating_duration_since(self.tuned)),
+        }
+    }
+}
+
+/// Convert the given duration to whole milliseconds.
+fn millis(d: Duration) -> u32 {
+    d.as_millis().min(u32::MAX as u128) as u32
+}
+
+/// Summary of one kind of latency (ms).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize)]
+pub struct LatencySummary {
+    /// Number of measurements.
+    count: u64,
+    /// Latest measurement.
+    last: u32,
+    /// Mean of all measurements.
+    mean: f32,
+    /// Largest measurement.
+    max: u32,
+}
+
+impl LatencySummary {
+    /// Add the given measurement.
+    fn record(&mut self, ms: u32) {
+        self.count += 1;
+        self.last = ms;
+        self.mean += (ms as f32 - self.mean) / self.count as f32;
+        self.max = self.max.max(ms);
+    }
+}
+
+/// Accumulates retune latencies.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize)]
+pub struct LatencyStats {
+    /// Time from grant to first voice frame.
+    grant: LatencySummary,
+    /// Time from retune to first voice frame.
+    retune: LatencySummary,
+    /// Time from each voice frame arriving at the SDR to its audio being written.
+    audio: LatencySummary,
+}
+
+impl LatencyStats {
+    /// Add the given measurement.
+    pub fn record(&mut self, l: &RetuneLatency) {
+        if let Some(ms) = l.grant {
+            self.grant.record(ms);
+        }
+
+        self.retune.record(l.retune);
+    }
+
+    /// Add the given end-to-end audio latency (ms).
+    pub fn record_audio(&mut self, ms: u32) {
+        self.audio.record(ms);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_latency() {
+        let t = Instant::now();
+        let r = Retune::new(101, Some(t), t + Duration::from_millis(400));
+
+        let l = r.finish(t + Duration::from_millis(650));
+        assert_eq!(l.talkgroup, 101);
+        assert_eq!(l.grant, Some(650));
+        assert_eq!(l.retune, 250);
+
+        let mut s = LatencyStats::default();
+        s.record(&l);
+        s.record(&Retune::new(102, None, t).finish(t + Duration::from_millis(150)));
+
+        assert_eq!(s.grant.count, 1);
+        assert_eq!(s.retune.count, 2);
+        assert_eq!(s.retune.last, 150);
+        assert_eq!(s.retune.mean, 200.0);
+        assert_eq!(s.retune.max, 250);
+
+        s.record_audio(120);
+        assert_eq!(s.audio.count, 1);
+        assert_eq!(s.retune.count, 2);
+    }
+}
@@ -0,0 +1,150 @@
+//! Audio gain and loudness normalization.
+
+use crate::consts::AUDIO_SAMPLE_RATE;
+
+/// Time constant (sec) of the loudness measurement, matching the EBU R128 momentary
+/// loudness window.
+const MEASURE_TIME: f32 = 0.4;
+/// Time constant (sec) for moving the applied gain toward the desired gain.
+const GAIN_TIME: f32 = 0.05;
+/// Level (dBFS) below which the signal is considered silence and the gain is held.
+const GATE_LEVEL: f32 = -50.0;
+/// Maximum gain (dB) applied when normalizing quiet signals.
+const MAX_GAIN: f32 = 30.0;
+
+/// Convert the given level in decibels to a linear amplitude factor.
+pub fn db_to_amplitude(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Convert the given level in decibels to a linear power factor.
+fn db_to_power(db: f32) -> f32 {
+    10f32.powf(db / 10.0)
+}
+
+/// Smoothing factor of a one-pole filter with the given time constant (sec) at the
+/// decoded audio rate.
+fn smoothing(t: f32) -> f32 {
+    1.0 - (-1.0 / (t * AUDIO_SAMPLE_RATE as f32)).exp()
+}
+
+/// Automatic gain control that drives the short-term loudness of the signal toward a
+/// target level.
+///
+/// Loudness is measured as mean-square power over a sliding window, and, similar to the
+/// R128 absolute gate, the gain is held during silence so noise between syllables isn't
+/// amplified.
+pub struct Normalizer {
+    /// Target mean-square power.
+    target: f32,
+    /// Mean-square power below which the gain is held.
+    gate: f32,
+    /// Maximum linear gain.
+    max_gain: f32,
+    /// Smoothing factor for the power measurement.
+    measure_alpha: f32,
+    /// Smoothing factor for gain changes.
+    gain_alpha: f32,
+    /// Current mean-square power measurement.
+    power: f32,
+    /// Current linear gain.
+    gain: f32,
+}
+
+impl Normalizer {
+    /// Create a new `Normalizer` with the given target level (dBFS RMS).
+    pub fn new(target: f32) -> Self {
+        Normalizer {
+            target: db_to_power(target),
+            gate: db_to_power(GATE_LEVEL),
+            max_gain: db_to_amplitude(MAX_GAIN),
+            measure_alpha: smoothing(MEASURE_TIME),
+            gain_alpha: smoothing(GAIN_TIME),
+            power: 0.0,
+            gain: 1.0,
+        }
+    }
+
+    /// Normalize the given samples in place.
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for s in samples.iter_mut() {
+            self.power += (*s * *s - self.power) * self.measure_alpha;
+
+            if self.power > self.gate {
+                let desired = (self.target / self.power).sqrt().min(self.max_gain);
+                self.gain += (desired - self.gain) * self.gain_alpha;
+            }
+
+            *s *= self.gain;
+        }
+    }
+
+    /// Clear the loudness measurement between transmissions, keeping the current gain
+    /// as the starting point for the next one.
+    pub fn reset(&mut self) {
+        self.power = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Compute the RMS level (dBFS) of the given samples.
+    fn level(samples: &[f32]) -> f32 {
+        let power = samples.iter().map(|&s| s * s).sum::<f32>() / samples.len() as f32;
+        10.0 * power.log10()
+    }
+
+    /// Generate 1 second of a 1kHz tone with the given peak amplitude.
+    fn tone(amp: f32) -> Vec<f32> {
+        (0..AUDIO_SAMPLE_RATE)
+            .map(|n| amp * (n as f32 * 2.0 * std::f32::consts::PI / 8.0).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_db() {
+        assert!((db_to_amplitude(0.0) - 1.0).abs() < 1e-6);
+        assert!((db_to_amplitude(20.0) - 10.0).abs() < 1e-4);
+        assert!((db_to_amplitude(-6.0) - 0.501).abs() < 1e-3);
+        assert!((db_to_power(10.0) - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_normalize() {
+        for &amp in &[0.01, 0.1, 0.9] {
+            let mut n = Normalizer::new(-20.0);
+            let mut samples = tone(amp);
+            n.process(&mut samples[..]);
+
+            // Skip the first half while the gain settles.
+            let l = level(&samples[4000..]);
+            assert!((l + 20.0).abs() < 1.0, "amp {} level {}", amp, l);
+        }
+    }
+
+    #[test]
+    fn test_gate() {
+        let mut n = Normalizer::new(-20.0);
+        let mut samples = tone(0.1);
+        n.process(&mut samples[..]);
+        let gain = n.gain;
+
+        n.reset();
+
+        // Near-silence doesn't change the gain.
+        let mut samples = tone(0.0001);
+        n.process(&mut samples[..]);
+        assert_eq!(n.gain, gain);
+    }
+
+    #[test]
+    fn test_max_gain() {
+        let mut n = Normalizer::new(-10.0);
+        let mut samples = tone(0.005);
+        n.process(&mut samples[..]);
+        assert!(n.gain > 1.0);
+        assert!(n.gain <= db_to_amplitude(MAX_GAIN));
+    }
+}
@@ -0,0 +1,286 @@
+//! Cumulative statistics kept across sessions.
+//!
+//! Most statistics start over each time the receiver is started, so running totals are
+//! kept for the current session and, when saved to a file, for every session together.
+
+use std::{collections::BTreeMap, fs, path::Path, time::Instant};
+
+use anyhow::{Context, Result};
+use p25::stats::{CodeStats, Stats};
+use serde_json::{self, Value};
+
+use crate::calls::Call;
+
+/// Totals over a span of operation.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Totals {
+    /// Number of calls followed.
+    calls: u64,
+    /// Total length (ms) of followed calls on each talkgroup.
+    airtime: BTreeMap<u16, u64>,
+    /// Number of code words decoded.
+    words: u64,
+    /// Number of code words decoded with errors.
+    errors: u64,
+    /// Time (ms) spent running.
+    uptime: u64,
+}
+
+impl Totals {
+    /// Combine with the given totals.
+    fn add(&self, other: &Totals) -> Totals {
+        let mut airtime = self.airtime.clone();
+
+        for (&tg, &ms) in &other.airtime {
+            *airtime.entry(tg).or_insert(0) += ms;
+        }
+
+        Totals {
+            calls: self.calls + other.calls,
+            airtime,
+            words: self.words + other.words,
+            errors: self.errors + other.errors,
+            uptime: self.uptime + other.uptime,
+        }
+    }
+
+    /// Describe the totals for clients.
+    pub fn summary(&self) -> Value {
+        let talkgroups = self
+            .airtime
+            .iter()
+            .map(|(&tg, &ms)| {
+                json!({
+                    "talkGroup": tg,
+                    "airtime": ms as f32 / 1000.0,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "calls": self.calls,
+            "airtime": self.airtime.values().sum::<u64>() as f32 / 1000.0,
+            "talkgroups": talkgroups,
+            "totalWords": self.words,
+            "errWords": self.errors,
+            "uptime": self.uptime / 1000,
+        })
+    }
+}
+
+/// Totals as saved to file.
+#[derive(Serialize, Deserialize)]
+struct SavedTotals {
+    calls: u64,
+    airtime: Vec<SavedAirtime>,
+    words: u64,
+    errors: u64,
+    uptime: u64,
+}
+
+/// Airtime of a talkgroup as saved to file.
+#[derive(Serialize, Deserialize)]
+struct SavedAirtime {
+    #[serde(rename = "talkGroup")]
+    talkgroup: u16,
+    airtime: u64,
+}
+
+/// Totals of the current session and of every session before it.
+pub struct LifetimeStats {
+    /// Totals of previous sessions.
+    previous: Totals,
+    /// Totals of the current session, apart from its uptime.
+    session: Totals,
+    /// Time the current session started.
+    started: Instant,
+    /// Code words and errors in the latest decoder statistics.
+    decoded: (u64, u64),
+}
+
+impl LifetimeStats {
+    /// Create a new `LifetimeStats` with no previous sessions.
+    pub fn new() -> Self {
+        LifetimeStats {
+            previous: Totals::default(),
+            session: Totals::default(),
+            started: Instant::now(),
+            decoded: (0, 0),
+        }
+    }
+
+    /// Load the totals of previous sessions from the given file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("unable to read statistics {}", path.display()))?;
+
+        let saved: SavedTotals = serde_json::from_str(&text)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("invalid statistics {}", path.display()))?;
+
+        Ok(LifetimeStats {
+            previous: Totals {
+                calls: saved.calls,
+                airtime: saved
+                    .airtime
+                    .into_iter()
+                    .map(|a| (a.talkgroup, a.airtime))
+                    .collect(),
+                words: saved.words,
+                errors: saved.errors,
+                uptime: saved.uptime,
+            },
+            ..LifetimeStats::new()
+        })
+    }
+
+    /// Save the totals of every session, including the current one, to the given file.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let t = self.lifetime();
+
+        let saved = SavedTotals {
+            calls: t.calls,
+            airtime: t
+                .airtime
+                .iter()
+                .map(|(&talkgroup, &airtime)| SavedAirtime {
+                    talkgroup,
+                    airtime,
+                })
+                .collect(),
+            words: t.words,
+            errors: t.errors,
+            uptime: t.uptime,
+        };
+
+        let text = serde_json::to_string(&saved).expect("unable to serialize statistics");
+
+        // Write a separate file first so a crash never leaves partial statistics.
+        let tmp = path.with_extension("tmp");
+
+        fs::write(&tmp, text)
+            .and_then(|_| fs::rename(&tmp, path))
+            .with_context(|| format!("unable to write statistics {}", path.display()))
+    }
+
+    /// Record the given ended call.
+    pub fn record_call(&mut self, call: &Call) {
+        self.session.calls += 1;
+        *self.session.airtime.entry(call.talkgroup()).or_insert(0) += call.airtime();
+    }
+
+    /// Record the code words decoded from the given latest decoder statistics.
+    pub fn record_decoding(&mut self, s: &Stats) {
+        let (words, errors) = decoded(s);
+
+        // The decoder statistics start over from zero when reset.
+        let (prev_words, prev_errors) = if words < self.decoded.0 {
+            (0, 0)
+        }
+        else {
+            self.decoded
+        };
+
+        self.session.words += words - prev_words;
+        self.session.errors += errors.saturating_sub(prev_errors);
+        self.decoded = (words, errors);
+    }
+
+    /// Get the totals of the current session.
+    pub fn session(&self) -> Totals {
+        Totals {
+            uptime: self.started.elapsed().as_millis() as u64,
+            ..self.session.clone()
+        }
+    }
+
+    /// Get the totals of every session, including the current one.
+    pub fn lifetime(&self) -> Totals {
+        self.previous.add(&self.session())
+    }
+}
+
+impl Default for LifetimeStats {
+    fn default() -> Self {
+        LifetimeStats::new()
+    }
+}
+
+/// Get the total code words decoded and those with errors in the given statistics.
+fn decoded(s: &Stats) -> (u64, u64) {
+    let codes: [&CodeStats; 12] = [
+        &s.bch,
+        &s.cyclic,
+        &s.golay_std,
+        &s.golay_ext,
+        &s.golay_short,
+        &s.hamming_std,
+        &s.hamming_short,
+        &s.rs_short,
+        &s.rs_med,
+        &s.rs_long,
+        &s.viterbi_dibit,
+        &s.viterbi_tribit,
+    ];
+
+    codes.iter().fold((0, 0), |(words, errs), c| {
+        (words + c.words as u64, errs + c.errs as u64)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{calls::CallHistory, testutil::TempDir};
+
+    #[test]
+    fn test_lifetime() {
+        let dir = TempDir::new("stats");
+        let path = dir.join("stats.json");
+
+        let mut s = LifetimeStats::new();
+
+        let mut h = CallHistory::default();
+        h.begin(101, 851000000);
+        s.record_call(&h.end().unwrap());
+
+        let mut stats = Stats::default();
+        stats.bch.words = 10;
+        stats.bch.errs = 2;
+        s.record_decoding(&stats);
+
+        stats.cyclic.words = 5;
+        s.record_decoding(&stats);
+
+        // Reset decoder statistics.
+        let mut stats = Stats::default();
+        stats.bch.words = 4;
+        stats.bch.errs = 1;
+        s.record_decoding(&stats);
+
+        let t = s.session();
+        assert_eq!(t.calls, 1);
+        assert!(t.airtime.contains_key(&101));
+        assert_eq!(t.words, 19);
+        assert_eq!(t.errors, 3);
+
+        s.save(&path).unwrap();
+
+        let mut s = LifetimeStats::load(&path).unwrap();
+        h.begin(102, 851000000);
+        s.record_call(&h.end().unwrap());
+
+        assert_eq!(s.session().calls, 1);
+        assert_eq!(s.session().words, 0);
+
+        let t = s.lifetime();
+        assert_eq!(t.calls, 2);
+        assert_eq!(t.words, 19);
+        assert_eq!(t.errors, 3);
+        assert_eq!(t.airtime.len(), 2);
+
+        let summary = t.summary();
+        assert_eq!(summary["calls"].as_u64(), Some(2));
+        assert_eq!(summary["talkgroups"][0]["talkGroup"].as_u64(), Some(101));
+    }
+}
@@ -0,0 +1,83 @@
+//! Log output setup.
+
+use std::{
+    io::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+use env_logger::{Builder, Env};
+use log::{LevelFilter, Record};
+use serde_json;
+
+/// Format of log lines.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable text.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Install the global logger with the given format and maximum level.
+///
+/// The level can be overridden per target with `RUST_LOG`.
+pub fn init(format: LogFormat, level: LevelFilter) {
+    let mut builder = Builder::from_env(Env::default());
+    builder.filter(None, level);
+
+    if let LogFormat::Json = format {
+        builder.format(|buf, record| writeln!(buf, "{}", format_json(record)));
+    }
+
+    builder.init();
+}
+
+/// Format the given record as a JSON object.
+fn format_json(record: &Record) -> String {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+
+    serde_json::to_string(&json!({
+        "time": time,
+        "level": record.level().to_string(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+        "module": record.module_path(),
+        "file": record.file(),
+        "line": record.line(),
+    }))
+    .expect("unable to serialize log record")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn test_format_json() {
+        let line = format_json(
+            &Record::builder()
+                .args(format_args!("tuning to {} Hz", 851000000))
+                .level(Level::Info)
+                .target("p25rx::recv")
+                .module_path(Some("p25rx::recv"))
+                .file(Some("src/recv.rs"))
+                .line(Some(42))
+                .build(),
+        );
+
+        let v: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+        assert!(!line.contains('\n'));
+        assert_eq!(v["level"].as_str(), Some("INFO"));
+        assert_eq!(v["target"].as_str(), Some("p25rx::recv"));
+        assert_eq!(v["message"].as_str(), Some("tuning to 851000000 Hz"));
+        assert_eq!(v["file"].as_str(), Some("src/recv.rs"));
+        assert_eq!(v["line"].as_u64(), Some(42));
+        assert!(v["time"].is_u64());
+    }
+}
@@ -19,7 +19,6 @@ extern crate fnv;
 extern crate imbe;
 extern crate libc;
 extern crate mio;
-extern crate mio_extras;
 extern crate moving_avg;
 extern crate num;
 extern crate p25;
@@ -27,11 +26,11 @@ extern crate p25_filts;
 extern crate pool;
 extern crate prctl;
 extern crate rtlsdr_iq;
+#[cfg(feature = "sdr")]
 extern crate rtlsdr_mt;
 extern crate serde;
 extern crate slice_cast;
 extern crate slice_mip;
-extern crate static_decimate;
 extern crate static_fir;
 extern crate throttle;
 extern crate uhttp_chunked_write;
@@ -42,226 +41,2072 @@ extern crate uhttp_sse;
 extern crate uhttp_status;
 extern crate uhttp_uri;
 extern crate uhttp_version;
+#[cfg(feature = "zmq")]
+extern crate zmq;
+#[cfg(feature = "zstd")]
+extern crate zstd;
 
 use std::{
-    fs::{File, OpenOptions},
-    io::{BufWriter, Write},
-    path::Path,
-    sync::mpsc::channel,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
 };
 
-use anyhow::Result;
-use clap::Parser;
-use env_logger::{Builder, Env};
+use anyhow::{bail, Context, Result};
+use clap::{
+    builder::{BoolishValueParser, PossibleValuesParser, TypedValueParser},
+    Args, CommandFactory, Parser, Subcommand, ValueEnum,
+};
 use log::LevelFilter;
-use rtlsdr_mt::TunerGains;
 
+mod alerts;
+mod aliases;
 mod audio;
+mod auth;
+mod band;
+mod baseband;
+mod bench;
+mod biquad;
+mod broadcast;
+mod calls;
+mod claim;
 mod consts;
+mod csvlog;
 mod demod;
+mod design;
+mod dibit;
+mod discrim;
+mod dump;
+mod eventlog;
+mod export;
+mod faults;
+mod fir;
+mod gaps;
+mod gen;
+mod grpc;
+mod health;
+mod hooks;
 mod http;
 mod hub;
+mod hunt;
+mod influx;
+mod iqcorr;
+mod latency;
+mod level;
+mod lifetime;
+mod logging;
+mod noise;
+mod pcap;
 mod policy;
+mod preroll;
+mod profiles;
+mod publish;
+mod queue;
+mod ratelimit;
+mod record;
 mod recv;
 mod replay;
+mod resample;
+mod responses;
+mod ring;
+mod rollup;
+mod scan;
+mod sched;
+mod schedule;
+mod scripts;
 mod sdr;
+mod settings;
+mod shift;
+mod signals;
+mod sites;
+mod squelch;
+mod stages;
+mod stereo;
+mod systemd;
 mod talkgroups;
+mod tap;
+#[cfg(test)]
+mod testutil;
+mod tgstats;
+mod trimport;
+mod tui;
+mod units;
+mod verify;
+mod vocoder;
+mod voting;
+mod www;
 
-use audio::{AudioOutput, AudioTask};
-use consts::{BASEBAND_SAMPLE_RATE, SDR_SAMPLE_RATE};
+use audio::{AudioFormat, AudioOutput, AudioTask, FifoSink};
+use band::{Avoid, AvoidList, BandMask};
+use baseband::{BasebandSink, BasebandWriter, SinkSet};
+use biquad::Biquad;
+use broadcast::EventBroadcast;
+use claim::DeviceClaim;
+use consts::{AUDIO_SAMPLE_RATE, BASEBAND_SAMPLE_RATE, BUF_BYTES, LOW_LATENCY_BUF_BYTES};
+use csvlog::MessageCsv;
 use demod::DemodTask;
+use design::FilterSpec;
+use dibit::DibitWriter;
+use discrim::Discriminator;
+use eventlog::EventLog;
+use export::FrameExport;
+use hooks::{Hook, Hooks};
+use http::CorsPolicy;
 use hub::HubTask;
+use influx::InfluxExport;
+use lifetime::LifetimeStats;
+use logging::LogFormat;
+use pcap::PcapWriter;
 use policy::ReceiverPolicy;
-use recv::RecvTask;
+use publish::Publisher;
+use queue::OverflowPolicy;
+use record::CallRecorder;
+use recv::{RecvEvent, RecvTask, WatchdogAction};
 use replay::ReplayReceiver;
-use sdr::{ControlTask, ReadTask};
+use sched::ThreadSched;
+use scripts::Scripts;
+use sdr::{
+    ControlTask, Controller, Device, DirectSampling, Gain, GainProfile, GainProfiles, OpenParams,
+    ReadTask, Reader,
+};
+use settings::SettingsFiles;
+use stereo::{Panner, StereoMode};
+use systemd::Notifier;
 use talkgroups::TalkgroupSelection;
+use tap::Tap;
+use tui::{LiveView, TuiTask};
+use units::UnitTable;
+use vocoder::VocoderSpec;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    log: LogArgs,
+}
 
+/// Options accepted without a subcommand, as before subcommands were added.
+///
+/// These are deprecated in favor of the equivalent subcommands.
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
-struct Args {
+struct LegacyCli {
+    #[command(flatten)]
+    log: LogArgs,
+
+    #[command(flatten)]
+    run: RunArgs,
+
+    /// replay from baseband samples in each FILE or directory of files
+    #[arg(short, long, num_args = 1..)]
+    replay: Vec<String>,
+
+    /// treat replayed files as symbol captures (one dibit per byte, as used by DSD and
+    /// OP25) instead of baseband samples
+    #[arg(long)]
+    replay_dibits: bool,
+}
+
+/// Options for log output.
+#[derive(Args)]
+struct LogArgs {
     /// enable verbose logging (pass twice to be extra verbose)
-    #[arg(short, long, action = clap::ArgAction::Count)]
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
     verbose: u8,
 
+    /// format of log output
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = LogFormat::Text,
+        global = true,
+        env = "P25RX_LOG_FORMAT",
+    )]
+    log_format: LogFormat,
+}
+
+/// Options for opening the RTL-SDR.
+#[derive(Args)]
+struct SdrArgs {
+    /// rtlsdr device index, or serial:SERIAL (use p25rx devices to show all)
+    #[arg(
+        short,
+        long,
+        default_value = "0",
+        value_parser = sdr::parse_device,
+        env = "P25RX_DEVICE"
+    )]
+    device: Device,
+
+    /// tuner gain in tenths of dB, or auto for hardware AGC (use p25rx gains to see all
+    /// options)
+    #[arg(
+        short,
+        long,
+        required = true,
+        value_parser = sdr::parse_gain,
+        env = "P25RX_GAIN"
+    )]
+    gain: Gain,
+
     /// ppm frequency adjustment
-    #[arg(short, long, default_value_t = 0)]
+    #[arg(short, long, default_value_t = 0, env = "P25RX_PPM")]
     ppm: i32,
 
-    /// file/fifo for audio samples (f32le/8kHz/mono)
-    #[arg(short, long, required = true)]
-    audio: String,
+    /// power an external LNA through the antenna input (on supported devices)
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "P25RX_BIAS_TEE")]
+    bias_tee: bool,
+
+    /// sample the I or Q branch input directly, bypassing the tuner, for HF converters
+    #[arg(long, value_enum, default_value = "off", env = "P25RX_DIRECT_SAMPLING")]
+    direct_sampling: DirectSampling,
+}
+
+impl SdrArgs {
+    /// Claim the RTL-SDR for this instance.
+    fn claim(&self) -> Result<DeviceClaim> {
+        sdr::claim(&self.device)
+    }
+
+    /// Open and configure the RTL-SDR.
+    fn open(&self) -> Result<(Controller, Reader)> {
+        sdr::open(
+            &self.device,
+            self.gain,
+            self.ppm,
+            self.bias_tee,
+            self.direct_sampling,
+        )
+    }
+}
+
+/// Options for decoded audio output.
+#[derive(Args)]
+struct AudioArgs {
+    /// file/fifo for audio samples, created as a fifo if it doesn't exist, or -
+    /// for stdout (no live audio if not given)
+    #[arg(short, long, env = "P25RX_AUDIO")]
+    audio: Option<String>,
+
+    /// audio sample format
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = AudioFormat::F32le,
+        env = "P25RX_AUDIO_FORMAT"
+    )]
+    audio_format: AudioFormat,
+
+    /// audio sample rate (Hz)
+    #[arg(
+        long,
+        default_value_t = AUDIO_SAMPLE_RATE,
+        value_parser = PossibleValuesParser::new(audio::AUDIO_RATES)
+            .map(|s| s.parse::<u32>().unwrap()),
+        env = "P25RX_AUDIO_RATE",
+    )]
+    audio_rate: u32,
+
+    /// write stereo audio, placing transmissions left/right by the given mode
+    #[arg(long, value_enum, env = "P25RX_STEREO")]
+    stereo: Option<StereoMode>,
+
+    /// talkgroups to place on the left in talkgroup stereo mode
+    #[arg(long, value_delimiter = ',', env = "P25RX_LEFT_TALKGROUPS")]
+    left_talkgroups: Vec<u16>,
+
+    /// talkgroups to place on the right in talkgroup stereo mode
+    #[arg(long, value_delimiter = ',', env = "P25RX_RIGHT_TALKGROUPS")]
+    right_talkgroups: Vec<u16>,
+
+    /// voice frame decoder: software, or a DVSI dongle with
+    /// dvsi:PATH[,baud=N][,channel=N]
+    #[arg(
+        long,
+        default_value = "software",
+        value_parser = vocoder::parse_spec,
+        env = "P25RX_VOCODER",
+    )]
+    vocoder: VocoderSpec,
 
-    /// tuner gain (use -g list to see all options)
-    #[arg(short, long, required = true)]
-    gain: String,
+    /// remove audio below the given frequency (Hz), e.g. sub-audible tones
+    #[arg(long, value_parser = biquad::parse_freq, env = "P25RX_AUDIO_HIGHPASS")]
+    audio_highpass: Option<f32>,
 
-    /// replay from baseband samples in FILE
-    #[arg(short, long)]
-    replay: Option<String>,
+    /// smooth audio above the given frequency (Hz)
+    #[arg(long, value_parser = biquad::parse_freq, env = "P25RX_AUDIO_LOWPASS")]
+    audio_lowpass: Option<f32>,
 
-    /// write baseband samples to FILE (f32le/48kHz/mono)
-    #[arg(short, long)]
-    write: Option<String>,
+    /// remove a narrow band of audio around each given frequency (Hz)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = biquad::parse_freq,
+        env = "P25RX_AUDIO_NOTCH",
+    )]
+    audio_notch: Vec<f32>,
+
+    /// audio output gain (dB)
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        env = "P25RX_AUDIO_GAIN"
+    )]
+    audio_gain: f32,
+
+    /// normalize audio loudness toward the given level (dBFS RMS, e.g. -20)
+    #[arg(long, allow_negative_numbers = true, env = "P25RX_AUDIO_NORMALIZE")]
+    audio_normalize: Option<f32>,
+
+    /// conceal or mute voice frames with more than N bit errors corrected, like noise
+    /// bursts at the end of calls
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = squelch::MAX_ERRORS,
+        env = "P25RX_SQUELCH_ERRORS",
+    )]
+    squelch_errors: usize,
+
+    /// never mute voice frames with bit errors
+    #[arg(
+        long,
+        conflicts_with = "squelch_errors",
+        value_parser = BoolishValueParser::new(),
+        env = "P25RX_NO_SQUELCH"
+    )]
+    no_squelch: bool,
+}
+
+/// Options for the live receiver.
+#[derive(Args)]
+struct RunArgs {
+    #[command(flatten)]
+    sdr: SdrArgs,
+
+    #[command(flatten)]
+    output: AudioArgs,
+
+    /// also write undecoded voice frames with call metadata to FILE, or to a UDP socket
+    /// with udp:HOST:PORT
+    #[arg(long, value_name = "FILE", env = "P25RX_EXPORT_FRAMES")]
+    export_frames: Option<String>,
+
+    /// record the audio of each call to a WAV file in DIR, served over HTTP at
+    /// /calls/ID/audio
+    #[arg(long, value_name = "DIR", env = "P25RX_RECORD")]
+    record: Option<PathBuf>,
+
+    /// keep the last MS of baseband, and decode voice from it on locking on to a call,
+    /// keeping the first words of transmissions that begin before the retune settles
+    #[arg(long, value_name = "MS", env = "P25RX_PRE_RECORD")]
+    pre_record: Option<u32>,
+
+    /// write baseband samples (f32le/48kHz/mono) to each TARGET: a file, tcp:HOST:PORT
+    /// to stream to a TCP listener, or zstd:FILE for a compressed file (requires the zstd
+    /// feature)
+    #[arg(short, long, value_name = "TARGET", env = "P25RX_WRITE")]
+    write: Vec<String>,
+
+    /// write sliced dibits to FILE as a symbol capture (one dibit per byte, as used by
+    /// DSD and OP25)
+    #[arg(long, value_name = "FILE", env = "P25RX_WRITE_DIBITS")]
+    write_dibits: Option<PathBuf>,
+
+    /// stream baseband samples (f32le/48kHz/mono) to each TCP client connecting to ADDR
+    #[arg(long, value_name = "ADDR", env = "P25RX_TAP_BASEBAND")]
+    tap_baseband: Option<SocketAddr>,
+
+    /// stream decoded audio (s16le/8kHz/mono) to each TCP client connecting to ADDR
+    #[arg(long, value_name = "ADDR", env = "P25RX_TAP_AUDIO")]
+    tap_audio: Option<SocketAddr>,
+
+    /// write decoded trunking and link control messages to FILE in pcapng format
+    #[arg(long, value_name = "FILE", env = "P25RX_PCAP")]
+    pcap: Option<PathBuf>,
+
+    /// append decoded trunking and link control messages to FILE as CSV rows
+    #[arg(long, value_name = "FILE", env = "P25RX_MESSAGE_CSV")]
+    message_csv: Option<PathBuf>,
+
+    /// stop after running for SECS, finishing any recordings
+    #[arg(long, value_name = "SECS", env = "P25RX_DURATION")]
+    duration: Option<u64>,
+
+    /// log every received trunking and link control message as hex with its opcode
+    #[arg(
+        long,
+        value_parser = BoolishValueParser::new(),
+        env = "P25RX_DUMP_CONTROL"
+    )]
+    dump_control: bool,
 
     /// frequency for initial control channel (Hz)
-    #[arg(short, long, required = true)]
+    #[arg(short, long, required = true, env = "P25RX_FREQ")]
     freq: u32,
 
-    /// rtlsdr device index (use -d list to show all)
-    #[arg(short, long, default_value = "0")]
-    device: String,
+    /// never follow calls on the given traffic channels, as frequencies (Hz) or
+    /// ID:NUMBER band identifiers and channel numbers
+    #[arg(
+        long,
+        value_name = "CHANNEL",
+        value_delimiter = ',',
+        value_parser = band::parse_avoid,
+        env = "P25RX_AVOID",
+    )]
+    avoid: Vec<Avoid>,
+
+    /// also follow unit-to-unit calls when no talkgroup call is available
+    #[arg(
+        long,
+        value_parser = BoolishValueParser::new(),
+        env = "P25RX_UNIT_CALLS"
+    )]
+    unit_calls: bool,
+
+    /// also follow telephone interconnect calls when no talkgroup call is available
+    #[arg(
+        long,
+        value_parser = BoolishValueParser::new(),
+        env = "P25RX_PHONE_CALLS"
+    )]
+    phone_calls: bool,
+
+    /// use GAIN instead of --gain when tuned within RANGE, e.g.
+    /// 851000000-855000000=280 (the first matching profile applies)
+    #[arg(
+        long,
+        value_name = "RANGE=GAIN",
+        value_delimiter = ',',
+        value_parser = sdr::parse_gain_profile,
+        env = "P25RX_GAIN_PROFILE",
+    )]
+    gain_profile: Vec<GainProfile>,
+
+    /// tune the SDR HZ away from each frequency (e.g. 50000) and shift the samples back,
+    /// keeping the channel clear of the DC spike at the center of the band
+    #[arg(
+        long,
+        value_name = "HZ",
+        default_value_t = 0,
+        allow_negative_numbers = true,
+        env = "P25RX_TUNE_OFFSET"
+    )]
+    tune_offset: i32,
+
+    /// design the filter decimating SDR samples to baseband from TAPS,CUTOFF[,ROLLOFF],
+    /// e.g. 95,9000,0.3, instead of using the built-in coefficients
+    #[arg(
+        long,
+        value_name = "SPEC",
+        value_parser = design::parse_spec,
+        env = "P25RX_DECIM_FILTER"
+    )]
+    decim_filter: Option<FilterSpec>,
+
+    /// design the channel-select filter from TAPS,CUTOFF[,ROLLOFF], e.g. 63,6250,0.2,
+    /// instead of using the built-in coefficients
+    #[arg(
+        long,
+        value_name = "SPEC",
+        value_parser = design::parse_spec,
+        env = "P25RX_CHANNEL_FILTER",
+    )]
+    channel_filter: Option<FilterSpec>,
+
+    /// FM discriminator recovering baseband: polar (the default), atan (cheapest), or pll
+    /// (most robust to fades and impulse noise, at the most CPU)
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Discriminator::Polar,
+        env = "P25RX_DISCRIMINATOR"
+    )]
+    discriminator: Discriminator,
+
+    /// don't remove the DC offset and I/Q imbalance of the SDR from its samples
+    #[arg(
+        long,
+        value_parser = BoolishValueParser::new(),
+        env = "P25RX_NO_IQ_CORRECTION"
+    )]
+    no_iq_correction: bool,
+
+    /// only allow tuning within the given frequency ranges (Hz), e.g. 851000000-869000000
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = band::parse_range,
+        env = "P25RX_BAND"
+    )]
+    band: Vec<(u32, u32)>,
+
+    /// HTTP socket bind address, e.g. [::]:8025 for IPv6 (can be given multiple times)
+    #[arg(
+        short,
+        long,
+        value_name = "ADDR",
+        default_value = "0.0.0.0:8025",
+        env = "P25RX_BIND"
+    )]
+    bind: Vec<SocketAddr>,
+
+    /// serve static files from DIR over HTTP, for custom web interfaces
+    #[arg(long, value_name = "DIR", env = "P25RX_WWW")]
+    www: Option<PathBuf>,
 
-    /// HTTP socket bind address
-    #[arg(short, long, default_value = "0.0.0.0:8025")]
-    bind: String,
+    /// append every event to FILE as JSON Lines, reopening the file on SIGHUP
+    #[arg(long, value_name = "FILE", env = "P25RX_EVENTLOG")]
+    eventlog: Option<PathBuf>,
+
+    /// send every event as a JSON datagram to ADDR, which can be a broadcast or multicast
+    /// address (can be given multiple times)
+    #[arg(long, value_name = "ADDR", env = "P25RX_BROADCAST")]
+    broadcast: Vec<SocketAddr>,
+
+    /// publish events and decoded audio on a ZeroMQ PUB socket bound to ENDPOINT, e.g.
+    /// tcp://*:5556 (requires the zmq feature)
+    #[arg(long, value_name = "ENDPOINT", env = "P25RX_ZMQ")]
+    zmq: Option<String>,
+
+    /// serve the gRPC API on ADDR, e.g. [::1]:8026 (requires the grpc feature)
+    #[arg(long, value_name = "ADDR", env = "P25RX_GRPC")]
+    grpc: Option<SocketAddr>,
+
+    /// show a live view of the receiver in the terminal instead of logging (requires the
+    /// tui feature)
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "P25RX_TUI")]
+    tui: bool,
+
+    /// load talkgroup names from FILE, with lines like 101,Fire Dispatch
+    #[arg(long, value_name = "FILE", env = "P25RX_ALIASES")]
+    aliases: Option<PathBuf>,
+
+    /// load talkgroup selection settings and names from the JSON FILE, reloading it
+    /// and the aliases file on SIGHUP
+    #[arg(long, value_name = "FILE", env = "P25RX_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// log a one-line summary of each call when it ends
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "P25RX_CALL_LOG")]
+    call_log: bool,
+
+    /// run the shell COMMAND on each EVENT (call-start, call-end, emergency, encrypted,
+    /// control-lost, or unit-seen), with the event as JSON on stdin
+    #[arg(
+        long,
+        value_name = "EVENT:COMMAND",
+        value_parser = hooks::parse_hook,
+        env = "P25RX_HOOK"
+    )]
+    hook: Vec<Hook>,
+
+    /// report activity by UNIT in grants, registrations, and voice traffic as unit-seen
+    /// events
+    #[arg(
+        long = "follow-unit",
+        value_name = "UNIT",
+        value_delimiter = ',',
+        env = "P25RX_FOLLOW_UNIT"
+    )]
+    follow_units: Vec<u32>,
+
+    /// choose calls granted to followed units over all other calls
+    #[arg(
+        long,
+        requires = "follow_units",
+        value_parser = BoolishValueParser::new(),
+        env = "P25RX_PREFER_FOLLOWED"
+    )]
+    prefer_followed: bool,
+
+    /// keep the unit registration and affiliation table in FILE across restarts
+    #[arg(long, value_name = "FILE", env = "P25RX_UNITS_DB")]
+    units_db: Option<PathBuf>,
+
+    /// keep cumulative call, airtime, and decoding statistics in FILE across restarts
+    #[arg(long, value_name = "FILE", env = "P25RX_STATS_DB")]
+    stats_db: Option<PathBuf>,
+
+    /// run the rhai scripts in DIR on events, reloading them on SIGHUP (requires the
+    /// scripts feature)
+    #[arg(long, value_name = "DIR", env = "P25RX_SCRIPTS")]
+    scripts: Option<PathBuf>,
+
+    /// send periodic measurements in InfluxDB line protocol to TARGET, either
+    /// udp:HOST:PORT or an HTTP write endpoint URL (which requires the alerts feature)
+    #[arg(long, value_name = "TARGET", env = "P25RX_INFLUX")]
+    influx: Option<String>,
+
+    /// time (sec) between measurements sent to InfluxDB
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 10,
+        requires = "influx",
+        env = "P25RX_INFLUX_INTERVAL"
+    )]
+    influx_interval: u64,
+
+    /// origin allowed to access the HTTP API from browsers (* for any, none to disable)
+    #[arg(
+        long,
+        default_value = "*",
+        value_parser = http::parse_cors,
+        env = "P25RX_CORS_ORIGIN"
+    )]
+    cors_origin: CorsPolicy,
+
+    /// most HTTP connections waiting to be handled at once, rejecting others with 429
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = hub::MAX_PENDING,
+        env = "P25RX_MAX_CONNECTIONS",
+    )]
+    max_connections: usize,
+
+    /// most HTTP requests per second from each client IP, rejecting others with 429
+    #[arg(long, value_name = "N", env = "P25RX_RATE_LIMIT")]
+    rate_limit: Option<f32>,
+
+    /// lowest rate (packets/sec) of trunking packets decoded on the control channel for
+    /// GET /healthz to report healthy
+    #[arg(
+        long,
+        value_name = "RATE",
+        default_value_t = health::DEFAULT_MIN_RATE,
+        env = "P25RX_HEALTH_MIN_RATE",
+    )]
+    health_min_rate: f32,
 
     /// disable frequency hopping
-    #[arg(short, long)]
+    #[arg(
+        short,
+        long,
+        value_parser = BoolishValueParser::new(),
+        env = "P25RX_NOHOP"
+    )]
     nohop: bool,
 
+    /// stay on the control channel without decoding audio, logging all unit activity
+    #[arg(
+        long,
+        conflicts_with_all = ["audio", "record", "export_frames", "tap_audio", "priority_sample"],
+        value_parser = BoolishValueParser::new(),
+        env = "P25RX_LOG_ONLY",
+    )]
+    log_only: bool,
+
     /// time (sec) to wait for voice message to be resumed
-    #[arg(short, long = "pause-timeout", default_value_t = 2.0)]
+    #[arg(
+        short,
+        long = "pause-timeout",
+        default_value_t = 2.0,
+        env = "P25RX_PAUSE_TIMEOUT"
+    )]
     pause: f32,
 
+    /// time (sec) to listen to the control channel during each voice message pause for
+    /// calls outranking the current call, by priority or preemption
+    #[arg(long, value_name = "SECS", env = "P25RX_PRIORITY_SAMPLE")]
+    priority_sample: Option<f32>,
+
     /// time (sec) to wait for voice message to begin
-    #[arg(short, long = "watchdog-timeout", default_value_t = 2.0)]
+    #[arg(
+        short,
+        long = "watchdog-timeout",
+        default_value_t = 2.0,
+        env = "P25RX_WATCHDOG_TIMEOUT"
+    )]
     watchdog: f32,
 
+    /// action taken when no voice is received before the watchdog timeout
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = WatchdogAction::Return,
+        env = "P25RX_WATCHDOG_ACTION",
+    )]
+    watchdog_action: WatchdogAction,
+
     /// time (sec) to collect talkgrouops before making a selection
-    #[arg(short, long = "tgselect-timeout", default_value_t = 1.0)]
+    #[arg(
+        short,
+        long = "tgselect-timeout",
+        default_value_t = 1.0,
+        env = "P25RX_TGSELECT_TIMEOUT"
+    )]
     tgselect: f32,
+
+    /// maximum number of messages held in each inter-task queue
+    #[arg(long, default_value_t = 256, env = "P25RX_QUEUE_SIZE")]
+    queue_size: usize,
+
+    /// action taken when a baseband, audio, or event queue is full, which only drops samples,
+    /// voice frames, and periodic measurements, never commands or changes of state
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OverflowPolicy::DropOldest,
+        env = "P25RX_OVERFLOW"
+    )]
+    overflow: OverflowPolicy,
+
+    /// shrink the SDR and audio output buffers to cut the delay from the air to the
+    /// audio output, at the cost of more CPU
+    #[arg(
+        long,
+        value_parser = BoolishValueParser::new(),
+        env = "P25RX_LOW_LATENCY"
+    )]
+    low_latency: bool,
+
+    /// second rtlsdr device, on its own antenna, to also decode the control channel
+    /// with, as an index or serial:SERIAL
+    #[arg(
+        long,
+        value_name = "DEVICE",
+        value_parser = sdr::parse_device,
+        env = "P25RX_DIVERSITY"
+    )]
+    diversity: Option<Device>,
+
+    /// ppm frequency adjustment of the diversity receiver
+    #[arg(
+        long,
+        default_value_t = 0,
+        requires = "diversity",
+        env = "P25RX_DIVERSITY_PPM"
+    )]
+    diversity_ppm: i32,
+
+    /// CPU core to pin the SDR reader thread to
+    #[arg(long, env = "P25RX_READER_CPU")]
+    reader_cpu: Option<usize>,
+
+    /// CPU core to pin the demodulator thread to
+    #[arg(long, env = "P25RX_DEMOD_CPU")]
+    demod_cpu: Option<usize>,
+
+    /// SCHED_FIFO priority (1-99) for the reader and demodulator threads
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(i32).range(1..=99),
+        env = "P25RX_RT_PRIORITY"
+    )]
+    rt_priority: Option<i32>,
+}
+
+impl RunArgs {
+    /// Describe the effective receiver configuration, logged in the given format, for
+    /// the HTTP API.
+    ///
+    /// Options that may carry credentials must be redacted here.
+    fn config(&self, log_format: LogFormat) -> serde_json::Value {
+        let output = &self.output;
+
+        let vocoder = match output.vocoder {
+            VocoderSpec::Software => json!({
+                "type": "software",
+            }),
+            VocoderSpec::Dvsi {
+                ref path,
+                baud,
+                channel,
+            } => json!({
+                "type": "dvsi",
+                "path": path,
+                "baud": baud,
+                "channel": channel,
+            }),
+        };
+
+        let cors = match self.cors_origin {
+            CorsPolicy::Disabled => "none",
+            CorsPolicy::Origin(ref origin) => &origin[..],
+        };
+
+        json!({
+            "logFormat": value_name(&log_format),
+            "ppm": self.sdr.ppm,
+            "gainProfiles": self
+                .gain_profile
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>(),
+            "tuneOffset": self.tune_offset,
+            "decimFilter": self.decim_filter.map(|f| f.to_string()),
+            "channelFilter": self.channel_filter.map(|f| f.to_string()),
+            "discriminator": value_name(&self.discriminator),
+            "iqCorrection": !self.no_iq_correction,
+            "biasTee": self.sdr.bias_tee,
+            "directSampling": value_name(&self.sdr.direct_sampling),
+            "audio": &output.audio,
+            "audioFormat": value_name(&output.audio_format),
+            "audioRate": output.audio_rate,
+            "audioGain": output.audio_gain,
+            "audioNormalize": output.audio_normalize,
+            "squelchErrors": (!output.no_squelch).then(|| output.squelch_errors),
+            "audioHighpass": output.audio_highpass,
+            "audioLowpass": output.audio_lowpass,
+            "audioNotch": &output.audio_notch,
+            "stereo": output.stereo.as_ref().map(value_name),
+            "leftTalkgroups": &output.left_talkgroups,
+            "rightTalkgroups": &output.right_talkgroups,
+            "vocoder": vocoder,
+            "exportFrames": &self.export_frames,
+            "record": &self.record,
+            "preRecord": self.pre_record,
+            "gain": self.sdr.gain.to_string(),
+            "write": &self.write,
+            "writeDibits": &self.write_dibits,
+            "tapBaseband": self.tap_baseband.map(|a| a.to_string()),
+            "tapAudio": self.tap_audio.map(|a| a.to_string()),
+            "pcap": &self.pcap,
+            "messageCsv": &self.message_csv,
+            "duration": self.duration,
+            "dumpControl": self.dump_control,
+            "freq": self.freq,
+            "band": &self.band,
+            "avoid": self.avoid.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+            "unitCalls": self.unit_calls,
+            "phoneCalls": self.phone_calls,
+            "device": self.sdr.device.to_string(),
+            "diversity": self.diversity.as_ref().map(|d| d.to_string()),
+            "diversityPpm": self.diversity_ppm,
+            "bind": &self.bind,
+            "www": &self.www,
+            "eventlog": &self.eventlog,
+            "broadcast": &self.broadcast,
+            "zmq": &self.zmq,
+            "grpc": &self.grpc,
+            "tui": self.tui,
+            "aliases": &self.aliases,
+            "configFile": &self.config,
+            "callLog": self.call_log,
+            // Commands may contain credentials.
+            "hooks": self.hook.iter().map(|h| h.event.name()).collect::<Vec<_>>(),
+            "scripts": &self.scripts,
+            // URLs may contain credentials.
+            "influx": self.influx.is_some(),
+            "influxInterval": self.influx_interval,
+            "followUnits": &self.follow_units,
+            "preferFollowed": self.prefer_followed,
+            "unitsDb": &self.units_db,
+            "statsDb": &self.stats_db,
+            "corsOrigin": cors,
+            "maxConnections": self.max_connections,
+            "rateLimit": self.rate_limit,
+            "healthMinRate": self.health_min_rate,
+            "nohop": self.nohop,
+            "logOnly": self.log_only,
+            "pauseTimeout": self.pause,
+            "prioritySample": self.priority_sample,
+            "watchdogTimeout": self.watchdog,
+            "watchdogAction": value_name(&self.watchdog_action),
+            "tgselectTimeout": self.tgselect,
+            "queueSize": self.queue_size,
+            "overflow": value_name(&self.overflow),
+            "lowLatency": self.low_latency,
+            "readerCpu": self.reader_cpu,
+            "demodCpu": self.demod_cpu,
+            "rtPriority": self.rt_priority,
+        })
+    }
+}
+
+/// Options for replaying recordings.
+#[derive(Args)]
+struct ReplayArgs {
+    /// baseband sample files (f32le/48kHz/mono), or directories of files, to replay
+    #[arg(required = true)]
+    files: Vec<String>,
+
+    /// treat files as symbol captures (one dibit per byte, as used by DSD and OP25)
+    /// instead of baseband samples
+    #[arg(long, value_parser = BoolishValueParser::new(), env = "P25RX_DIBITS")]
+    dibits: bool,
+
+    /// write sliced dibits to FILE as a symbol capture
+    #[arg(long, value_name = "FILE", env = "P25RX_WRITE_DIBITS")]
+    write_dibits: Option<PathBuf>,
+
+    /// write decoded trunking and link control messages to FILE in pcapng format
+    #[arg(long, value_name = "FILE", env = "P25RX_PCAP")]
+    pcap: Option<PathBuf>,
+
+    /// append decoded trunking and link control messages to FILE as CSV rows
+    #[arg(long, value_name = "FILE", env = "P25RX_MESSAGE_CSV")]
+    message_csv: Option<PathBuf>,
+
+    #[command(flatten)]
+    output: AudioArgs,
+}
+
+/// Create a pcapng file for decoded messages at the given path.
+fn open_pcap(path: &Path) -> Result<PcapWriter<BufWriter<File>>> {
+    info!("writing decoded messages to {}", path.display());
+
+    PcapWriter::create(path)
+        .with_context(|| format!("unable to create pcapng file {}", path.display()))
+}
+
+/// Open a CSV file for appending decoded messages at the given path.
+fn open_csv(path: &Path) -> Result<MessageCsv<BufWriter<File>>> {
+    info!("appending decoded messages to {}", path.display());
+
+    MessageCsv::append(path).with_context(|| format!("unable to open CSV file {}", path.display()))
+}
+
+/// Create a symbol capture file for sliced dibits at the given path.
+fn open_dibits(path: &Path) -> Result<DibitWriter> {
+    info!("writing dibits to {}", path.display());
+
+    DibitWriter::create(path)
+        .with_context(|| format!("unable to create dibit file {}", path.display()))
+}
+
+/// Get the command-line name of the given option value.
+fn value_name<T: ValueEnum>(v: &T) -> String {
+    v.to_possible_value()
+        .map(|p| p.get_name().to_string())
+        .unwrap_or_default()
+}
+
+#[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
+enum Command {
+    /// Receive a P25 trunking system with an RTL-SDR
+    Run(RunArgs),
+    /// Decode recorded baseband samples or symbol captures
+    Replay(ReplayArgs),
+    /// List attached RTL-SDR devices
+    Devices,
+    /// List the gains supported by an RTL-SDR tuner
+    Gains {
+        /// rtlsdr device index, or serial:SERIAL
+        #[arg(short, long, default_value = "0", value_parser = sdr::parse_device)]
+        device: Device,
+    },
+    /// Search frequency ranges for P25 control channels
+    Scan {
+        /// frequency ranges (Hz) to search, e.g. 851000000-869000000
+        #[arg(
+            required = true,
+            value_delimiter = ',',
+            value_parser = band::parse_range
+        )]
+        ranges: Vec<(u32, u32)>,
+
+        /// spacing (Hz) between searched frequencies
+        #[arg(long, default_value_t = 12500)]
+        step: u32,
+
+        /// time (sec) to listen on each frequency
+        #[arg(long, default_value_t = 0.5)]
+        dwell: f32,
+
+        #[command(flatten)]
+        sdr: SdrArgs,
+    },
+    /// Measure the tuner frequency error (ppm) using a known control channel
+    Calibrate {
+        /// control channel frequency (Hz)
+        #[arg(short, long)]
+        freq: u32,
+
+        /// time (sec) to measure for
+        #[arg(short, long, default_value_t = 5.0)]
+        secs: f32,
+
+        #[command(flatten)]
+        sdr: SdrArgs,
+    },
+    /// Find the tuner gain that best decodes a known control channel
+    TuneGain {
+        /// control channel frequency (Hz)
+        #[arg(short, long)]
+        freq: u32,
+
+        /// time (sec) to measure each gain for
+        #[arg(short, long, default_value_t = 3.0)]
+        secs: f32,
+
+        /// rtlsdr device index, or serial:SERIAL
+        #[arg(short, long, default_value = "0", value_parser = sdr::parse_device)]
+        device: Device,
+
+        /// ppm frequency adjustment
+        #[arg(short, long, default_value_t = 0)]
+        ppm: i32,
+
+        /// power an external LNA through the antenna input (on supported devices)
+        #[arg(long)]
+        bias_tee: bool,
+
+        /// sample the I or Q branch input directly, bypassing the tuner
+        #[arg(long, value_enum, default_value = "off")]
+        direct_sampling: DirectSampling,
+    },
+    /// Convert a trunk-recorder config.json into a p25rx settings file
+    ImportTr {
+        /// trunk-recorder config.json to convert
+        config: PathBuf,
+
+        /// settings file to write, for use with run --config
+        output: PathBuf,
+
+        /// shortName of the system to convert, instead of the first P25 system
+        #[arg(short, long)]
+        system: Option<String>,
+    },
+    /// Replay baseband samples and compare decoded events against a golden file
+    Verify {
+        /// baseband samples (f32le/48kHz/mono) to replay
+        replay: String,
+
+        /// JSON file of expected events
+        expected: String,
+
+        /// overwrite the expected events file with the decoded events
+        #[arg(short, long)]
+        update: bool,
+    },
+    /// Generate synthetic baseband samples (f32le/48kHz/mono) with voice grants/calls
+    Generate {
+        /// file to write baseband samples into
+        output: String,
+
+        /// talkgroups to generate voice grants for
+        #[arg(short, long, value_delimiter = ',', default_value = "1")]
+        talkgroups: Vec<u16>,
+
+        /// number of times to repeat the grant sequence
+        #[arg(short, long, default_value_t = 10)]
+        repeat: usize,
+
+        /// number of voice superframes (LDU1 and LDU2) to follow each grant sequence
+        #[arg(long, default_value_t = 0)]
+        voice: usize,
+
+        /// network access code
+        #[arg(long, default_value_t = 0x293)]
+        nac: u16,
+
+        /// standard deviation of added Gaussian noise
+        #[arg(long, default_value_t = 0.0)]
+        noise: f32,
+
+        /// carrier frequency offset (Hz)
+        #[arg(long, default_value_t = 0.0)]
+        offset: f32,
+
+        /// seed for generated noise
+        #[arg(long, default_value_t = 0)]
+        seed: usize,
+    },
+    /// Measure the throughput of each signal processing stage
+    Bench {
+        /// baseband samples (f32le/48kHz/mono) to use instead of generated samples
+        #[arg(short, long)]
+        input: Option<String>,
+
+        /// duration (sec) of generated samples
+        #[arg(short, long, default_value_t = 60.0)]
+        secs: f32,
+    },
+}
+
+/// Check if the given arguments use options without a subcommand, as before subcommands
+/// were added.
+fn is_legacy(args: &[String]) -> bool {
+    let cmd = Cli::command();
+
+    let is_command = |arg: &str| {
+        arg == "help"
+            || cmd
+                .get_subcommands()
+                .any(|c| c.get_name() == arg || c.get_all_aliases().any(|a| a == arg))
+    };
+
+    let is_info = |arg: &str| matches!(arg, "-h" | "--help" | "-V" | "--version");
+
+    !args[1..].iter().any(|a| is_command(a))
+        && args[1..].iter().any(|a| a.starts_with('-') && !is_info(a))
+}
+
+/// Parse options given without a subcommand, like `p25rx -f FREQ -g GAIN -a FILE` or
+/// `p25rx -d list`, into the equivalent command.
+fn parse_legacy(args: &[String]) -> Cli {
+    let device = option_value(args, "-d", "--device");
+
+    if device == Some("list") {
+        return Cli::parse_from(["p25rx", "devices"]);
+    }
+
+    if option_value(args, "-g", "--gain") == Some("list") {
+        return Cli::parse_from(["p25rx", "gains", "--device", device.unwrap_or("0")]);
+    }
+
+    let legacy = LegacyCli::parse_from(args);
+
+    let command = if legacy.replay.is_empty() {
+        Command::Run(legacy.run)
+    }
+    else {
+        Command::Replay(ReplayArgs {
+            files: legacy.replay,
+            dibits: legacy.replay_dibits,
+            write_dibits: legacy.run.write_dibits,
+            pcap: legacy.run.pcap,
+            output: legacy.run.output,
+        })
+    };
+
+    Cli {
+        command,
+        log: legacy.log,
+    }
+}
+
+/// Get the value given to the option with the given short or long name, if any.
+fn option_value<'a>(args: &'a [String], short: &str, long: &str) -> Option<&'a str> {
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if arg == short || arg == long {
+            args.get(i + 1).map(|s| &s[..])
+        }
+        else {
+            arg.strip_prefix(long)
+                .or_else(|| arg.strip_prefix(short))
+                .and_then(|v| v.strip_prefix('='))
+        }
+    })
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let argv = std::env::args().collect::<Vec<_>>();
+    let legacy = is_legacy(&argv);
+
+    let cli = if legacy {
+        parse_legacy(&argv)
+    }
+    else {
+        Cli::parse()
+    };
 
     {
-        let level = match args.verbose {
+        let tui =
+            cfg!(feature = "tui") && matches!(cli.command, Command::Run(ref args) if args.tui);
+
+        // Log lines would be drawn over the terminal view.
+        let level = match cli.log.verbose {
+            _ if tui => LevelFilter::Off,
             0 => LevelFilter::Info,
             1 => LevelFilter::Debug,
             _ => LevelFilter::Trace,
         };
 
-        Builder::from_env(Env::default()).filter(None, level).init();
+        logging::init(cli.log.log_format, level);
     }
 
-    let audio_out = || {
-        let path = args.audio;
-        info!("writing audio frames to {}", path);
+    if legacy {
+        warn!(
+            "options without a subcommand are deprecated and will be removed in the next \
+             release: use p25rx run, replay, devices, or gains instead"
+        );
+    }
 
-        // Create audio fifo if it does not already exist.
-        match Path::new(&path).exists() {
-            true => {
-                info!("File {path} already exists, no need to create it.");
-            }
-            false => unsafe {
-                match libc::mkfifo(path.as_ptr() as *const c_char, 0o644) {
-                    0 => {
-                        info!("File {path} created, ready to use.");
+    match cli.command {
+        Command::Run(args) => run(args, cli.log.log_format),
+        Command::Replay(args) => replay(args),
+        Command::Devices => sdr::print_devices(),
+        Command::Gains {
+            device,
+        } => sdr::print_gains(&device),
+        Command::Scan {
+            ranges,
+            step,
+            dwell,
+            sdr,
+        } => scan(&scan::frequencies(&ranges, step), dwell, &sdr),
+        Command::Calibrate {
+            freq,
+            secs,
+            sdr,
+        } => calibrate(freq, secs, &sdr),
+        Command::TuneGain {
+            freq,
+            secs,
+            device,
+            ppm,
+            bias_tee,
+            direct_sampling,
+        } => {
+            let sdr = SdrArgs {
+                device,
+                // Replaced by each tuner gain in turn.
+                gain: Gain::Auto,
+                ppm,
+                bias_tee,
+                direct_sampling,
+            };
+
+            tune_gain(freq, secs, &sdr)
+        }
+        Command::ImportTr {
+            config,
+            output,
+            system,
+        } => import_tr(&config, &output, system.as_deref()),
+        Command::Verify {
+            replay,
+            expected,
+            update,
+        } => verify::verify(Path::new(&replay), Path::new(&expected), update),
+        Command::Generate {
+            output,
+            talkgroups,
+            repeat,
+            voice,
+            nac,
+            noise,
+            offset,
+            seed,
+        } => {
+            let mut g = gen::Generator::new(nac, noise, offset, seed);
+            let mut samples = vec![];
+
+            g.idle(100, &mut samples);
+
+            for _ in 0..repeat {
+                g.tsbk(
+                    &[gen::channel_params_update(1, 851006250, 6250)],
+                    &mut samples,
+                );
+
+                for (ch, &tg) in talkgroups.iter().enumerate() {
+                    g.tsbk(
+                        &[gen::group_voice_grant(tg, 0x1000 | ch as u16, 1)],
+                        &mut samples,
+                    );
+                }
+
+                for i in 0..voice {
+                    let mut frames = [[0; 8]; 9];
+
+                    for (j, f) in frames.iter_mut().enumerate() {
+                        *f = gen::voice_pattern(i * 18 + j);
                     }
-                    _ => {
-                        panic!("Unable to create fifo {path}.");
+
+                    g.ldu1(&frames, &mut samples);
+
+                    for (j, f) in frames.iter_mut().enumerate() {
+                        *f = gen::voice_pattern(i * 18 + 9 + j);
                     }
+
+                    g.ldu2(&frames, &mut samples);
                 }
-            },
-        };
 
-        AudioOutput::new(BufWriter::new(
+                if voice > 0 {
+                    g.tdu(&mut samples);
+                }
+            }
+
+            g.idle(100, &mut samples);
+
+            File::create(output)?.write_all(unsafe { slice_cast::cast(&samples[..]) })?;
+
+            Ok(())
+        }
+        Command::Bench {
+            input,
+            secs,
+        } => {
+            let samples = match input {
+                Some(path) => {
+                    let mut bytes = vec![];
+                    File::open(path)?.read_to_end(&mut bytes)?;
+
+                    let samples: &[f32] = unsafe { slice_cast::cast(&bytes[..]) };
+                    samples.to_vec()
+                }
+                None => bench::generate(secs),
+            };
+
+            bench::bench(&samples[..]);
+
+            Ok(())
+        }
+    }
+}
+
+/// Open the audio output as either stdout or a FIFO/file path, creating a FIFO if the
+/// path doesn't exist, or discard audio if no output was given. Replay writes block so
+/// no audio is dropped, while the live receiver never blocks on audio, and can shrink a
+/// FIFO to the given capacity (bytes).
+fn audio_stream(
+    args: &AudioArgs,
+    blocking: bool,
+    pipe_size: Option<usize>,
+) -> Result<Box<dyn Write + Send>> {
+    let path = match args.audio.as_deref() {
+        Some("-") => {
+            info!("writing audio frames to stdout");
+            return Ok(Box::new(io::stdout()));
+        }
+        Some(path) => Path::new(path),
+        None => {
+            // Audio is still decoded for recordings and HTTP listeners.
+            info!("not writing live audio");
+            return Ok(Box::new(io::sink()));
+        }
+    };
+
+    info!("writing audio frames to {}", path.display());
+
+    if !path.exists() {
+        audio::create_fifo(path)
+            .with_context(|| format!("unable to create fifo {}", path.display()))?;
+        info!("created fifo {}", path.display());
+    }
+
+    Ok(if blocking {
+        Box::new(BufWriter::new(
             OpenOptions::new()
                 .write(true)
                 .open(path)
-                .expect("unable to open audio output file"),
+                .context("unable to open audio output file")?,
         ))
-    };
+    }
+    else {
+        let fifo = FifoSink::new(path);
+
+        match pipe_size {
+            Some(bytes) => Box::new(fifo.pipe_size(bytes)),
+            None => Box::new(fifo),
+        }
+    })
+}
 
-    if let Some(path) = args.replay {
-        let mut stream = File::open(path).expect("unable to open replay file");
-        let mut recv = ReplayReceiver::new(audio_out());
+/// Wrap the given stream with the configured audio processing.
+fn audio_output<W: Write>(args: &AudioArgs, stream: W) -> Result<AudioOutput<W>> {
+    let vocoder = args.vocoder.open().context("unable to open vocoder")?;
 
-        recv.replay(&mut stream);
+    let mut audio = AudioOutput::new(stream, args.audio_format, args.audio_rate)
+        .vocoder(vocoder)
+        .gain(args.audio_gain);
 
-        return Ok(());
+    if !args.no_squelch {
+        audio = audio.squelch(args.squelch_errors);
     }
 
-    let samples_file = args
-        .write
-        .map(|path| File::create(path).expect("unable to open baseband file"));
+    if let Some(freq) = args.audio_highpass {
+        audio = audio.filter(Biquad::highpass(freq));
+    }
 
-    let dev: u32 = match &args.device[..] {
-        "list" => {
-            for (idx, name) in rtlsdr_mt::devices().enumerate() {
-                println!("{}: {}", idx, name.to_str().unwrap());
-            }
+    if let Some(freq) = args.audio_lowpass {
+        audio = audio.filter(Biquad::lowpass(freq));
+    }
 
-            return Ok(());
-        }
-        s => s.parse().expect("invalid device index"),
+    for &freq in &args.audio_notch {
+        audio = audio.filter(Biquad::notch(freq));
+    }
+
+    if let Some(mode) = args.stereo {
+        audio = audio.stereo(Panner::new(
+            mode,
+            &args.left_talkgroups,
+            &args.right_talkgroups,
+        ));
+    }
+
+    Ok(match args.audio_normalize {
+        Some(target) => audio.normalize(target),
+        None => audio,
+    })
+}
+
+/// Decode each of the given recordings, writing a summary of each.
+fn replay(args: ReplayArgs) -> Result<()> {
+    let paths = replay::collect_paths(&args.files)?;
+    let mut recv = ReplayReceiver::new(audio_output(
+        &args.output,
+        audio_stream(&args.output, true, None)?,
+    )?);
+
+    if let Some(ref path) = args.pcap {
+        recv = recv.pcap(open_pcap(path)?);
+    }
+
+    if let Some(ref path) = args.message_csv {
+        recv = recv.message_csv(open_csv(path)?);
+    }
+
+    if let Some(ref path) = args.write_dibits {
+        recv = recv.dibits(open_dibits(path)?);
+    }
+
+    // Keep summaries out of the audio stream if it's going to stdout.
+    let mut summaries: Box<dyn Write> = if args.output.audio.as_deref() == Some("-") {
+        Box::new(io::stderr())
+    }
+    else {
+        Box::new(io::stdout())
     };
 
-    info!("opening RTL-SDR at index {}", dev);
-    let (mut control, reader) = rtlsdr_mt::open(dev).expect("unable to open rtlsdr");
+    for path in paths {
+        info!("replaying {}", path.display());
+
+        let res = File::open(&path)
+            .with_context(|| format!("unable to open replay file {}", path.display()))
+            .and_then(|mut stream| {
+                if args.dibits {
+                    recv.replay_dibits(&mut stream)
+                }
+                else {
+                    recv.replay(&mut stream)
+                }
+            });
 
-    match &args.gain[..] {
-        "list" => {
-            let mut gains = TunerGains::default();
+        // Emit one summary object per line, noting files that couldn't be replayed
+        // rather than giving up on the rest.
+        let line = match res {
+            Ok(summary) => serde_json::to_string(&summary.serialize(&path))?,
+            Err(e) => {
+                error!("{:#}", e);
 
-            for g in control.tuner_gains(&mut gains) {
-                println!("{}", g);
+                serde_json::to_string(&json!({
+                    "file": path.display().to_string(),
+                    "error": format!("{:#}", e),
+                }))?
             }
+        };
 
-            println!("auto");
+        writeln!(summaries, "{}", line)?;
+    }
 
-            return Ok(());
-        }
-        "auto" => {
-            info!("enabling hardware AGC");
-            control.enable_agc().expect("unable to enable agc");
+    Ok(())
+}
+
+/// Listen to each of the given frequencies (Hz) for the given time (sec), printing those
+/// carrying a control channel.
+fn scan(freqs: &[u32], dwell: f32, sdr: &SdrArgs) -> Result<()> {
+    let _claim = sdr.claim()?;
+    let (mut control, mut reader) = sdr.open()?;
+
+    info!(
+        "searching {} frequencies for about {:.0} sec",
+        freqs.len(),
+        freqs.len() as f32 * dwell
+    );
+
+    let mut found = 0;
+
+    scan::survey(&mut control, &mut reader, freqs, time_samples(dwell), |m| {
+        debug!(
+            "{} Hz: {:.1} dBm, {} trunking packets",
+            m.freq, m.power, m.tsbks
+        );
+
+        if m.tsbks > 0 {
+            found += 1;
+            println!(
+                "{} Hz: control channel, {:.1} dBm, {} trunking packets",
+                m.freq, m.power, m.tsbks
+            );
         }
-        s => {
-            let gain = s.parse().expect("invalid gain");
-            info!("setting hardware gain to {:.1} dB", gain as f32 / 10.0);
-            control.set_tuner_gain(gain).expect("unable to set gain");
+    })?;
+
+    info!("found {} control channels", found);
+
+    Ok(())
+}
+
+/// Measure the tuner frequency error by listening to the control channel at the given
+/// frequency (Hz) for the given time (sec), printing the corrected ppm.
+fn calibrate(freq: u32, secs: f32, sdr: &SdrArgs) -> Result<()> {
+    let _claim = sdr.claim()?;
+    let (mut control, mut reader) = sdr.open()?;
+    let mut measured = None;
+
+    info!("measuring {} Hz for {:.0} sec", freq, secs);
+    scan::survey(
+        &mut control,
+        &mut reader,
+        &[freq],
+        time_samples(secs),
+        |m| measured = Some(m.clone()),
+    )?;
+
+    let m = measured.context("no samples received")?;
+
+    if m.tsbks == 0 {
+        bail!("no control channel decoded at {} Hz", freq);
+    }
+
+    info!(
+        "carrier offset {:.0} Hz at {:.1} dBm, {} trunking packets",
+        m.offset, m.power, m.tsbks
+    );
+
+    println!("{}", scan::correct_ppm(&m, sdr.ppm));
+
+    Ok(())
+}
+
+/// Measure the control channel at the given frequency (Hz) with each tuner gain for the
+/// given time (sec), printing the gain that decodes it best.
+fn tune_gain(freq: u32, secs: f32, sdr: &SdrArgs) -> Result<()> {
+    let _claim = sdr.claim()?;
+    let (mut control, mut reader) = sdr.open()?;
+    let gains = sdr::tuner_gains(&mut control);
+    let mut results = vec![];
+
+    info!(
+        "measuring {} gains at {} Hz for about {:.0} sec",
+        gains.len(),
+        freq,
+        gains.len() as f32 * secs
+    );
+
+    scan::sweep_gains(
+        &mut control,
+        &mut reader,
+        freq,
+        &gains,
+        time_samples(secs),
+        |g, m| {
+            info!(
+                "{:.1} dB: {:.1} trunking packets/sec, {:.1} dBm, {:.1} dB SNR",
+                g as f32 / 10.0,
+                m.tsbks as f32 / secs,
+                m.power,
+                m.snr
+            );
+
+            results.push((g, m.clone()));
+        },
+    )?;
+
+    let best = scan::best_gain(&results)
+        .with_context(|| format!("no control channel decoded at {} Hz", freq))?;
+
+    info!("best gain {:.1} dB", best as f32 / 10.0);
+    println!("{}", best);
+
+    Ok(())
+}
+
+/// Convert the given trunk-recorder config into the given settings file, printing the
+/// options to run the converted system with.
+fn import_tr(config: &Path, output: &Path, system: Option<&str>) -> Result<()> {
+    let c = trimport::convert(config, system)?;
+
+    let text = serde_json::to_string_pretty(&c.settings).context("unable to encode settings")?;
+    fs::write(output, text + "\n")
+        .with_context(|| format!("unable to write {}", output.display()))?;
+
+    info!("converted system {} into {}", c.system, output.display());
+
+    if c.skipped > 0 {
+        warn!("skipped {} talkgroups too large for P25", c.skipped);
+    }
+
+    if c.control_channels.len() > 1 {
+        info!("secondary control channels are learned from the system while running");
+    }
+
+    if c.error_hz {
+        warn!("tuning error in Hz isn't converted; measure --ppm with p25rx calibrate");
+    }
+
+    let mut cmd = format!("p25rx run -f {}", c.control_channels[0]);
+
+    match c.gain {
+        Some(g) => cmd += &format!(" -g {}", g),
+        None => cmd += " -g auto",
+    }
+
+    if let Some(ppm) = c.ppm {
+        cmd += &format!(" --ppm {}", ppm);
+    }
+
+    if let Some(dev) = c.device {
+        cmd += &format!(" -d {}", dev);
+    }
+
+    println!("{} --config {}", cmd, output.display());
+
+    Ok(())
+}
+
+/// Run the live receiver, logging in the given format.
+fn run(args: RunArgs, log_format: LogFormat) -> Result<()> {
+    let config = args.config(log_format);
+
+    if args.tui && !cfg!(feature = "tui") {
+        bail!(tui::NO_TUI);
+    }
+
+    if args.tui && args.output.audio.as_deref() == Some("-") {
+        bail!("audio can't be written to stdout while showing the terminal view");
+    }
+
+    // Both filters pass the baseband, so anything above its Nyquist frequency aliases.
+    for spec in args.decim_filter.iter().chain(args.channel_filter.iter()) {
+        if spec.cutoff >= BASEBAND_SAMPLE_RATE as f32 / 2.0 {
+            bail!(
+                "filter cutoff must be below {} Hz",
+                BASEBAND_SAMPLE_RATE / 2
+            );
         }
     }
 
+    if args.tune_offset.unsigned_abs() > shift::MAX_OFFSET {
+        bail!("tuning offset must be at most {} Hz", shift::MAX_OFFSET);
+    }
+
+    let files = SettingsFiles {
+        config: args.config.clone(),
+        aliases: args.aliases.clone(),
+    };
+
+    let settings = files.load()?;
+
+    let mut outputs = SinkSet::default();
+
+    for target in &args.write {
+        info!("writing baseband to {}", target);
+        outputs.open(target)?;
+    }
+
+    if let Some(addr) = args.tap_baseband {
+        let tap = Tap::bind("baseband", addr)
+            .with_context(|| format!("unable to listen for baseband tap clients on {}", addr))?;
+
+        info!("serving baseband tap on {}", addr);
+        outputs.add(format!("tap {}", addr), Box::new(tap));
+    }
+
+    // Held until the receiver exits, so other instances leave the devices alone even while
+    // they're released by a pause.
+    let _claim = args.sdr.claim()?;
+    let _diversity_claim = match args.diversity {
+        Some(ref dev) => Some(sdr::claim(dev).context("unable to claim diversity receiver")?),
+        None => None,
+    };
+
+    let (control, reader) = args.sdr.open()?;
+
+    let diversity = match args.diversity {
+        Some(ref dev) => {
+            info!("opening diversity receiver {}", dev);
+
+            let sdr = sdr::open(
+                dev,
+                args.sdr.gain,
+                args.diversity_ppm,
+                args.sdr.bias_tee,
+                args.sdr.direct_sampling,
+            )
+            .context("unable to open diversity receiver")?;
+
+            Some(sdr)
+        }
+        None => None,
+    };
+
     let pause = time_samples(args.pause);
     let watchdog = time_samples(args.watchdog);
     let tgselect = time_samples(args.tgselect);
 
-    info!("setting frequency offset to {} PPM", args.ppm);
-    control.set_ppm(args.ppm).expect("unable to set ppm");
-    control
-        .set_sample_rate(SDR_SAMPLE_RATE)
-        .expect("unable to set sample rate");
+    let band = BandMask::new(args.band);
+
+    band.check(args.freq)
+        .map_err(anyhow::Error::msg)
+        .context("invalid control channel frequency")?;
 
     info!("using control channel frequency {} Hz", args.freq);
 
-    let (tx_ctl, rx_ctl) = channel();
-    let (tx_recv, rx_recv) = channel();
-    let (tx_read, rx_read) = channel();
-    let (tx_audio, rx_audio) = channel();
-    let (tx_hub, rx_hub) = mio_extras::channel::channel();
+    // Frequency changes must never be dropped, so the SDR control queue always blocks.
+    let (tx_ctl, rx_ctl) = queue::queue("control", args.queue_size, OverflowPolicy::Block);
+    let (tx_recv, rx_recv) = queue::queue("baseband", args.queue_size, args.overflow);
+    let buf_bytes = if args.low_latency {
+        LOW_LATENCY_BUF_BYTES
+    }
+    else {
+        BUF_BYTES
+    };
+
+    let (tx_read, rx_read) = ring::ring(16, buf_bytes);
+    let (tx_audio, rx_audio) = queue::queue("audio", args.queue_size, args.overflow);
+    let (tx_hub, rx_hub) = queue::evented_queue("hub", args.queue_size, args.overflow);
+
+    // A slow disk drops baseband rather than stalling decoding.
+    let (tx_write, rx_write) = queue::queue("write", args.queue_size, OverflowPolicy::DropNewest);
+
+    let mut queues = vec![
+        tx_ctl.metrics(),
+        tx_recv.metrics(),
+        tx_audio.metrics(),
+        tx_hub.metrics(),
+    ];
+
+    // Dibits are sliced on the receiver thread, and baseband outputs written by the
+    // writer task.
+    let mut sinks = SinkSet::default();
+
+    if let Some(ref path) = args.write_dibits {
+        sinks.add(path.display().to_string(), Box::new(open_dibits(path)?));
+    }
+
+    let writer = if outputs.is_empty() {
+        None
+    }
+    else {
+        queues.push(tx_write.metrics());
+        sinks.add("write", Box::new(tx_write));
+
+        Some(BasebandWriter::new(outputs, rx_write))
+    };
+
+    let mut policy = ReceiverPolicy::new(tgselect, watchdog, pause);
+
+    if let Some(secs) = args.priority_sample {
+        if secs >= args.pause {
+            bail!("priority sample time must be shorter than the pause timeout");
+        }
+
+        policy = policy.priority_sampling(time_samples(secs));
+    }
+    let mut talkgroups = TalkgroupSelection::default();
+    talkgroups.configure(settings.talkgroups.clone());
+
+    for addr in &args.bind {
+        info!("starting HTTP server at http://{}", addr);
+    }
+
+    let mut hub = HubTask::new(rx_hub, tx_recv.clone(), &args.bind, queues)?;
+    hub = hub
+        .cors(args.cors_origin)
+        .band(band.clone())
+        .config(config)
+        .settings(files.clone(), settings.clone());
+
+    if args.call_log {
+        hub = hub.call_log();
+    }
+
+    if args.log_only {
+        info!("logging only, without decoding audio");
+        hub = hub.activity_log();
+    }
+
+    if !args.hook.is_empty() {
+        hub = hub.hooks(Hooks::new(args.hook.clone()));
+    }
+
+    if let Some(ref path) = args.eventlog {
+        let log = EventLog::open(path)
+            .with_context(|| format!("unable to open event log {}", path.display()))?;
+
+        info!("logging events to {}", path.display());
+        hub = hub.eventlog(log);
+    }
+
+    if !args.broadcast.is_empty() {
+        let b = EventBroadcast::open(&args.broadcast).context("unable to open broadcast socket")?;
+
+        info!("broadcasting events to {} targets", args.broadcast.len());
+        hub = hub.broadcast(b);
+    }
+
+    if let Some(ref endpoint) = args.zmq {
+        let p = Publisher::bind(endpoint)
+            .with_context(|| format!("unable to bind ZeroMQ publisher to {}", endpoint))?;
+
+        info!("publishing events and audio on {}", endpoint);
+        hub = hub.publisher(p);
+    }
+
+    if let Some(addr) = args.grpc {
+        let g = grpc::serve(addr, tx_recv.clone(), tx_hub.clone(), band.clone())
+            .context("unable to start gRPC server")?;
+
+        info!("serving gRPC API on {}", addr);
+        hub = hub.grpc(g);
+    }
+
+    if !args.follow_units.is_empty() {
+        hub = hub.follow_units(&args.follow_units);
+    }
+
+    if let Some(ref path) = args.units_db {
+        let table = if path.exists() {
+            UnitTable::load(path)?
+        }
+        else {
+            UnitTable::default()
+        };
+
+        info!("saving unit database to {}", path.display());
+        hub = hub.unit_db(path.clone(), table);
+    }
+
+    if let Some(ref path) = args.stats_db {
+        let stats = if path.exists() {
+            LifetimeStats::load(path)?
+        }
+        else {
+            LifetimeStats::default()
+        };
+
+        info!("saving statistics to {}", path.display());
+        hub = hub.stats_db(path.clone(), stats);
+    }
+
+    if let Some(ref dir) = args.scripts {
+        let scripts = Scripts::load(dir)?;
+
+        info!("loaded {} scripts from {}", scripts.count(), dir.display());
+        hub = hub.scripts(scripts);
+    }
+
+    if let Some(ref target) = args.influx {
+        if args.influx_interval == 0 {
+            bail!("influx interval must be positive");
+        }
+
+        let influx = InfluxExport::open(target, Duration::from_secs(args.influx_interval))
+            .with_context(|| format!("unable to open influx target {}", target))?;
+
+        info!("sending measurements every {}s", args.influx_interval);
+        hub = hub.influx(influx);
+    }
+
+    if args.eventlog.is_some() || !files.is_empty() || args.scripts.is_some() {
+        signals::handle_sighup().context("unable to handle SIGHUP")?;
+    }
+
+    hub = hub
+        .max_connections(args.max_connections)
+        .discriminator(args.discriminator);
+
+    if let Some(rate) = args.rate_limit {
+        if rate.is_nan() || rate <= 0.0 {
+            bail!("rate limit must be positive");
+        }
+
+        hub = hub.rate_limit(rate);
+    }
+
+    if args.health_min_rate.is_nan() || args.health_min_rate < 0.0 {
+        bail!("health check rate must not be negative");
+    }
+
+    hub = hub.health_rate(args.health_min_rate);
+
+    if let Some(dir) = args.www {
+        info!("serving static files from {}", dir.display());
+        hub = hub.serve_static(dir);
+    }
 
-    let policy = ReceiverPolicy::new(tgselect, watchdog, pause);
-    let talkgroups = TalkgroupSelection::default();
+    let tui = if args.tui {
+        let view = Arc::new(Mutex::new(LiveView::new(settings.aliases)));
+        hub = hub.view(view.clone());
 
-    info!("starting HTTP server at http://{}", args.bind);
-    let mut hub = HubTask::new(rx_hub, tx_recv.clone(), &args.bind.parse()?)?;
-    let mut control = ControlTask::new(control, rx_ctl);
-    let mut read = ReadTask::new(tx_read);
-    let mut demod = DemodTask::new(rx_read, tx_hub.clone(), tx_recv.clone());
+        Some(TuiTask::new(view))
+    }
+    else {
+        None
+    };
+    // Pausing through the API cancels reading and resumes it through this channel.
+    let (tx_pause, rx_pause) = mpsc::channel();
+
+    let mut control = ControlTask::new(control, args.sdr.gain, rx_ctl).pausable(
+        tx_pause,
+        OpenParams {
+            device: args.sdr.device.clone(),
+            ppm: args.sdr.ppm,
+            bias_tee: args.sdr.bias_tee,
+            direct: args.sdr.direct_sampling,
+        },
+        tx_recv.clone(),
+    );
+
+    control = control
+        .tune_offset(args.tune_offset)
+        .report_errors(tx_hub.clone());
+
+    if !args.gain_profile.is_empty() {
+        control =
+            control.gain_profiles(GainProfiles::new(args.sdr.gain, args.gain_profile.clone()));
+    }
+
+    // The diversity receiver has its own reader and demodulator, feeding the same
+    // receiver task as the primary.
+    let diversity = match diversity {
+        Some((sdr, reader)) => {
+            control = control.diversity(sdr);
+
+            let (tx, rx) = ring::ring(16, buf_bytes);
+            let demod = DemodTask::new(rx, tx_hub.clone(), tx_recv.clone())
+                .discriminator(args.discriminator)
+                .tune_offset(args.tune_offset)
+                .diversity();
+            let demod = demod_filters(
+                demod,
+                args.decim_filter,
+                args.channel_filter,
+                args.no_iq_correction,
+            );
+
+            Some((reader, ReadTask::new(tx).buffer_size(buf_bytes), demod))
+        }
+        None => None,
+    };
+
+    let mut read = ReadTask::new(tx_read)
+        .buffer_size(buf_bytes)
+        .pausable(rx_pause)
+        .report_drops(tx_hub.clone());
+    let mut demod = demod_filters(
+        DemodTask::new(rx_read, tx_hub.clone(), tx_recv.clone())
+            .discriminator(args.discriminator)
+            .tune_offset(args.tune_offset),
+        args.decim_filter,
+        args.channel_filter,
+        args.no_iq_correction,
+    );
     let mut recv = RecvTask::new(
         rx_recv,
         tx_hub.clone(),
         tx_ctl.clone(),
-        tx_audio.clone(),
+        (!args.log_only).then(|| tx_audio.clone()),
         args.freq,
-        !args.nohop,
+        !args.nohop && !args.log_only,
         policy,
         talkgroups,
-    );
-    let mut audio = AudioTask::new(audio_out(), rx_audio);
+    )
+    .baseband_seq(demod.baseband_seq());
+
+    if args.prefer_followed {
+        recv = recv.prefer_units(&args.follow_units);
+    }
+
+    if !args.avoid.is_empty() {
+        recv = recv.avoid(AvoidList::new(args.avoid.clone()));
+    }
+
+    if let Some(ms) = args.pre_record {
+        recv = recv.pre_record(ms);
+    }
+
+    if args.watchdog_action != WatchdogAction::Return {
+        recv = recv.watchdog_action(args.watchdog_action);
+    }
+
+    if let Some((_, _, ref demod)) = diversity {
+        recv = recv.diversity(demod.baseband_seq());
+    }
+
+    if args.dump_control {
+        recv = recv.dump_control();
+    }
+
+    if args.unit_calls || args.phone_calls {
+        recv = recv.private_calls(args.unit_calls, args.phone_calls);
+    }
+
+    if let Some(ref path) = args.pcap {
+        recv = recv.pcap(open_pcap(path)?);
+    }
+
+    if let Some(ref path) = args.message_csv {
+        recv = recv.message_csv(open_csv(path)?);
+    }
+
+    if let Some(notifier) = Notifier::from_env().context("unable to connect to systemd")? {
+        info!("sending notifications to systemd");
+        recv = recv.systemd(notifier);
+    }
+
+    // Only about a tenth of a second of audio can wait in a FIFO in low-latency mode.
+    let pipe_size = args.low_latency.then(|| {
+        let channels = if args.output.stereo.is_some() { 2 } else { 1 };
+        let rate = args.output.audio_rate as usize * channels * args.output.audio_format.width();
+
+        (rate as f32 * audio::LOW_LATENCY_FIFO_SECS) as usize
+    });
+
+    // No audio task is started when logging only, so nothing decodes voice frames.
+    let audio = if args.log_only {
+        None
+    }
+    else {
+        let mut audio = AudioTask::new(
+            audio_output(&args.output, audio_stream(&args.output, false, pipe_size)?)?,
+            rx_audio,
+            tx_hub.clone(),
+        );
+
+        if let Some(ref target) = args.export_frames {
+            info!("exporting voice frames to {}", target);
+            audio = audio.export(
+                FrameExport::open(target)
+                    .with_context(|| format!("unable to open frame export {}", target))?,
+            );
+        }
+
+        if let Some(ref dir) = args.record {
+            fs::create_dir_all(dir).with_context(|| {
+                format!("unable to create recording directory {}", dir.display())
+            })?;
+
+            info!("recording calls to {}", dir.display());
+            audio = audio.record(CallRecorder::new(dir));
+        }
+
+        if let Some(addr) = args.tap_audio {
+            let tap = Tap::bind("audio", addr)
+                .with_context(|| format!("unable to listen for audio tap clients on {}", addr))?;
+
+            info!("serving audio tap on {}", addr);
+            audio = audio.tap(tap);
+        }
+
+        Some(audio)
+    };
+
+    // Processing metrics of each task, in pipeline order.
+    let mut stages = vec![read.metrics(), demod.metrics()];
+
+    if let Some((_, _, ref demod)) = diversity {
+        stages.push(demod.metrics());
+    }
+
+    stages.push(recv.metrics());
+    stages.extend(audio.as_ref().map(|a| a.metrics()));
+
+    hub = hub.stages(stages);
+
+    let reader_sched = ThreadSched {
+        cpu: args.reader_cpu,
+        priority: args.rt_priority,
+    };
+    let demod_sched = ThreadSched {
+        cpu: args.demod_cpu,
+        priority: args.rt_priority,
+    };
 
     crossbeam::scope(|scope| {
         scope.spawn(move || {
@@ -276,37 +2121,108 @@ fn main() -> Result<()> {
 
         scope.spawn(move || {
             prctl::set_name("reader").unwrap();
+            reader_sched.apply("reader");
             read.run(reader);
         });
 
         scope.spawn(move || {
             prctl::set_name("demod").unwrap();
+            demod_sched.apply("demod");
             demod.run();
         });
 
-        scope.spawn(move || {
+        if let Some((reader, mut read, mut demod)) = diversity {
+            scope.spawn(move || {
+                prctl::set_name("div-reader").unwrap();
+                read.run(reader);
+            });
+
+            scope.spawn(move || {
+                prctl::set_name("div-demod").unwrap();
+                demod.run();
+            });
+        }
+
+        let recv_thread = scope.spawn(move || {
             prctl::set_name("receiver").unwrap();
 
-            if let Some(mut f) = samples_file {
-                recv.run(|samples| {
-                    f.write_all(unsafe { slice_cast::cast(samples) })
-                        .expect("unable to write baseband");
-                })
-            }
-            else {
-                recv.run(|_| {})
-            }
+            recv.run(&mut sinks);
+            sinks.finish().ok();
         });
 
-        scope.spawn(move || {
-            prctl::set_name("audio").unwrap();
-            audio.run();
+        let writer_thread = writer.map(|mut writer| {
+            scope.spawn(move || {
+                prctl::set_name("writer").unwrap();
+                writer.run();
+            })
         });
+
+        let audio_thread = audio.map(|mut audio| {
+            scope.spawn(move || {
+                prctl::set_name("audio").unwrap();
+                audio.run();
+            })
+        });
+
+        if let Some(mut tui) = tui {
+            scope.spawn(move || {
+                prctl::set_name("tui").unwrap();
+                tui.run().expect("unable to draw terminal view");
+
+                // Other tasks run forever, so quitting the view quits the receiver.
+                std::process::exit(0);
+            });
+        }
+
+        if let Some(secs) = args.duration {
+            thread::sleep(Duration::from_secs(secs));
+            info!("stopping after {} seconds", secs);
+
+            tx_recv
+                .send(RecvEvent::Stop)
+                .expect("unable to stop receiver");
+
+            // Wait for files to be finished before quitting the other tasks.
+            recv_thread.join();
+
+            if let Some(t) = writer_thread {
+                t.join();
+            }
+
+            if let Some(t) = audio_thread {
+                t.join();
+            }
+
+            std::process::exit(0);
+        }
     });
 
     Ok(())
 }
 
+/// Apply the given decimation and channel-select filter designs, if any, to the given
+/// demodulator, and disable its I/Q correction if `raw_iq` is set.
+fn demod_filters(
+    mut demod: DemodTask,
+    decim: Option<FilterSpec>,
+    channel: Option<FilterSpec>,
+    raw_iq: bool,
+) -> DemodTask {
+    if raw_iq {
+        demod = demod.raw_iq();
+    }
+
+    if let Some(spec) = decim {
+        demod = demod.decim_filter(spec);
+    }
+
+    if let Some(spec) = channel {
+        demod = demod.channel_filter(spec);
+    }
+
+    demod
+}
+
 /// Convert the given seconds into an amount of baseband samples.
 fn time_samples(t: f32) -> usize {
     (t * BASEBAND_SAMPLE_RATE as f32) as usize
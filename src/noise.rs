@@ -0,0 +1,93 @@
+//! Noise floor estimation for reporting signal-to-noise ratio.
+//!
+//! Raw signal power depends on the tuner gain, so it can't be compared across gain
+//! settings or devices. Traffic channels go quiet between transmissions, and the power
+//! measured then is the noise floor at the current gain. The floor is estimated as the
+//! lowest power measured on traffic channels over a recent window, which passes over the
+//! measurements taken while a carrier is present.
+
+use std::collections::VecDeque;
+
+/// Default number of recent measurements the floor is taken from, about a minute of
+/// time on traffic channels.
+pub const WINDOW: usize = 256;
+
+/// Measurements needed before the floor is reported.
+const MIN_MEASUREMENTS: usize = 8;
+
+/// Estimates the noise floor from signal power measured on idle channels.
+pub struct NoiseFloor {
+    /// Most recent measurements kept.
+    window: usize,
+    /// Recent power measurements (dBm), oldest first.
+    recent: VecDeque<f32>,
+}
+
+impl NoiseFloor {
+    /// Create a new `NoiseFloor` estimated from the given number of recent measurements.
+    pub fn new(window: usize) -> Self {
+        NoiseFloor {
+            window,
+            recent: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Record the given power (dBm) measured on a channel that may be idle.
+    pub fn record(&mut self, power: f32) {
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+
+        self.recent.push_back(power);
+    }
+
+    /// Get the estimated noise floor (dBm), if enough power has been measured.
+    pub fn floor(&self) -> Option<f32> {
+        if self.recent.len() < MIN_MEASUREMENTS {
+            return None;
+        }
+
+        self.recent.iter().cloned().reduce(f32::min)
+    }
+
+    /// Get the signal-to-noise ratio (dB) of the given power (dBm), if the floor is
+    /// known.
+    pub fn snr(&self, power: f32) -> Option<f32> {
+        self.floor().map(|floor| (power - floor).max(0.0))
+    }
+}
+
+impl Default for NoiseFloor {
+    fn default() -> Self {
+        NoiseFloor::new(WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_floor() {
+        let mut n = NoiseFloor::new(10);
+
+        for _ in 0..MIN_MEASUREMENTS - 1 {
+            n.record(-40.0);
+        }
+
+        assert_eq!(n.floor(), None);
+        assert_eq!(n.snr(-40.0), None);
+
+        n.record(-75.0);
+        assert_eq!(n.floor(), Some(-75.0));
+        assert_eq!(n.snr(-40.0), Some(35.0));
+        assert_eq!(n.snr(-80.0), Some(0.0));
+
+        // The quiet measurement ages out of the window.
+        for _ in 0..10 {
+            n.record(-60.0);
+        }
+
+        assert_eq!(n.floor(), Some(-60.0));
+    }
+}
@@ -0,0 +1,313 @@
+//! Export of decoded trunking and link control messages to pcapng files.
+//!
+//! No link type is assigned to the P25 air interface, and the decoder only keeps the
+//! decoded fields of each message (not the NAC or the raw opcode of unrecognized
+//! messages), so the original frames can't be reconstructed. Packets instead use the
+//! `USER0` link type, and each also carries a packet comment describing the message as
+//! `--dump-control` logs it, which Wireshark and `tshark` show without any plugin. The
+//! dissector in `contrib/p25rx.lua` decodes the packet fields. Each packet has the
+//! following layout, with multi-byte fields in big-endian order:
+//!
+//! | Offset | Size | Field                                                  |
+//! |--------|------|--------------------------------------------------------|
+//! | 0      | 1    | Message type: 1 for TSBK, 2 for link control           |
+//! | 1      | 1    | Flags: bit 0 is set if the CRC was valid               |
+//! | 2      | 4    | Receive frequency (Hz), or 0 if unknown                |
+//! | 6      | 1    | Manufacturer ID, or 0 for link control                 |
+//! | 7      | 1    | Length N of the opcode name, or 0 if unknown           |
+//! | 8      | N    | Opcode name (ASCII)                                    |
+//! | 8 + N  | -    | Message payload                                        |
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use p25::{trunking::tsbk::TsbkFields, voice::control::LinkControlFields};
+
+use crate::dump;
+
+/// Type of the section header block.
+const SECTION_HEADER: u32 = 0x0A0D_0D0A;
+/// Type of the interface description block.
+const INTERFACE_DESCRIPTION: u32 = 1;
+/// Type of the enhanced packet block.
+const ENHANCED_PACKET: u32 = 6;
+/// Magic number giving the byte order of the section.
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+/// Option holding a comment, valid in any block.
+const OPT_COMMENT: u16 = 1;
+/// Section header option naming the application that wrote the file.
+const SHB_USERAPPL: u16 = 4;
+/// Interface description option naming the interface.
+const IF_NAME: u16 = 2;
+
+/// Link type reserved for private use.
+const LINKTYPE_USER0: u32 = 147;
+/// Maximum size of each packet (bytes).
+const SNAPLEN: u32 = 256;
+
+/// Type of message held in a packet.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum MessageType {
+    Tsbk = 1,
+    LinkControl = 2,
+}
+
+/// Writes decoded messages as packets in a pcapng file.
+pub struct PcapWriter<W: Write> {
+    /// Stream to write into.
+    stream: W,
+}
+
+impl PcapWriter<BufWriter<File>> {
+    /// Create a pcapng file at the given path.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        PcapWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Create a new `PcapWriter` over the given stream, writing the section header and
+    /// the description of the single interface.
+    pub fn new(mut stream: W) -> io::Result<Self> {
+        stream.write_all(&section_header())?;
+        stream.write_all(&interface_description())?;
+
+        Ok(PcapWriter {
+            stream,
+        })
+    }
+
+    /// Write the given trunking packet, received on the given frequency (Hz).
+    pub fn write_tsbk(&mut self, tsbk: &TsbkFields, freq: u32) -> io::Result<()> {
+        let opcode = tsbk.opcode().map(|o| format!("{:?}", o));
+        let packet = encode(
+            MessageType::Tsbk,
+            tsbk.crc_valid(),
+            freq,
+            tsbk.mfg(),
+            opcode.as_deref(),
+            tsbk.payload(),
+        );
+
+        self.write_packet(&packet, dump::tsbk(tsbk), freq)
+    }
+
+    /// Write the given link control word, received on the given frequency (Hz).
+    pub fn write_lc(&mut self, lc: &LinkControlFields, freq: u32) -> io::Result<()> {
+        let opcode = lc.opcode().map(|o| format!("{:?}", o));
+
+        // Link control words are only emitted after error correction succeeds.
+        let packet = encode(
+            MessageType::LinkControl,
+            true,
+            freq,
+            0,
+            opcode.as_deref(),
+            lc.payload(),
+        );
+
+        self.write_packet(&packet, dump::lc(lc), freq)
+    }
+
+    /// Write the given packet, commented with the given description of its message
+    /// and the given receive frequency (Hz), timestamped with the current time.
+    fn write_packet(&mut self, packet: &[u8], desc: String, freq: u32) -> io::Result<()> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let comment = if freq == 0 {
+            desc
+        }
+        else {
+            format!("{} freq={}", desc, freq)
+        };
+
+        self.stream
+            .write_all(&enhanced_packet(time, packet, &comment))?;
+
+        // Keep the file readable by other tools while it's being written.
+        self.stream.flush()
+    }
+}
+
+/// Encode a block of the given type with the given body, padded to 32 bits.
+fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+    let len = (12 + padded(body.len())) as u32;
+    let mut buf = Vec::with_capacity(len as usize);
+
+    buf.extend_from_slice(&kind.to_le_bytes());
+    buf.extend_from_slice(&len.to_le_bytes());
+    buf.extend_from_slice(body);
+    buf.resize(len as usize - 4, 0);
+    buf.extend_from_slice(&len.to_le_bytes());
+
+    buf
+}
+
+/// Append an option with the given code and value, padded to 32 bits, to the given
+/// block body.
+fn push_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend_from_slice(&code.to_le_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    buf.extend_from_slice(value);
+    buf.resize(buf.len() + padded(value.len()) - value.len(), 0);
+}
+
+/// Append the option ending the options of the given block body.
+fn end_options(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&[0; 4]);
+}
+
+/// Get the given length rounded up to a multiple of 32 bits.
+fn padded(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Encode the section header block.
+fn section_header() -> Vec<u8> {
+    let mut body = vec![];
+
+    body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+    // Version 1.0.
+    body.extend_from_slice(&1u16.to_le_bytes());
+    body.extend_from_slice(&0u16.to_le_bytes());
+    // Section length isn't known in advance.
+    body.extend_from_slice(&(-1i64).to_le_bytes());
+    push_option(
+        &mut body,
+        SHB_USERAPPL,
+        concat!("p25rx ", env!("CARGO_PKG_VERSION")).as_bytes(),
+    );
+    end_options(&mut body);
+
+    block(SECTION_HEADER, &body)
+}
+
+/// Encode the description of the interface every packet is captured on, with the
+/// default microsecond timestamp resolution.
+fn interface_description() -> Vec<u8> {
+    let mut body = vec![];
+
+    body.extend_from_slice(&(LINKTYPE_USER0 as u16).to_le_bytes());
+    // Reserved.
+    body.extend_from_slice(&0u16.to_le_bytes());
+    body.extend_from_slice(&SNAPLEN.to_le_bytes());
+    push_option(&mut body, IF_NAME, b"p25rx");
+    end_options(&mut body);
+
+    block(INTERFACE_DESCRIPTION, &body)
+}
+
+/// Encode an enhanced packet block holding the given packet, received at the given time
+/// since the Unix epoch, with the given comment.
+fn enhanced_packet(time: Duration, packet: &[u8], comment: &str) -> Vec<u8> {
+    let micros = time.as_micros() as u64;
+    let len = packet.len() as u32;
+    let mut body = vec![];
+
+    // Interface ID.
+    body.extend_from_slice(&0u32.to_le_bytes());
+    body.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+    body.extend_from_slice(&(micros as u32).to_le_bytes());
+    // Captured and original lengths.
+    body.extend_from_slice(&len.to_le_bytes());
+    body.extend_from_slice(&len.to_le_bytes());
+    body.extend_from_slice(packet);
+    body.resize(padded(body.len()), 0);
+    push_option(&mut body, OPT_COMMENT, comment.as_bytes());
+    end_options(&mut body);
+
+    block(ENHANCED_PACKET, &body)
+}
+
+/// Encode a packet with the given fields.
+fn encode(
+    kind: MessageType,
+    crc_valid: bool,
+    freq: u32,
+    mfg: u8,
+    opcode: Option<&str>,
+    payload: &[u8],
+) -> Vec<u8> {
+    let opcode = opcode.unwrap_or("").as_bytes();
+    let mut buf = Vec::with_capacity(8 + opcode.len() + payload.len());
+
+    buf.push(kind as u8);
+    buf.push(crc_valid as u8);
+    buf.extend_from_slice(&freq.to_be_bytes());
+    buf.push(mfg);
+    buf.push(opcode.len() as u8);
+    buf.extend_from_slice(opcode);
+    buf.extend_from_slice(payload);
+
+    buf
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(
+            encode(
+                MessageType::Tsbk,
+                true,
+                851006250,
+                0x90,
+                Some("AdjacentSite"),
+                &[1, 2, 3, 4, 5, 6, 7, 8],
+            ),
+            b"\x01\x01\x32\xB9\x53\x2A\x90\x0CAdjacentSite\x01\x02\x03\x04\x05\x06\x07\x08"
+                .to_vec()
+        );
+        assert_eq!(
+            encode(MessageType::LinkControl, false, 0, 0, None, &[0xAB]),
+            vec![2, 0, 0, 0, 0, 0, 0, 0, 0xAB]
+        );
+    }
+
+    #[test]
+    fn test_header() {
+        let w = PcapWriter::new(vec![]).unwrap();
+        let shb = section_header();
+
+        assert_eq!(&shb[0..4], &[0x0A, 0x0D, 0x0D, 0x0A]);
+        assert_eq!(&shb[8..12], &[0x4D, 0x3C, 0x2B, 0x1A]);
+        assert_eq!(&shb[4..8], &(shb.len() as u32).to_le_bytes());
+        assert_eq!(&shb[shb.len() - 4..], &(shb.len() as u32).to_le_bytes());
+
+        let idb = &w.stream[shb.len()..];
+
+        assert_eq!(&w.stream[..shb.len()], &shb[..]);
+        assert_eq!(
+            idb,
+            b"\x01\0\0\0\x24\0\0\0\x93\0\0\0\0\x01\0\0\
+              \x02\0\x05\0p25rx\0\0\0\0\0\0\0\x24\0\0\0"
+        );
+    }
+
+    #[test]
+    fn test_packet() {
+        let time = Duration::from_micros(0x1_0000_0002);
+
+        assert_eq!(
+            enhanced_packet(time, &[0xAB], "LC"),
+            [
+                &[6, 0, 0, 0, 48, 0, 0, 0][..],
+                &[0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0],
+                &[0xAB, 0, 0, 0],
+                &[1, 0, 2, 0, b'L', b'C', 0, 0],
+                &[0, 0, 0, 0],
+                &[48, 0, 0, 0],
+            ]
+            .concat()
+        );
+    }
+}
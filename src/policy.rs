@@ -13,6 +13,12 @@ pub enum PolicyEvent {
     ReturnControl,
     /// Choose a new talkgroup.
     ChooseTalkgroup,
+    /// Briefly listen to the control channel during a pause in the current call.
+    SampleControl,
+    /// Return to the current call unless a higher-priority call was granted.
+    ResumeTraffic,
+    /// No voice was received on the traffic channel before the watchdog timeout.
+    VoiceTimeout,
 }
 
 /// Current state of receiver.
@@ -27,12 +33,16 @@ enum ReceiverState {
     Traffic(Timer, bool),
     /// Pausing after a call termination.
     Paused(Timer),
+    /// Sampling the control channel during a pause, with a sampling timer and the pause
+    /// timer.
+    Sampling(Timer, Timer),
 }
 
 /// How the state machine should change/output.
 enum StateChange {
     Change(ReceiverState),
     Event(PolicyEvent),
+    Switch(ReceiverState, PolicyEvent),
     NoChange,
 }
 
@@ -46,6 +56,8 @@ pub struct ReceiverPolicy {
     watchdog_time: usize,
     /// Call term pause timeout.
     pause_time: usize,
+    /// Time to sample the control channel during pauses, if enabled.
+    sample_time: Option<usize>,
 }
 
 impl ReceiverPolicy {
@@ -61,9 +73,17 @@ impl ReceiverPolicy {
             select_time: select,
             watchdog_time: watchdog,
             pause_time: pause,
+            sample_time: None,
         }
     }
 
+    /// Sample the control channel for the given amount of baseband samples at the start
+    /// of each pause.
+    pub fn priority_sampling(mut self, samples: usize) -> Self {
+        self.sample_time = Some(samples);
+        self
+    }
+
     /// Record a given elapsed amount of baseband samples.
     pub fn handle_elapsed(&mut self, samples: usize) -> Option<PolicyEvent> {
         // FIXME: non-lexical borrowing
@@ -77,7 +97,16 @@ impl ReceiverPolicy {
                     NoChange
                 }
             }
-            Traffic(ref mut t, _) | Paused(ref mut t) => {
+            Traffic(ref mut t, true) => {
+                if t.expired(samples) {
+                    debug!("watchdog timeout before voice");
+                    Event(VoiceTimeout)
+                }
+                else {
+                    NoChange
+                }
+            }
+            Traffic(ref mut t, false) | Paused(ref mut t) => {
                 if t.expired(samples) {
                     debug!("watchdog timeout");
                     Event(ReturnControl)
@@ -86,6 +115,18 @@ impl ReceiverPolicy {
                     NoChange
                 }
             }
+            Sampling(ref mut sample, ref mut pause) => {
+                if pause.expired(samples) {
+                    debug!("watchdog timeout while sampling");
+                    Event(ReturnControl)
+                }
+                else if sample.expired(samples) {
+                    Event(ResumeTraffic)
+                }
+                else {
+                    NoChange
+                }
+            }
         };
 
         self.handle_change(next)
@@ -117,7 +158,13 @@ impl ReceiverPolicy {
             Traffic(ref mut t, false) => match nid.data_unit {
                 VoiceLCTerminator | VoiceSimpleTerminator => {
                     debug!("pausing for voice message continuation");
-                    Change(Paused(Timer::new(self.pause_time)))
+
+                    let pause = Timer::new(self.pause_time);
+
+                    match self.sample_time {
+                        Some(s) => Switch(Sampling(Timer::new(s), pause), SampleControl),
+                        None => Change(Paused(pause)),
+                    }
                 }
                 VoiceHeader | VoiceLCFrameGroup | VoiceCCFrameGroup => {
                     // Let the watchdog know that voice packets are still being received.
@@ -126,6 +173,8 @@ impl ReceiverPolicy {
                 }
                 _ => NoChange,
             },
+            // NIDs are from the control channel while sampling.
+            Sampling(..) => NoChange,
             Paused(..) => match nid.data_unit {
                 VoiceHeader | VoiceLCFrameGroup | VoiceCCFrameGroup => {
                     debug!("resuming voice message");
@@ -143,7 +192,7 @@ impl ReceiverPolicy {
         self.handle_change(match self.state {
             // Ignore spurious term messages that may occur immediately after switching to
             // the control channel.
-            Control(..) => NoChange,
+            Control(..) | Sampling(..) => NoChange,
             // The call terminator indicates that the current voice message won't be
             // continued.
             Traffic(..) | Paused(..) => {
@@ -158,11 +207,25 @@ impl ReceiverPolicy {
         self.state = self.state_traffic(true);
     }
 
+    /// Indicate the receiver has returned to the current call after sampling the control
+    /// channel.
+    pub fn resume_traffic(&mut self) {
+        if let Sampling(_, pause) = self.state {
+            self.state = Paused(pause);
+        }
+    }
+
     /// Indicate the receiver has moved to the control channel.
     pub fn enter_control(&mut self) {
         self.state = self.state_control();
     }
 
+    /// Check if the receiver is waiting for the current call to continue after a voice
+    /// message ended.
+    pub fn paused(&self) -> bool {
+        matches!(self.state, Paused(..) | Sampling(..))
+    }
+
     /// Apply the given state change.
     fn handle_change(&mut self, c: StateChange) -> Option<PolicyEvent> {
         match c {
@@ -171,6 +234,10 @@ impl ReceiverPolicy {
                 None
             }
             Event(e) => Some(e),
+            Switch(s, e) => {
+                self.state = s;
+                Some(e)
+            }
             NoChange => None,
         }
     }
@@ -223,6 +290,39 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn test_sampling() {
+        let mut p = ReceiverPolicy::new(10, 20, 30).priority_sampling(4);
+        let nid = |du| NetworkId::new(NetworkAccessCode::Default, du);
+
+        p.enter_traffic();
+        assert_eq!(p.handle_nid(nid(VoiceHeader)), None);
+        assert_eq!(p.handle_nid(nid(VoiceLCTerminator)), Some(SampleControl));
+        assert_eq!(p.handle_nid(nid(TrunkingSignaling)), None);
+        assert_eq!(p.handle_call_term(), None);
+        assert_eq!(p.handle_elapsed(3), None);
+        assert_eq!(p.handle_elapsed(1), Some(ResumeTraffic));
+
+        p.resume_traffic();
+        assert_eq!(p.handle_nid(nid(VoiceHeader)), None);
+        assert_eq!(p.handle_nid(nid(VoiceLCFrameGroup)), None);
+        assert_eq!(
+            p.handle_nid(nid(VoiceSimpleTerminator)),
+            Some(SampleControl)
+        );
+        assert_eq!(p.handle_elapsed(4), Some(ResumeTraffic));
+
+        // The pause continues from where sampling left off.
+        p.resume_traffic();
+        assert_eq!(p.handle_elapsed(25), None);
+        assert_eq!(p.handle_elapsed(1), Some(ReturnControl));
+
+        p.enter_traffic();
+        assert_eq!(p.handle_nid(nid(VoiceHeader)), None);
+        assert_eq!(p.handle_nid(nid(VoiceLCTerminator)), Some(SampleControl));
+        assert_eq!(p.handle_elapsed(30), Some(ReturnControl));
+    }
+
     #[test]
     fn test_policy() {
         let mut p = ReceiverPolicy::new(10, 20, 30);
@@ -233,7 +333,7 @@ mod test {
 
         p.enter_traffic();
         assert_eq!(p.handle_elapsed(5), None);
-        assert_eq!(p.handle_elapsed(15), Some(ReturnControl));
+        assert_eq!(p.handle_elapsed(15), Some(VoiceTimeout));
 
         p.enter_traffic();
         assert_eq!(p.handle_elapsed(5), None);
@@ -251,7 +351,7 @@ mod test {
             )),
             None
         );
-        assert_eq!(p.handle_elapsed(15), Some(ReturnControl));
+        assert_eq!(p.handle_elapsed(15), Some(VoiceTimeout));
 
         p.enter_control();
         assert_eq!(p.handle_elapsed(5), None);
@@ -0,0 +1,98 @@
+//! Rolling buffer of recent baseband for recovering the start of calls.
+//!
+//! After tuning to a call, the receiver discards the baseband received while the retune
+//! settles, and only then locks on to the traffic channel. A transmission that begins
+//! in that time would lose its first words, so the last few hundred milliseconds of
+//! baseband are always kept, whatever channel they were received on. When the receiver
+//! locks on, the part received since the retune is decoded for voice ahead of the live
+//! samples.
+
+use std::collections::VecDeque;
+
+/// Keeps the most recent baseband samples.
+pub struct PreRoll {
+    /// Samples, oldest first.
+    samples: VecDeque<f32>,
+    /// Most samples kept.
+    cap: usize,
+    /// Samples added since the last retune, if one was marked.
+    since: Option<usize>,
+}
+
+impl PreRoll {
+    /// Create a new `PreRoll` keeping the given number of samples.
+    pub fn new(cap: usize) -> Self {
+        PreRoll {
+            samples: VecDeque::with_capacity(cap),
+            cap,
+            since: None,
+        }
+    }
+
+    /// Add the given samples, dropping the oldest beyond the capacity.
+    pub fn push(&mut self, samples: &[f32]) {
+        // Only the newest samples of a chunk larger than the buffer are kept.
+        let samples = &samples[samples.len().saturating_sub(self.cap)..];
+        let excess = (self.samples.len() + samples.len()).saturating_sub(self.cap);
+
+        self.samples.drain(..excess);
+        self.samples.extend(samples);
+
+        if let Some(ref mut n) = self.since {
+            *n += samples.len();
+        }
+    }
+
+    /// Mark a retune to a call, with samples added after this received on the traffic
+    /// channel.
+    pub fn retune(&mut self) {
+        self.since = Some(0);
+    }
+
+    /// Forget any marked retune.
+    pub fn clear(&mut self) {
+        self.since = None;
+    }
+
+    /// Take the samples still kept that were added since the marked retune, if any,
+    /// clearing the mark.
+    pub fn take(&mut self) -> Option<Vec<f32>> {
+        let n = self.since.take()?.min(self.samples.len());
+        let start = self.samples.len() - n;
+
+        Some(self.samples.range(start..).cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_preroll() {
+        let mut p = PreRoll::new(4);
+
+        p.push(&[1.0, 2.0, 3.0]);
+        assert_eq!(p.take(), None);
+
+        p.retune();
+        assert_eq!(p.take(), Some(vec![]));
+        assert_eq!(p.take(), None);
+
+        p.retune();
+        p.push(&[4.0]);
+        p.push(&[5.0, 6.0]);
+        assert_eq!(p.samples, [3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(p.take(), Some(vec![4.0, 5.0, 6.0]));
+
+        // Only the samples still kept are taken.
+        p.retune();
+        p.push(&[7.0, 8.0, 9.0, 10.0, 11.0, 12.0]);
+        assert_eq!(p.take(), Some(vec![9.0, 10.0, 11.0, 12.0]));
+
+        p.retune();
+        p.push(&[13.0]);
+        p.clear();
+        assert_eq!(p.take(), None);
+    }
+}
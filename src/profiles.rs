@@ -0,0 +1,166 @@
+//! Talkgroup selection profiles that are active at scheduled times.
+//!
+//! Each profile is an entry of the settings file `profiles` array, like
+//!
+//! ```json
+//! {"name": "day", "days": ["mon", "tue"], "start": "08:00", "end": "18:00", "include": [101]}
+//! ```
+//!
+//! with these fields:
+//!
+//! - `name`: name reported when the profile is active
+//! - `days`, `start`, and `end`: weekly time window, described in the `schedule` module
+//! - `include`, `exclude`, `preempt`, `priorities`, `weights`, and `strategy`: talkgroup
+//!   selection settings replacing those at the top level of the file while active
+
+use std::collections::BTreeMap;
+
+use crate::{
+    schedule::{self, Window},
+    settings,
+    talkgroups::{self, FeatureWeights, Filter, TalkgroupConfig},
+};
+
+/// Profile entry of a settings file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileEntry {
+    name: String,
+    #[serde(default)]
+    days: Vec<String>,
+    start: String,
+    end: String,
+    #[serde(default)]
+    include: Option<Vec<u16>>,
+    #[serde(default)]
+    exclude: Option<Vec<u16>>,
+    #[serde(default)]
+    preempt: Option<Vec<u16>>,
+    #[serde(default)]
+    priorities: Option<BTreeMap<String, f32>>,
+    #[serde(default)]
+    weights: Option<FeatureWeights>,
+    #[serde(default)]
+    strategy: Option<String>,
+}
+
+/// Talkgroup selection settings active during a weekly time window.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    /// Name of the profile.
+    pub name: String,
+    /// Time window the profile is active in.
+    window: Window,
+    /// Talkgroup selection settings while active.
+    pub talkgroups: TalkgroupConfig,
+}
+
+impl Profile {
+    /// Check if the profile is active at the given minute after midnight of the given
+    /// day (0 for Monday).
+    pub fn active_at(&self, day: u32, minute: u32) -> bool {
+        self.window.active_at(day, minute)
+    }
+}
+
+/// Find the first of the given profiles active at the current local time.
+pub fn active(profiles: &[Profile]) -> Option<&Profile> {
+    let (day, minute) = schedule::now();
+
+    profiles.iter().find(|p| p.active_at(day, minute))
+}
+
+/// Parse the given profile entry, taking settings it doesn't replace from the given
+/// top-level talkgroup selection settings.
+pub fn parse_profile(e: ProfileEntry, base: &TalkgroupConfig) -> Result<Profile, String> {
+    let window = Window::parse(&e.days, &e.start, &e.end)
+        .map_err(|what| format!("profile {}: invalid {}", e.name, what))?;
+
+    let mut tg = base.clone();
+
+    match (e.include, e.exclude) {
+        (Some(_), Some(_)) => {
+            return Err(format!(
+                "profile {}: include and exclude can't both be given",
+                e.name
+            ))
+        }
+        (Some(ref tgs), None) => tg.filter = Filter::include(tgs),
+        (None, Some(ref tgs)) => tg.filter = Filter::exclude(tgs),
+        (None, None) => {}
+    }
+
+    if let Some(preempt) = e.preempt {
+        tg.preempt = preempt.into_iter().collect();
+    }
+
+    if let Some(prios) = e.priorities {
+        tg.prios.clear();
+
+        for (t, prio) in prios {
+            tg.prios.insert(settings::parse_talkgroup(&t)?, prio);
+        }
+    }
+
+    if let Some(weights) = e.weights {
+        tg.weights = weights;
+    }
+
+    if let Some(ref s) = e.strategy {
+        tg.strategy = talkgroups::parse_strategy(s)?;
+    }
+
+    Ok(Profile {
+        name: e.name,
+        window,
+        talkgroups: tg,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn profile(json: &str) -> Result<Profile, String> {
+        let e: ProfileEntry = serde_json::from_str(json).unwrap();
+        parse_profile(e, &TalkgroupConfig::default())
+    }
+
+    #[test]
+    fn test_active_at() {
+        let day =
+            profile(r#"{"name": "day", "days": ["mon", "fri"], "start": "08:00", "end": "18:00"}"#)
+                .unwrap();
+
+        assert!(day.active_at(0, 8 * 60));
+        assert!(day.active_at(4, 17 * 60 + 59));
+        assert!(!day.active_at(0, 18 * 60));
+        assert!(!day.active_at(1, 12 * 60));
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let p = profile(
+            r#"{"name": "fire", "start": "0:00", "end": "6:00", "include": [101],
+                "priorities": {"101": 2.0}, "strategy": "priority"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(p.talkgroups.filter, Filter::include(&[101]));
+        assert_eq!(p.talkgroups.prios.get(&101), Some(&2.0));
+        assert_eq!(p.talkgroups.strategy, talkgroups::Strategy::Priority);
+        assert_eq!(p.talkgroups.weights, FeatureWeights::default());
+
+        assert_eq!(
+            profile(r#"{"name": "x", "days": ["mo"], "start": "0:00", "end": "1:00"}"#)
+                .unwrap_err(),
+            "profile x: invalid day"
+        );
+        assert!(profile(r#"{"name": "x", "start": "25:00", "end": "1:00"}"#).is_err());
+        assert!(profile(r#"{"name": "x", "start": "1:00", "end": "1:60"}"#).is_err());
+        assert!(profile(
+            r#"{"name": "x", "start": "1:00", "end": "2:00", "include": [1], "exclude": [2]}"#
+        )
+        .is_err());
+    }
+}
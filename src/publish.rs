@@ -0,0 +1,92 @@
+//! ZeroMQ publisher of hub events and decoded audio.
+//!
+//! Each message is sent as two frames, the topic followed by the body:
+//!
+//! | Topic    | Body                                                            |
+//! |----------|-----------------------------------------------------------------|
+//! | `events` | Event as a JSON object, in the same format as the event log     |
+//! | `audio`  | Decoded voice audio, as 16-bit little-endian mono samples       |
+
+#[cfg(not(feature = "zmq"))]
+use anyhow::anyhow;
+use anyhow::Result;
+use serde::Serialize;
+
+#[cfg(feature = "zmq")]
+use crate::eventlog;
+
+/// Topic of event messages.
+#[cfg(feature = "zmq")]
+const EVENTS_TOPIC: &[u8] = b"events";
+
+/// Topic of audio messages.
+#[cfg(feature = "zmq")]
+const AUDIO_TOPIC: &[u8] = b"audio";
+
+/// Publishes events and audio on a ZeroMQ PUB socket.
+///
+/// Messages are dropped rather than queued without bound when subscribers fall behind.
+#[cfg(feature = "zmq")]
+pub struct Publisher {
+    /// Context the socket belongs to.
+    _ctx: zmq::Context,
+    /// Bound PUB socket.
+    socket: zmq::Socket,
+}
+
+#[cfg(feature = "zmq")]
+impl Publisher {
+    /// Bind a new publisher to the given endpoint, like `tcp://*:5556`.
+    pub fn bind(endpoint: &str) -> Result<Self> {
+        let ctx = zmq::Context::new();
+        let socket = ctx.socket(zmq::PUB)?;
+        socket.bind(endpoint)?;
+
+        Ok(Publisher {
+            _ctx: ctx,
+            socket,
+        })
+    }
+
+    /// Publish the given event, timestamped with the current time.
+    pub fn send_event<T: Serialize>(&self, event: &str, payload: &T) {
+        if let Ok(msg) = eventlog::encode(event, payload) {
+            self.send(EVENTS_TOPIC, &msg[..]);
+        }
+    }
+
+    /// Publish the given decoded audio samples.
+    pub fn send_audio(&self, samples: &[u8]) {
+        self.send(AUDIO_TOPIC, samples);
+    }
+
+    /// Publish the given body under the given topic.
+    fn send(&self, topic: &[u8], body: &[u8]) {
+        // PUB sockets drop messages at the high water mark rather than blocking, so
+        // the only errors are for a closed context.
+        if let Err(e) = self.socket.send_multipart([topic, body], zmq::DONTWAIT) {
+            debug!("unable to publish: {}", e);
+        }
+    }
+}
+
+/// Stands in for the publisher when built without ZeroMQ, and can't be created.
+#[cfg(not(feature = "zmq"))]
+pub enum Publisher {}
+
+#[cfg(not(feature = "zmq"))]
+impl Publisher {
+    pub fn bind(_: &str) -> Result<Self> {
+        Err(anyhow!(
+            "p25rx was built without ZeroMQ support (the zmq feature)"
+        ))
+    }
+
+    pub fn send_event<T: Serialize>(&self, _: &str, _: &T) {
+        match *self {}
+    }
+
+    pub fn send_audio(&self, _: &[u8]) {
+        match *self {}
+    }
+}
@@ -0,0 +1,454 @@
+//! Bounded queues between tasks with a configurable overflow policy.
+
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{RecvError, SendError, TryRecvError},
+        Arc, Condvar, Mutex,
+    },
+};
+
+use clap::ValueEnum;
+use mio::{Evented, Poll, PollOpt, Ready, Registration, SetReadiness, Token};
+
+/// Action taken when sending into a full queue.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, ValueEnum)]
+pub enum OverflowPolicy {
+    /// Block the sender until space is available.
+    Block,
+    /// Discard the oldest queued item to make room.
+    DropOldest,
+    /// Discard the item being sent.
+    DropNewest,
+}
+
+/// Item sent through a queue.
+pub trait QueueItem {
+    /// Check if the item is bulk data that can be discarded when the queue is full, rather
+    /// than a command or change of state that must be delivered.
+    fn droppable(&self) -> bool {
+        true
+    }
+}
+
+/// Counters shared by both ends of a queue.
+pub struct QueueMetrics {
+    /// Name of the queue for reporting.
+    pub name: &'static str,
+    /// Maximum number of queued items.
+    pub cap: usize,
+    /// Overflow policy.
+    pub policy: OverflowPolicy,
+    /// Current number of queued items.
+    len: AtomicUsize,
+    /// Total number of items sent into a full queue.
+    overflows: AtomicUsize,
+}
+
+impl QueueMetrics {
+    /// Current number of queued items.
+    pub fn depth(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Total number of items sent into a full queue.
+    pub fn overflows(&self) -> usize {
+        self.overflows.load(Ordering::Relaxed)
+    }
+}
+
+/// State shared by both ends of a queue.
+struct Shared<T> {
+    /// Queued items.
+    items: Mutex<VecDeque<T>>,
+    /// Signalled when an item is queued or all senders have hung up.
+    not_empty: Condvar,
+    /// Signalled when an item is dequeued or the receiver has hung up.
+    not_full: Condvar,
+    /// Number of live senders.
+    senders: AtomicUsize,
+    /// Whether the receiver is still alive.
+    receiver: AtomicBool,
+    /// Readiness notifier for evented receivers.
+    readiness: Option<SetReadiness>,
+    /// Reported counters.
+    metrics: Arc<QueueMetrics>,
+}
+
+/// Create a new queue with the given name, capacity, and overflow policy.
+pub fn queue<T>(
+    name: &'static str,
+    cap: usize,
+    policy: OverflowPolicy,
+) -> (QueueSender<T>, QueueReceiver<T>) {
+    create(name, cap, policy, None)
+}
+
+/// Create a new queue with a receiver that can be registered with a `mio::Poll`.
+pub fn evented_queue<T>(
+    name: &'static str,
+    cap: usize,
+    policy: OverflowPolicy,
+) -> (QueueSender<T>, EventedReceiver<T>) {
+    let (reg, readiness) = Registration::new2();
+    let (tx, rx) = create(name, cap, policy, Some(readiness));
+
+    (
+        tx,
+        EventedReceiver {
+            rx,
+            reg,
+        },
+    )
+}
+
+fn create<T>(
+    name: &'static str,
+    cap: usize,
+    policy: OverflowPolicy,
+    readiness: Option<SetReadiness>,
+) -> (QueueSender<T>, QueueReceiver<T>) {
+    assert!(cap > 0);
+
+    let shared = Arc::new(Shared {
+        items: Mutex::new(VecDeque::with_capacity(cap)),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+        senders: AtomicUsize::new(1),
+        receiver: AtomicBool::new(true),
+        readiness,
+        metrics: Arc::new(QueueMetrics {
+            name,
+            cap,
+            policy,
+            len: AtomicUsize::new(0),
+            overflows: AtomicUsize::new(0),
+        }),
+    });
+
+    (
+        QueueSender {
+            shared: shared.clone(),
+        },
+        QueueReceiver {
+            shared,
+        },
+    )
+}
+
+/// Sending end of a queue.
+pub struct QueueSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: QueueItem> QueueSender<T> {
+    /// Send the given item, applying the overflow policy if the queue is full.
+    ///
+    /// Under the dropping policies, only droppable items are discarded, and others are
+    /// queued past the capacity. Return an error if the receiver has hung up.
+    pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+        let shared = &self.shared;
+        let metrics = &shared.metrics;
+        let mut items = shared.items.lock().unwrap();
+
+        if !shared.receiver.load(Ordering::Relaxed) {
+            return Err(SendError(t));
+        }
+
+        if items.len() >= metrics.cap {
+            if metrics.overflows.fetch_add(1, Ordering::Relaxed) == 0 {
+                warn!(
+                    "{} queue overflowed (policy {:?})",
+                    metrics.name, metrics.policy
+                );
+            }
+
+            match metrics.policy {
+                OverflowPolicy::Block => {
+                    while items.len() >= metrics.cap {
+                        if !shared.receiver.load(Ordering::Relaxed) {
+                            return Err(SendError(t));
+                        }
+
+                        items = shared.not_full.wait(items).unwrap();
+                    }
+                }
+                // Commands are rare enough that the queue can't grow far past its capacity.
+                _ if !t.droppable() => {}
+                OverflowPolicy::DropOldest => match items.iter().position(T::droppable) {
+                    Some(idx) => {
+                        items.remove(idx);
+                    }
+                    None => return Ok(()),
+                },
+                OverflowPolicy::DropNewest => return Ok(()),
+            }
+        }
+
+        items.push_back(t);
+        metrics.len.store(items.len(), Ordering::Relaxed);
+
+        drop(items);
+
+        shared.not_empty.notify_one();
+
+        if let Some(ref r) = shared.readiness {
+            r.set_readiness(Ready::readable()).ok();
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> QueueSender<T> {
+    /// Retrieve the counters for the queue.
+    pub fn metrics(&self) -> Arc<QueueMetrics> {
+        self.shared.metrics.clone()
+    }
+}
+
+impl<T> Clone for QueueSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+
+        QueueSender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) != 1 {
+            return;
+        }
+
+        // Take the lock so a waiting receiver can't miss the hangup.
+        drop(self.shared.items.lock().unwrap());
+        self.shared.not_empty.notify_all();
+
+        if let Some(ref r) = self.shared.readiness {
+            r.set_readiness(Ready::readable()).ok();
+        }
+    }
+}
+
+/// Receiving end of a queue.
+pub struct QueueReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueReceiver<T> {
+    /// Wait for the next item, blocking the current thread.
+    ///
+    /// Return an error if the queue is empty and all senders have hung up.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let shared = &self.shared;
+        let mut items = shared.items.lock().unwrap();
+
+        loop {
+            if let Some(t) = items.pop_front() {
+                shared.metrics.len.store(items.len(), Ordering::Relaxed);
+                drop(items);
+                shared.not_full.notify_one();
+
+                return Ok(t);
+            }
+
+            if shared.senders.load(Ordering::Acquire) == 0 {
+                return Err(RecvError);
+            }
+
+            items = shared.not_empty.wait(items).unwrap();
+        }
+    }
+
+    /// Retrieve the next item without blocking.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let shared = &self.shared;
+        let mut items = shared.items.lock().unwrap();
+
+        match items.pop_front() {
+            Some(t) => {
+                shared.metrics.len.store(items.len(), Ordering::Relaxed);
+                drop(items);
+                shared.not_full.notify_one();
+
+                Ok(t)
+            }
+            None if shared.senders.load(Ordering::Acquire) == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Retrieve the counters for the queue.
+    pub fn metrics(&self) -> Arc<QueueMetrics> {
+        self.shared.metrics.clone()
+    }
+}
+
+impl<T> Drop for QueueReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver.store(false, Ordering::Relaxed);
+
+        // Take the lock so a blocked sender can't miss the hangup.
+        drop(self.shared.items.lock().unwrap());
+        self.shared.not_full.notify_all();
+    }
+}
+
+/// Receiving end of a queue that can be polled for readiness.
+pub struct EventedReceiver<T> {
+    rx: QueueReceiver<T>,
+    reg: Registration,
+}
+
+impl<T> EventedReceiver<T> {
+    /// Retrieve the next item without blocking.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let res = self.rx.try_recv();
+
+        if let Err(TryRecvError::Empty) = res {
+            let shared = &self.rx.shared;
+            let readiness = shared.readiness.as_ref().unwrap();
+
+            readiness.set_readiness(Ready::empty()).ok();
+
+            // Recheck in case an item was sent after the queue was found empty but
+            // before readiness was cleared.
+            if !shared.items.lock().unwrap().is_empty() {
+                readiness.set_readiness(Ready::readable()).ok();
+            }
+        }
+
+        res
+    }
+
+    /// Retrieve the counters for the queue.
+    pub fn metrics(&self) -> Arc<QueueMetrics> {
+        self.rx.metrics()
+    }
+}
+
+impl<T> Evented for EventedReceiver<T> {
+    fn register(
+        &self,
+        poll: &Poll,
+        token: Token,
+        interest: Ready,
+        opts: PollOpt,
+    ) -> io::Result<()> {
+        self.reg.register(poll, token, interest, opts)
+    }
+
+    fn reregister(
+        &self,
+        poll: &Poll,
+        token: Token,
+        interest: Ready,
+        opts: PollOpt,
+    ) -> io::Result<()> {
+        self.reg.reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        self.reg.deregister(poll)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Negative numbers stand in for commands.
+    impl QueueItem for i32 {
+        fn droppable(&self) -> bool {
+            *self >= 0
+        }
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let (tx, rx) = queue("test", 2, OverflowPolicy::DropOldest);
+
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        tx.send(3).unwrap();
+
+        assert_eq!(tx.metrics().overflows(), 1);
+        assert_eq!(tx.metrics().depth(), 2);
+        assert_eq!(rx.recv(), Ok(2));
+        assert_eq!(rx.recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        drop(tx);
+        assert_eq!(rx.recv(), Err(RecvError));
+    }
+
+    #[test]
+    fn test_drop_newest() {
+        let (tx, rx) = queue("test", 2, OverflowPolicy::DropNewest);
+
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        tx.send(3).unwrap();
+
+        assert_eq!(rx.metrics().overflows(), 1);
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.recv(), Ok(2));
+
+        drop(rx);
+        assert!(tx.send(4).is_err());
+    }
+
+    #[test]
+    fn test_commands() {
+        let (tx, rx) = queue("test", 2, OverflowPolicy::DropOldest);
+
+        tx.send(-1).unwrap();
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        tx.send(-2).unwrap();
+
+        assert_eq!(rx.recv(), Ok(-1));
+        assert_eq!(rx.recv(), Ok(2));
+        assert_eq!(rx.recv(), Ok(-2));
+
+        tx.send(-3).unwrap();
+        tx.send(-4).unwrap();
+        tx.send(3).unwrap();
+
+        assert_eq!(tx.metrics().depth(), 2);
+        assert_eq!(rx.recv(), Ok(-3));
+        assert_eq!(rx.recv(), Ok(-4));
+
+        let (tx, rx) = queue("test", 1, OverflowPolicy::DropNewest);
+
+        tx.send(1).unwrap();
+        tx.send(-1).unwrap();
+        tx.send(2).unwrap();
+
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.recv(), Ok(-1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn test_block() {
+        let (tx, rx) = queue("test", 1, OverflowPolicy::Block);
+
+        let t = std::thread::spawn(move || {
+            for i in 0..100 {
+                tx.send(i).unwrap();
+            }
+        });
+
+        for i in 0..100 {
+            assert_eq!(rx.recv(), Ok(i));
+        }
+
+        t.join().unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+//! Per-client request rate limiting for the HTTP server.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use fnv::FnvBuildHasher;
+
+/// Number of tracked clients above which idle clients are forgotten.
+const MAX_CLIENTS: usize = 1024;
+
+/// Request allowance of a single client.
+#[derive(Copy, Clone, Debug)]
+struct Bucket {
+    /// Requests that can currently be made.
+    tokens: f32,
+    /// Time the allowance was last updated.
+    updated: Instant,
+}
+
+/// Limits the requests per second each client IP can make, allowing short bursts.
+pub struct RateLimiter {
+    /// Sustained requests per second allowed for each client.
+    rate: f32,
+    /// Most requests a client can make at once.
+    burst: f32,
+    /// Allowance of each client seen recently.
+    clients: HashMap<IpAddr, Bucket, FnvBuildHasher>,
+}
+
+impl RateLimiter {
+    /// Create a new `RateLimiter` allowing the given requests per second for each
+    /// client, with bursts of up to twice that.
+    pub fn new(rate: f32) -> Self {
+        RateLimiter {
+            rate,
+            burst: (rate * 2.0).max(1.0),
+            clients: HashMap::default(),
+        }
+    }
+
+    /// Check if the given client can make a request at the given time, counting the
+    /// request if so.
+    pub fn allow(&mut self, ip: IpAddr, now: Instant) -> bool {
+        if self.clients.len() >= MAX_CLIENTS {
+            self.prune(now);
+        }
+
+        let (rate, burst) = (self.rate, self.burst);
+
+        let b = self.clients.entry(ip).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+
+        b.tokens = refill(b, rate, burst, now);
+        b.updated = now;
+
+        if b.tokens < 1.0 {
+            return false;
+        }
+
+        b.tokens -= 1.0;
+
+        true
+    }
+
+    /// Forget clients whose allowance has fully recovered.
+    fn prune(&mut self, now: Instant) {
+        let (rate, burst) = (self.rate, self.burst);
+        self.clients
+            .retain(|_, b| refill(b, rate, burst, now) < burst);
+    }
+}
+
+/// Compute the allowance of the given client at the given time.
+fn refill(b: &Bucket, rate: f32, burst: f32, now: Instant) -> f32 {
+    let elapsed = now.saturating_duration_since(b.updated);
+    (b.tokens + secs(elapsed) * rate).min(burst)
+}
+
+/// Convert the given duration to fractional seconds.
+fn secs(d: Duration) -> f32 {
+    d.as_secs() as f32 + d.subsec_nanos() as f32 / 1e9
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_allow() {
+        let mut r = RateLimiter::new(2.0);
+        let a = "10.0.0.1".parse().unwrap();
+        let b = "::1".parse().unwrap();
+        let t = Instant::now();
+
+        for _ in 0..4 {
+            assert!(r.allow(a, t));
+        }
+
+        assert!(!r.allow(a, t));
+        assert!(r.allow(b, t));
+
+        assert!(!r.allow(a, t + Duration::from_millis(250)));
+        assert!(r.allow(a, t + Duration::from_millis(500)));
+        assert!(!r.allow(a, t + Duration::from_millis(500)));
+
+        r.prune(t + Duration::from_secs(10));
+        assert!(r.clients.is_empty());
+    }
+}
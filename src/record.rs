@@ -0,0 +1,271 @@
+//! Per-call audio recording.
+//!
+//! Each followed call is written to its own WAV file (16-bit mono PCM at the decoded
+//! audio rate) named by its start time (ms since the Unix epoch) and talkgroup, like
+//! `1500000000000-101.wav`, or call type for private calls, like
+//! `1500000000000-unit.wav`.
+//!
+//! Once the call is known, a `LIST` chunk of `INFO` tags is appended to each file, with
+//! the title (`INAM`), start time (`ICRD`), recording software (`ISFT`), and the call
+//! details as a JSON object (`ICMT`), so archives don't depend on the file name.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{TimeZone, UTC};
+
+use crate::{
+    calls::CallKind,
+    consts::{AUDIO_SAMPLE_RATE, VERSION},
+};
+
+/// Size of the WAV header (bytes).
+const HEADER_BYTES: u32 = 44;
+/// Size of each sample (bytes).
+const SAMPLE_BYTES: u32 = 2;
+
+/// Write a WAV header for the given number of sample bytes.
+///
+/// Streams of unknown length can pass `u32::MAX` bytes.
+pub fn write_header<W: Write>(mut w: W, data_bytes: u32) -> io::Result<()> {
+    w.write_all(b"RIFF")?;
+    w.write_all(&(HEADER_BYTES - 8).saturating_add(data_bytes).to_le_bytes())?;
+    w.write_all(b"WAVE")?;
+
+    w.write_all(b"fmt ")?;
+    w.write_all(&16u32.to_le_bytes())?;
+    // PCM format.
+    w.write_all(&1u16.to_le_bytes())?;
+    // Mono.
+    w.write_all(&1u16.to_le_bytes())?;
+    w.write_all(&AUDIO_SAMPLE_RATE.to_le_bytes())?;
+    w.write_all(&(AUDIO_SAMPLE_RATE * SAMPLE_BYTES).to_le_bytes())?;
+    w.write_all(&(SAMPLE_BYTES as u16).to_le_bytes())?;
+    w.write_all(&(SAMPLE_BYTES as u16 * 8).to_le_bytes())?;
+
+    w.write_all(b"data")?;
+    w.write_all(&data_bytes.to_le_bytes())
+}
+
+/// Append `INFO` tags with the given title, start time (ms since the Unix epoch), and
+/// comment to the given finished recording.
+pub fn tag(path: &Path, title: &str, start: u64, comment: &str) -> io::Result<()> {
+    let date = UTC.timestamp((start / 1000) as i64, (start % 1000) as u32 * 1_000_000);
+
+    let mut list = b"INFO".to_vec();
+    info_tag(&mut list, b"INAM", title);
+    info_tag(
+        &mut list,
+        b"ICRD",
+        &date.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+    );
+    info_tag(&mut list, b"ISFT", &format!("p25rx {}", VERSION));
+    info_tag(&mut list, b"ICMT", comment);
+
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let end = file.seek(SeekFrom::End(0))?;
+
+    file.write_all(b"LIST")?;
+    file.write_all(&(list.len() as u32).to_le_bytes())?;
+    file.write_all(&list)?;
+
+    // The RIFF size covers everything after the 8-byte RIFF header, which now includes
+    // the 8-byte LIST header.
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&(end as u32 + list.len() as u32).to_le_bytes())
+}
+
+/// Append an `INFO` subchunk with the given ID and text to the given buffer.
+fn info_tag(buf: &mut Vec<u8>, id: &[u8; 4], text: &str) {
+    // Text is null-terminated, and chunks are padded to an even length.
+    let size = text.len() as u32 + 1;
+
+    buf.extend_from_slice(id);
+    buf.extend_from_slice(&size.to_le_bytes());
+    buf.extend_from_slice(text.as_bytes());
+    buf.push(0);
+
+    if size % 2 == 1 {
+        buf.push(0);
+    }
+}
+
+/// Recording in progress.
+struct Recording {
+    /// Path of the file being written.
+    path: PathBuf,
+    /// Talkgroup of the call.
+    talkgroup: u16,
+    /// File being written.
+    file: BufWriter<File>,
+    /// Number of sample bytes written.
+    data_bytes: u32,
+}
+
+/// Writes the audio of each call to a file in a directory.
+pub struct CallRecorder {
+    /// Directory to write recordings into.
+    dir: PathBuf,
+    /// Recording of the current call, if any.
+    cur: Option<Recording>,
+}
+
+impl CallRecorder {
+    /// Create a new `CallRecorder` writing into the given directory.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        CallRecorder {
+            dir: dir.into(),
+            cur: None,
+        }
+    }
+
+    /// Start recording a call of the given type on the given talkgroup, finishing any
+    /// current recording.
+    ///
+    /// Group calls are named by talkgroup and private calls by their type.
+    pub fn begin(&mut self, talkgroup: u16, kind: CallKind) -> io::Result<Option<(u16, PathBuf)>> {
+        let prev = self.finish()?;
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        let path = match kind {
+            CallKind::Group => self.dir.join(format!("{}-{}.wav", time, talkgroup)),
+            _ => self.dir.join(format!("{}-{}.wav", time, kind.name())),
+        };
+        let mut file = BufWriter::new(File::create(&path)?);
+
+        // Sizes are filled in when the recording is finished.
+        write_header(&mut file, 0)?;
+
+        self.cur = Some(Recording {
+            path,
+            talkgroup,
+            file,
+            data_bytes: 0,
+        });
+
+        Ok(prev)
+    }
+
+    /// Append the given samples, in the range [-1, 1], to the current recording, if any.
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        let rec = match self.cur {
+            Some(ref mut rec) => rec,
+            None => return Ok(()),
+        };
+
+        for &s in samples {
+            let s = (s.max(-1.0).min(1.0) * i16::MAX as f32) as i16;
+            rec.file.write_all(&s.to_le_bytes())?;
+        }
+
+        rec.data_bytes += samples.len() as u32 * SAMPLE_BYTES;
+
+        Ok(())
+    }
+
+    /// Finish the current recording, if any, returning its talkgroup and path.
+    ///
+    /// Recordings without any audio are removed.
+    pub fn finish(&mut self) -> io::Result<Option<(u16, PathBuf)>> {
+        let mut rec = match self.cur.take() {
+            Some(rec) => rec,
+            None => return Ok(None),
+        };
+
+        if rec.data_bytes == 0 {
+            drop(rec.file);
+            fs::remove_file(&rec.path)?;
+
+            return Ok(None);
+        }
+
+        rec.file.seek(SeekFrom::Start(0))?;
+        write_header(&mut rec.file, rec.data_bytes)?;
+        rec.file.flush()?;
+
+        Ok(Some((rec.talkgroup, rec.path)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_recorder() {
+        let dir = TempDir::new("record");
+        let mut r = CallRecorder::new(dir.path());
+
+        assert_eq!(r.finish().unwrap(), None);
+        assert_eq!(r.begin(101, CallKind::Group).unwrap(), None);
+
+        r.write(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+
+        let (tg, path) = r.begin(0, CallKind::Unit).unwrap().unwrap();
+        assert_eq!(tg, 101);
+        assert!(path.to_str().unwrap().ends_with("-101.wav"));
+
+        r.write(&[0.0]).unwrap();
+
+        let (tg, unit) = r.begin(102, CallKind::Group).unwrap().unwrap();
+        assert_eq!(tg, 0);
+        assert!(unit.to_str().unwrap().ends_with("-unit.wav"));
+        fs::remove_file(&unit).unwrap();
+
+        let wav = fs::read(&path).unwrap();
+        assert_eq!(wav.len(), 44 + 8);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[4..8], &44u32.to_le_bytes());
+        assert_eq!(&wav[24..28], &8000u32.to_le_bytes());
+        assert_eq!(&wav[40..44], &8u32.to_le_bytes());
+        assert_eq!(&wav[44..], &[0, 0, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x7F]);
+
+        // Calls without audio leave no recording.
+        assert_eq!(r.finish().unwrap(), None);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_tag() {
+        let dir = TempDir::new("tag");
+        let path = dir.join("call.wav");
+
+        let mut file = File::create(&path).unwrap();
+        write_header(&mut file, 2).unwrap();
+        file.write_all(&[0, 0]).unwrap();
+        drop(file);
+
+        tag(&path, "Fire", 1500000000000, "{}").unwrap();
+
+        let wav = fs::read(&path).unwrap();
+
+        assert_eq!(&wav[4..8], &(wav.len() as u32 - 8).to_le_bytes());
+        assert_eq!(&wav[46..50], b"LIST");
+        assert_eq!(&wav[50..54], &(wav.len() as u32 - 54).to_le_bytes());
+        assert_eq!(&wav[54..58], b"INFO");
+        assert_eq!(&wav[58..62], b"INAM");
+        assert_eq!(&wav[62..66], &5u32.to_le_bytes());
+        assert_eq!(&wav[66..72], b"Fire\0\0");
+        assert_eq!(&wav[72..76], b"ICRD");
+        assert_eq!(&wav[80..100], b"2017-07-14T02:40:00Z");
+    }
+
+    #[test]
+    fn test_stream_header() {
+        let mut buf = vec![];
+        write_header(&mut buf, u32::MAX).unwrap();
+
+        assert_eq!(buf.len(), HEADER_BYTES as usize);
+        assert_eq!(&buf[4..8], &u32::MAX.to_le_bytes());
+        assert_eq!(&buf[40..44], &u32::MAX.to_le_bytes());
+    }
+}
@@ -2,10 +2,19 @@
 
 use std::{
     self,
-    sync::mpsc::{Receiver, Sender},
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::BufWriter,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
-use mio_extras;
+use clap::ValueEnum;
+
+use fnv::FnvBuildHasher;
 use p25::{
     message::receiver::MessageReceiver,
     stats::Stats,
@@ -20,32 +29,170 @@ use throttle::Throttler;
 
 use crate::{
     audio::AudioEvent,
+    band::AvoidList,
+    baseband::BasebandSink,
+    calls::{CallKind, PrivateCall},
+    consts::{BASEBAND_SAMPLE_RATE, BUF_COUNT},
+    csvlog::MessageCsv,
+    dump,
+    faults::{Fault, FaultCategory},
     hub::{HubEvent, StateEvent},
+    hunt::ControlHunt,
+    latency::Retune,
+    pcap::PcapWriter,
     policy::{PolicyEvent, ReceiverPolicy},
+    preroll::PreRoll,
+    queue::{QueueItem, QueueReceiver, QueueSender},
     sdr::ControlTaskEvent,
-    talkgroups::TalkgroupSelection,
+    stages::StageMetrics,
+    systemd::Notifier,
+    talkgroups::{TalkgroupConfig, TalkgroupSelection},
+    voting::{Branch, Voter},
 };
 
+/// Baseband samples on the control channel without a valid trunking packet before the
+/// control channel is reported lost.
+const CONTROL_TIMEOUT: usize = 5 * BASEBAND_SAMPLE_RATE as usize;
+
+/// Time a traffic channel is avoided after no voice was received on it.
+const TIMEOUT_AVOID: Duration = Duration::from_secs(60);
+
+/// Action taken when no voice is received on a traffic channel before the watchdog
+/// timeout.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, ValueEnum, Serialize)]
+pub enum WatchdogAction {
+    /// Return to the control channel.
+    #[serde(rename = "return")]
+    Return,
+    /// Retune to the traffic channel once before returning to the control channel.
+    #[serde(rename = "retry")]
+    Retry,
+    /// Return to the control channel and avoid the traffic channel for a minute.
+    #[serde(rename = "avoid")]
+    Avoid,
+}
+
+/// Traffic channel that produced no voice before the watchdog timeout.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct FailedTune {
+    /// Talkgroup of the call, or 0 for private calls.
+    #[serde(rename = "talkGroup")]
+    pub talkgroup: u16,
+    /// Traffic channel frequency (Hz).
+    pub freq: u32,
+    /// Action taken.
+    pub action: WatchdogAction,
+}
+
 /// Messages for `RecvTask`.
 pub enum RecvEvent {
-    /// Chunk of baseband samples.
-    Baseband(Checkout<Vec<f32>>),
+    /// Chunk of baseband samples, with the time the last of them was read from the SDR
+    /// and its sequence number.
+    Baseband(Checkout<Vec<f32>>, Instant, usize),
+    /// Chunk of baseband samples from the diversity receiver, with its sequence number.
+    DiversityBaseband(Checkout<Vec<f32>>, usize),
     /// Change the control channel frequency.
     SetControlFreq(u32),
     /// Reset stat counters.
     ResetStats,
+    /// Apply the given talkgroup selection settings.
+    Configure(TalkgroupConfig),
+    /// Only follow the given talkgroup, or any allowed talkgroup if `None`.
+    Hold(Option<u16>),
+    /// Enable or disable recording of calls and baseband.
+    Capture(bool),
+    /// Silence or restore the live audio output.
+    Mute(bool),
+    /// Stop receiving, finishing any recordings.
+    Stop,
+    /// Stop reading samples from the SDR, closing it if the contained flag is set.
+    Pause(bool),
+    /// Resume reading samples and reacquire the control channel.
+    Resume,
+    /// Reading couldn't be resumed because the SDR couldn't be reopened.
+    ResumeFailed,
+    /// Close and reopen the SDR and reacquire the control channel.
+    Restart,
+    /// Report liveness to systemd while paused.
+    Heartbeat,
+}
+
+impl QueueItem for RecvEvent {
+    fn droppable(&self) -> bool {
+        matches!(
+            *self,
+            RecvEvent::Baseband(..) | RecvEvent::DiversityBaseband(..)
+        )
+    }
+}
+
+/// Activity of the receiver.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+pub enum RecvState {
+    /// Waiting for the first trunking packet on a newly tuned control channel.
+    #[serde(rename = "searching")]
+    Searching,
+    /// Decoding the control channel.
+    #[serde(rename = "lockedControl")]
+    LockedControl,
+    /// Following a call on a traffic channel.
+    #[serde(rename = "tunedVoice")]
+    TunedVoice,
+    /// Waiting for the current call to continue after a voice message ended.
+    #[serde(rename = "hangTime")]
+    HangTime,
+    /// Trying to regain the lost control channel, or another known control channel.
+    #[serde(rename = "recoveringControl")]
+    RecoveringControl,
+    /// Not reading samples from the SDR.
+    #[serde(rename = "paused")]
+    Paused,
+}
+
+/// Number of baseband buffers discarded after retuning, beyond those already sent by the
+/// demodulator, which may hold samples from the previous frequency: those filled by the
+/// SDR, plus the one being demodulated.
+const FLUSH_BUFS: usize = BUF_COUNT + 2;
+
+/// Counts the baseband buffers sent by a demodulator, shared with the receiver so it can
+/// tell which were sent before retuning, however many other events are queued with them.
+#[derive(Clone, Default)]
+pub struct BasebandSeq(Arc<AtomicUsize>);
+
+impl BasebandSeq {
+    /// Get the sequence number of the next buffer to send.
+    pub fn next(&self) -> usize {
+        self.0.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Get the sequence number of the last buffer sent.
+    fn last(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Decoding state of the diversity receiver.
+struct Diversity {
+    /// Receiver state machine.
+    msg: MessageReceiver,
+    /// Chooses between the trunking packets of both receivers.
+    voter: Voter,
+    /// Counts the baseband buffers sent by the demodulator.
+    seq: BasebandSeq,
+    /// Sequence number of the last baseband buffer to discard.
+    flush: usize,
 }
 
 /// Processes P25 baseband and performs the duties of a trunking receiver.
 pub struct RecvTask {
     /// Receiver events.
-    events: Receiver<RecvEvent>,
+    events: QueueReceiver<RecvEvent>,
     /// Event streaming.
-    hub: mio_extras::channel::Sender<HubEvent>,
+    hub: QueueSender<HubEvent>,
     /// SDR control task.
-    sdr: Sender<ControlTaskEvent>,
-    /// Audio output task.
-    audio: Sender<AudioEvent>,
+    sdr: QueueSender<ControlTaskEvent>,
+    /// Audio output task, if audio is decoded.
+    audio: Option<QueueSender<AudioEvent>>,
     /// Control channel frequency (Hz).
     ctlfreq: u32,
     /// Whether frequency hopping is enabled.
@@ -64,15 +211,75 @@ pub struct RecvTask {
     curgroup: u16,
     /// Accumlated statistics.
     stats: Stats,
+    /// Writes decoded messages, if enabled.
+    pcap: Option<PcapWriter<BufWriter<File>>>,
+    /// Appends decoded messages as CSV rows, if enabled.
+    csv: Option<MessageCsv<BufWriter<File>>>,
+    /// Whether to log every received control message as hex.
+    dump: bool,
+    /// Reports service state to systemd, if enabled.
+    systemd: Option<Notifier>,
+    /// Baseband samples received on the control channel since the last valid trunking
+    /// packet.
+    ctl_idle: usize,
+    /// Whether the control channel has been reported lost.
+    ctl_lost: bool,
+    /// Whether a valid trunking packet has been received on the control channel since it
+    /// was tuned.
+    ctl_locked: bool,
+    /// Activity last reported to the hub.
+    state: RecvState,
+    /// Control channels to try when the current one is lost.
+    hunt: ControlHunt,
+    /// Units whose calls are chosen over others.
+    preferred_units: HashSet<u32, FnvBuildHasher>,
+    /// Time the first grant for each talkgroup was received since the last selection.
+    granted: HashMap<u16, Instant, FnvBuildHasher>,
+    /// Followed call that hasn't produced a voice frame yet, if any.
+    retune: Option<Retune>,
+    /// Time the current chunk of baseband was read from the SDR, and its length.
+    chunk: (Instant, usize),
+    /// Position of the sample being handled in the current chunk.
+    chunk_pos: usize,
+    /// Counts the baseband buffers sent by the demodulator.
+    seq: BasebandSeq,
+    /// Sequence number of the last baseband buffer to discard.
+    flush: usize,
+    /// Recent baseband, decoded for voice on locking on to a call, if enabled.
+    preroll: Option<PreRoll>,
+    /// Traffic channel (Hz) of the paused call while sampling the control channel.
+    sampling: Option<u32>,
+    /// Traffic channels never followed.
+    avoid: AvoidList,
+    /// Action taken when no voice is received on a traffic channel.
+    watchdog_action: WatchdogAction,
+    /// Whether the current traffic channel has been retuned after no voice.
+    retried: bool,
+    /// Whether unit-to-unit calls are followed.
+    unit_calls: bool,
+    /// Whether telephone interconnect calls are followed.
+    phone_calls: bool,
+    /// First private call granted since the last selection, if any.
+    private: Option<PrivateCall>,
+    /// Whether baseband is passed on for recording.
+    capture: bool,
+    /// Baseband samples received, used to time trunking packets for voting.
+    clock: usize,
+    /// Diversity receiver, if enabled.
+    diversity: Option<Diversity>,
+    /// Whether reading samples is paused.
+    paused: bool,
+    /// Processing metrics.
+    stage: Arc<StageMetrics>,
 }
 
 impl RecvTask {
     /// Create a new `RecvTask`.
     pub fn new(
-        events: Receiver<RecvEvent>,
-        hub: mio_extras::channel::Sender<HubEvent>,
-        sdr: Sender<ControlTaskEvent>,
-        audio: Sender<AudioEvent>,
+        events: QueueReceiver<RecvEvent>,
+        hub: QueueSender<HubEvent>,
+        sdr: QueueSender<ControlTaskEvent>,
+        audio: Option<QueueSender<AudioEvent>>,
         ctlfreq: u32,
         hopping: bool,
         policy: ReceiverPolicy,
@@ -92,10 +299,131 @@ impl RecvTask {
             curfreq: std::u32::MAX,
             curgroup: 0,
             stats: Stats::default(),
+            pcap: None,
+            csv: None,
+            dump: false,
+            systemd: None,
+            ctl_idle: 0,
+            ctl_lost: false,
+            ctl_locked: false,
+            state: RecvState::Searching,
+            hunt: ControlHunt::new(ctlfreq),
+            preferred_units: HashSet::default(),
+            granted: HashMap::default(),
+            retune: None,
+            chunk: (Instant::now(), 0),
+            chunk_pos: 0,
+            seq: BasebandSeq::default(),
+            flush: 0,
+            preroll: None,
+            sampling: None,
+            avoid: AvoidList::default(),
+            watchdog_action: WatchdogAction::Return,
+            retried: false,
+            unit_calls: false,
+            phone_calls: false,
+            private: None,
+            capture: true,
+            clock: 0,
+            diversity: None,
+            paused: false,
+            stage: StageMetrics::new("recv"),
         }
         .init(ctlfreq)
     }
 
+    /// Get the processing metrics of the task.
+    pub fn metrics(&self) -> Arc<StageMetrics> {
+        self.stage.clone()
+    }
+
+    /// Also write each decoded trunking packet and link control word to the given pcap
+    /// file.
+    pub fn pcap(mut self, pcap: PcapWriter<BufWriter<File>>) -> Self {
+        self.pcap = Some(pcap);
+        self
+    }
+
+    /// Also append each decoded trunking packet and link control word to the given CSV
+    /// file.
+    pub fn message_csv(mut self, csv: MessageCsv<BufWriter<File>>) -> Self {
+        self.csv = Some(csv);
+        self
+    }
+
+    /// Count the baseband buffers sent by the demodulator with the given sequence.
+    pub fn baseband_seq(mut self, seq: BasebandSeq) -> Self {
+        self.seq = seq;
+        self
+    }
+
+    /// Keep the given milliseconds of recent baseband, and decode voice from it on
+    /// locking on to a call, keeping the start of transmissions that begin before the
+    /// retune settles.
+    pub fn pre_record(mut self, ms: u32) -> Self {
+        let samples = ms as usize * BASEBAND_SAMPLE_RATE as usize / 1000;
+        self.preroll = Some(PreRoll::new(samples));
+        self
+    }
+
+    /// Also decode trunking packets from the diversity receiver, tuned along with this
+    /// one, keeping packets decoded by either receiver, and counting the baseband
+    /// buffers sent by its demodulator with the given sequence.
+    pub fn diversity(mut self, seq: BasebandSeq) -> Self {
+        self.diversity = Some(Diversity {
+            msg: MessageReceiver::new(),
+            // Both receivers' baseband is queued together, so copies of a packet arrive
+            // within about a buffer of each other.
+            voter: Voter::new(BASEBAND_SAMPLE_RATE as usize),
+            seq,
+            flush: 0,
+        });
+
+        self
+    }
+
+    /// Take the given action when no voice is received on a traffic channel before the
+    /// watchdog timeout.
+    pub fn watchdog_action(mut self, action: WatchdogAction) -> Self {
+        self.watchdog_action = action;
+        self
+    }
+
+    /// Log every received trunking packet and link control word as hex, along with its
+    /// decoded opcode.
+    pub fn dump_control(mut self) -> Self {
+        self.dump = true;
+        self
+    }
+
+    /// Report readiness, status, and watchdog heartbeats to systemd with the given
+    /// notifier.
+    pub fn systemd(mut self, systemd: Notifier) -> Self {
+        self.systemd = Some(systemd);
+        self.report_status();
+        self
+    }
+
+    /// Choose calls granted to any of the given units over other calls.
+    pub fn prefer_units(mut self, units: &[u32]) -> Self {
+        self.preferred_units = units.iter().cloned().collect();
+        self
+    }
+
+    /// Never follow calls on the given traffic channels.
+    pub fn avoid(mut self, avoid: AvoidList) -> Self {
+        self.avoid = avoid;
+        self
+    }
+
+    /// Also follow unit-to-unit calls and/or telephone interconnect calls when no
+    /// talkgroup call is available.
+    pub fn private_calls(mut self, unit: bool, phone: bool) -> Self {
+        self.unit_calls = unit;
+        self.phone_calls = phone;
+        self
+    }
+
     /// Finalize initialization of the receiver.
     fn init(mut self, freq: u32) -> Self {
         self.set_control_freq(freq);
@@ -110,21 +438,18 @@ impl RecvTask {
         if freq != self.ctlfreq {
             self.channels = ChannelParamsMap::default();
             self.talkgroups.clear_state();
+            self.ctl_locked = false;
         }
 
         self.ctlfreq = freq;
 
-        self.hub
-            .send(HubEvent::State(StateEvent::UpdateCtlFreq(freq)))
-            .expect("unable to send control frequency");
+        self.send_hub(HubEvent::State(StateEvent::UpdateCtlFreq(freq)));
         self.switch_control();
     }
 
     /// Move to the control channel.
     fn switch_control(&mut self) {
-        self.audio
-            .send(AudioEvent::EndTransmission)
-            .expect("unable to send end of transmission");
+        self.leave_call();
 
         // FIXME: non-lexical borrowing
         let freq = self.ctlfreq;
@@ -133,48 +458,239 @@ impl RecvTask {
         self.policy.enter_control();
     }
 
+    /// End any current call.
+    fn leave_call(&mut self) {
+        self.retune = None;
+        self.sampling = None;
+        self.private = None;
+        self.retried = false;
+
+        self.send_audio(AudioEvent::EndTransmission);
+        self.send_hub(HubEvent::EndTransmission);
+    }
+
+    /// Leave any current call and stop reading samples, closing the SDR if `release` is
+    /// set.
+    fn pause(&mut self, release: bool) {
+        if self.paused {
+            return;
+        }
+
+        info!("pausing receiver");
+
+        self.paused = true;
+        self.leave_call();
+
+        self.send_sdr(ControlTaskEvent::Pause(release));
+    }
+
+    /// Resume reading samples and reacquire the control channel.
+    fn resume(&mut self) {
+        if !self.paused {
+            return;
+        }
+
+        info!("resuming receiver");
+
+        self.paused = false;
+        self.ctl_locked = false;
+
+        self.send_sdr(ControlTaskEvent::Resume);
+
+        // The SDR may have been reopened or retuned by other software.
+        self.switch_control();
+    }
+
+    /// Pause again after the SDR couldn't be reopened, so a later resume tries again.
+    fn resume_failed(&mut self) {
+        info!("receiver staying paused");
+
+        self.paused = true;
+    }
+
+    /// Close the SDR and reopen it with the same settings, then reacquire the control
+    /// channel, whether or not reading is paused.
+    fn restart(&mut self) {
+        info!("restarting receiver");
+
+        if !self.paused {
+            self.paused = true;
+            self.leave_call();
+        }
+
+        // The SDR is already closed if it was released by a pause.
+        self.send_sdr(ControlTaskEvent::Pause(true));
+
+        self.resume();
+    }
+
+    /// Send the given event to the audio task, if audio is decoded, carrying on without
+    /// audio if the task has stopped.
+    fn send_audio(&mut self, e: AudioEvent) {
+        let stopped = match self.audio {
+            Some(ref audio) => audio.send(e).is_err(),
+            None => false,
+        };
+
+        if stopped {
+            self.report(Fault::error(
+                FaultCategory::Audio,
+                "audio task stopped, disabling audio",
+            ));
+            self.audio = None;
+        }
+    }
+
+    /// Send the given event to the SDR control task, reporting an error if it has
+    /// stopped, which leaves the SDR where it is.
+    fn send_sdr(&self, e: ControlTaskEvent) {
+        if self.sdr.send(e).is_err() {
+            self.report(Fault::error(FaultCategory::Sdr, "sdr control task stopped"));
+        }
+    }
+
+    /// Send the given event to the hub. The hub only stops by panicking, which is
+    /// already reported, so decoding carries on without it.
+    fn send_hub(&self, e: HubEvent) {
+        self.hub.send(e).ok();
+    }
+
+    /// Log the given fault and report it to the hub.
+    fn report(&self, fault: Fault) {
+        fault.log();
+        self.send_hub(HubEvent::Fault(fault));
+    }
+
     /// Move to the given frequency (Hz).
     fn set_freq(&mut self, freq: u32) {
         debug!("moving to frequency {} Hz", freq);
         self.curfreq = freq;
+        self.ctl_idle = 0;
+
+        if let Some(ref mut p) = self.preroll {
+            p.clear();
+        }
+
+        self.report_status();
 
-        self.hub
-            .send(HubEvent::UpdateCurFreq(freq))
-            .expect("unable to send current frequency");
-        self.sdr
-            .send(ControlTaskEvent::SetFreq(freq))
-            .expect("unable to set freq in sdr");
+        self.send_hub(HubEvent::UpdateCurFreq(freq));
+        self.send_sdr(ControlTaskEvent::SetFreq(freq));
+
+        // Baseband already sent was received on the previous frequency.
+        self.flush = self.seq.last() + FLUSH_BUFS;
+
+        if let Some(ref mut d) = self.diversity {
+            d.flush = d.seq.last() + FLUSH_BUFS;
+            d.msg.resync();
+        }
 
         self.msg.resync();
     }
 
-    /// Begin processing baseband samples, blocking the current thread.
-    pub fn run<F: FnMut(&[f32])>(&mut self, mut cb: F) {
+    /// Begin processing baseband samples, blocking the current thread until stopped and
+    /// writing each chunk to the given sink while recording is enabled.
+    pub fn run<S: BasebandSink>(&mut self, sink: &mut S) {
         let mut stats_notifier = Throttler::new(16);
 
         loop {
-            match self.events.recv().expect("unable to receive baseband") {
-                RecvEvent::Baseband(samples) => {
+            let event = match self.events.recv() {
+                Ok(event) => event,
+                // Every sender, including the demodulator, has stopped.
+                Err(_) => {
+                    warn!("baseband stopped");
+                    return;
+                }
+            };
+            let _timer = self.stage.start(self.events.metrics().depth());
+
+            match event {
+                // Samples queued before pausing were received before the call ended.
+                RecvEvent::Baseband(..) | RecvEvent::DiversityBaseband(..) if self.paused => {}
+                RecvEvent::Baseband(samples, written, seq) => {
                     self.talkgroups.record_elapsed(samples.len());
+                    self.clock += samples.len();
+
+                    // Voice that began while the retune settled leads the live samples.
+                    let pre = match self.preroll {
+                        Some(ref mut p) if seq <= self.flush => {
+                            p.push(&samples[..]);
+                            None
+                        }
+                        Some(ref mut p) => {
+                            let pre = p.take();
+                            p.push(&samples[..]);
+                            pre
+                        }
+                        None => None,
+                    };
 
-                    for &s in samples.iter() {
-                        self.handle_sample(s);
+                    if seq > self.flush {
+                        for s in pre.into_iter().flatten() {
+                            self.handle_sample(s, true);
+                        }
+
+                        self.chunk = (written, samples.len());
+
+                        for (i, &s) in samples.iter().enumerate() {
+                            self.chunk_pos = i;
+                            self.handle_sample(s, false);
+                        }
+
+                        self.check_control(samples.len());
+
+                        if self.capture {
+                            // Failed sinks are disabled and reported by the sink itself.
+                            sink.write(&samples[..]).ok();
+                        }
                     }
 
-                    cb(&samples[..]);
+                    // Heartbeats stop if baseband stops flowing or this thread wedges.
+                    self.notify(Notifier::heartbeat);
 
                     // FIXME: non-lexical borrowing
                     let event = self.policy.handle_elapsed(samples.len());
                     self.handle_policy(event);
                 }
-                RecvEvent::SetControlFreq(freq) => self.set_control_freq(freq),
+                RecvEvent::DiversityBaseband(samples, seq) => {
+                    self.handle_diversity(&samples[..], seq)
+                }
+                RecvEvent::SetControlFreq(freq) => {
+                    // Secondary channels of the previous system no longer apply.
+                    self.hunt = ControlHunt::new(freq);
+                    self.set_control_freq(freq);
+                }
                 RecvEvent::ResetStats => self.stats.clear(),
+                RecvEvent::Configure(c) => self.talkgroups.configure(c),
+                RecvEvent::Hold(tg) => self.hold(tg),
+                RecvEvent::Capture(capture) => {
+                    self.capture = capture;
+                    self.send_audio(AudioEvent::Capture(capture));
+                }
+                RecvEvent::Mute(muted) => self.send_audio(AudioEvent::Mute(muted)),
+                RecvEvent::Stop => {
+                    self.send_audio(AudioEvent::Stop);
+                    return;
+                }
+                RecvEvent::Pause(release) => self.pause(release),
+                RecvEvent::Resume => self.resume(),
+                RecvEvent::ResumeFailed => self.resume_failed(),
+                RecvEvent::Restart => self.restart(),
+                RecvEvent::Heartbeat => self.notify(Notifier::heartbeat),
             }
 
+            self.update_state();
+
             stats_notifier.throttle(|| {
-                self.hub
-                    .send(HubEvent::UpdateStats(self.stats))
-                    .expect("unable to send stats");
+                self.send_hub(HubEvent::UpdateStats(self.stats));
+
+                if let Some(ref d) = self.diversity {
+                    let (primary, diversity) = d.voter.only();
+
+                    debug!(
+                        "trunking packets decoded by only one receiver: {} primary, {} diversity",
+                        primary, diversity
+                    );
+                }
             });
         }
     }
@@ -195,27 +711,208 @@ impl RecvTask {
                 if let Some((tg, freq)) = self.talkgroups.select_idle() {
                     self.select_talkgroup(tg, freq);
                 }
+                else if let Some(p) = self.private.take() {
+                    self.select_private(p);
+                }
             }
+            SampleControl => self.sample_control(),
+            ResumeTraffic => self.resume_traffic(),
+            VoiceTimeout => self.voice_timeout(),
         }
     }
 
+    /// Take the configured action after no voice was received on the current traffic
+    /// channel.
+    fn voice_timeout(&mut self) {
+        let freq = self.curfreq;
+
+        // Only one retry is made on each channel.
+        let action = match self.watchdog_action {
+            WatchdogAction::Retry if self.retried => WatchdogAction::Return,
+            a => a,
+        };
+
+        debug!(
+            "no voice for talkgroup {} on {} Hz, taking {:?} action",
+            self.curgroup, freq, action
+        );
+
+        self.send_hub(HubEvent::VoiceTimeout(FailedTune {
+            talkgroup: self.curgroup,
+            freq,
+            action,
+        }));
+
+        match action {
+            WatchdogAction::Return => self.switch_control(),
+            WatchdogAction::Retry => {
+                self.retried = true;
+                self.set_freq(freq);
+                self.policy.enter_traffic();
+            }
+            WatchdogAction::Avoid => {
+                self.avoid.avoid_for(freq, TIMEOUT_AVOID);
+                self.switch_control();
+            }
+        }
+    }
+
+    /// Listen to the control channel during a pause in the current call.
+    fn sample_control(&mut self) {
+        if !self.hopping {
+            return;
+        }
+
+        debug!(
+            "sampling control channel during talkgroup {}",
+            self.curgroup
+        );
+
+        self.sampling = Some(self.curfreq);
+
+        // FIXME: non-lexical borrowing
+        let freq = self.ctlfreq;
+        self.set_freq(freq);
+    }
+
+    /// Follow any call granted while sampling that outranks the paused call, or return to
+    /// the paused call otherwise.
+    fn resume_traffic(&mut self) {
+        let freq = match self.sampling.take() {
+            Some(f) => f,
+            None => return,
+        };
+
+        if let Some((tg, next)) = self.talkgroups.select_over(self.curgroup) {
+            info!("leaving talkgroup {} for talkgroup {}", self.curgroup, tg);
+
+            self.send_audio(AudioEvent::EndTransmission);
+            self.send_hub(HubEvent::EndTransmission);
+
+            self.select_talkgroup(tg, next);
+            return;
+        }
+
+        self.set_freq(freq);
+        self.policy.resume_traffic();
+    }
+
+    /// Only follow the given talkgroup, or any allowed talkgroup if `None`.
+    fn hold(&mut self, tg: Option<u16>) {
+        match tg {
+            Some(tg) => info!("holding talkgroup {}", tg),
+            None => info!("releasing talkgroup hold"),
+        }
+
+        self.talkgroups.hold(tg);
+    }
+
     /// Choose the given talkgroup as the next to monitor.
     fn select_talkgroup(&mut self, tg: u16, freq: u32) {
+        let decision = self.talkgroups.take_decision();
+
         if !self.hopping {
             return;
         }
 
+        if let Some(d) = decision {
+            debug!(
+                "chose talkgroup {} by {} rule from {:?}",
+                d.talkgroup, d.rule, d.candidates
+            );
+
+            self.send_hub(HubEvent::SelectionDecision(d));
+        }
+
         self.curgroup = tg;
+        self.enter_call(freq, CallKind::Group);
+
+        self.send_hub(HubEvent::UpdateTalkGroup(self.curgroup));
+    }
+
+    /// Choose the given private call as the next to monitor.
+    fn select_private(&mut self, p: PrivateCall) {
+        if !self.hopping {
+            return;
+        }
+
+        debug!("following {} call from unit {}", p.kind.name(), p.src_unit);
+
+        // Private calls have no talkgroup.
+        self.curgroup = 0;
+        self.enter_call(p.freq, p.kind);
+
+        self.send_hub(HubEvent::BeginPrivateCall(p));
+    }
+
+    /// Move to the traffic channel at the given frequency (Hz) to follow a call of the
+    /// given type on the current talkgroup.
+    fn enter_call(&mut self, freq: u32, kind: CallKind) {
+        let tg = self.curgroup;
+
+        self.private = None;
+        self.retune = Some(Retune::new(
+            tg,
+            self.granted.get(&tg).cloned(),
+            Instant::now(),
+        ));
+        self.granted.clear();
+
+        let from_control = self.curfreq == self.ctlfreq;
         self.set_freq(freq);
+
+        // Baseband from another call's traffic channel could hold its voice.
+        if from_control {
+            if let Some(ref mut p) = self.preroll {
+                p.retune();
+            }
+        }
+
         self.policy.enter_traffic();
 
-        self.hub
-            .send(HubEvent::UpdateTalkGroup(self.curgroup))
-            .expect("unable to send talkgroup");
+        self.send_audio(AudioEvent::BeginCall(tg, freq, kind));
+    }
+
+    /// Report the control channel lost and move to the next known control channel if no
+    /// valid trunking packets have been received on it after the given number of
+    /// additional samples.
+    fn check_control(&mut self, samples: usize) {
+        if self.curfreq != self.ctlfreq {
+            return;
+        }
+
+        self.ctl_idle += samples;
+
+        if self.ctl_idle < CONTROL_TIMEOUT {
+            return;
+        }
+
+        // Wait a full timeout on each channel tried.
+        self.ctl_idle = 0;
+
+        if !self.ctl_lost {
+            self.report(Fault::warning(
+                FaultCategory::Decode,
+                format!("lost control channel at {} Hz", self.ctlfreq),
+            ));
+            self.ctl_lost = true;
+
+            self.send_hub(HubEvent::ControlLost);
+        }
+
+        if !self.hopping {
+            return;
+        }
+
+        if let Some(freq) = self.hunt.next(self.ctlfreq) {
+            info!("trying control channel at {} Hz", freq);
+            self.set_control_freq(freq);
+        }
     }
 
-    /// Process the given baseband sample.
-    fn handle_sample(&mut self, s: f32) {
+    /// Process the given baseband sample, only handling voice if it's from baseband being
+    /// discarded after a retune.
+    fn handle_sample(&mut self, s: f32, flushed: bool) {
         use p25::message::receiver::MessageEvent::*;
 
         let event = match self.msg.feed(s) {
@@ -225,6 +922,11 @@ impl RecvTask {
 
         self.stats.merge(&mut self.msg);
 
+        // Other messages may have come from the control channel before the retune.
+        if flushed && !matches!(event, VoiceFrame(_)) {
+            return;
+        }
+
         match event {
             Error(e) => self.stats.record_err(e),
             PacketNID(nid) => {
@@ -239,17 +941,87 @@ impl RecvTask {
             CryptoControl(cc) => self.handle_crypto(cc.alg()),
             LowSpeedDataFragment(_) => {}
             VoiceFrame(vf) => {
-                self.audio
-                    .send(AudioEvent::VoiceFrame(vf))
-                    .expect("unable to send voice frame");
+                if let Some(r) = self.retune.take() {
+                    let l = r.finish(Instant::now());
+
+                    debug!(
+                        "first voice on talkgroup {} {} ms after retune",
+                        l.talkgroup, l.retune
+                    );
+
+                    self.send_hub(HubEvent::RetuneLatency(l));
+                }
+
+                // Frames from discarded baseband weren't received live.
+                let arrived = (!flushed).then(|| self.sample_arrival());
+
+                self.send_audio(AudioEvent::VoiceFrame(vf, arrived));
             }
-            TrunkingControl(tsbk) => self.handle_tsbk(tsbk),
+            TrunkingControl(tsbk) => self.vote_tsbk(tsbk, Branch::Primary),
             VoiceTerm(lc) => self.handle_lc(lc),
         }
     }
 
+    /// Estimate the time the sample being handled arrived at the SDR.
+    fn sample_arrival(&self) -> Instant {
+        let (written, len) = self.chunk;
+        let later = len.saturating_sub(self.chunk_pos + 1);
+
+        written - Duration::from_secs_f32(later as f32 / BASEBAND_SAMPLE_RATE as f32)
+    }
+
+    /// Process the given baseband samples from the diversity receiver, with the given
+    /// sequence number.
+    fn handle_diversity(&mut self, samples: &[f32], seq: usize) {
+        use p25::message::receiver::MessageEvent::TrunkingControl;
+
+        let mut packets = vec![];
+
+        match self.diversity {
+            Some(ref d) if seq <= d.flush => {}
+            Some(ref mut d) => {
+                for &s in samples {
+                    if let Some(TrunkingControl(tsbk)) = d.msg.feed(s) {
+                        packets.push(tsbk);
+                    }
+                }
+            }
+            None => return,
+        }
+
+        for tsbk in packets {
+            self.vote_tsbk(tsbk, Branch::Diversity);
+        }
+    }
+
+    /// Process the given trunking packet decoded by the given receiver, unless it's a
+    /// copy of one already processed from the other receiver.
+    ///
+    /// Only packets received on the control channel are voted on, and the diversity
+    /// receiver's are otherwise ignored.
+    fn vote_tsbk(&mut self, tsbk: TsbkFields, branch: Branch) {
+        let on_control = self.curfreq == self.ctlfreq;
+        let clock = self.clock;
+
+        let accept = match self.diversity {
+            Some(ref mut d) if on_control && tsbk.crc_valid() => {
+                d.voter.accept(dump::tsbk(&tsbk), clock, branch)
+            }
+            _ => branch == Branch::Primary,
+        };
+
+        if accept {
+            self.handle_tsbk(tsbk);
+        }
+    }
+
     /// Process the given trunking packet.
     fn handle_tsbk(&mut self, tsbk: TsbkFields) {
+        let freq = self.curfreq;
+        self.write_pcap(|p| p.write_tsbk(&tsbk, freq));
+        self.write_csv(|c| c.write_tsbk(&tsbk, freq));
+        self.dump_message(|| dump::tsbk(&tsbk));
+
         if tsbk.mfg() != 0 {
             return;
         }
@@ -265,24 +1037,78 @@ impl RecvTask {
 
         trace!("received TSBK with opcode {:?}", opcode);
 
-        self.hub
-            .send(HubEvent::TrunkingControl(tsbk))
-            .expect("unable to send trunking control");
+        if self.curfreq == self.ctlfreq {
+            // The receiver is ready once it's decoding the control channel.
+            self.notify(Notifier::ready);
+            self.ctl_idle = 0;
+            self.ctl_locked = true;
+
+            if self.ctl_lost {
+                info!("regained control channel at {} Hz", self.ctlfreq);
+                self.ctl_lost = false;
+
+                self.send_hub(HubEvent::ControlRestored);
+            }
+        }
+
+        self.send_hub(HubEvent::TrunkingControl(tsbk));
 
         match opcode {
             TsbkOpcode::GroupVoiceGrant => {
                 let grant = tsbk::GroupVoiceGrant::new(tsbk);
-                self.add_talkgroup(grant.talkgroup(), grant.channel());
+
+                if let TalkGroup::Other(tg) = grant.talkgroup() {
+                    self.granted.entry(tg).or_insert_with(Instant::now);
+                }
+
+                if let Some((tg, freq)) = self.avoided(grant.talkgroup(), grant.channel()) {
+                    debug!("ignoring grant for talkgroup {} on avoided {} Hz", tg, freq);
+
+                    self.send_hub(HubEvent::AvoidedGrant(tg, freq));
+
+                    return;
+                }
+
+                if self.preferred_units.contains(&grant.src_unit()) {
+                    self.add_preferred(grant.talkgroup(), grant.channel());
+                }
+                else {
+                    self.add_talkgroup(grant.talkgroup(), grant.channel());
+                }
             }
             TsbkOpcode::GroupVoiceUpdate => {
                 self.handle_traffic_updates(&fields::GroupTrafficUpdate::new(tsbk.payload()));
             }
+            TsbkOpcode::UnitVoiceGrant if self.unit_calls => {
+                let grant = tsbk::UnitVoiceGrant::new(tsbk);
+
+                self.add_private(
+                    CallKind::Unit,
+                    grant.src_unit(),
+                    Some(grant.dest_unit()),
+                    grant.channel(),
+                );
+            }
+            TsbkOpcode::PhoneGrant if self.phone_calls => {
+                let grant = tsbk::PhoneGrant::new(tsbk);
+                self.add_private(CallKind::Phone, grant.unit(), None, grant.channel());
+            }
+            TsbkOpcode::AltControlChannel => {
+                for &(ch, _) in fields::AltControlChannel::new(tsbk.payload()).alts().iter() {
+                    let freq = match self.channels.lookup(ch.id()) {
+                        Some(p) => p.rx_freq(ch.number()),
+                        None => continue,
+                    };
+
+                    if self.hunt.add(freq) {
+                        info!("found secondary control channel at {} Hz", freq);
+                    }
+                }
+            }
             TsbkOpcode::ChannelParamsUpdate => {
                 let dec = fields::ChannelParamsUpdate::new(tsbk.payload());
                 self.channels.update(&dec);
-                self.hub
-                    .send(HubEvent::State(StateEvent::UpdateChannelParams(tsbk)))
-                    .expect("unable to send channel update");
+                self.send_hub(HubEvent::State(StateEvent::UpdateChannelParams(tsbk)));
             }
             _ => {}
         }
@@ -290,7 +1116,12 @@ impl RecvTask {
 
     /// Process the given link control word.
     fn handle_lc(&mut self, lc: LinkControlFields) {
-        use p25::voice::control::LinkControlOpcode;
+        use p25::voice::control::{GroupVoiceTraffic, LinkControlOpcode};
+
+        let freq = self.curfreq;
+        self.write_pcap(|p| p.write_lc(&lc, freq));
+        self.write_csv(|c| c.write_lc(&lc, freq));
+        self.dump_message(|| dump::lc(&lc));
 
         let opcode = match lc.opcode() {
             Some(o) => o,
@@ -299,11 +1130,14 @@ impl RecvTask {
 
         trace!("received LC word with opcode {:?}", opcode);
 
-        self.hub
-            .send(HubEvent::LinkControl(lc))
-            .expect("unable to send link control");
+        self.send_hub(HubEvent::LinkControl(lc));
 
         match opcode {
+            LinkControlOpcode::GroupVoiceTraffic => {
+                self.send_audio(AudioEvent::UpdateSrcUnit(
+                    GroupVoiceTraffic::new(lc).src_unit(),
+                ));
+            }
             LinkControlOpcode::CallTermination => {
                 // FIXME: non-lexical borrowing
                 let event = self.policy.handle_call_term();
@@ -320,6 +1154,127 @@ impl RecvTask {
         }
     }
 
+    /// Write a message to the pcap file, if enabled, disabling the export after a failed
+    /// write.
+    fn write_pcap<F>(&mut self, write: F)
+    where
+        F: FnOnce(&mut PcapWriter<BufWriter<File>>) -> std::io::Result<()>,
+    {
+        let res = match self.pcap {
+            Some(ref mut p) => write(p),
+            None => return,
+        };
+
+        if let Err(e) = res {
+            warn!("disabling pcap export: {}", e);
+            self.pcap = None;
+        }
+    }
+
+    /// Write a message to the CSV file, if enabled, disabling the export after a failed
+    /// write.
+    fn write_csv<F>(&mut self, write: F)
+    where
+        F: FnOnce(&mut MessageCsv<BufWriter<File>>) -> std::io::Result<()>,
+    {
+        let res = match self.csv {
+            Some(ref mut c) => write(c),
+            None => return,
+        };
+
+        if let Err(e) = res {
+            warn!("disabling CSV export: {}", e);
+            self.csv = None;
+        }
+    }
+
+    /// Log the given control message description, at info level if dumping is enabled
+    /// and trace level otherwise.
+    fn dump_message<F: FnOnce() -> String>(&self, describe: F) {
+        if self.dump {
+            info!(target: "control", "{}", describe());
+        }
+        else {
+            trace!(target: "control", "{}", describe());
+        }
+    }
+
+    /// Get the current activity of the receiver.
+    fn current_state(&self) -> RecvState {
+        if self.paused {
+            RecvState::Paused
+        }
+        else if self.curfreq != self.ctlfreq {
+            if self.policy.paused() {
+                RecvState::HangTime
+            }
+            else {
+                RecvState::TunedVoice
+            }
+        }
+        else if self.sampling.is_some() {
+            // Sampling the control channel is part of the pause in the current call.
+            RecvState::HangTime
+        }
+        else if self.ctl_lost {
+            RecvState::RecoveringControl
+        }
+        else if self.ctl_locked {
+            RecvState::LockedControl
+        }
+        else {
+            RecvState::Searching
+        }
+    }
+
+    /// Report the current activity to the hub if it has changed.
+    fn update_state(&mut self) {
+        let state = self.current_state();
+
+        if state == self.state {
+            return;
+        }
+
+        debug!("receiver state {:?}", state);
+        self.state = state;
+
+        self.send_hub(HubEvent::RecvStateChange(state));
+    }
+
+    /// Report the current frequency and talkgroup to systemd, if enabled.
+    fn report_status(&mut self) {
+        let freq = self.curfreq as f64 / 1e6;
+
+        let status = if self.curfreq == self.ctlfreq {
+            format!("Monitoring control channel at {:.4} MHz", freq)
+        }
+        else if self.curgroup == 0 {
+            format!("Following private call at {:.4} MHz", freq)
+        }
+        else {
+            format!("Following talkgroup {} at {:.4} MHz", self.curgroup, freq)
+        };
+
+        self.notify(|n| n.status(status));
+    }
+
+    /// Send a notification to systemd, if enabled, disabling notifications after a
+    /// failed send.
+    fn notify<F>(&mut self, send: F)
+    where
+        F: FnOnce(&mut Notifier) -> std::io::Result<()>,
+    {
+        let res = match self.systemd {
+            Some(ref mut n) => send(n),
+            None => return,
+        };
+
+        if let Err(e) = res {
+            warn!("disabling systemd notifications: {}", e);
+            self.systemd = None;
+        }
+    }
+
     /// Collect talkgroups from the given traffic update packet.
     fn handle_traffic_updates(&mut self, u: &fields::GroupTrafficUpdate) {
         for &(ch, tg) in u.updates().iter() {
@@ -334,28 +1289,90 @@ impl RecvTask {
         }
 
         self.switch_control();
+
+        // Private calls have no talkgroup to remember as encrypted.
+        if self.curgroup == 0 {
+            return;
+        }
+
         self.talkgroups.record_encrypted(self.curgroup, alg);
 
-        self.hub
-            .send(HubEvent::State(StateEvent::UpdateEncrypted(
-                self.curgroup,
-                alg,
-            )))
-            .expect("unable to send encrypted talkgroups");
+        self.send_hub(HubEvent::State(StateEvent::UpdateEncrypted(
+            self.curgroup,
+            alg,
+        )));
     }
 
     /// Collect the given talkgroup and associated traffic channel.
     fn add_talkgroup(&mut self, tg: TalkGroup, ch: Channel) {
-        let tg = match tg {
-            TalkGroup::Other(x) => x,
-            _ => return,
-        };
+        if let Some((tg, freq)) = self.resolve(tg, ch) {
+            self.talkgroups.add_talkgroup(tg, freq);
+        }
+    }
+
+    /// Collect the given talkgroup and associated traffic channel, choosing it over
+    /// other talkgroups.
+    fn add_preferred(&mut self, tg: TalkGroup, ch: Channel) {
+        if let Some((tg, freq)) = self.resolve(tg, ch) {
+            self.talkgroups.add_preferred(tg, freq);
+        }
+    }
+
+    /// Collect the given private call, if no other has been granted since the last
+    /// selection, it's on a known channel that isn't avoided, and no talkgroup is held.
+    fn add_private(&mut self, kind: CallKind, src: u32, dest: Option<u32>, ch: Channel) {
+        if self.private.is_some() || self.talkgroups.holding() {
+            return;
+        }
 
         let freq = match self.channels.lookup(ch.id()) {
             Some(p) => p.rx_freq(ch.number()),
             None => return,
         };
 
-        self.talkgroups.add_talkgroup(tg, freq);
+        if self.avoid.avoids(ch.id(), ch.number(), freq) {
+            return;
+        }
+
+        debug!("collecting {} call from unit {}", kind.name(), src);
+
+        self.private = Some(PrivateCall {
+            kind,
+            src_unit: src,
+            dest_unit: dest,
+            freq,
+        });
+    }
+
+    /// Get the ID and traffic channel frequency (Hz) of the given talkgroup and channel,
+    /// if it's an ordinary talkgroup on a known channel that isn't avoided.
+    fn resolve(&self, tg: TalkGroup, ch: Channel) -> Option<(u16, u32)> {
+        let (tg, freq) = self.lookup(tg, ch)?;
+
+        if self.avoid.avoids(ch.id(), ch.number(), freq) {
+            return None;
+        }
+
+        Some((tg, freq))
+    }
+
+    /// Get the ID and traffic channel frequency (Hz) of the given talkgroup and channel
+    /// if the channel is avoided.
+    fn avoided(&self, tg: TalkGroup, ch: Channel) -> Option<(u16, u32)> {
+        self.lookup(tg, ch)
+            .filter(|&(_, freq)| self.avoid.avoids(ch.id(), ch.number(), freq))
+    }
+
+    /// Get the ID and traffic channel frequency (Hz) of the given talkgroup and channel,
+    /// if it's an ordinary talkgroup on a known channel.
+    fn lookup(&self, tg: TalkGroup, ch: Channel) -> Option<(u16, u32)> {
+        let tg = match tg {
+            TalkGroup::Other(x) => x,
+            _ => return None,
+        };
+
+        let freq = self.channels.lookup(ch.id())?.rx_freq(ch.number());
+
+        Some((tg, freq))
     }
 }
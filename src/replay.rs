@@ -1,18 +1,49 @@
 //! Replay saved baseband recordings.
 
-use std::io::{Read, Write};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
 
+use anyhow::{Context, Result};
+use imbe::consts::SAMPLES_PER_FRAME;
+use p25::{
+    message::receiver::{MessageEvent, MessageReceiver},
+    stats::Stats,
+    trunking::{
+        fields::{self, TalkGroup},
+        tsbk::{self, TsbkFields, TsbkOpcode},
+    },
+    voice::control::{self, LinkControlFields, LinkControlOpcode},
+};
+use serde::Serialize;
+use serde_json::Value;
 use slice_cast;
 
 use crate::{
     audio::AudioOutput,
-    p25::{message::receiver::MessageReceiver, stats::Stats},
+    consts::AUDIO_SAMPLE_RATE,
+    csvlog::MessageCsv,
+    dibit::{self, DibitWriter},
+    dump::hex,
+    hub,
+    pcap::PcapWriter,
 };
 
 pub struct ReplayReceiver<W: Write> {
     audio: AudioOutput<W>,
     msg: MessageReceiver,
-    stats: Stats,
+    summary: ReplaySummary,
+    /// Decoded events, if event capture is enabled.
+    events: Option<Vec<Value>>,
+    /// Writes decoded messages, if enabled.
+    pcap: Option<PcapWriter<BufWriter<File>>>,
+    /// Appends decoded messages as CSV rows, if enabled.
+    csv: Option<MessageCsv<BufWriter<File>>>,
+    /// Writes sliced dibits, if enabled.
+    dibits: Option<DibitWriter>,
 }
 
 impl<W: Write> ReplayReceiver<W> {
@@ -20,40 +51,292 @@ impl<W: Write> ReplayReceiver<W> {
         ReplayReceiver {
             audio,
             msg: MessageReceiver::new(),
-            stats: Stats::default(),
+            summary: ReplaySummary::default(),
+            events: None,
+            pcap: None,
+            csv: None,
+            dibits: None,
         }
     }
 
-    pub fn replay<R: Read>(&mut self, stream: &mut R) {
+    /// Also write each decoded trunking packet and link control word to the given pcap
+    /// file.
+    pub fn pcap(mut self, pcap: PcapWriter<BufWriter<File>>) -> Self {
+        self.pcap = Some(pcap);
+        self
+    }
+
+    /// Also append each decoded trunking packet and link control word to the given CSV
+    /// file.
+    pub fn message_csv(mut self, csv: MessageCsv<BufWriter<File>>) -> Self {
+        self.csv = Some(csv);
+        self
+    }
+
+    /// Also write the dibits sliced from replayed samples to the given symbol capture.
+    pub fn dibits(mut self, dibits: DibitWriter) -> Self {
+        self.dibits = Some(dibits);
+        self
+    }
+
+    /// Capture a serialized copy of each decoded event, to be retrieved with
+    /// `take_events`.
+    pub fn capture_events(mut self) -> Self {
+        self.events = Some(vec![]);
+        self
+    }
+
+    /// Retrieve the events captured since the last call.
+    pub fn take_events(&mut self) -> Vec<Value> {
+        self.events.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Replay all samples in the given stream and return a summary of what was decoded.
+    ///
+    /// Decoder state is reset before each replay, so each stream is summarized
+    /// independently of any previously replayed streams.
+    pub fn replay<R: Read>(&mut self, stream: &mut R) -> Result<ReplaySummary> {
         let mut buf = [0; 32768];
+        // Bytes of a sample split across reads, kept at the start of the buffer.
+        let mut partial = 0;
+
+        self.msg = MessageReceiver::new();
+        self.audio.reset();
+        self.summary = ReplaySummary::default();
 
         loop {
-            let size = stream.read(&mut buf).expect("unable to read samples");
+            let size = match stream.read(&mut buf[partial..]) {
+                Ok(size) => size,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e).context("unable to read samples"),
+            };
 
             if size == 0 {
                 break;
             }
 
-            self.feed(unsafe { slice_cast::cast(&buf[..]) });
+            let len = partial + size;
+            let whole = len - len % 4;
+
+            self.feed(unsafe { slice_cast::cast(&buf[..whole]) });
+
+            buf.copy_within(whole..len, 0);
+            partial = len - whole;
+        }
+
+        if partial != 0 {
+            warn!("ignoring {} trailing bytes of a partial sample", partial);
         }
+
+        Ok(std::mem::take(&mut self.summary))
+    }
+
+    /// Replay all dibits in the given symbol capture and return a summary of what was
+    /// decoded.
+    pub fn replay_dibits<R: Read>(&mut self, stream: &mut R) -> Result<ReplaySummary> {
+        let samples = dibit::read_capture(stream).context("unable to read dibits")?;
+
+        self.msg = MessageReceiver::new();
+        self.audio.reset();
+        self.summary = ReplaySummary::default();
+
+        self.feed(&samples);
+
+        Ok(std::mem::take(&mut self.summary))
     }
 
     fn feed(&mut self, samples: &[f32]) {
         use p25::message::receiver::MessageEvent::*;
 
+        if let Some(ref mut dibits) = self.dibits {
+            dibits.write(samples).expect("unable to write dibits");
+        }
+
         for &sample in samples {
             let event = match self.msg.feed(sample) {
                 Some(event) => event,
                 None => continue,
             };
 
-            self.stats.merge(&mut self.msg);
+            self.summary.stats.merge(&mut self.msg);
+
+            if let Some(ref mut events) = self.events {
+                events.push(serialize_event(&event));
+            }
+
+            if let Some(ref mut pcap) = self.pcap {
+                // Replayed recordings have no known frequency.
+                let res = match event {
+                    TrunkingControl(ref tsbk) => pcap.write_tsbk(tsbk, 0),
+                    LinkControl(ref lc) | VoiceTerm(ref lc) => pcap.write_lc(lc, 0),
+                    _ => Ok(()),
+                };
+
+                res.expect("unable to write pcap");
+            }
+
+            if let Some(ref mut csv) = self.csv {
+                let res = match event {
+                    TrunkingControl(ref tsbk) => csv.write_tsbk(tsbk, 0),
+                    LinkControl(ref lc) | VoiceTerm(ref lc) => csv.write_lc(lc, 0),
+                    _ => Ok(()),
+                };
+
+                res.expect("unable to write CSV");
+            }
 
             match event {
-                Error(e) => self.stats.record_err(e),
-                VoiceFrame(vf) => self.audio.play(&vf),
+                Error(e) => {
+                    *self.summary.errors.entry(format!("{:?}", e)).or_insert(0) += 1;
+                    self.summary.stats.record_err(e);
+                }
+                VoiceFrame(vf) => {
+                    self.summary.voice_frames += 1;
+                    self.audio.play(&vf).expect("unable to write audio samples");
+                }
+                TrunkingControl(tsbk) => self.summary.record_tsbk(tsbk),
+                LinkControl(lc) | VoiceTerm(lc) => self.summary.record_lc(lc),
                 _ => {}
             }
         }
     }
 }
+
+/// Summary of the messages decoded from a single replayed recording.
+#[derive(Default)]
+pub struct ReplaySummary {
+    /// Number of voice frames decoded.
+    voice_frames: usize,
+    /// Number of valid trunking packets decoded.
+    tsbks: usize,
+    /// Number of link control words decoded.
+    link_controls: usize,
+    /// Count of each type of decoding error.
+    errors: BTreeMap<String, usize>,
+    /// Talkgroups seen in voice grants, updates, and link control.
+    talkgroups: BTreeSet<u16>,
+    /// Accumulated error correction statistics.
+    stats: Stats,
+}
+
+impl ReplaySummary {
+    /// Record the given trunking packet.
+    fn record_tsbk(&mut self, tsbk: TsbkFields) {
+        if tsbk.mfg() != 0 || !tsbk.crc_valid() {
+            return;
+        }
+
+        self.tsbks += 1;
+
+        match tsbk.opcode() {
+            Some(TsbkOpcode::GroupVoiceGrant) => {
+                self.record_talkgroup(tsbk::GroupVoiceGrant::new(tsbk).talkgroup());
+            }
+            Some(TsbkOpcode::GroupVoiceUpdate) => {
+                for &(_, tg) in fields::GroupTrafficUpdate::new(tsbk.payload())
+                    .updates()
+                    .iter()
+                {
+                    self.record_talkgroup(tg);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Record the given link control word.
+    fn record_lc(&mut self, lc: LinkControlFields) {
+        self.link_controls += 1;
+
+        if let Some(LinkControlOpcode::GroupVoiceTraffic) = lc.opcode() {
+            self.record_talkgroup(control::GroupVoiceTraffic::new(lc).talkgroup());
+        }
+    }
+
+    /// Record that the given talkgroup was seen.
+    fn record_talkgroup(&mut self, tg: TalkGroup) {
+        if let TalkGroup::Other(tg) = tg {
+            self.talkgroups.insert(tg);
+        }
+    }
+
+    /// Total seconds of decoded voice audio.
+    pub fn voice_secs(&self) -> f32 {
+        (self.voice_frames * SAMPLES_PER_FRAME) as f32 / AUDIO_SAMPLE_RATE as f32
+    }
+
+    /// Serialize the summary, tagged with the given source file path.
+    pub fn serialize(&self, path: &Path) -> impl Serialize {
+        json!({
+            "file": path.display().to_string(),
+            "voiceFrames": self.voice_frames,
+            "voiceSecs": self.voice_secs(),
+            "tsbks": self.tsbks,
+            "linkControls": self.link_controls,
+            "errors": &self.errors,
+            "talkgroups": &self.talkgroups,
+            "stats": hub::serialize_stats(&self.stats),
+        })
+    }
+}
+
+/// Serialize the given decoded event into a stable form suitable for comparison.
+fn serialize_event(e: &MessageEvent) -> Value {
+    use p25::message::receiver::MessageEvent::*;
+
+    match *e {
+        Error(ref e) => json!({ "event": "error", "kind": format!("{:?}", e) }),
+        PacketNID(ref nid) => json!({
+            "event": "nid",
+            "dataUnit": format!("{:?}", nid.data_unit),
+        }),
+        VoiceHeader(_) => json!({ "event": "voiceHeader" }),
+        LinkControl(lc) => json!({
+            "event": "linkControl",
+            "opcode": format!("{:?}", lc.opcode()),
+            "payload": hex(lc.payload()),
+        }),
+        CryptoControl(_) => json!({ "event": "cryptoControl" }),
+        LowSpeedDataFragment(_) => json!({ "event": "lowSpeedData" }),
+        VoiceFrame(_) => json!({ "event": "voiceFrame" }),
+        TrunkingControl(tsbk) => json!({
+            "event": "tsbk",
+            "opcode": format!("{:?}", tsbk.opcode()),
+            "crcValid": tsbk.crc_valid(),
+            "payload": hex(tsbk.payload()),
+        }),
+        VoiceTerm(lc) => json!({
+            "event": "voiceTerm",
+            "opcode": format!("{:?}", lc.opcode()),
+            "payload": hex(lc.payload()),
+        }),
+    }
+}
+
+/// Expand the given replay paths into a list of recordings.
+///
+/// Files are used as-is, and directories are expanded (non-recursively) to the files
+/// they contain, sorted by name.
+pub fn collect_paths<P: AsRef<Path>>(paths: &[P]) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+
+    for path in paths {
+        let path = path.as_ref();
+
+        if !path.is_dir() {
+            files.push(path.to_path_buf());
+            continue;
+        }
+
+        let mut entries = fs::read_dir(path)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        entries.retain(|p| p.is_file());
+        entries.sort();
+
+        files.extend(entries);
+    }
+
+    Ok(files)
+}
@@ -0,0 +1,91 @@
+//! Sample rate conversion for audio output.
+
+/// Converts a continuous stream of samples between sample rates using linear
+/// interpolation.
+pub struct Resampler {
+    /// Input sample rate (Hz).
+    input: u64,
+    /// Output sample rate (Hz).
+    output: u64,
+    /// Position of the next output sample, in units of 1/`output` input samples, where
+    /// 0 is the last sample of the previous input block and `output` is the first sample
+    /// of the current block. Kept as an integer so long streams don't drift.
+    pos: u64,
+    /// Last sample of the previous input block.
+    prev: f32,
+}
+
+impl Resampler {
+    /// Create a new `Resampler` from the given input rate to the given output rate.
+    pub fn new(input: u32, output: u32) -> Self {
+        Resampler {
+            input: input as u64,
+            output: output as u64,
+            pos: 0,
+            prev: 0.0,
+        }
+    }
+
+    /// Resample the given block of input samples, appending the output samples.
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        let len = input.len() as u64 * self.output;
+
+        while self.pos < len {
+            let idx = (self.pos / self.output) as usize;
+            let frac = (self.pos % self.output) as f32 / self.output as f32;
+
+            let a = if idx == 0 { self.prev } else { input[idx - 1] };
+            let b = input[idx];
+
+            out.push(a + (b - a) * frac);
+            self.pos += self.input;
+        }
+
+        self.pos -= len;
+
+        if let Some(&s) = input.last() {
+            self.prev = s;
+        }
+    }
+
+    /// Clear history between unrelated streams.
+    pub fn reset(&mut self) {
+        self.pos = 0;
+        self.prev = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resample() {
+        let mut r = Resampler::new(8000, 8000);
+        let mut out = vec![];
+        r.process(&[1.0, 2.0, 3.0], &mut out);
+        r.process(&[4.0, 5.0], &mut out);
+        assert_eq!(&out[..], &[0.0, 1.0, 2.0, 3.0, 4.0]);
+
+        let mut r = Resampler::new(8000, 16000);
+        let mut out = vec![];
+        r.process(&[2.0, 4.0], &mut out);
+        assert_eq!(&out[..], &[0.0, 1.0, 2.0, 3.0]);
+        r.process(&[6.0], &mut out);
+        assert_eq!(&out[..], &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+
+        let mut r = Resampler::new(8000, 4000);
+        let mut out = vec![];
+        r.process(&[1.0, 2.0, 3.0, 4.0, 5.0], &mut out);
+        assert_eq!(&out[..], &[0.0, 2.0, 4.0]);
+
+        let mut r = Resampler::new(8000, 44100);
+        let mut out = vec![];
+
+        for _ in 0..50 {
+            r.process(&[0.5; 160], &mut out);
+        }
+
+        assert_eq!(out.len(), 44100);
+    }
+}
@@ -0,0 +1,183 @@
+//! Responses to unit service requests broadcast on the control channel.
+
+use p25::trunking::tsbk::{TsbkFields, TsbkOpcode};
+
+/// Kind of response to a service request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum ResponseKind {
+    /// Request was acknowledged.
+    #[serde(rename = "ack")]
+    Ack,
+    /// Request was queued until resources are available.
+    #[serde(rename = "queued")]
+    Queued,
+    /// Request was denied.
+    #[serde(rename = "deny")]
+    Deny,
+}
+
+impl ResponseKind {
+    /// Get the name of the event reporting responses of this kind.
+    pub fn event(&self) -> &'static str {
+        match *self {
+            ResponseKind::Ack => "ackResponse",
+            ResponseKind::Queued => "queuedResponse",
+            ResponseKind::Deny => "denyResponse",
+        }
+    }
+}
+
+/// Response to a service request made by a unit.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct Response {
+    /// Kind of response.
+    pub kind: ResponseKind,
+    /// Opcode of the requested service.
+    pub service: u8,
+    /// Reason for queueing or denying the request.
+    pub reason: Option<u8>,
+    /// Description of the reason, if it's a standard one.
+    #[serde(rename = "reasonName")]
+    pub reason_name: Option<&'static str>,
+    /// Additional information, usually the requesting unit for acknowledgements and the
+    /// requested talkgroup or unit otherwise, if given.
+    pub info: Option<u32>,
+    /// Unit the response is sent to.
+    pub target: u32,
+}
+
+impl Response {
+    /// Decode the given trunking packet if it's a response to a service request.
+    pub fn from_tsbk(tsbk: TsbkFields) -> Option<Self> {
+        let kind = match tsbk.opcode()? {
+            TsbkOpcode::AckResponse => ResponseKind::Ack,
+            TsbkOpcode::QueuedResponse => ResponseKind::Queued,
+            TsbkOpcode::DenyResponse => ResponseKind::Deny,
+            _ => return None,
+        };
+
+        Some(Self::new(kind, tsbk.payload()))
+    }
+
+    /// Decode a response of the given kind from the given trunking packet payload.
+    fn new(kind: ResponseKind, p: &[u8]) -> Self {
+        // Additional information is given with this flag.
+        let aiv = p[0] & 0x80 != 0;
+        let service = p[0] & 0x3F;
+        let target = u24(&p[5..]);
+
+        let (reason, info) = match kind {
+            // With this flag, the system of the requesting unit is given instead of the
+            // unit itself.
+            ResponseKind::Ack if p[0] & 0x40 != 0 => (None, None),
+            ResponseKind::Ack => (None, Some(u24(&p[2..]))),
+            _ => (Some(p[1]), Some(u24(&p[2..]))),
+        };
+
+        let reason_name = reason.and_then(|r| match kind {
+            ResponseKind::Ack => None,
+            ResponseKind::Queued => queue_reason(r),
+            ResponseKind::Deny => deny_reason(r),
+        });
+
+        Response {
+            kind,
+            service,
+            reason,
+            reason_name,
+            info: info.filter(|_| aiv),
+            target,
+        }
+    }
+}
+
+/// Get the 24-bit value at the start of the given bytes.
+fn u24(b: &[u8]) -> u32 {
+    (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32
+}
+
+/// Describe the given standard reason for denying a request.
+fn deny_reason(r: u8) -> Option<&'static str> {
+    Some(match r {
+        0x00 => "no reason",
+        0x10 => "requesting unit not valid",
+        0x11 => "requesting unit not authorized",
+        0x20 => "target unit not valid",
+        0x21 => "target unit not authorized",
+        0x2F => "target unit refused call",
+        0x30 => "target group not valid",
+        0x31 => "target group not authorized",
+        0x40 => "invalid dialing",
+        0x41 => "telephone number not authorized",
+        0x42 => "PSTN not valid",
+        0x50 => "call timeout",
+        0x51 => "landline terminated call",
+        0x52 => "unit terminated call",
+        0x5F => "call preempted",
+        0x60 => "site access denied",
+        0xF0 => "call options not valid",
+        0xF1 => "protection option not valid",
+        0xF2 => "duplex option not valid",
+        0xF3 => "circuit or packet mode not valid",
+        0xFF => "service not supported",
+        _ => return None,
+    })
+}
+
+/// Describe the given standard reason for queueing a request.
+fn queue_reason(r: u8) -> Option<&'static str> {
+    Some(match r {
+        0x00 => "no reason",
+        0x10 => "requesting unit busy",
+        0x2F => "target unit busy",
+        0x3F => "target group busy",
+        0x40 => "channel resources not available",
+        0x41 => "telephone resources not available",
+        0x42 => "data resources not available",
+        0x43 => "same service already queued",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_response() {
+        let r = Response::new(
+            ResponseKind::Deny,
+            &[0x80, 0x31, 0x00, 0x11, 0xA9, 0x0D, 0xC0, 0x31],
+        );
+
+        assert_eq!(r.service, 0x00);
+        assert_eq!(r.reason, Some(0x31));
+        assert_eq!(r.reason_name, Some("target group not authorized"));
+        assert_eq!(r.info, Some(4521));
+        assert_eq!(r.target, 901169);
+
+        let r = Response::new(
+            ResponseKind::Queued,
+            &[0x04, 0x40, 0x00, 0x00, 0x00, 0x0D, 0xC0, 0x31],
+        );
+
+        assert_eq!(r.service, 0x04);
+        assert_eq!(r.reason_name, Some("channel resources not available"));
+        assert_eq!(r.info, None);
+
+        let r = Response::new(
+            ResponseKind::Ack,
+            &[0x80, 0, 0x0D, 0xC0, 0x32, 0x0D, 0xC0, 0x31],
+        );
+        assert_eq!(r.reason, None);
+        assert_eq!(r.info, Some(901170));
+
+        let r = Response::new(
+            ResponseKind::Ack,
+            &[0xC0, 0, 0x0D, 0xC0, 0x32, 0x0D, 0xC0, 0x31],
+        );
+        assert_eq!(r.info, None);
+
+        assert_eq!(deny_reason(0x99), None);
+    }
+}
@@ -0,0 +1,245 @@
+//! Lock-free ring of preallocated sample buffers.
+//!
+//! This carries SDR sample chunks from the reader callback to the demodulator with a
+//! single copy into a preallocated slot and no allocation, locking, or channel
+//! overhead. If the consumer falls behind and every slot is full, the incoming chunk is
+//! dropped and counted rather than blocking the SDR callback.
+
+use std::{
+    cell::UnsafeCell,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+/// Maximum time to sleep before rechecking for new buffers.
+const PARK_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// Buffer in the ring.
+struct Slot {
+    /// Bytes written into the slot.
+    bytes: Vec<u8>,
+    /// Time the bytes were written.
+    written: Instant,
+}
+
+/// State shared between the writer and reader.
+struct Ring {
+    /// Preallocated buffers.
+    slots: Box<[UnsafeCell<Slot>]>,
+    /// Count of buffers written (only modified by the writer.)
+    head: AtomicUsize,
+    /// Count of buffers consumed (only modified by the reader.)
+    tail: AtomicUsize,
+    /// Count of buffers dropped due to a full ring.
+    dropped: AtomicUsize,
+    /// Reader thread to wake when a buffer is written.
+    reader: OnceLock<Thread>,
+}
+
+// This is safe because each slot is only accessed by the writer while it's free and by
+// the reader while it's filled, with the transitions synchronized by `head` and `tail`.
+unsafe impl Sync for Ring {}
+
+/// Create a new ring with the given number of slots, each able to hold the given number
+/// of bytes without reallocating.
+pub fn ring(slots: usize, size: usize) -> (RingWriter, RingReader) {
+    assert!(slots > 0);
+
+    let ring = Arc::new(Ring {
+        slots: (0..slots)
+            .map(|_| {
+                UnsafeCell::new(Slot {
+                    bytes: Vec::with_capacity(size),
+                    written: Instant::now(),
+                })
+            })
+            .collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        dropped: AtomicUsize::new(0),
+        reader: OnceLock::new(),
+    });
+
+    (
+        RingWriter {
+            ring: ring.clone(),
+        },
+        RingReader {
+            ring,
+        },
+    )
+}
+
+/// Producer side of the ring.
+pub struct RingWriter {
+    ring: Arc<Ring>,
+}
+
+impl RingWriter {
+    /// Copy the given bytes into the next free slot.
+    ///
+    /// Return `false` if the ring was full and the bytes were dropped.
+    pub fn write(&self, bytes: &[u8]) -> bool {
+        let ring = &self.ring;
+        let head = ring.head.load(Ordering::Relaxed);
+        let tail = ring.tail.load(Ordering::Acquire);
+
+        if head.wrapping_sub(tail) == ring.slots.len() {
+            ring.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        // This is safe because the slot is free, so the reader won't access it until
+        // `head` is advanced below.
+        let slot = unsafe { &mut *ring.slots[head % ring.slots.len()].get() };
+
+        slot.bytes.clear();
+        slot.bytes.extend_from_slice(bytes);
+        slot.written = Instant::now();
+
+        ring.head.store(head.wrapping_add(1), Ordering::Release);
+
+        if let Some(t) = ring.reader.get() {
+            t.unpark();
+        }
+
+        true
+    }
+
+    /// Retrieve the total number of dropped buffers.
+    pub fn dropped(&self) -> usize {
+        self.ring.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Consumer side of the ring.
+pub struct RingReader {
+    ring: Arc<Ring>,
+}
+
+impl RingReader {
+    /// Wait for the next filled buffer, blocking the current thread.
+    ///
+    /// The slot is released back to the writer when the returned guard is dropped.
+    pub fn recv(&mut self) -> RingSlot<'_> {
+        let ring = &self.ring;
+        ring.reader.get_or_init(thread::current);
+
+        let tail = ring.tail.load(Ordering::Relaxed);
+
+        while ring.head.load(Ordering::Acquire) == tail {
+            thread::park_timeout(PARK_TIMEOUT);
+        }
+
+        RingSlot {
+            ring,
+            idx: tail,
+        }
+    }
+
+    /// Retrieve the total number of dropped buffers.
+    pub fn dropped(&self) -> usize {
+        self.ring.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Retrieve the number of filled buffers waiting to be read.
+    pub fn depth(&self) -> usize {
+        let ring = &self.ring;
+        let head = ring.head.load(Ordering::Acquire);
+
+        head.wrapping_sub(ring.tail.load(Ordering::Relaxed))
+    }
+}
+
+/// Filled buffer borrowed from the ring.
+pub struct RingSlot<'a> {
+    ring: &'a Ring,
+    /// Count of the slot being read.
+    idx: usize,
+}
+
+impl<'a> RingSlot<'a> {
+    /// Get the slot being read.
+    fn slot(&self) -> &Slot {
+        // This is safe because the slot is filled, so the writer won't access it until
+        // `tail` is advanced when the guard is dropped.
+        unsafe { &*self.ring.slots[self.idx % self.ring.slots.len()].get() }
+    }
+
+    /// Get the time the buffer was written into the ring.
+    pub fn written(&self) -> Instant {
+        self.slot().written
+    }
+}
+
+impl<'a> Deref for RingSlot<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.slot().bytes[..]
+    }
+}
+
+impl<'a> Drop for RingSlot<'a> {
+    fn drop(&mut self) {
+        self.ring
+            .tail
+            .store(self.idx.wrapping_add(1), Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_full() {
+        let (w, mut r) = ring(2, 4);
+
+        assert!(w.write(&[1, 2]));
+        assert!(w.write(&[3, 4, 5]));
+        assert!(!w.write(&[6]));
+        assert_eq!(w.dropped(), 1);
+
+        assert_eq!(r.depth(), 2);
+        let start = Instant::now();
+        assert_eq!(&r.recv()[..], &[1, 2]);
+        assert_eq!(r.depth(), 1);
+        assert!(w.write(&[7]));
+        assert_eq!(&r.recv()[..], &[3, 4, 5]);
+        let slot = r.recv();
+        assert_eq!(&slot[..], &[7]);
+        assert!(slot.written() >= start);
+        drop(slot);
+        assert_eq!(r.dropped(), 1);
+    }
+
+    #[test]
+    fn test_threads() {
+        let (w, mut r) = ring(4, 8);
+
+        let t = thread::spawn(move || {
+            let mut n = 0u8;
+
+            while n < 200 {
+                if w.write(&[n; 8]) {
+                    n += 1;
+                }
+                else {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        for n in 0..200u8 {
+            assert_eq!(&r.recv()[..], &[n; 8]);
+        }
+
+        t.join().unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+//! Activity of each talkgroup bucketed by hour and by day, for rendering activity
+//! heatmaps without an external database.
+//!
+//! Buckets start on UTC hour and day boundaries. Only recent buckets are kept, so memory
+//! stays bounded however long the receiver runs.
+
+use std::collections::BTreeMap;
+
+/// Length (ms) of an hourly bucket.
+const HOUR: u64 = 60 * 60 * 1000;
+
+/// Length (ms) of a daily bucket.
+const DAY: u64 = 24 * HOUR;
+
+/// Number of hourly buckets kept, covering a week.
+const HOURS_KEPT: u64 = 7 * 24;
+
+/// Number of daily buckets kept, covering about a quarter.
+const DAYS_KEPT: u64 = 90;
+
+/// Length of time covered by each bucket.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum Period {
+    /// Hourly buckets.
+    #[serde(rename = "hour")]
+    Hour,
+    /// Daily buckets.
+    #[serde(rename = "day")]
+    Day,
+}
+
+impl Default for Period {
+    fn default() -> Self {
+        Period::Hour
+    }
+}
+
+/// Criteria for selecting buckets.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RollupQuery {
+    /// Length of each bucket.
+    period: Period,
+    /// Only buckets ending after this time (ms since the Unix epoch).
+    since: Option<u64>,
+    /// Only buckets of this talkgroup.
+    talkgroup: Option<u16>,
+}
+
+/// Parse bucket selection criteria from a query string like `period=day&tg=101`.
+pub fn parse_query(query: &str) -> Result<RollupQuery, String> {
+    let mut q = RollupQuery::default();
+
+    for param in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, val) = param.split_once('=').unwrap_or((param, ""));
+        let err = || format!("invalid {} {}", key, val);
+
+        match key {
+            "period" => {
+                q.period = match val {
+                    "hour" => Period::Hour,
+                    "day" => Period::Day,
+                    _ => return Err(err()),
+                }
+            }
+            "since" => q.since = Some(val.parse().map_err(|_| err())?),
+            "tg" => q.talkgroup = Some(val.parse().map_err(|_| err())?),
+            _ => {}
+        }
+    }
+
+    Ok(q)
+}
+
+/// Activity of a talkgroup within a bucket.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct Bucket {
+    /// Number of voice grants seen.
+    grants: u64,
+    /// Number of calls followed.
+    calls: u64,
+    /// Total length (ms) of followed calls.
+    airtime: u64,
+}
+
+/// Activity of a talkgroup within a bucket, as reported to clients.
+#[derive(Serialize)]
+pub struct BucketSummary {
+    /// Time (ms since the Unix epoch) the bucket starts.
+    start: u64,
+    /// Talkgroup ID.
+    #[serde(rename = "talkGroup")]
+    talkgroup: u16,
+    /// Number of voice grants seen.
+    grants: u64,
+    /// Number of calls followed.
+    calls: u64,
+    /// Total length (sec) of followed calls.
+    airtime: f32,
+}
+
+/// Buckets selected by a query, oldest first.
+#[derive(Serialize)]
+pub struct RollupPage {
+    /// Length of each bucket.
+    period: Period,
+    /// Matching buckets.
+    buckets: Vec<BucketSummary>,
+}
+
+/// Buckets of a single period, by start time (ms since the Unix epoch) and talkgroup.
+#[derive(Default)]
+struct Buckets {
+    /// Activity in each bucket.
+    buckets: BTreeMap<(u64, u16), Bucket>,
+}
+
+impl Buckets {
+    /// Get the bucket of the given length (ms) covering the given time for the given
+    /// talkgroup, dropping any more than the given number of buckets older.
+    fn get(&mut self, len: u64, kept: u64, time: u64, tg: u16) -> &mut Bucket {
+        let start = time - time % len;
+        let cutoff = start.saturating_sub((kept - 1) * len);
+
+        if self
+            .buckets
+            .keys()
+            .next()
+            .map_or(false, |&(s, _)| s < cutoff)
+        {
+            self.buckets = self.buckets.split_off(&(cutoff, 0));
+        }
+
+        self.buckets.entry((start, tg)).or_default()
+    }
+}
+
+/// Activity of each talkgroup in hourly and daily buckets.
+#[derive(Default)]
+pub struct Rollups {
+    /// Hourly buckets.
+    hours: Buckets,
+    /// Daily buckets.
+    days: Buckets,
+}
+
+impl Rollups {
+    /// Apply the given update to the buckets of the given talkgroup covering the given
+    /// time (ms since the Unix epoch).
+    fn update<F: Fn(&mut Bucket)>(&mut self, tg: u16, time: u64, f: F) {
+        f(self.hours.get(HOUR, HOURS_KEPT, time, tg));
+        f(self.days.get(DAY, DAYS_KEPT, time, tg));
+    }
+
+    /// Record a voice grant on the given talkgroup at the given time.
+    pub fn record_grant(&mut self, tg: u16, time: u64) {
+        self.update(tg, time, |b| b.grants += 1);
+    }
+
+    /// Record a call on the given talkgroup, starting at the given time and lasting the
+    /// given length (ms).
+    ///
+    /// The call is counted in the buckets it started in, which only misplaces the
+    /// airtime of calls crossing a boundary.
+    pub fn record_call(&mut self, tg: u16, start: u64, airtime: u64) {
+        self.update(tg, start, |b| {
+            b.calls += 1;
+            b.airtime += airtime;
+        });
+    }
+
+    /// Select the buckets matching the given query.
+    pub fn query(&self, q: &RollupQuery) -> RollupPage {
+        let (buckets, len) = match q.period {
+            Period::Hour => (&self.hours, HOUR),
+            Period::Day => (&self.days, DAY),
+        };
+
+        let buckets = buckets
+            .buckets
+            .iter()
+            .filter(|&(&(start, _), _)| q.since.map_or(true, |t| start + len > t))
+            .filter(|&(&(_, tg), _)| q.talkgroup.map_or(true, |t| tg == t))
+            .map(|(&(start, tg), b)| BucketSummary {
+                start,
+                talkgroup: tg,
+                grants: b.grants,
+                calls: b.calls,
+                airtime: b.airtime as f32 / 1000.0,
+            })
+            .collect();
+
+        RollupPage {
+            period: q.period,
+            buckets,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rollups() {
+        let mut r = Rollups::default();
+        let t = 1700000000000 - 1700000000000 % DAY;
+
+        r.record_grant(101, t + 1000);
+        r.record_call(101, t + 2000, 5000);
+        r.record_grant(101, t + HOUR + 1000);
+        r.record_grant(102, t + HOUR + 2000);
+
+        let page = r.query(&RollupQuery::default());
+        assert_eq!(page.buckets.len(), 3);
+        assert_eq!(page.buckets[0].start, t);
+        assert_eq!(page.buckets[0].talkgroup, 101);
+        assert_eq!(page.buckets[0].grants, 1);
+        assert_eq!(page.buckets[0].calls, 1);
+        assert_eq!(page.buckets[0].airtime, 5.0);
+        assert_eq!(page.buckets[1].start, t + HOUR);
+
+        let page = r.query(&parse_query("period=day&tg=101").unwrap());
+        assert_eq!(page.period, Period::Day);
+        assert_eq!(page.buckets.len(), 1);
+        assert_eq!(page.buckets[0].grants, 2);
+
+        let page = r.query(&parse_query("since=1&tg=102").unwrap());
+        assert_eq!(page.buckets.len(), 1);
+
+        let page = r.query(&parse_query(&format!("since={}", t + HOUR)).unwrap());
+        assert_eq!(page.buckets.len(), 2);
+
+        // Old hourly buckets are dropped, but their days are kept.
+        r.record_grant(101, t + HOURS_KEPT * HOUR);
+        assert!(r.query(&RollupQuery::default()).buckets[0].start > t);
+        assert_eq!(
+            r.query(&parse_query("period=day").unwrap()).buckets[0].start,
+            t
+        );
+
+        assert!(parse_query("period=week").is_err());
+        assert!(parse_query("tg=abc").is_err());
+    }
+}
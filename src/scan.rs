@@ -0,0 +1,392 @@
+//! Searching for control channels, measuring tuner frequency error, and comparing tuner
+//! gains.
+
+use std::{f32::consts::PI, mem};
+
+use anyhow::{anyhow, Result};
+use demod_fm::FmDemod;
+use moving_avg::MovingAverage;
+use num::{complex::Complex32, traits::Zero};
+use p25::message::receiver::{MessageEvent, MessageReceiver};
+use rtlsdr_iq::IQ;
+
+use crate::{
+    consts::{BASEBAND_SAMPLE_RATE, BUF_BYTES, BUF_COUNT},
+    demod::{self, AVG_LEN, FM_DEVIATION},
+    fir::{ComplexDecimator, ComplexFir},
+    sdr::{self, Controller, Gain, Reader},
+};
+
+/// Number of buffers discarded after retuning, which may hold samples from the previous
+/// frequency.
+const SETTLE_BUFS: usize = BUF_COUNT + 1;
+
+/// Fraction of the decimated bandwidth occupied by a 12.5 kHz channel.
+const CHANNEL_FRACTION: f32 = 12500.0 / BASEBAND_SAMPLE_RATE as f32;
+
+/// Fraction of the most trunking packets decoded at any gain that a gain must decode to
+/// be chosen by SNR.
+const DECODE_MARGIN: f32 = 0.95;
+
+/// Signal measured on a single frequency.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Measurement {
+    /// Frequency (Hz) the tuner was set to.
+    pub freq: u32,
+    /// Average signal power (dBm).
+    pub power: f32,
+    /// Number of valid trunking packets decoded.
+    pub tsbks: usize,
+    /// Offset (Hz) of the received carrier from the tuned frequency.
+    pub offset: f32,
+    /// Signal-to-noise ratio (dB), against the noise just outside the channel.
+    pub snr: f32,
+}
+
+/// Measures the signal in I/Q samples from the SDR, demodulating them as `DemodTask`
+/// does.
+pub struct Meter {
+    /// Decimates I/Q signal.
+    decim: ComplexDecimator,
+    /// Channel-select lowpass filter.
+    bandpass: ComplexFir,
+    /// Demodulates FM signal.
+    demod: FmDemod,
+    /// Moving average filter.
+    avg: MovingAverage<f32>,
+    /// Decodes messages from baseband.
+    msg: MessageReceiver,
+    /// Previous channel sample.
+    prev: Complex32,
+    /// Sum of the rotation between consecutive channel samples.
+    rotation: Complex32,
+    /// Sum of the power (dBm) of each buffer.
+    power: f32,
+    /// Sum of the linear power of each sample within the channel.
+    inside: f32,
+    /// Sum of the linear power of each sample outside the channel.
+    outside: f32,
+    /// Number of buffers measured.
+    bufs: usize,
+    /// Number of valid trunking packets decoded.
+    tsbks: usize,
+    /// Number of baseband samples measured.
+    len: usize,
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Meter {
+            decim: demod::decimator(),
+            bandpass: demod::bandpass(),
+            demod: FmDemod::new(FM_DEVIATION, BASEBAND_SAMPLE_RATE),
+            avg: MovingAverage::new(AVG_LEN),
+            msg: MessageReceiver::new(),
+            prev: Complex32::zero(),
+            rotation: Complex32::zero(),
+            power: 0.0,
+            inside: 0.0,
+            outside: 0.0,
+            bufs: 0,
+            tsbks: 0,
+            len: 0,
+        }
+    }
+}
+
+impl Meter {
+    /// Measure the given interleaved 8-bit I/Q pairs.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        // This is safe because it's transforming an array of N 8-bit words to an array
+        // of N/2 16-bit words.
+        let pairs =
+            unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const u16, bytes.len() / 2) };
+
+        let mut samples = pairs.iter().map(|&s| IQ[s]).collect::<Vec<_>>();
+
+        let len = self.decim.decim_in_place(&mut samples[..]);
+        samples.truncate(len);
+
+        let total = samples.iter().fold(0.0, |p, s| p + s.norm_sqr());
+
+        for s in samples.iter_mut() {
+            *s = self.bandpass.feed(*s);
+        }
+
+        let inside = samples.iter().fold(0.0, |p, s| p + s.norm_sqr());
+
+        self.inside += inside;
+        self.outside += (total - inside).max(0.0);
+
+        self.power += demod::power_dbm(&samples[..], 1.0);
+        self.bufs += 1;
+        self.len += len;
+
+        for &s in &samples {
+            // Weighting each rotation by power keeps noise from dominating the average.
+            self.rotation += s * self.prev.conj();
+            self.prev = s;
+
+            let baseband = self.avg.feed(self.demod.feed(s));
+
+            if let Some(MessageEvent::TrunkingControl(tsbk)) = self.msg.feed(baseband) {
+                if tsbk.crc_valid() {
+                    self.tsbks += 1;
+                }
+            }
+        }
+    }
+
+    /// Get the number of baseband samples measured.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Finish measuring the given tuned frequency (Hz).
+    pub fn finish(self, freq: u32) -> Measurement {
+        // Noise outside the channel is assumed to be as dense as the noise within it.
+        let noise = self.outside * CHANNEL_FRACTION / (1.0 - CHANNEL_FRACTION);
+
+        Measurement {
+            freq,
+            power: self.power / self.bufs.max(1) as f32,
+            tsbks: self.tsbks,
+            offset: self.rotation.arg() * BASEBAND_SAMPLE_RATE as f32 / (2.0 * PI),
+            snr: 10.0 * (self.inside / noise.max(f32::MIN_POSITIVE)).log10(),
+        }
+    }
+}
+
+/// Listen to each of the given frequencies (Hz) in turn for the given number of baseband
+/// samples, passing the measurement of each to the given closure.
+pub fn survey<F>(
+    control: &mut Controller,
+    reader: &mut Reader,
+    freqs: &[u32],
+    dwell: usize,
+    mut cb: F,
+) -> Result<()>
+where
+    F: FnMut(&Measurement),
+{
+    sweep(
+        control,
+        reader,
+        freqs,
+        dwell,
+        |c, f| {
+            c.set_center_freq(f)
+                .map_err(|_| anyhow!("unable to set frequency"))
+        },
+        |f, meter| cb(&meter.finish(f)),
+    )
+}
+
+/// Listen at the given frequency (Hz) with each of the given tuner gains (tenths of dB) in
+/// turn for the given number of baseband samples, passing each gain and its measurement
+/// to the given closure.
+pub fn sweep_gains<F>(
+    control: &mut Controller,
+    reader: &mut Reader,
+    freq: u32,
+    gains: &[i32],
+    dwell: usize,
+    mut cb: F,
+) -> Result<()>
+where
+    F: FnMut(i32, &Measurement),
+{
+    control
+        .set_center_freq(freq)
+        .map_err(|_| anyhow!("unable to set frequency"))?;
+
+    sweep(
+        control,
+        reader,
+        gains,
+        dwell,
+        |c, g| sdr::set_gain(c, Gain::Manual(g)),
+        |g, meter| cb(g, &meter.finish(freq)),
+    )
+}
+
+/// Apply each of the given tuner settings in turn with the first closure, then measure
+/// for the given number of baseband samples, passing each setting and its meter to the
+/// second closure.
+fn sweep<T, A, F>(
+    control: &mut Controller,
+    reader: &mut Reader,
+    steps: &[T],
+    dwell: usize,
+    mut apply: A,
+    mut cb: F,
+) -> Result<()>
+where
+    T: Copy,
+    A: FnMut(&mut Controller, T) -> Result<()>,
+    F: FnMut(T, Meter),
+{
+    let mut steps = steps.iter().cloned();
+
+    let mut cur = match steps.next() {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+
+    apply(control, cur)?;
+
+    let mut meter = Meter::default();
+    let mut settle = SETTLE_BUFS;
+    let mut done = false;
+    // Failure to apply a setting, which stops the sweep.
+    let mut failed = None;
+
+    reader
+        .read_async(BUF_COUNT as u32, BUF_BYTES as u32, |bytes| {
+            // Buffers already in flight may arrive after cancelling.
+            if done {
+                return;
+            }
+
+            if settle > 0 {
+                settle -= 1;
+                return;
+            }
+
+            meter.feed(bytes);
+
+            if meter.len() < dwell {
+                return;
+            }
+
+            cb(cur, mem::take(&mut meter));
+
+            match steps.next() {
+                Some(s) => {
+                    cur = s;
+                    settle = SETTLE_BUFS;
+
+                    if let Err(e) = apply(control, s) {
+                        failed = Some(e);
+                        done = true;
+                        control.cancel_async_read();
+                    }
+                }
+                None => {
+                    done = true;
+                    control.cancel_async_read();
+                }
+            }
+        })
+        .map_err(|_| anyhow!("error in async read"))?;
+
+    match failed {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// List the frequencies (Hz) spaced by the given step within each of the given inclusive
+/// ranges.
+pub fn frequencies(ranges: &[(u32, u32)], step: u32) -> Vec<u32> {
+    ranges
+        .iter()
+        .flat_map(|&(lo, hi)| (lo..=hi).step_by(step.max(1) as usize))
+        .collect()
+}
+
+/// Choose the best of the given tuner gains (tenths of dB) from their measurements: the
+/// one with the highest SNR among those decoding nearly the most trunking packets.
+pub fn best_gain(results: &[(i32, Measurement)]) -> Option<i32> {
+    let most = results.iter().map(|&(_, ref m)| m.tsbks).max()?;
+
+    if most == 0 {
+        return None;
+    }
+
+    let min = (most as f32 * DECODE_MARGIN).ceil() as usize;
+
+    results
+        .iter()
+        .filter(|&&(_, ref m)| m.tsbks >= min)
+        .max_by(|&&(_, ref a), &&(_, ref b)| a.snr.partial_cmp(&b.snr).unwrap())
+        .map(|&(g, _)| g)
+}
+
+/// Calculate the frequency correction (ppm) that cancels the given measured carrier
+/// offset, which was measured with the given correction applied.
+pub fn correct_ppm(m: &Measurement, ppm: i32) -> i32 {
+    // A carrier received above the tuned frequency means the tuner is running slow.
+    ppm - (m.offset as f64 / m.freq as f64 * 1e6).round() as i32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bench;
+
+    #[test]
+    fn test_frequencies() {
+        assert_eq!(
+            frequencies(&[(851000000, 851025000), (852000000, 852000000)], 12500),
+            vec![851000000, 851012500, 851025000, 852000000]
+        );
+        assert!(frequencies(&[], 12500).is_empty());
+    }
+
+    #[test]
+    fn test_correct_ppm() {
+        let m = Measurement {
+            freq: 850000000,
+            offset: -1700.0,
+            ..Measurement::default()
+        };
+
+        assert_eq!(correct_ppm(&m, 0), 2);
+        assert_eq!(correct_ppm(&m, -3), -1);
+    }
+
+    #[test]
+    fn test_best_gain() {
+        let m = |tsbks, snr| Measurement {
+            tsbks,
+            snr,
+            ..Measurement::default()
+        };
+
+        assert_eq!(best_gain(&[]), None);
+        assert_eq!(best_gain(&[(0, m(0, 5.0)), (100, m(0, 8.0))]), None);
+
+        // Gains decoding nearly as many packets are chosen by SNR.
+        let results = [
+            (100, m(120, 9.0)),
+            (280, m(200, 14.0)),
+            (372, m(196, 17.5)),
+            (496, m(150, 21.0)),
+        ];
+
+        assert_eq!(best_gain(&results), Some(372));
+    }
+
+    #[test]
+    fn test_meter() {
+        let baseband = bench::generate(2.0);
+
+        let mut meter = Meter::default();
+        meter.feed(&bench::modulate(&baseband));
+        let m = meter.finish(851012500);
+
+        assert_eq!(m.freq, 851012500);
+        assert!(m.tsbks > 0);
+        assert!(m.snr > 20.0);
+
+        // A constant baseband level shifts the carrier by that fraction of the deviation.
+        let shifted = baseband.iter().map(|&s| s + 0.2).collect::<Vec<_>>();
+
+        let mut meter = Meter::default();
+        meter.feed(&bench::modulate(&shifted));
+        let shift = meter.finish(851012500).offset - m.offset;
+
+        assert!((shift - 0.2 * FM_DEVIATION as f32).abs() < 20.0);
+    }
+}
@@ -0,0 +1,64 @@
+//! Thread CPU affinity and scheduling priority.
+
+use std::{self, io};
+
+use libc;
+
+/// Scheduling options for a single thread.
+#[derive(Copy, Clone, Default)]
+pub struct ThreadSched {
+    /// CPU core to pin the thread to.
+    pub cpu: Option<usize>,
+    /// `SCHED_FIFO` real-time priority.
+    pub priority: Option<i32>,
+}
+
+impl ThreadSched {
+    /// Apply the options to the current thread.
+    ///
+    /// Failures (typically due to missing privileges) are logged and otherwise ignored,
+    /// since the thread can still run with default scheduling.
+    pub fn apply(&self, name: &str) {
+        if let Some(cpu) = self.cpu {
+            match pin_cpu(cpu) {
+                Ok(()) => info!("pinned {} thread to CPU {}", name, cpu),
+                Err(e) => warn!("unable to pin {} thread to CPU {}: {}", name, cpu, e),
+            }
+        }
+
+        if let Some(prio) = self.priority {
+            match set_realtime(prio) {
+                Ok(()) => info!("set {} thread to real-time priority {}", name, prio),
+                Err(e) => warn!("unable to set {} thread priority: {}", name, e),
+            }
+        }
+    }
+}
+
+/// Restrict the current thread to run only on the given CPU.
+fn pin_cpu(cpu: usize) -> io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+
+        libc::CPU_ZERO(&mut set);
+        libc::CPU_SET(cpu, &mut set);
+
+        // A pid of zero refers to the calling thread.
+        match libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+/// Switch the current thread to `SCHED_FIFO` scheduling with the given priority.
+fn set_realtime(prio: i32) -> io::Result<()> {
+    let param = libc::sched_param {
+        sched_priority: prio,
+    };
+
+    match unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) } {
+        0 => Ok(()),
+        e => Err(io::Error::from_raw_os_error(e)),
+    }
+}
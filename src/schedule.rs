@@ -0,0 +1,150 @@
+//! Weekly time windows, used by scheduled profiles and capture schedules.
+//!
+//! Each window is given in a settings file like
+//!
+//! ```json
+//! {"days": ["mon", "tue"], "start": "08:00", "end": "18:00"}
+//! ```
+//!
+//! with these fields:
+//!
+//! - `days`: days the window starts on, as `mon` to `sun` (default every day)
+//! - `start` and `end`: local time window as `HH:MM`, ending the next day if `end` isn't
+//!   after `start`
+
+use chrono::{Datelike, Local, Timelike};
+
+/// Minutes in a day.
+pub const DAY_MINS: u32 = 24 * 60;
+
+/// Names of the days of the week, starting with Monday.
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// Window entry of a settings file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WindowEntry {
+    #[serde(default)]
+    days: Vec<String>,
+    start: String,
+    end: String,
+}
+
+/// Time window repeating weekly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Window {
+    /// Days (0 for Monday) the window starts on.
+    days: [bool; 7],
+    /// Start of the window (minutes after midnight).
+    start: u32,
+    /// End of the window (minutes after midnight), on the next day if not after `start`.
+    end: u32,
+}
+
+impl Window {
+    /// Parse a window starting on the given days, or every day if none, between the
+    /// given times of day, returning the name of the invalid field on failure.
+    pub fn parse(days: &[String], start: &str, end: &str) -> Result<Window, &'static str> {
+        let mut set = [days.is_empty(); 7];
+
+        for d in days {
+            let idx = DAYS
+                .iter()
+                .position(|&n| d.eq_ignore_ascii_case(n))
+                .ok_or("day")?;
+
+            set[idx] = true;
+        }
+
+        Ok(Window {
+            days: set,
+            start: parse_time(start).ok_or("start")?,
+            end: parse_time(end).ok_or("end")?,
+        })
+    }
+
+    /// Check if the window is active at the given minute after midnight of the given
+    /// day (0 for Monday).
+    pub fn active_at(&self, day: u32, minute: u32) -> bool {
+        let day = day as usize % 7;
+
+        if self.start < self.end {
+            return self.days[day] && (self.start..self.end).contains(&minute);
+        }
+
+        // The window wraps past midnight, so it's active late on its days and early on
+        // the days after.
+        (self.days[day] && minute >= self.start) || (self.days[(day + 6) % 7] && minute < self.end)
+    }
+
+    /// Check if the window is active at the current local time.
+    pub fn active(&self) -> bool {
+        let (day, minute) = now();
+        self.active_at(day, minute)
+    }
+}
+
+/// Parse the given window entry.
+pub fn parse_window(e: WindowEntry) -> Result<Window, String> {
+    Window::parse(&e.days, &e.start, &e.end).map_err(|what| format!("invalid window {}", what))
+}
+
+/// Get the current local day (0 for Monday) and minute after midnight.
+pub fn now() -> (u32, u32) {
+    let now = Local::now();
+    (
+        now.weekday().num_days_from_monday(),
+        now.hour() * 60 + now.minute(),
+    )
+}
+
+/// Parse a time of day like `08:30` into minutes after midnight.
+fn parse_time(s: &str) -> Option<u32> {
+    let (h, m) = s.split_once(':')?;
+    let (h, m): (u32, u32) = (h.parse().ok()?, m.parse().ok()?);
+
+    if h > 24 || m > 59 || h * 60 + m > DAY_MINS {
+        return None;
+    }
+
+    Some(h * 60 + m)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn window(days: &[&str], start: &str, end: &str) -> Result<Window, &'static str> {
+        let days: Vec<String> = days.iter().map(|d| d.to_string()).collect();
+        Window::parse(&days, start, end)
+    }
+
+    #[test]
+    fn test_active_at() {
+        let day = window(&["mon", "fri"], "08:00", "18:00").unwrap();
+
+        assert!(day.active_at(0, 8 * 60));
+        assert!(day.active_at(4, 17 * 60 + 59));
+        assert!(!day.active_at(0, 18 * 60));
+        assert!(!day.active_at(1, 12 * 60));
+
+        let night = window(&["Sun"], "20:00", "06:00").unwrap();
+
+        assert!(night.active_at(6, 23 * 60));
+        assert!(night.active_at(0, 5 * 60));
+        assert!(!night.active_at(0, 6 * 60));
+        assert!(!night.active_at(0, 23 * 60));
+        assert!(!night.active_at(6, 5 * 60));
+
+        let all = window(&[], "00:00", "24:00").unwrap();
+        assert!((0..7).all(|d| all.active_at(d, 0) && all.active_at(d, DAY_MINS - 1)));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(window(&["mo"], "0:00", "1:00"), Err("day"));
+        assert_eq!(window(&[], "25:00", "1:00"), Err("start"));
+        assert_eq!(window(&[], "1:00", "1:60"), Err("end"));
+        assert_eq!(window(&[], "1:00", "1"), Err("end"));
+    }
+}
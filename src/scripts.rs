@@ -0,0 +1,363 @@
+//! Rhai scripts run on receiver events.
+//!
+//! Each `.rhai` file in the scripts directory can define a function for any of the hook
+//! events, named like `on_call_start` for `call-start`, which is called with the event
+//! payload as an object map. Scripts act on the receiver with these functions:
+//!
+//! - `hold(tg)`: only follow the given talkgroup until released
+//! - `release()`: return to normal talkgroup selection
+//! - `tag(id, text)`: tag the call with the given ID
+//! - `webhook(url, payload)`: send the given value as JSON in a POST request to the URL
+//! - `print(text)`: log the given text
+
+use std::path::Path;
+#[cfg(feature = "scripts")]
+use std::{
+    fs, mem,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+#[cfg(feature = "scripts")]
+use anyhow::Context;
+use anyhow::{anyhow, Result};
+#[cfg(feature = "scripts")]
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde_json::Value;
+
+#[cfg(feature = "scripts")]
+use crate::alerts;
+use crate::hooks::HookEvent;
+
+/// Maximum number of operations a script can perform per event, so a runaway script
+/// can't stall the receiver.
+#[cfg(feature = "scripts")]
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// Receiver action requested by a script.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(not(feature = "scripts"), allow(dead_code))]
+pub enum Action {
+    /// Only follow the contained talkgroup.
+    Hold(u16),
+    /// Return to normal talkgroup selection.
+    Release,
+    /// Tag the call with the contained ID with the contained text.
+    Tag(u64, String),
+}
+
+/// Compiled script file.
+#[cfg(feature = "scripts")]
+struct Script {
+    /// File the script was loaded from.
+    path: PathBuf,
+    /// Compiled script.
+    ast: AST,
+}
+
+/// Runs the scripts in a directory on events.
+#[cfg(feature = "scripts")]
+pub struct Scripts {
+    /// Directory the scripts were loaded from, if any.
+    dir: Option<PathBuf>,
+    /// Script interpreter.
+    engine: Engine,
+    /// Loaded scripts, in file name order.
+    scripts: Vec<Script>,
+    /// Actions requested by the running script.
+    actions: Arc<Mutex<Vec<Action>>>,
+}
+
+#[cfg(feature = "scripts")]
+impl Default for Scripts {
+    fn default() -> Self {
+        let actions = Arc::new(Mutex::new(vec![]));
+
+        Scripts {
+            dir: None,
+            engine: engine(actions.clone()),
+            scripts: vec![],
+            actions,
+        }
+    }
+}
+
+#[cfg(feature = "scripts")]
+impl Scripts {
+    /// Load and compile each `.rhai` file in the given directory.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut paths = fs::read_dir(dir)
+            .with_context(|| format!("unable to read scripts directory {}", dir.display()))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().map_or(false, |ext| ext == "rhai"))
+            .collect::<Vec<_>>();
+
+        paths.sort();
+
+        let mut s = Scripts {
+            dir: Some(dir.to_path_buf()),
+            ..Scripts::default()
+        };
+
+        for path in paths {
+            let ast = s
+                .engine
+                .compile_file(path.clone())
+                .map_err(|e| anyhow!("invalid script {}: {}", path.display(), e))?;
+
+            s.scripts.push(Script {
+                path,
+                ast,
+            });
+        }
+
+        Ok(s)
+    }
+
+    /// Load the scripts again from the same directory.
+    pub fn reload(&self) -> Result<Self> {
+        match self.dir {
+            Some(ref dir) => Scripts::load(dir),
+            None => Ok(Scripts::default()),
+        }
+    }
+
+    /// Get the number of loaded scripts.
+    pub fn count(&self) -> usize {
+        self.scripts.len()
+    }
+
+    /// Check if any script handles the given event.
+    pub fn handles(&self, event: HookEvent) -> bool {
+        let name = handler(event);
+        self.scripts.iter().any(|s| defines(&s.ast, &name))
+    }
+
+    /// Run each script handling the given event with the given payload, returning the
+    /// actions they requested.
+    pub fn run(&self, event: HookEvent, payload: &Value) -> Vec<Action> {
+        let name = handler(event);
+        let arg = to_dynamic(payload);
+
+        for s in self.scripts.iter().filter(|s| defines(&s.ast, &name)) {
+            let res = self.engine.call_fn_with_options::<Dynamic>(
+                CallFnOptions::new().eval_ast(false),
+                &mut Scope::new(),
+                &s.ast,
+                &name,
+                (arg.clone(),),
+            );
+
+            if let Err(e) = res {
+                warn!(
+                    "script {} failed on {}: {}",
+                    s.path.display(),
+                    event.name(),
+                    e
+                );
+            }
+        }
+
+        mem::take(&mut *self.actions.lock().unwrap())
+    }
+}
+
+/// Stands in for the scripts when built without rhai, running none.
+#[cfg(not(feature = "scripts"))]
+#[derive(Default)]
+pub struct Scripts;
+
+#[cfg(not(feature = "scripts"))]
+impl Scripts {
+    pub fn load(_: &Path) -> Result<Self> {
+        Err(anyhow!(
+            "p25rx was built without script support (the scripts feature)"
+        ))
+    }
+
+    pub fn reload(&self) -> Result<Self> {
+        Ok(Scripts)
+    }
+
+    pub fn count(&self) -> usize {
+        0
+    }
+
+    pub fn handles(&self, _: HookEvent) -> bool {
+        false
+    }
+
+    pub fn run(&self, _: HookEvent, _: &Value) -> Vec<Action> {
+        vec![]
+    }
+}
+
+/// Create a script interpreter that records actions in the given list.
+#[cfg(feature = "scripts")]
+fn engine(actions: Arc<Mutex<Vec<Action>>>) -> Engine {
+    let mut engine = Engine::new();
+
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|s| info!("{}", s));
+
+    let a = actions.clone();
+    engine.register_fn("hold", move |tg: i64| -> Result<(), Box<EvalAltResult>> {
+        let tg = u16::try_from(tg).map_err(|_| format!("invalid talkgroup {}", tg))?;
+        a.lock().unwrap().push(Action::Hold(tg));
+        Ok(())
+    });
+
+    let a = actions.clone();
+    engine.register_fn("release", move || {
+        a.lock().unwrap().push(Action::Release);
+    });
+
+    engine.register_fn("tag", move |id: i64, text: &str| {
+        actions
+            .lock()
+            .unwrap()
+            .push(Action::Tag(id as u64, text.to_string()));
+    });
+
+    engine.register_fn("webhook", |url: &str, payload: Dynamic| {
+        let url = url.to_string();
+        let body = serde_json::to_string(&to_json(&payload)).expect("unable to serialize payload");
+
+        // Requests are sent in the background so slow servers never hold up the hub.
+        thread::spawn(move || {
+            if let Err(e) = alerts::post_json(&url, &body) {
+                warn!("webhook to {} failed: {}", url, e);
+            }
+        });
+    });
+
+    engine
+}
+
+/// Get the name of the script function that handles the given event.
+#[cfg(feature = "scripts")]
+fn handler(event: HookEvent) -> String {
+    format!("on_{}", event.name().replace('-', "_"))
+}
+
+/// Check if the given script defines a handler function with the given name.
+#[cfg(feature = "scripts")]
+fn defines(ast: &AST, name: &str) -> bool {
+    ast.iter_functions()
+        .any(|f| f.name == name && f.params.len() == 1)
+}
+
+/// Convert the given JSON value to a script value.
+#[cfg(feature = "scripts")]
+fn to_dynamic(v: &Value) -> Dynamic {
+    match *v {
+        Value::Null => Dynamic::UNIT,
+        Value::Bool(b) => b.into(),
+        Value::String(ref s) => s.clone().into(),
+        Value::Array(ref a) => a.iter().map(to_dynamic).collect::<Array>().into(),
+        Value::Object(ref m) => m
+            .iter()
+            .map(|(k, v)| (k.as_str().into(), to_dynamic(v)))
+            .collect::<Map>()
+            .into(),
+        ref n => match n.as_i64() {
+            Some(i) => i.into(),
+            None => n.as_f64().unwrap_or(0.0).into(),
+        },
+    }
+}
+
+/// Convert the given script value to a JSON value.
+#[cfg(feature = "scripts")]
+fn to_json(d: &Dynamic) -> Value {
+    if d.is_unit() {
+        Value::Null
+    }
+    else if let Ok(b) = d.as_bool() {
+        json!(b)
+    }
+    else if let Ok(i) = d.as_int() {
+        json!(i)
+    }
+    else if let Ok(f) = d.as_float() {
+        json!(f)
+    }
+    else if let Some(a) = d.clone().try_cast::<Array>() {
+        Value::Array(a.iter().map(to_json).collect())
+    }
+    else if let Some(m) = d.clone().try_cast::<Map>() {
+        let mut obj = serde_json::Map::new();
+
+        for (k, v) in m.iter() {
+            obj.insert(k.to_string(), to_json(v));
+        }
+
+        Value::Object(obj)
+    }
+    else {
+        // Strings and any other values are sent as their text.
+        Value::String(d.to_string())
+    }
+}
+
+#[cfg(all(test, feature = "scripts"))]
+mod test {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_convert() {
+        let v = json!({
+            "talkGroup": 101,
+            "srcUnit": null,
+            "alias": "Fire Dispatch",
+            "emergency": true,
+            "tags": ["fire"],
+        });
+
+        assert_eq!(to_json(&to_dynamic(&v)), v);
+    }
+
+    #[test]
+    fn test_run() {
+        let dir = TempDir::new("scripts");
+
+        fs::write(
+            dir.join("fire.rhai"),
+            r#"
+                fn on_call_start(call) {
+                    if call.talkGroup == 101 {
+                        tag(call.id, "fire");
+                        hold(call.talkGroup);
+                    }
+                }
+
+                fn on_call_end(call) {
+                    release();
+                }
+            "#,
+        )
+        .unwrap();
+
+        fs::write(dir.join("notes.txt"), "not a script").unwrap();
+
+        let s = Scripts::load(dir.path()).unwrap();
+
+        assert_eq!(s.count(), 1);
+        assert!(s.handles(HookEvent::CallStart));
+        assert!(!s.handles(HookEvent::Emergency));
+
+        assert_eq!(
+            s.run(HookEvent::CallStart, &json!({"id": 7, "talkGroup": 101})),
+            vec![Action::Tag(7, "fire".to_string()), Action::Hold(101)]
+        );
+        assert!(s
+            .run(HookEvent::CallStart, &json!({"id": 8, "talkGroup": 102}))
+            .is_empty());
+        assert_eq!(s.run(HookEvent::CallEnd, &json!({})), vec![Action::Release]);
+
+        fs::write(dir.join("broken.rhai"), "fn on_call_end(call) {").unwrap();
+        assert!(s.reload().is_err());
+    }
+}
@@ -1,36 +1,573 @@
 //! Interface to RTL-SDR.
+//!
+//! Without the `sdr` feature, the crate doesn't link librtlsdr and opening a device
+//! always fails, which still allows replaying recordings.
 
-use std::sync::mpsc::{Receiver, Sender};
+#[cfg(feature = "sdr")]
+use std::ffi::{CStr, CString};
+use std::{
+    fmt,
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
 
-use crate::consts::{BUF_BYTES, BUF_COUNT};
-use pool::{Checkout, Pool};
-use rtlsdr_mt::{Controller, Reader};
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+#[cfg(feature = "sdr")]
+use rtlsdr_mt::TunerGains;
 
-/// Reads chunks of samples from the SDR and sends them over a channel.
+#[cfg(feature = "sdr")]
+pub use rtlsdr_mt::{Controller, Reader};
+
+#[cfg(not(feature = "sdr"))]
+pub use self::nosdr::{Controller, Reader};
+
+#[cfg(feature = "sdr")]
+use crate::claim;
+use crate::{
+    band,
+    claim::DeviceClaim,
+    consts::{BUF_BYTES, BUF_COUNT, SDR_SAMPLE_RATE},
+    faults::{Fault, FaultCategory},
+    gaps::{DropCause, GapDetector, SampleDrop},
+    hub::HubEvent,
+    queue::{QueueItem, QueueReceiver, QueueSender},
+    recv::RecvEvent,
+    ring::RingWriter,
+    stages::StageMetrics,
+};
+
+/// Number of attempts made to reopen a released SDR before giving up on resuming.
+const REOPEN_ATTEMPTS: usize = 5;
+
+/// Time to wait between attempts to reopen a released SDR.
+const REOPEN_DELAY: Duration = Duration::from_secs(1);
+
+/// Number of attempts made to tune the SDR before giving up on a frequency.
+const TUNE_ATTEMPTS: usize = 3;
+
+/// Time to wait between attempts to tune the SDR.
+const TUNE_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Tuner gain setting.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Gain {
+    /// Hardware automatic gain control.
+    Auto,
+    /// Fixed gain (tenths of dB).
+    Manual(i32),
+}
+
+impl fmt::Display for Gain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Gain::Auto => write!(f, "auto"),
+            Gain::Manual(g) => write!(f, "{}", g),
+        }
+    }
+}
+
+/// Tuner gain applied within a frequency range.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GainProfile {
+    /// Lowest frequency (Hz) in the range.
+    lo: u32,
+    /// Highest frequency (Hz) in the range.
+    hi: u32,
+    /// Gain applied within the range.
+    gain: Gain,
+}
+
+impl fmt::Display for GainProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}={}", self.lo, self.hi, self.gain)
+    }
+}
+
+/// Parse a gain profile like `851000000-855000000=496` or `860000000-869000000=auto`.
+pub fn parse_gain_profile(s: &str) -> Result<GainProfile, String> {
+    let (range, gain) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected RANGE=GAIN in {}", s))?;
+
+    let (lo, hi) = band::parse_range(range)?;
+
+    Ok(GainProfile {
+        lo,
+        hi,
+        gain: parse_gain(gain)?,
+    })
+}
+
+/// Chooses the tuner gain for each frequency.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GainProfiles {
+    /// Gain outside every profile.
+    default: Gain,
+    /// Profiles in order of precedence.
+    profiles: Vec<GainProfile>,
+}
+
+impl GainProfiles {
+    /// Create a new `GainProfiles` using the given gain outside the given profiles.
+    pub fn new(default: Gain, profiles: Vec<GainProfile>) -> Self {
+        GainProfiles {
+            default,
+            profiles,
+        }
+    }
+
+    /// Get the gain to apply at the given frequency (Hz), from the first profile
+    /// covering it.
+    pub fn gain(&self, freq: u32) -> Gain {
+        self.profiles
+            .iter()
+            .find(|p| p.lo <= freq && freq <= p.hi)
+            .map_or(self.default, |p| p.gain)
+    }
+}
+
+/// RTL-SDR selected by the user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Device {
+    /// Device at the given index in the attached device list.
+    Index(u32),
+    /// Device with the given USB serial number.
+    Serial(String),
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Device::Index(idx) => write!(f, "{}", idx),
+            Device::Serial(ref serial) => write!(f, "serial:{}", serial),
+        }
+    }
+}
+
+/// Parse a device like `0` (index) or `serial:00000001`.
+pub fn parse_device(s: &str) -> Result<Device, String> {
+    match s.strip_prefix("serial:") {
+        Some("") => Err("expected serial number after serial:".to_string()),
+        Some(serial) => Ok(Device::Serial(serial.to_string())),
+        None => s
+            .parse()
+            .map(Device::Index)
+            .map_err(|_| "expected device index or serial:SERIAL".to_string()),
+    }
+}
+
+/// Claim the given RTL-SDR for this instance, failing if another instance on the host
+/// already has it.
+pub fn claim(dev: &Device) -> Result<DeviceClaim> {
+    DeviceClaim::acquire(&claim_name(dev))
+}
+
+/// Get the name the given RTL-SDR is claimed under, which is the same whether it was
+/// selected by index or by serial number.
+fn claim_name(dev: &Device) -> String {
+    match *dev {
+        Device::Index(idx) => index_claim_name(idx),
+        Device::Serial(ref serial) => format!("serial-{}", serial),
+    }
+}
+
+/// Get the name the RTL-SDR at the given index is claimed under.
+#[cfg(feature = "sdr")]
+fn index_claim_name(idx: u32) -> String {
+    match device_serial(idx) {
+        Some(serial) => format!("serial-{}", serial),
+        None => format!("index-{}", idx),
+    }
+}
+
+#[cfg(not(feature = "sdr"))]
+fn index_claim_name(idx: u32) -> String {
+    format!("index-{}", idx)
+}
+
+/// Sampling of the ADC input without the tuner, for HF reception.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, ValueEnum)]
+pub enum DirectSampling {
+    /// Sample the tuner output as usual.
+    Off,
+    /// Sample the I branch input directly.
+    I,
+    /// Sample the Q branch input directly.
+    Q,
+}
+
+impl DirectSampling {
+    /// Get the librtlsdr mode number.
+    fn mode(self) -> i32 {
+        match self {
+            DirectSampling::Off => 0,
+            DirectSampling::I => 1,
+            DirectSampling::Q => 2,
+        }
+    }
+}
+
+/// Parse a gain like `auto` or `496` (tenths of dB).
+pub fn parse_gain(s: &str) -> Result<Gain, String> {
+    match s {
+        "auto" => Ok(Gain::Auto),
+        s => s
+            .parse()
+            .map(Gain::Manual)
+            .map_err(|_| "expected auto or gain in tenths of dB".to_string()),
+    }
+}
+
+/// Open the given RTL-SDR with the given gain, frequency correction (ppm), bias tee
+/// power, and direct sampling mode, at the expected sample rate.
+pub fn open(
+    dev: &Device,
+    gain: Gain,
+    ppm: i32,
+    bias_tee: bool,
+    direct: DirectSampling,
+) -> Result<(Controller, Reader)> {
+    let (mut control, reader) = open_device(dev)?;
+
+    set_gain(&mut control, gain)?;
+
+    if bias_tee {
+        info!("enabling bias tee");
+        control
+            .set_bias_tee(true)
+            .map_err(|_| anyhow!("unable to enable bias tee"))?;
+    }
+
+    if direct != DirectSampling::Off {
+        info!("enabling direct sampling on {:?} branch", direct);
+        control
+            .set_direct_sampling(direct.mode())
+            .map_err(|_| anyhow!("unable to enable direct sampling"))?;
+    }
+
+    info!("setting frequency offset to {} PPM", ppm);
+    control
+        .set_ppm(ppm)
+        .map_err(|_| anyhow!("unable to set ppm"))?;
+    control
+        .set_sample_rate(SDR_SAMPLE_RATE)
+        .map_err(|_| anyhow!("unable to set sample rate"))?;
+
+    Ok((control, reader))
+}
+
+/// Apply the given gain to the given SDR.
+pub fn set_gain(control: &mut Controller, gain: Gain) -> Result<()> {
+    match gain {
+        Gain::Auto => {
+            info!("enabling hardware AGC");
+            control
+                .enable_agc()
+                .map_err(|_| anyhow!("unable to enable agc"))
+        }
+        Gain::Manual(g) => {
+            info!("setting hardware gain to {:.1} dB", g as f32 / 10.0);
+            control
+                .set_tuner_gain(g)
+                .map_err(|_| anyhow!("unable to set gain"))
+        }
+    }
+}
+
+/// Open the given RTL-SDR without configuring it.
+#[cfg(feature = "sdr")]
+fn open_device(dev: &Device) -> Result<(Controller, Reader)> {
+    let idx = match *dev {
+        Device::Index(idx) => idx,
+        Device::Serial(ref serial) => find_serial(serial)?,
+    };
+
+    info!("opening RTL-SDR at index {}", idx);
+
+    rtlsdr_mt::open(idx).map_err(|_| anyhow!("unable to open RTL-SDR at index {}", idx))
+}
+
+#[cfg(not(feature = "sdr"))]
+fn open_device(_: &Device) -> Result<(Controller, Reader)> {
+    Err(anyhow!(
+        "p25rx was built without RTL-SDR support (the sdr feature)"
+    ))
+}
+
+// Parts of librtlsdr not wrapped by rtlsdr_mt, which already links the library.
+#[cfg(feature = "sdr")]
+extern "C" {
+    fn rtlsdr_get_device_usb_strings(
+        index: u32,
+        manufact: *mut libc::c_char,
+        product: *mut libc::c_char,
+        serial: *mut libc::c_char,
+    ) -> libc::c_int;
+
+    fn rtlsdr_get_index_by_serial(serial: *const libc::c_char) -> libc::c_int;
+}
+
+/// Find the index of the attached RTL-SDR with the given serial number.
+#[cfg(feature = "sdr")]
+fn find_serial(serial: &str) -> Result<u32> {
+    let cserial = CString::new(serial).map_err(|_| anyhow!("invalid serial {}", serial))?;
+
+    match unsafe { rtlsdr_get_index_by_serial(cserial.as_ptr()) } {
+        idx if idx >= 0 => Ok(idx as u32),
+        _ => Err(anyhow!(
+            "no RTL-SDR with serial {} (use p25rx devices)",
+            serial
+        )),
+    }
+}
+
+/// Get the USB serial number of the RTL-SDR at the given index, if it has one.
+#[cfg(feature = "sdr")]
+fn device_serial(idx: u32) -> Option<String> {
+    // librtlsdr writes at most 256 bytes into each string.
+    let mut manufact = [0 as libc::c_char; 256];
+    let mut product = [0 as libc::c_char; 256];
+    let mut serial = [0 as libc::c_char; 256];
+
+    let res = unsafe {
+        rtlsdr_get_device_usb_strings(
+            idx,
+            manufact.as_mut_ptr(),
+            product.as_mut_ptr(),
+            serial.as_mut_ptr(),
+        )
+    };
+
+    if res != 0 {
+        return None;
+    }
+
+    let serial = unsafe { CStr::from_ptr(serial.as_ptr()) };
+
+    match serial.to_str() {
+        Ok("") | Err(_) => None,
+        Ok(s) => Some(s.to_string()),
+    }
+}
+
+/// Print the index, name, and serial number of each attached RTL-SDR, and the instance
+/// it's claimed by, if any.
+#[cfg(feature = "sdr")]
+pub fn print_devices() -> Result<()> {
+    for (idx, name) in rtlsdr_mt::devices().enumerate() {
+        let idx = idx as u32;
+
+        let serial = match device_serial(idx) {
+            Some(serial) => format!(" (serial:{})", serial),
+            None => String::new(),
+        };
+
+        let holder = match claim::holder(&index_claim_name(idx)) {
+            Some(pid) => format!(", in use by p25rx pid {}", pid),
+            None => String::new(),
+        };
+
+        println!("{}: {}{}{}", idx, name.to_str().unwrap(), serial, holder);
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "sdr"))]
+pub fn print_devices() -> Result<()> {
+    open_device(&Device::Index(0)).map(|_| ())
+}
+
+/// Print each gain (tenths of dB) supported by the tuner of the given RTL-SDR.
+#[cfg(feature = "sdr")]
+pub fn print_gains(dev: &Device) -> Result<()> {
+    let (mut control, _) = open_device(dev)?;
+    let mut gains = TunerGains::default();
+
+    for g in control.tuner_gains(&mut gains) {
+        println!("{}", g);
+    }
+
+    println!("auto");
+
+    Ok(())
+}
+
+#[cfg(not(feature = "sdr"))]
+pub fn print_gains(dev: &Device) -> Result<()> {
+    match open_device(dev)?.0 {}
+}
+
+/// Get the gains (tenths of dB) supported by the tuner of the given SDR.
+#[cfg(feature = "sdr")]
+pub fn tuner_gains(control: &mut Controller) -> Vec<i32> {
+    let mut gains = TunerGains::default();
+    control.tuner_gains(&mut gains).to_vec()
+}
+
+#[cfg(not(feature = "sdr"))]
+pub fn tuner_gains(control: &mut Controller) -> Vec<i32> {
+    match *control {}
+}
+
+/// Settings an SDR was opened with, used to reopen it after releasing it.
+#[derive(Clone, Debug)]
+pub struct OpenParams {
+    /// Device to open.
+    pub device: Device,
+    /// Frequency correction (ppm).
+    pub ppm: i32,
+    /// Whether to power an external LNA.
+    pub bias_tee: bool,
+    /// Direct sampling mode.
+    pub direct: DirectSampling,
+}
+
+/// Messages for `ReadTask` while reading is paused.
+pub enum ReadTaskEvent {
+    /// Drop the reader so the SDR can be closed.
+    Release,
+    /// Resume reading, from the given reader if the SDR was reopened.
+    Resume(Option<Reader>),
+}
+
+/// Reads chunks of samples from the SDR and copies them into a buffer ring.
 pub struct ReadTask {
-    /// Channel to send chunks over.
-    chan: Sender<Checkout<Vec<u8>>>,
+    /// Ring to copy chunks into.
+    ring: RingWriter,
+    /// Channel for messages while paused, if reading can be paused.
+    events: Option<mpsc::Receiver<ReadTaskEvent>>,
+    /// Detects samples lost by the SDR.
+    gaps: GapDetector,
+    /// Number of drops since reading started.
+    drops: u64,
+    /// Event streaming for dropped samples, if enabled.
+    hub: Option<QueueSender<HubEvent>>,
+    /// Processing metrics.
+    stage: Arc<StageMetrics>,
+    /// Size of each buffer read from the SDR (bytes).
+    buf_bytes: usize,
 }
 
 impl ReadTask {
-    /// Create a new `ReadTask` communicating over the given channel.
-    pub fn new(chan: Sender<Checkout<Vec<u8>>>) -> Self {
+    /// Create a new `ReadTask` writing into the given ring.
+    pub fn new(ring: RingWriter) -> Self {
         ReadTask {
-            chan,
+            ring,
+            events: None,
+            // A transfer read up to a buffer late is still within the device buffer.
+            gaps: GapDetector::new(SDR_SAMPLE_RATE, BUF_BYTES as u64 / 2),
+            drops: 0,
+            hub: None,
+            stage: StageMetrics::new("read"),
+            buf_bytes: BUF_BYTES,
         }
     }
 
+    /// Read buffers of the given size (bytes) from the SDR, which must be a multiple of
+    /// 512 and no larger than the default.
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        assert!(bytes % 512 == 0 && bytes <= BUF_BYTES);
+
+        self.buf_bytes = bytes;
+        self.gaps = GapDetector::new(SDR_SAMPLE_RATE, bytes as u64 / 2);
+        self
+    }
+
+    /// Wait for messages from the given channel whenever reading is cancelled, rather
+    /// than stopping.
+    pub fn pausable(mut self, events: mpsc::Receiver<ReadTaskEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Get the processing metrics of the task.
+    pub fn metrics(&self) -> Arc<StageMetrics> {
+        self.stage.clone()
+    }
+
+    /// Report each drop of samples as an event on the given channel.
+    pub fn report_drops(mut self, hub: QueueSender<HubEvent>) -> Self {
+        self.hub = Some(hub);
+        self
+    }
+
     /// Start reading samples, blocking the thread.
-    pub fn run(&mut self, mut reader: Reader) {
-        let mut pool = Pool::with_capacity(16, || vec![0; BUF_BYTES]);
+    pub fn run(&mut self, reader: Reader) {
+        let mut reader = Some(reader);
+
+        loop {
+            if let Some(ref mut r) = reader {
+                // Time spent paused isn't a gap.
+                self.gaps.reset();
+
+                r.read_async(BUF_COUNT as u32, self.buf_bytes as u32, |bytes| {
+                    // Chunks come straight from the SDR, with nothing queued before them.
+                    let _timer = self.stage.start(0);
+
+                    if let Some(lost) = self.gaps.record(Instant::now(), bytes.len() / 2) {
+                        self.report_drop(DropCause::Overrun, lost);
+                    }
+
+                    if !self.ring.write(bytes) {
+                        self.report_drop(DropCause::Ring, bytes.len() as u64 / 2);
+                    }
+                })
+                .expect("error in async read");
+            }
+
+            // Reading is only cancelled to pause.
+            let events = match self.events {
+                Some(ref e) => e,
+                None => return,
+            };
+
+            loop {
+                match events.recv().expect("unable to receive reader event") {
+                    ReadTaskEvent::Release => reader = None,
+                    ReadTaskEvent::Resume(r) => {
+                        if r.is_some() {
+                            reader = r;
+                        }
+
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Count and report the given number of lost samples.
+    fn report_drop(&mut self, cause: DropCause, samples: u64) {
+        self.drops += 1;
 
-        reader
-            .read_async(BUF_COUNT as u32, BUF_BYTES as u32, |bytes| {
-                let mut samples = pool.checkout().expect("unable to allocate samples");
-                (&mut samples[..]).copy_from_slice(bytes);
-                self.chan.send(samples).expect("unable to send sdr samples");
-            })
-            .expect("error in async read");
+        // Ring drops come from the demodulator not keeping up.
+        let fault = match cause {
+            DropCause::Overrun => Fault::warning(
+                FaultCategory::Sdr,
+                format!(
+                    "sdr overrun lost about {} samples ({} drops)",
+                    samples, self.drops
+                ),
+            ),
+            DropCause::Ring => Fault::warning(
+                FaultCategory::Demod,
+                format!("dropped sdr samples ({} total)", self.ring.dropped()),
+            ),
+        };
+
+        if let Some(ref hub) = self.hub {
+            hub.send(HubEvent::DroppedSamples(SampleDrop {
+                cause,
+                samples,
+                total: self.drops,
+            }))
+            .ok();
+        }
+
+        report(&self.hub, fault);
     }
 }
 
@@ -38,39 +575,391 @@ impl ReadTask {
 pub enum ControlTaskEvent {
     /// Set the center frequency to the contained value (Hz).
     SetFreq(u32),
+    /// Stop reading samples, closing the SDR if the contained flag is set.
+    Pause(bool),
+    /// Resume reading samples, reopening the SDR if it was closed.
+    Resume,
+}
+
+impl QueueItem for ControlTaskEvent {
+    fn droppable(&self) -> bool {
+        false
+    }
 }
 
 /// Controls SDR parameters.
 pub struct ControlTask {
-    /// SDR interface.
-    sdr: Controller,
+    /// SDR interface, or `None` while released.
+    sdr: Option<Controller>,
     /// Channel for messages.
-    events: Receiver<ControlTaskEvent>,
+    events: QueueReceiver<ControlTaskEvent>,
+    /// Gain for each frequency, if any profiles are set.
+    profiles: Option<GainProfiles>,
+    /// Gain currently applied.
+    gain: Gain,
+    /// Diversity receiver SDR tuned along with this one, if any.
+    diversity: Option<Controller>,
+    /// Offset (Hz) from each requested frequency the SDRs are actually tuned to.
+    offset: i32,
+    /// Channel to the reader task, settings to reopen the SDR with, and channel to the
+    /// receiver for reporting a failure to reopen it, if reading can be paused.
+    pausing: Option<(
+        mpsc::Sender<ReadTaskEvent>,
+        OpenParams,
+        QueueSender<RecvEvent>,
+    )>,
+    /// Channel to the hub for reporting errors, if any.
+    hub: Option<QueueSender<HubEvent>>,
 }
 
 impl ControlTask {
-    /// Create a new `ControlTask` over the given SDR, receiving messages from the given
-    /// channel.
-    pub fn new(sdr: Controller, events: Receiver<ControlTaskEvent>) -> Self {
+    /// Create a new `ControlTask` over the given SDR, which has the given gain applied,
+    /// receiving messages from the given channel.
+    pub fn new(sdr: Controller, gain: Gain, events: QueueReceiver<ControlTaskEvent>) -> Self {
         ControlTask {
-            sdr,
+            sdr: Some(sdr),
             events,
+            profiles: None,
+            gain,
+            diversity: None,
+            offset: 0,
+            pausing: None,
+            hub: None,
+        }
+    }
+
+    /// Allow reading to be paused through the given channel to the reader task, reopening
+    /// the SDR with the given settings if it was released, and telling the receiver
+    /// through the given channel if it couldn't be reopened.
+    pub fn pausable(
+        mut self,
+        reader: mpsc::Sender<ReadTaskEvent>,
+        params: OpenParams,
+        recv: QueueSender<RecvEvent>,
+    ) -> Self {
+        self.pausing = Some((reader, params, recv));
+        self
+    }
+
+    /// Also tune the given diversity receiver SDR, which has the same gain applied, on
+    /// each retune.
+    pub fn diversity(mut self, sdr: Controller) -> Self {
+        self.diversity = Some(sdr);
+        self
+    }
+
+    /// Tune the SDRs the given offset (Hz) away from each requested frequency, leaving
+    /// the demodulator to shift the samples back.
+    pub fn tune_offset(mut self, offset: i32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Report errors to the hub through the given channel.
+    pub fn report_errors(mut self, hub: QueueSender<HubEvent>) -> Self {
+        self.hub = Some(hub);
+        self
+    }
+
+    /// Change the gain on each retune according to the given profiles.
+    pub fn gain_profiles(mut self, profiles: GainProfiles) -> Self {
+        self.profiles = Some(profiles);
+        self
+    }
+
+    /// Tune to the given frequency (Hz), applying any gain profile covering it.
+    fn set_freq(&mut self, freq: u32) {
+        let gain = match self.profiles {
+            Some(ref p) => p.gain(freq),
+            None => self.gain,
+        };
+
+        // The receiver doesn't tune while released.
+        let sdr = match self.sdr {
+            Some(ref mut sdr) => sdr,
+            None => return,
+        };
+
+        if gain != self.gain {
+            // Keep receiving with the previous gain rather than stopping.
+            match set_gain(sdr, gain) {
+                Ok(()) => self.gain = gain,
+                Err(e) => report(
+                    &self.hub,
+                    Fault::warning(FaultCategory::Sdr, format!("{:#}", e)),
+                ),
+            }
+
+            if let Some(ref mut d) = self.diversity {
+                if let Err(e) = set_gain(d, gain) {
+                    report(
+                        &self.hub,
+                        Fault::warning(FaultCategory::Sdr, format!("{:#}", e)),
+                    );
+                }
+            }
+        }
+
+        let freq = (freq as i64 + self.offset as i64) as u32;
+
+        // Stay on the previous frequency if tuning keeps failing, which the receiver
+        // recovers from by losing sync and retuning.
+        if !tune(sdr, freq) {
+            self.report_error(format!("unable to set frequency to {} Hz", freq));
+        }
+
+        let failed = match self.diversity {
+            Some(ref mut d) => !tune(d, freq),
+            None => false,
+        };
+
+        if failed {
+            self.report_error(format!("unable to set diversity frequency to {} Hz", freq));
         }
     }
 
+    /// Log the given error and report it to the hub, if connected.
+    fn report_error(&self, msg: String) {
+        report(&self.hub, Fault::error(FaultCategory::Sdr, msg));
+    }
+
     /// Start managing the SDR, blocking the thread.
     pub fn run(&mut self) {
         loop {
-            match self
-                .events
-                .recv()
-                .expect("unable to receive controller event")
-            {
-                ControlTaskEvent::SetFreq(freq) => self
-                    .sdr
-                    .set_center_freq(freq)
-                    .expect("unable to set frequency"),
+            // The receiver only stops when shutting down.
+            let event = match self.events.recv() {
+                Ok(event) => event,
+                Err(_) => {
+                    info!("receiver stopped, stopping controller");
+                    return;
+                }
+            };
+
+            match event {
+                ControlTaskEvent::SetFreq(freq) => self.set_freq(freq),
+                ControlTaskEvent::Pause(release) => self.pause(release),
+                ControlTaskEvent::Resume => self.resume(),
+            }
+        }
+    }
+
+    /// Stop the reader task, closing the SDR if `release` is set.
+    fn pause(&mut self, release: bool) {
+        let reader = match self.pausing {
+            Some((ref r, _, _)) => r,
+            None => return,
+        };
+
+        let mut sdr = match self.sdr.take() {
+            Some(sdr) => sdr,
+            None => return,
+        };
+
+        sdr.cancel_async_read();
+
+        if release {
+            info!("releasing sdr");
+
+            if reader.send(ReadTaskEvent::Release).is_err() {
+                self.report_error("unable to release reader".to_string());
             }
         }
+        else {
+            self.sdr = Some(sdr);
+        }
+    }
+
+    /// Restart the reader task, reopening the SDR if it was closed.
+    fn resume(&mut self) {
+        let (reader, params, recv) = match self.pausing {
+            Some((ref r, ref p, ref recv)) => (r, p, recv),
+            None => return,
+        };
+
+        if self.sdr.is_some() {
+            if reader.send(ReadTaskEvent::Resume(None)).is_err() {
+                self.report_error("unable to resume reader".to_string());
+            }
+
+            return;
+        }
+
+        // Other software may not have closed the device yet.
+        let mut attempt = 1;
+
+        let (control, r) = loop {
+            match open(
+                &params.device,
+                self.gain,
+                params.ppm,
+                params.bias_tee,
+                params.direct,
+            ) {
+                Ok(opened) => break opened,
+                Err(e) if attempt < REOPEN_ATTEMPTS => {
+                    warn!("{:#}, retrying", e);
+                    thread::sleep(REOPEN_DELAY);
+                    attempt += 1;
+                }
+                // The receiver pauses again, so a later resume tries again.
+                Err(e) => {
+                    self.report_error(format!("{:#}, staying paused", e));
+
+                    if recv.send(RecvEvent::ResumeFailed).is_err() {
+                        self.report_error("unable to pause receiver".to_string());
+                    }
+
+                    return;
+                }
+            }
+        };
+
+        self.sdr = Some(control);
+
+        if reader.send(ReadTaskEvent::Resume(Some(r))).is_err() {
+            self.report_error("unable to resume reader".to_string());
+        }
+    }
+}
+
+/// Log the given fault and report it through the given channel to the hub, if connected.
+fn report(hub: &Option<QueueSender<HubEvent>>, fault: Fault) {
+    fault.log();
+
+    if let Some(ref hub) = *hub {
+        hub.send(HubEvent::Fault(fault)).ok();
+    }
+}
+
+/// Tune the given SDR to the given frequency (Hz), retrying a few times since the tuner
+/// occasionally fails to lock. Return whether it was tuned.
+fn tune(sdr: &mut Controller, freq: u32) -> bool {
+    for attempt in 1..=TUNE_ATTEMPTS {
+        if sdr.set_center_freq(freq).is_ok() {
+            return true;
+        }
+
+        warn!(
+            "unable to set frequency (attempt {} of {})",
+            attempt, TUNE_ATTEMPTS
+        );
+
+        if attempt < TUNE_ATTEMPTS {
+            thread::sleep(TUNE_RETRY_DELAY);
+        }
+    }
+
+    false
+}
+
+/// Stand-ins for the RTL-SDR interface when built without librtlsdr, which can never
+/// be created.
+#[cfg(not(feature = "sdr"))]
+mod nosdr {
+    /// Controls SDR parameters.
+    pub enum Controller {}
+
+    impl Controller {
+        pub fn enable_agc(&mut self) -> Result<(), ()> {
+            match *self {}
+        }
+
+        pub fn set_tuner_gain(&mut self, _: i32) -> Result<(), ()> {
+            match *self {}
+        }
+
+        pub fn set_ppm(&mut self, _: i32) -> Result<(), ()> {
+            match *self {}
+        }
+
+        pub fn set_bias_tee(&mut self, _: bool) -> Result<(), ()> {
+            match *self {}
+        }
+
+        pub fn set_direct_sampling(&mut self, _: i32) -> Result<(), ()> {
+            match *self {}
+        }
+
+        pub fn set_sample_rate(&mut self, _: u32) -> Result<(), ()> {
+            match *self {}
+        }
+
+        pub fn set_center_freq(&mut self, _: u32) -> Result<(), ()> {
+            match *self {}
+        }
+
+        pub fn cancel_async_read(&mut self) {
+            match *self {}
+        }
+    }
+
+    /// Reads samples from the SDR.
+    pub enum Reader {}
+
+    impl Reader {
+        pub fn read_async<F: FnMut(&[u8])>(&mut self, _: u32, _: u32, _: F) -> Result<(), ()> {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_gain() {
+        assert_eq!(parse_gain("auto"), Ok(Gain::Auto));
+        assert_eq!(parse_gain("496"), Ok(Gain::Manual(496)));
+        assert!(parse_gain("list").is_err());
+        assert!(parse_gain("49.6").is_err());
+        assert_eq!(Gain::Manual(496).to_string(), "496");
+        assert_eq!(Gain::Auto.to_string(), "auto");
+    }
+
+    #[test]
+    fn test_gain_profiles() {
+        let p = GainProfiles::new(
+            Gain::Manual(496),
+            vec![
+                parse_gain_profile("851000000-855000000=280").unwrap(),
+                parse_gain_profile("854000000-869000000=auto").unwrap(),
+            ],
+        );
+
+        assert_eq!(p.gain(851000000), Gain::Manual(280));
+        assert_eq!(p.gain(854500000), Gain::Manual(280));
+        assert_eq!(p.gain(860000000), Gain::Auto);
+        assert_eq!(p.gain(770000000), Gain::Manual(496));
+
+        assert_eq!(
+            parse_gain_profile("851000000-855000000=280")
+                .unwrap()
+                .to_string(),
+            "851000000-855000000=280"
+        );
+        assert!(parse_gain_profile("851000000-855000000").is_err());
+        assert!(parse_gain_profile("855000000-851000000=280").is_err());
+        assert!(parse_gain_profile("851000000-855000000=loud").is_err());
+    }
+
+    #[test]
+    fn test_parse_device() {
+        assert_eq!(parse_device("1"), Ok(Device::Index(1)));
+        assert_eq!(
+            parse_device("serial:00000001"),
+            Ok(Device::Serial("00000001".to_string()))
+        );
+        assert!(parse_device("serial:").is_err());
+        assert!(parse_device("list").is_err());
+        assert_eq!(
+            claim_name(&Device::Serial("00000001".to_string())),
+            "serial-00000001"
+        );
+        assert_eq!(Device::Index(2).to_string(), "2");
+        assert_eq!(
+            Device::Serial("00000001".to_string()).to_string(),
+            "serial:00000001"
+        );
     }
 }
@@ -0,0 +1,294 @@
+//! Settings loaded from files, which can be reloaded while running.
+//!
+//! The settings file is a JSON object with the following optional fields:
+//!
+//! - `include`: array of talkgroups, the only ones that can be selected
+//! - `exclude`: array of talkgroups that are never selected
+//! - `preempt`: array of talkgroups that interrupt other calls
+//! - `priorities`: object of talkgroup to selection priority (default 1.0)
+//! - `weights`: object with the `prio`, `age`, and `recent` selection feature weights
+//! - `strategy`: rule for choosing between talkgroups, one of `weighted` (default),
+//!   `priority`, `recent`, `waiting`, or `round-robin`
+//! - `aliases`: object of talkgroup to name
+//! - `alerts`: array of alert rules, described in the `alerts` module
+//! - `profiles`: array of scheduled talkgroup selection profiles, described in the
+//!   `profiles` module
+//! - `recordSchedule`: array of weekly time windows, described in the `schedule` module,
+//!   outside of which nothing is recorded (default always recording)
+
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use serde_json;
+
+use crate::{
+    alerts::{self, AlertEntry, AlertRule},
+    aliases::TalkgroupAliases,
+    profiles::{self, Profile, ProfileEntry},
+    schedule::{self, Window, WindowEntry},
+    talkgroups::{self, FeatureWeights, Filter, TalkgroupConfig},
+};
+
+/// Contents of a settings file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SettingsFile {
+    #[serde(default)]
+    include: Option<Vec<u16>>,
+    #[serde(default)]
+    exclude: Vec<u16>,
+    #[serde(default)]
+    preempt: Vec<u16>,
+    /// Priorities keyed by talkgroup, since JSON object keys are strings.
+    #[serde(default)]
+    priorities: BTreeMap<String, f32>,
+    #[serde(default)]
+    weights: Option<FeatureWeights>,
+    #[serde(default)]
+    strategy: Option<String>,
+    /// Names keyed by talkgroup.
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+    #[serde(default)]
+    alerts: Vec<AlertEntry>,
+    #[serde(default)]
+    profiles: Vec<ProfileEntry>,
+    #[serde(default, rename = "recordSchedule")]
+    record_schedule: Vec<WindowEntry>,
+}
+
+/// Settings that can be changed while running.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Settings {
+    /// Talkgroup selection settings.
+    pub talkgroups: TalkgroupConfig,
+    /// Talkgroup names.
+    pub aliases: TalkgroupAliases,
+    /// Alert rules.
+    pub alerts: Vec<AlertRule>,
+    /// Scheduled talkgroup selection profiles, in order of precedence.
+    pub profiles: Vec<Profile>,
+    /// Time windows calls and baseband are recorded in, or empty to always record.
+    pub record_schedule: Vec<Window>,
+}
+
+impl Settings {
+    /// Describe each difference from the given previous settings.
+    pub fn changes(&self, prev: &Settings) -> Vec<String> {
+        let (cur, old) = (&self.talkgroups, &prev.talkgroups);
+        let mut changes = vec![];
+
+        if cur.filter != old.filter {
+            changes.push("talkgroup filter changed".to_string());
+        }
+
+        if cur.preempt != old.preempt {
+            changes.push("preempting talkgroups changed".to_string());
+        }
+
+        if cur.prios != old.prios {
+            changes.push("talkgroup priorities changed".to_string());
+        }
+
+        if cur.weights != old.weights {
+            changes.push("selection weights changed".to_string());
+        }
+
+        if cur.strategy != old.strategy {
+            changes.push("selection strategy changed".to_string());
+        }
+
+        let (added, removed, renamed) = self.aliases.diff(&prev.aliases);
+
+        if added + removed + renamed > 0 {
+            changes.push(format!(
+                "aliases changed: {} added, {} removed, {} renamed",
+                added, removed, renamed
+            ));
+        }
+
+        if self.alerts != prev.alerts {
+            changes.push(format!("alert rules changed: {} rules", self.alerts.len()));
+        }
+
+        if self.profiles != prev.profiles {
+            changes.push(format!(
+                "profiles changed: {} profiles",
+                self.profiles.len()
+            ));
+        }
+
+        if self.record_schedule != prev.record_schedule {
+            changes.push(format!(
+                "record schedule changed: {} windows",
+                self.record_schedule.len()
+            ));
+        }
+
+        changes
+    }
+}
+
+/// Files that settings are loaded from.
+#[derive(Clone, Debug, Default)]
+pub struct SettingsFiles {
+    /// JSON settings file, if any.
+    pub config: Option<PathBuf>,
+    /// CSV file of talkgroup names, if any, which are overridden by names in the
+    /// settings file.
+    pub aliases: Option<PathBuf>,
+}
+
+impl SettingsFiles {
+    /// Load settings from each file.
+    pub fn load(&self) -> Result<Settings> {
+        let mut settings = match self.config {
+            Some(ref path) => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("unable to read config file {}", path.display()))?;
+
+                parse(&text)
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| format!("invalid config file {}", path.display()))?
+            }
+            None => Settings::default(),
+        };
+
+        if let Some(ref path) = self.aliases {
+            let mut aliases = TalkgroupAliases::load(path)?;
+            aliases.extend(settings.aliases);
+            settings.aliases = aliases;
+        }
+
+        Ok(settings)
+    }
+
+    /// Check if there are any files to load.
+    pub fn is_empty(&self) -> bool {
+        self.config.is_none() && self.aliases.is_none()
+    }
+}
+
+/// Parse settings from the given JSON text.
+pub fn parse(text: &str) -> Result<Settings, String> {
+    let file: SettingsFile = serde_json::from_str(text).map_err(|e| e.to_string())?;
+
+    let filter = match file.include {
+        Some(_) if !file.exclude.is_empty() => {
+            return Err("include and exclude can't both be given".to_string())
+        }
+        Some(ref tgs) => Filter::include(tgs),
+        None => Filter::exclude(&file.exclude),
+    };
+
+    let mut talkgroups = TalkgroupConfig {
+        filter,
+        preempt: file.preempt.iter().cloned().collect(),
+        weights: file.weights.unwrap_or_default(),
+        strategy: match file.strategy {
+            Some(ref s) => talkgroups::parse_strategy(s)?,
+            None => Default::default(),
+        },
+        ..TalkgroupConfig::default()
+    };
+
+    for (tg, prio) in file.priorities {
+        talkgroups.prios.insert(parse_talkgroup(&tg)?, prio);
+    }
+
+    let mut aliases = TalkgroupAliases::default();
+
+    for (tg, name) in file.aliases {
+        aliases.insert(parse_talkgroup(&tg)?, name);
+    }
+
+    let alerts = file
+        .alerts
+        .into_iter()
+        .map(alerts::parse_rule)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let profiles = file
+        .profiles
+        .into_iter()
+        .map(|p| profiles::parse_profile(p, &talkgroups))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let record_schedule = file
+        .record_schedule
+        .into_iter()
+        .map(schedule::parse_window)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Settings {
+        talkgroups,
+        aliases,
+        alerts,
+        profiles,
+        record_schedule,
+    })
+}
+
+/// Parse a talkgroup used as an object key.
+pub fn parse_talkgroup(s: &str) -> Result<u16, String> {
+    s.parse().map_err(|_| format!("invalid talkgroup {}", s))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::talkgroups::Strategy;
+
+    #[test]
+    fn test_parse() {
+        let s = parse(
+            r#"{
+                "include": [101, 102],
+                "preempt": [102],
+                "strategy": "round-robin",
+                "priorities": {"101": 2.5},
+                "aliases": {"101": "Fire Dispatch"},
+                "alerts": [{"event": "call-start", "talkgroups": [101], "webhook": "http://w"}]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(s.talkgroups.filter, Filter::include(&[101, 102]));
+        assert!(s.talkgroups.preempt.contains(&102));
+        assert_eq!(s.talkgroups.prios.get(&101), Some(&2.5));
+        assert_eq!(s.talkgroups.weights, FeatureWeights::default());
+        assert_eq!(s.talkgroups.strategy, Strategy::RoundRobin);
+        assert_eq!(s.aliases.get(101), Some("Fire Dispatch"));
+        assert_eq!(s.alerts.len(), 1);
+        assert_eq!(s.alerts[0].talkgroups, vec![101]);
+
+        assert_eq!(parse("{}").unwrap(), Settings::default());
+
+        assert!(parse(r#"{"include": [1], "exclude": [2]}"#).is_err());
+        assert_eq!(
+            parse(r#"{"priorities": {"abc": 1.0}}"#).unwrap_err(),
+            "invalid talkgroup abc"
+        );
+        assert!(parse(r#"{"exlude": [1]}"#).is_err());
+        assert!(parse(r#"{"strategy": "random"}"#).is_err());
+        assert!(parse(r#"{"alerts": [{"event": "call-start"}]}"#).is_err());
+        assert!(parse(r#"{"recordSchedule": [{"start": "8:00", "end": "9:0x"}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_changes() {
+        let prev = parse(r#"{"exclude": [1], "aliases": {"1": "A", "2": "B"}}"#).unwrap();
+
+        assert!(prev.changes(&prev).is_empty());
+
+        let cur = parse(r#"{"exclude": [2], "aliases": {"1": "C", "3": "D"}}"#).unwrap();
+
+        assert_eq!(
+            cur.changes(&prev),
+            vec![
+                "talkgroup filter changed",
+                "aliases changed: 1 added, 1 removed, 1 renamed",
+            ]
+        );
+    }
+}
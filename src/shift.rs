@@ -0,0 +1,75 @@
+//! Frequency shifting of SDR samples, for tuning the SDR off the channel.
+//!
+//! RTL-SDR dongles put a DC spur at the center of the band. With the SDR tuned to a
+//! frequency offset from the channel, the channel lands away from the spur, and shifting
+//! the samples by the offset brings it back to the center for demodulation, where the spur
+//! is then outside the channel and removed by the decimation filter.
+
+use std::f32::consts::PI;
+
+use num::complex::Complex32;
+
+/// Largest offset (Hz) the SDR can be tuned from the channel, keeping the channel well
+/// within the sampled band.
+pub const MAX_OFFSET: u32 = 100000;
+
+/// Shifts samples up in frequency with a complex oscillator.
+pub struct FreqShift {
+    /// Rotation of the oscillator each sample.
+    step: Complex32,
+    /// Current value of the oscillator.
+    osc: Complex32,
+}
+
+impl FreqShift {
+    /// Create a new `FreqShift` moving samples up by the given frequency (Hz) at the
+    /// given sample rate (Hz).
+    pub fn new(shift: i32, rate: u32) -> Self {
+        FreqShift {
+            step: Complex32::from_polar(&1.0, &(2.0 * PI * shift as f32 / rate as f32)),
+            osc: Complex32::new(1.0, 0.0),
+        }
+    }
+
+    /// Shift the given samples in place, continuing from the previous buffer.
+    pub fn shift(&mut self, samples: &mut [Complex32]) {
+        for s in samples.iter_mut() {
+            *s = *s * self.osc;
+            self.osc = self.osc * self.step;
+        }
+
+        // Keep rounding errors from building up in the amplitude.
+        self.osc = self.osc / self.osc.norm();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shift() {
+        let mut shift = FreqShift::new(50000, 240000);
+        let step = Complex32::from_polar(&1.0, &(-2.0 * PI * 50000.0 / 240000.0));
+        let mut tone = Complex32::new(1.0, 0.0);
+
+        for _ in 0..100 {
+            // Tone 50 kHz below the center, as with the SDR tuned 50 kHz above.
+            let mut samples: Vec<Complex32> = (0..16384)
+                .map(|_| {
+                    let s = tone;
+                    tone = tone * step;
+                    tone = tone / tone.norm();
+                    s
+                })
+                .collect();
+
+            shift.shift(&mut samples[..]);
+
+            // The tone is moved to DC with its amplitude kept.
+            assert!(samples
+                .iter()
+                .all(|&s| (s - Complex32::new(1.0, 0.0)).norm() < 0.01));
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Handling of Unix signals.
+
+use std::{
+    io,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Number of SIGHUP signals received.
+static SIGHUPS: AtomicUsize = AtomicUsize::new(0);
+
+/// Count a received SIGHUP, as the signal handler.
+pub extern "C" fn count_sighup(_: libc::c_int) {
+    SIGHUPS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Handle SIGHUP, as sent by logrotate and `systemctl reload`, instead of terminating.
+///
+/// Each `SighupWatch` then sees every signal received.
+pub fn handle_sighup() -> io::Result<()> {
+    let handler = count_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t;
+
+    match unsafe { libc::signal(libc::SIGHUP, handler) } {
+        libc::SIG_ERR => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Detects SIGHUP signals received since the last check.
+pub struct SighupWatch {
+    /// Number of signals received as of the last check.
+    seen: usize,
+}
+
+impl Default for SighupWatch {
+    fn default() -> Self {
+        SighupWatch {
+            seen: SIGHUPS.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl SighupWatch {
+    /// Check if SIGHUP has been received since the last check.
+    pub fn received(&mut self) -> bool {
+        let count = SIGHUPS.load(Ordering::Relaxed);
+        let received = count != self.seen;
+
+        self.seen = count;
+
+        received
+    }
+}
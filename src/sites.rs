@@ -0,0 +1,132 @@
+//! Table of neighboring sites advertised on the control channel.
+
+use std::collections::BTreeMap;
+
+use p25::trunking::fields::{self, SystemServices};
+
+/// A neighboring site, as reported to clients.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AdjacentSite {
+    /// Location registration area.
+    area: u8,
+    /// System ID.
+    system: u16,
+    /// RF subsystem ID.
+    rfss: u8,
+    /// Site ID.
+    site: u8,
+    /// Control channel frequency (Hz), if its channel parameters are known.
+    freq: Option<u32>,
+    /// Services advertised by the site.
+    services: Vec<&'static str>,
+    /// Time (ms since the Unix epoch) the site was last advertised.
+    #[serde(rename = "lastSeen")]
+    last_seen: u64,
+}
+
+/// Sites selected for a client, most recently seen first.
+#[derive(Serialize)]
+pub struct SitePage<'a> {
+    /// Known sites.
+    sites: Vec<&'a AdjacentSite>,
+}
+
+/// Accumulates the neighboring sites advertised on the current control channel.
+#[derive(Default)]
+pub struct SiteTable {
+    /// Latest advertisement of each site, by system, RFSS, and site ID.
+    sites: BTreeMap<(u16, u8, u8), AdjacentSite>,
+}
+
+impl SiteTable {
+    /// Record the given adjacent site broadcast, with the given control channel frequency
+    /// (Hz), received at the given time (ms since the Unix epoch).
+    pub fn record(&mut self, f: fields::AdjacentSite, freq: Option<u32>, time: u64) {
+        self.insert(AdjacentSite {
+            area: f.area(),
+            system: f.system(),
+            rfss: f.rfss(),
+            site: f.site(),
+            freq,
+            services: service_names(f.services()),
+            last_seen: time,
+        });
+    }
+
+    /// Replace any previous advertisement of the given site.
+    fn insert(&mut self, s: AdjacentSite) {
+        self.sites.insert((s.system, s.rfss, s.site), s);
+    }
+
+    /// Forget all sites.
+    pub fn clear(&mut self) {
+        self.sites.clear();
+    }
+
+    /// Select all known sites, most recently seen first.
+    pub fn query(&self) -> SitePage<'_> {
+        let mut sites = self.sites.values().collect::<Vec<_>>();
+        sites.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+
+        SitePage {
+            sites,
+        }
+    }
+}
+
+/// List the names of the given advertised services.
+fn service_names(s: SystemServices) -> Vec<&'static str> {
+    [
+        (s.is_composite(), "composite"),
+        (s.has_updates(), "updates"),
+        (s.is_backup(), "backup"),
+        (s.has_data(), "data"),
+        (s.has_voice(), "voice"),
+        (s.has_registration(), "registration"),
+        (s.has_auth(), "auth"),
+    ]
+    .iter()
+    .filter(|&&(has, _)| has)
+    .map(|&(_, name)| name)
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn site(rfss: u8, site: u8, last_seen: u64) -> AdjacentSite {
+        AdjacentSite {
+            area: 1,
+            system: 946,
+            rfss,
+            site,
+            freq: Some(851287500),
+            services: vec!["data", "voice"],
+            last_seen,
+        }
+    }
+
+    #[test]
+    fn test_sites() {
+        let mut t = SiteTable::default();
+
+        t.insert(site(1, 2, 1000));
+        t.insert(site(1, 3, 2000));
+        t.insert(site(1, 2, 3000));
+
+        let page = t.query();
+        assert_eq!(page.sites.len(), 2);
+        assert_eq!((page.sites[0].site, page.sites[0].last_seen), (2, 3000));
+        assert_eq!((page.sites[1].site, page.sites[1].last_seen), (3, 2000));
+
+        t.clear();
+        assert!(t.query().sites.is_empty());
+    }
+
+    #[test]
+    fn test_service_names() {
+        let s = service_names(SystemServices::new(0b0001_1100));
+        assert_eq!(s, vec!["data", "voice", "registration"]);
+    }
+}
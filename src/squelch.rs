@@ -0,0 +1,240 @@
+//! Concealment and muting of voice frames corrupted by bit errors.
+//!
+//! Frames decoded from a fading signal, and the noise burst at the end of many
+//! transmissions, come out of the vocoder as a harsh screech. These frames can be
+//! recognized by the number of bit errors corrected in them. A short run of them is
+//! concealed by decoding the last clean frame again in their place, so the vocoder
+//! carries on smoothly from its parameters, at decreasing volume. Longer runs are replaced
+//! by silence, fading in and out to avoid clicks. This is modeled on the frame repeat and
+//! muting rules of the IMBE vocoder spec, but doesn't follow them exactly.
+
+use p25::voice::frame::VoiceFrame;
+
+use crate::{consts::AUDIO_SAMPLE_RATE, level};
+
+/// Default most bit errors corrected in a frame before it's concealed.
+pub const MAX_ERRORS: usize = 10;
+
+/// Most corrupted frames in a row concealed by decoding the last clean frame again
+/// before muting.
+const MAX_REPEATS: usize = 3;
+
+/// Attenuation (dB) added with each repeat of the last clean frame.
+const REPEAT_ATTEN: f32 = 3.0;
+
+/// Consecutive clean frames needed to unmute, so audio between the errors of a burst
+/// isn't let through.
+const HOLD_FRAMES: usize = 2;
+
+/// Time (sec) to fade between muted and unmuted.
+const FADE_TIME: f32 = 0.005;
+
+/// Conceals and mutes runs of voice frames with too many bit errors.
+pub struct ErrorSquelch {
+    /// Most bit errors in a frame before it's concealed.
+    max_errors: usize,
+    /// Last clean frame, if any since the start of the transmission.
+    last: Option<VoiceFrame>,
+    /// Times the last clean frame has been repeated in a row.
+    repeats: usize,
+    /// Whether frames are being muted.
+    muted: bool,
+    /// Clean frames since the last corrupted frame.
+    clean: usize,
+    /// Current linear gain, moving toward 0 when muted and 1 otherwise.
+    gain: f32,
+    /// Gain change per sample while fading.
+    step: f32,
+}
+
+impl ErrorSquelch {
+    /// Create a new `ErrorSquelch` concealing frames with more than the given number of
+    /// bit errors.
+    pub fn new(max_errors: usize) -> Self {
+        ErrorSquelch {
+            max_errors,
+            last: None,
+            repeats: 0,
+            muted: false,
+            clean: 0,
+            gain: 1.0,
+            step: 1.0 / (FADE_TIME * AUDIO_SAMPLE_RATE as f32),
+        }
+    }
+
+    /// Check if a frame with the given number of bit errors is corrupted, so it
+    /// shouldn't be decoded.
+    fn corrupt(&self, errors: usize) -> bool {
+        errors > self.max_errors
+    }
+
+    /// Choose the frame to decode for the given frame: the frame itself if it's clean,
+    /// the last clean frame if it's corrupted and can be concealed, or none to decode
+    /// silence instead.
+    ///
+    /// Corrupted frames are never decoded, since they would also throw off the vocoder
+    /// state used for the frames after them.
+    pub fn select<'a>(&'a mut self, frame: &'a VoiceFrame) -> Option<&'a VoiceFrame> {
+        if !self.corrupt(frame.errors) {
+            self.repeats = 0;
+            self.last = Some(*frame);
+
+            if self.muted {
+                self.clean += 1;
+                self.muted = self.clean < HOLD_FRAMES;
+            }
+
+            Some(frame)
+        }
+        else if !self.muted && self.repeats < MAX_REPEATS {
+            self.repeats += 1;
+
+            // Silence stands in until the first clean frame.
+            self.last.as_ref()
+        }
+        else {
+            if !self.muted {
+                debug!("muting voice frames after {} bit errors", frame.errors);
+            }
+
+            self.muted = true;
+            self.clean = 0;
+
+            None
+        }
+    }
+
+    /// Attenuate or mute the given samples, decoded from the frame chosen by the latest
+    /// call to `select`, if needed.
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if self.repeats > 0 && !self.muted {
+            let gain = level::db_to_amplitude(-REPEAT_ATTEN * self.repeats as f32);
+
+            for s in samples.iter_mut() {
+                *s *= gain;
+            }
+        }
+
+        let target = if self.muted { 0.0 } else { 1.0 };
+
+        for s in samples.iter_mut() {
+            self.gain = if self.gain < target {
+                (self.gain + self.step).min(target)
+            }
+            else {
+                (self.gain - self.step).max(target)
+            };
+
+            *s *= self.gain;
+        }
+    }
+
+    /// Clear the last clean frame and unmute for a new transmission.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.repeats = 0;
+        self.muted = false;
+        self.clean = 0;
+        self.gain = 1.0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use imbe::consts::SAMPLES_PER_FRAME;
+
+    use super::*;
+
+    fn frame(id: u32, errors: usize) -> VoiceFrame {
+        VoiceFrame {
+            chunks: [id; 8],
+            errors,
+        }
+    }
+
+    fn samples(v: f32) -> [f32; SAMPLES_PER_FRAME] {
+        [v; SAMPLES_PER_FRAME]
+    }
+
+    fn selected(sq: &mut ErrorSquelch, f: VoiceFrame) -> Option<u32> {
+        sq.select(&f).map(|f| f.chunks[0])
+    }
+
+    #[test]
+    fn test_conceal() {
+        let mut sq = ErrorSquelch::new(4);
+
+        assert_eq!(selected(&mut sq, frame(1, 4)), Some(1));
+
+        let mut s = samples(0.5);
+        sq.process(&mut s[..]);
+        assert!(s.iter().all(|&s| s == 0.5));
+
+        // Corrupted frames are replaced by the last clean frame, quieter each time.
+        let mut prev = 0.5;
+
+        for _ in 0..MAX_REPEATS {
+            assert_eq!(selected(&mut sq, frame(2, 5)), Some(1));
+
+            let mut s = samples(0.5);
+            sq.process(&mut s[..]);
+            assert!(s[0] < prev && s[0] > 0.0);
+            prev = s[0];
+        }
+
+        // Muting fades out rather than cutting off.
+        assert_eq!(selected(&mut sq, frame(2, 5)), None);
+
+        let mut s = samples(1.0);
+        sq.process(&mut s[..]);
+        assert!(s[0] > 0.9);
+        assert!(s[40..].iter().all(|&s| s == 0.0));
+
+        // Muting holds through the first clean frame, which is still decoded to keep the
+        // vocoder state.
+        assert_eq!(selected(&mut sq, frame(3, 0)), Some(3));
+
+        let mut s = samples(1.0);
+        sq.process(&mut s[..]);
+        assert!(s.iter().all(|&s| s == 0.0));
+
+        assert_eq!(selected(&mut sq, frame(4, 0)), Some(4));
+
+        let mut s = samples(1.0);
+        sq.process(&mut s[..]);
+        assert!(s[0] < 0.1);
+        assert!(s[40..].iter().all(|&s| s == 1.0));
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut sq = ErrorSquelch::new(4);
+
+        selected(&mut sq, frame(1, 0));
+
+        for _ in 0..=MAX_REPEATS {
+            selected(&mut sq, frame(2, 9));
+            sq.process(&mut samples(1.0)[..]);
+        }
+
+        sq.reset();
+
+        // Silence stands in for corrupted frames until a clean frame arrives.
+        assert_eq!(selected(&mut sq, frame(2, 9)), None);
+
+        assert_eq!(selected(&mut sq, frame(3, 0)), Some(3));
+
+        let mut s = samples(1.0);
+        sq.process(&mut s[..]);
+        assert!(s.iter().all(|&s| s == 1.0));
+
+        // Repeats start over from the new transmission.
+        assert_eq!(selected(&mut sq, frame(4, 9)), Some(3));
+
+        let mut s = samples(1.0);
+        sq.process(&mut s[..]);
+        assert!(s
+            .iter()
+            .all(|&s| s == level::db_to_amplitude(-REPEAT_ATTEN)));
+    }
+}
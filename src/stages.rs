@@ -0,0 +1,143 @@
+//! Processing metrics for each stage of the receive pipeline.
+//!
+//! Each task times how long it spends on every item it takes from its input, and notes
+//! how many items were still waiting behind it. A stage busy close to all of the time,
+//! or with a growing backlog, is where latency builds up and what limits the pipeline on
+//! slow hardware.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Counters for a single pipeline stage, shared with the hub for reporting.
+pub struct StageMetrics {
+    /// Name of the stage for reporting.
+    pub name: &'static str,
+    /// Time the stage was created.
+    created: Instant,
+    /// Number of items processed.
+    items: AtomicU64,
+    /// Total time (ns) spent processing items.
+    busy: AtomicU64,
+    /// Longest time (ns) spent processing a single item.
+    max: AtomicU64,
+    /// Items waiting when the latest one was taken.
+    backlog: AtomicUsize,
+}
+
+impl StageMetrics {
+    /// Create new metrics for the stage with the given name.
+    pub fn new(name: &'static str) -> Arc<Self> {
+        Arc::new(StageMetrics {
+            name,
+            created: Instant::now(),
+            items: AtomicU64::new(0),
+            busy: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+            backlog: AtomicUsize::new(0),
+        })
+    }
+
+    /// Start timing an item just taken from the input, with the given number of items
+    /// still waiting behind it. The time is recorded when the returned timer is dropped.
+    pub fn start(self: &Arc<Self>, backlog: usize) -> StageTimer {
+        self.backlog.store(backlog, Ordering::Relaxed);
+
+        StageTimer {
+            stage: self.clone(),
+            start: Instant::now(),
+        }
+    }
+
+    /// Record an item that took the given time to process.
+    fn record(&self, busy: Duration) {
+        let ns = busy.as_nanos().min(u64::MAX as u128) as u64;
+
+        self.items.fetch_add(1, Ordering::Relaxed);
+        self.busy.fetch_add(ns, Ordering::Relaxed);
+        self.max.fetch_max(ns, Ordering::Relaxed);
+    }
+
+    /// Number of items processed.
+    pub fn items(&self) -> u64 {
+        self.items.load(Ordering::Relaxed)
+    }
+
+    /// Total time spent processing items.
+    pub fn busy(&self) -> Duration {
+        Duration::from_nanos(self.busy.load(Ordering::Relaxed))
+    }
+
+    /// Mean time spent processing an item.
+    pub fn mean(&self) -> Duration {
+        match self.items() {
+            0 => Duration::ZERO,
+            n => self.busy() / n as u32,
+        }
+    }
+
+    /// Longest time spent processing an item.
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max.load(Ordering::Relaxed))
+    }
+
+    /// Fraction of the time since the stage was created spent processing items.
+    pub fn load(&self) -> f32 {
+        let elapsed = self.created.elapsed().as_secs_f32();
+
+        if elapsed == 0.0 {
+            return 0.0;
+        }
+
+        self.busy().as_secs_f32() / elapsed
+    }
+
+    /// Items waiting when the latest one was taken.
+    pub fn backlog(&self) -> usize {
+        self.backlog.load(Ordering::Relaxed)
+    }
+}
+
+/// Times the processing of a single item.
+pub struct StageTimer {
+    /// Stage the item belongs to.
+    stage: Arc<StageMetrics>,
+    /// Time processing started.
+    start: Instant,
+}
+
+impl Drop for StageTimer {
+    fn drop(&mut self) {
+        self.stage.record(self.start.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stage() {
+        let stage = StageMetrics::new("test");
+        assert_eq!(stage.mean(), Duration::ZERO);
+
+        stage.record(Duration::from_millis(2));
+
+        {
+            let _timer = stage.start(3);
+        }
+
+        stage.record(Duration::from_millis(4));
+
+        assert_eq!(stage.items(), 3);
+        assert_eq!(stage.backlog(), 3);
+        assert_eq!(stage.max(), Duration::from_millis(4));
+        assert!(stage.busy() >= Duration::from_millis(6));
+        assert!(stage.mean() >= Duration::from_millis(2));
+        assert!(stage.load() > 0.0);
+    }
+}
@@ -0,0 +1,116 @@
+//! Stereo placement of voice transmissions.
+
+use std::{collections::HashSet, f32::consts::FRAC_1_SQRT_2};
+
+use clap::ValueEnum;
+use fnv::FnvBuildHasher;
+
+/// How transmissions are assigned to stereo channels.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, ValueEnum)]
+pub enum StereoMode {
+    /// Alternate between left and right on each new transmission.
+    Alternate,
+    /// Place listed talkgroups left or right, and all others in the center.
+    Talkgroup,
+}
+
+/// Position of a transmission in the stereo field.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Position {
+    Left,
+    Center,
+    Right,
+}
+
+impl Position {
+    /// Left and right channel gains, keeping equal power in each position.
+    pub fn gains(self) -> (f32, f32) {
+        match self {
+            Position::Left => (1.0, 0.0),
+            Position::Center => (FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+            Position::Right => (0.0, 1.0),
+        }
+    }
+}
+
+/// Chooses the stereo position of each transmission.
+pub struct Panner {
+    /// Assignment mode.
+    mode: StereoMode,
+    /// Talkgroups placed on the left.
+    left: HashSet<u16, FnvBuildHasher>,
+    /// Talkgroups placed on the right.
+    right: HashSet<u16, FnvBuildHasher>,
+    /// Position of the current transmission.
+    pos: Position,
+}
+
+impl Panner {
+    /// Create a new `Panner` with the given mode and talkgroup assignments.
+    pub fn new(mode: StereoMode, left: &[u16], right: &[u16]) -> Self {
+        Panner {
+            mode,
+            left: left.iter().cloned().collect(),
+            right: right.iter().cloned().collect(),
+            pos: Position::Center,
+        }
+    }
+
+    /// Choose the position for a new transmission on the given talkgroup.
+    pub fn begin_call(&mut self, tg: u16) {
+        self.pos = match self.mode {
+            StereoMode::Alternate => match self.pos {
+                Position::Left => Position::Right,
+                _ => Position::Left,
+            },
+            StereoMode::Talkgroup if self.left.contains(&tg) => Position::Left,
+            StereoMode::Talkgroup if self.right.contains(&tg) => Position::Right,
+            StereoMode::Talkgroup => Position::Center,
+        };
+    }
+
+    /// Position of the current transmission.
+    pub fn position(&self) -> Position {
+        self.pos
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_alternate() {
+        let mut p = Panner::new(StereoMode::Alternate, &[], &[]);
+        assert_eq!(p.position(), Position::Center);
+
+        p.begin_call(1);
+        assert_eq!(p.position(), Position::Left);
+        p.begin_call(1);
+        assert_eq!(p.position(), Position::Right);
+        p.begin_call(2);
+        assert_eq!(p.position(), Position::Left);
+    }
+
+    #[test]
+    fn test_talkgroup() {
+        let mut p = Panner::new(StereoMode::Talkgroup, &[1, 2], &[3]);
+
+        p.begin_call(2);
+        assert_eq!(p.position(), Position::Left);
+        p.begin_call(3);
+        assert_eq!(p.position(), Position::Right);
+        p.begin_call(4);
+        assert_eq!(p.position(), Position::Center);
+        p.begin_call(1);
+        assert_eq!(p.position(), Position::Left);
+    }
+
+    #[test]
+    fn test_gains() {
+        for &pos in &[Position::Left, Position::Center, Position::Right] {
+            let (l, r) = pos.gains();
+            assert!((l * l + r * r - 1.0).abs() < 1e-6);
+        }
+    }
+}
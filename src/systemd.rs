@@ -0,0 +1,176 @@
+//! Notifications to the systemd service manager.
+//!
+//! When started by a `Type=notify` service, systemd passes a socket in `NOTIFY_SOCKET`
+//! that accepts newline-separated state assignments like `READY=1`. If the service sets
+//! `WatchdogSec=`, the interval is passed in `WATCHDOG_USEC`, and the service is
+//! restarted if `WATCHDOG=1` isn't sent within each interval.
+
+use std::{
+    env, io,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+    process,
+    time::{Duration, Instant},
+};
+
+/// Sends service state to systemd.
+pub struct Notifier {
+    /// Unbound socket to send from.
+    sock: UnixDatagram,
+    /// Address of the service manager socket.
+    addr: SocketAddr,
+    /// Time between watchdog heartbeats, if the watchdog is enabled.
+    heartbeat: Option<Duration>,
+    /// Time of the last watchdog heartbeat.
+    last_beat: Instant,
+    /// Whether readiness has been reported.
+    ready: bool,
+    /// Last reported status, to avoid repeating it.
+    status: String,
+}
+
+impl Notifier {
+    /// Connect to the service manager, if running under a `Type=notify` service.
+    pub fn from_env() -> io::Result<Option<Self>> {
+        let path = match env::var("NOTIFY_SOCKET") {
+            Ok(path) => path,
+            Err(_) => return Ok(None),
+        };
+
+        // Sockets starting with @ are in the abstract namespace.
+        let addr = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(&path)?,
+        };
+
+        let heartbeat = parse_watchdog(
+            env::var("WATCHDOG_USEC").ok().as_deref(),
+            env::var("WATCHDOG_PID").ok().as_deref(),
+            process::id(),
+        );
+
+        Ok(Some(Notifier {
+            sock: UnixDatagram::unbound()?,
+            addr,
+            heartbeat,
+            last_beat: Instant::now(),
+            ready: false,
+            status: String::new(),
+        }))
+    }
+
+    /// Report that startup has finished, if not already reported.
+    pub fn ready(&mut self) -> io::Result<()> {
+        if self.ready {
+            return Ok(());
+        }
+
+        self.ready = true;
+        self.send("READY=1")
+    }
+
+    /// Send a watchdog heartbeat if one is due.
+    pub fn heartbeat(&mut self) -> io::Result<()> {
+        match self.heartbeat {
+            Some(t) if self.last_beat.elapsed() >= t => {}
+            _ => return Ok(()),
+        }
+
+        self.last_beat = Instant::now();
+        self.send("WATCHDOG=1")
+    }
+
+    /// Report the given single-line status, shown by `systemctl status`.
+    pub fn status(&mut self, status: String) -> io::Result<()> {
+        if status == self.status {
+            return Ok(());
+        }
+
+        self.send(&format!("STATUS={}", status))?;
+        self.status = status;
+
+        Ok(())
+    }
+
+    fn send(&self, msg: &str) -> io::Result<()> {
+        self.sock
+            .send_to_addr(msg.as_bytes(), &self.addr)
+            .map(|_| ())
+    }
+}
+
+/// Get the time between watchdog heartbeats from the given watchdog interval (usec) and
+/// target process ID, if the watchdog is enabled for the given process.
+fn parse_watchdog(usec: Option<&str>, pid: Option<&str>, cur: u32) -> Option<Duration> {
+    let usec: u64 = usec?.parse().ok().filter(|&t| t > 0)?;
+
+    // The watchdog may be meant for a different process in the service.
+    if let Some(pid) = pid {
+        if pid.parse::<u32>() != Ok(cur) {
+            return None;
+        }
+    }
+
+    // Send heartbeats at twice the required rate, as recommended by sd_watchdog_enabled.
+    Some(Duration::from_micros(usec / 2))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_parse_watchdog() {
+        assert_eq!(parse_watchdog(None, None, 1), None);
+        assert_eq!(
+            parse_watchdog(Some("30000000"), None, 1),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            parse_watchdog(Some("30000000"), Some("42"), 42),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(parse_watchdog(Some("30000000"), Some("43"), 42), None);
+        assert_eq!(parse_watchdog(Some("0"), None, 1), None);
+        assert_eq!(parse_watchdog(Some("abc"), None, 1), None);
+    }
+
+    #[test]
+    fn test_notify() {
+        let dir = TempDir::new("notify");
+        let path = dir.join("notify");
+        let server = UnixDatagram::bind(&path).unwrap();
+
+        let mut n = Notifier {
+            sock: UnixDatagram::unbound().unwrap(),
+            addr: SocketAddr::from_pathname(&path).unwrap(),
+            heartbeat: Some(Duration::from_secs(0)),
+            last_beat: Instant::now(),
+            ready: false,
+            status: String::new(),
+        };
+
+        n.ready().unwrap();
+        n.ready().unwrap();
+        n.status("Monitoring 851.0125 MHz".to_string()).unwrap();
+        n.status("Monitoring 851.0125 MHz".to_string()).unwrap();
+        n.heartbeat().unwrap();
+
+        let mut msgs = vec![];
+        let mut buf = [0; 256];
+
+        server.set_nonblocking(true).unwrap();
+
+        while let Ok(len) = server.recv(&mut buf) {
+            msgs.push(String::from_utf8(buf[..len].to_vec()).unwrap());
+        }
+
+        assert_eq!(
+            msgs,
+            vec!["READY=1", "STATUS=Monitoring 851.0125 MHz", "WATCHDOG=1"]
+        );
+    }
+}
@@ -8,6 +8,98 @@ use p25::voice::crypto::CryptoAlgorithm;
 /// Maps talkgroups to associated encryption algorithm.
 pub type GroupCryptoMap = HashMap<u16, CryptoAlgorithm, FnvBuildHasher>;
 
+/// User-set talkgroup selection settings.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TalkgroupConfig {
+    /// Talkgroups included in or excluded from selection.
+    pub filter: Filter,
+    /// Talkgroups that can preempt a conversation.
+    pub preempt: HashSet<u16, FnvBuildHasher>,
+    /// Priority of each talkgroup, 1.0 if not given.
+    pub prios: HashMap<u16, f32, FnvBuildHasher>,
+    /// Weights for each feature used when scoring each talkgroup.
+    pub weights: FeatureWeights,
+    /// Rule for choosing between candidate talkgroups.
+    pub strategy: Strategy,
+}
+
+/// Rule for choosing between candidate talkgroups.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Strategy {
+    /// Highest weighted sum of priority, age, and recent selection.
+    Weighted,
+    /// Highest user priority, breaking ties by weighted score.
+    Priority,
+    /// Most recently granted.
+    Recent,
+    /// Longest since last followed.
+    Waiting,
+    /// Next talkgroup ID after the last followed, wrapping around.
+    RoundRobin,
+}
+
+impl Strategy {
+    /// Get the name of the strategy, as used in settings.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Strategy::Weighted => "weighted",
+            Strategy::Priority => "priority",
+            Strategy::Recent => "recent",
+            Strategy::Waiting => "waiting",
+            Strategy::RoundRobin => "round-robin",
+        }
+    }
+}
+
+impl Default for Strategy {
+    fn default() -> Self {
+        Strategy::Weighted
+    }
+}
+
+/// Talkgroup considered in a selection.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Candidate {
+    /// Talkgroup ID.
+    #[serde(rename = "talkGroup")]
+    talkgroup: u16,
+    /// User priority.
+    priority: f32,
+    /// Weighted score.
+    score: f32,
+    /// Whether the talkgroup can preempt a conversation.
+    preempt: bool,
+    /// Whether the talkgroup was granted to a preferred unit.
+    preferred: bool,
+}
+
+/// Explanation of why a talkgroup was selected.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Decision {
+    /// Selected talkgroup.
+    #[serde(rename = "talkGroup")]
+    pub talkgroup: u16,
+    /// Rule that selected the talkgroup: `preempt`, `preferred`, or the strategy name.
+    pub rule: &'static str,
+    /// Every candidate talkgroup, including the selected one.
+    pub candidates: Vec<Candidate>,
+}
+
+/// Parse a selection strategy like `round-robin`.
+pub fn parse_strategy(s: &str) -> Result<Strategy, String> {
+    match s {
+        "weighted" => Ok(Strategy::Weighted),
+        "priority" => Ok(Strategy::Priority),
+        "recent" => Ok(Strategy::Recent),
+        "waiting" => Ok(Strategy::Waiting),
+        "round-robin" => Ok(Strategy::RoundRobin),
+        _ => Err(format!(
+            "invalid strategy {} (expected weighted, priority, recent, waiting, or round-robin)",
+            s
+        )),
+    }
+}
+
 /// Collects, prioritizes, filters, and selects talkgroups.
 #[derive(Default)]
 pub struct TalkgroupSelection {
@@ -23,11 +115,74 @@ pub struct TalkgroupSelection {
     preempt: HashSet<u16, FnvBuildHasher>,
     /// User-set included/excluded talkgroups.
     filter: Filter,
+    /// Talkgroup that is the only one selected, if any.
+    held: Option<u16>,
+    /// Subset of `cur` talkgroups chosen over all others.
+    preferred: Vec<u16>,
+    /// Rule for choosing between candidates.
+    strategy: Strategy,
+    /// Explanation of the latest selection, if not yet taken.
+    decision: Option<Decision>,
     /// Talkgroup selection features.
     feats: TalkgroupFeatures,
 }
 
 impl TalkgroupSelection {
+    /// Apply the given settings, keeping the current site state.
+    ///
+    /// Candidates that are no longer allowed are dropped immediately.
+    pub fn configure(&mut self, c: TalkgroupConfig) {
+        self.filter = c.filter;
+        self.preempt = c.preempt;
+        self.feats.prios = c.prios;
+        self.feats.weights = c.weights;
+        self.strategy = c.strategy;
+        self.drop_disallowed();
+    }
+
+    /// Only select the given talkgroup, or any allowed talkgroup if `None`.
+    pub fn hold(&mut self, tg: Option<u16>) {
+        self.held = tg;
+        self.drop_disallowed();
+    }
+
+    /// Check if a single talkgroup is being held.
+    pub fn holding(&self) -> bool {
+        self.held.is_some()
+    }
+
+    /// Check if the given talkgroup can be selected.
+    fn allowed(&self, tg: u16) -> bool {
+        !self.filter.excluded(tg) && self.held.map_or(true, |h| h == tg)
+    }
+
+    /// Drop candidates that are no longer allowed.
+    fn drop_disallowed(&mut self) {
+        let dropped = self
+            .channels
+            .keys()
+            .cloned()
+            .filter(|&tg| !self.allowed(tg))
+            .collect::<Vec<_>>();
+
+        // Forget their channels too so they're collected again once allowed.
+        for tg in dropped {
+            self.channels.remove(&tg);
+        }
+
+        let channels = &self.channels;
+        self.cur.retain(|tg| channels.contains_key(tg));
+        self.preferred.retain(|tg| channels.contains_key(tg));
+
+        let preempt = &self.preempt;
+        self.cur_preempt = self
+            .cur
+            .iter()
+            .cloned()
+            .filter(|tg| preempt.contains(tg))
+            .collect();
+    }
+
     /// Record the given elapsed amount of baseband samples.
     pub fn record_elapsed(&mut self, samples: usize) {
         self.feats.record_elapsed(samples);
@@ -35,7 +190,7 @@ impl TalkgroupSelection {
 
     /// Consider the given talkgroup for the current set of candidate talkgroups.
     pub fn add_talkgroup(&mut self, tg: u16, freq: u32) {
-        if self.encrypted.contains_key(&tg) || self.filter.excluded(tg) {
+        if self.encrypted.contains_key(&tg) || !self.allowed(tg) {
             return;
         }
 
@@ -54,6 +209,15 @@ impl TalkgroupSelection {
         }
     }
 
+    /// Consider the given talkgroup, choosing it over any talkgroups not added this way.
+    pub fn add_preferred(&mut self, tg: u16, freq: u32) {
+        self.add_talkgroup(tg, freq);
+
+        if self.channels.contains_key(&tg) && !self.preferred.contains(&tg) {
+            self.preferred.push(tg);
+        }
+    }
+
     /// Select a talkgroup from the set of candidate non-preempting talkgroups.
     ///
     /// If a talkgroup is available, return `Some((tg, freq))`, where `tg` is the
@@ -61,7 +225,18 @@ impl TalkgroupSelection {
     /// return `None` if no talkgroups are available.
     pub fn select_idle(&mut self) -> Option<(u16, u32)> {
         debug!("selecting from {} talkgroups", self.cur.len());
-        self.feats.max_score(&self.cur).map(|tg| self.select_tg(tg))
+
+        let (groups, rule) = if self.preferred.is_empty() {
+            (&self.cur, self.strategy.name())
+        }
+        else {
+            (&self.preferred, "preferred")
+        };
+
+        let tg = self.choose(groups)?;
+        self.decision = Some(self.explain(tg, rule));
+
+        Some(self.select_tg(tg))
     }
 
     /// Select a talkgroup from the set of candidate preempting talkgroups.
@@ -70,9 +245,98 @@ impl TalkgroupSelection {
     /// talkgroup ID and `freq` is the traffic channel center frequency (Hz). Otherwise,
     /// return `None` if no talkgroups are available.
     pub fn select_preempt(&mut self) -> Option<(u16, u32)> {
-        self.feats
-            .max_score(&self.cur_preempt)
-            .map(|tg| self.select_tg(tg))
+        let tg = self.choose(&self.cur_preempt)?;
+        self.decision = Some(self.explain(tg, "preempt"));
+
+        Some(self.select_tg(tg))
+    }
+
+    /// Select a talkgroup that outranks the given talkgroup, by preempting or by having a
+    /// higher priority, to interrupt its call.
+    ///
+    /// If a talkgroup is available, return `Some((tg, freq))` as with `select_idle`.
+    pub fn select_over(&mut self, tg: u16) -> Option<(u16, u32)> {
+        let prio = self.priority(tg);
+
+        let groups = self
+            .cur
+            .iter()
+            .cloned()
+            .filter(|&c| c != tg && (self.preempt.contains(&c) || self.priority(c) > prio))
+            .collect::<Vec<_>>();
+
+        let next = self.choose(&groups)?;
+        self.decision = Some(self.explain(next, "priority-sample"));
+
+        Some(self.select_tg(next))
+    }
+
+    /// Get the user priority of the given talkgroup.
+    fn priority(&self, tg: u16) -> f32 {
+        *self.feats.prios.get(&tg).unwrap_or(&1.0)
+    }
+
+    /// Take the explanation of the latest selection, if any.
+    pub fn take_decision(&mut self) -> Option<Decision> {
+        self.decision.take()
+    }
+
+    /// Explain the selection of the given talkgroup from the current candidates by the
+    /// given rule.
+    fn explain(&self, tg: u16, rule: &'static str) -> Decision {
+        let score = self.feats.scorer();
+
+        Decision {
+            talkgroup: tg,
+            rule,
+            candidates: self
+                .cur
+                .iter()
+                .map(|&c| Candidate {
+                    talkgroup: c,
+                    priority: self.priority(c),
+                    score: score(c),
+                    preempt: self.cur_preempt.contains(&c),
+                    preferred: self.preferred.contains(&c),
+                })
+                .collect(),
+        }
+    }
+
+    /// Choose a talkgroup from the given candidates with the current strategy.
+    fn choose(&self, groups: &[u16]) -> Option<u16> {
+        let feats = &self.feats;
+
+        match self.strategy {
+            Strategy::Weighted => feats.max_score(groups),
+            Strategy::Priority => {
+                let prio = |tg| *feats.prios.get(&tg).unwrap_or(&1.0);
+
+                let max = groups
+                    .iter()
+                    .map(|&tg| prio(tg))
+                    .fold(f32::NEG_INFINITY, f32::max);
+
+                let top = groups
+                    .iter()
+                    .cloned()
+                    .filter(|&tg| prio(tg) == max)
+                    .collect::<Vec<_>>();
+
+                feats.max_score(&top)
+            }
+            Strategy::Recent => groups.iter().cloned().max_by_key(|tg| feats.age[tg]),
+            Strategy::Waiting => groups
+                .iter()
+                .cloned()
+                .min_by_key(|tg| feats.visited.get(tg).cloned()),
+            Strategy::RoundRobin => groups
+                .iter()
+                .cloned()
+                .filter(|&tg| tg > feats.recent)
+                .min()
+                .or_else(|| groups.iter().cloned().min()),
+        }
     }
 
     /// Record that the given talkgroup is encrypted.
@@ -97,6 +361,7 @@ impl TalkgroupSelection {
     fn clear_candidates(&mut self) {
         self.cur.clear();
         self.cur_preempt.clear();
+        self.preferred.clear();
         self.channels.clear();
     }
 
@@ -119,6 +384,10 @@ struct TalkgroupFeatures {
     age: HashMap<u16, usize, FnvBuildHasher>,
     /// Set of recently-visited talkgroups and associated visit timestamp.
     recent: u16,
+    /// Baseband samples elapsed since the features were reset.
+    clock: u64,
+    /// Value of `clock` when each talkgroup was last selected.
+    visited: HashMap<u16, u64, FnvBuildHasher>,
     /// User-set talkgroup priorities.
     pub prios: HashMap<u16, f32, FnvBuildHasher>,
     /// User-set weights for each feature used when scoring each talkgroup.
@@ -129,6 +398,7 @@ impl TalkgroupFeatures {
     /// Record elapsed baseband samples.
     pub fn record_elapsed(&mut self, samples: usize) {
         self.elapsed = self.elapsed.wrapping_add(samples);
+        self.clock += samples as u64;
     }
 
     /// Add the given talkgroup to the set of candidates (or update its age.)
@@ -141,10 +411,12 @@ impl TalkgroupFeatures {
         self.age.clear();
         self.recent = tg;
         self.elapsed = 0;
+        self.visited.insert(tg, self.clock);
     }
 
     /// Reset talkgroup-related state.
     pub fn reset(&mut self) {
+        self.visited.clear();
         self.select(0);
     }
 
@@ -158,13 +430,25 @@ impl TalkgroupFeatures {
     ///
     /// Each talkgroup must have been previously recorded with the `add` method.
     pub fn max_score(&self, groups: &[u16]) -> Option<u16> {
+        let score = self.scorer();
+
+        groups
+            .iter()
+            .cloned()
+            .max_by(|&a, &b| score(a).partial_cmp(&score(b)).unwrap())
+    }
+
+    /// Create a function computing the score of a talkgroup.
+    ///
+    /// Each scored talkgroup must have been previously recorded with the `add` method.
+    fn scorer(&self) -> impl Fn(u16) -> f32 + '_ {
         let oldest = self.oldest() as f32;
 
         // If the oldest talkgroup has no age, then none of the others will either, so
         // just set the multiplier to zero to avoid divide-by-zero.
         let mul = if oldest == 0.0 { 0.0 } else { oldest.recip() };
 
-        let score = |tg| {
+        move |tg| {
             // Older talkgroups score lower.
             let age = 1.0 - self.elapsed.wrapping_sub(self.age[&tg]) as f32 * mul;
             // Recent talkgroup gets a reward.
@@ -173,17 +457,12 @@ impl TalkgroupFeatures {
             self.prios.get(&tg).unwrap_or(&1.0) * self.weights.prio
                 + age * self.weights.age
                 + recent * self.weights.recent
-        };
-
-        groups
-            .iter()
-            .cloned()
-            .max_by(|&a, &b| score(a).partial_cmp(&score(b)).unwrap())
+        }
     }
 }
 
 /// Weights for features used in talkgroup selection.
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct FeatureWeights {
     /// Weight of user priority.
     prio: f32,
@@ -204,7 +483,7 @@ impl Default for FeatureWeights {
 }
 
 /// Filters talkgroups with an include-by-default or exclude-by-default policy.
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Filter {
     /// Whether the talkgroups in `tg` should be excluded (include-by-default) or included
     /// (exclude-by-default).
@@ -224,6 +503,22 @@ impl Default for Filter {
 }
 
 impl Filter {
+    /// Create a new `Filter` that excludes only the given talkgroups.
+    pub fn exclude(tgs: &[u16]) -> Self {
+        Filter {
+            exclude: true,
+            filt: tgs.iter().cloned().collect(),
+        }
+    }
+
+    /// Create a new `Filter` that includes only the given talkgroups.
+    pub fn include(tgs: &[u16]) -> Self {
+        Filter {
+            exclude: false,
+            filt: tgs.iter().cloned().collect(),
+        }
+    }
+
     /// Check if the given talkgroup is excluded from selection.
     pub fn excluded(&self, tg: u16) -> bool {
         let filtered = self.filt.contains(&tg);
@@ -260,6 +555,173 @@ mod test {
         assert!(f.filt.contains(&3));
     }
 
+    #[test]
+    fn test_configure() {
+        let mut ts = TalkgroupSelection::default();
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(20, 200);
+        ts.add_talkgroup(30, 300);
+
+        let mut c = TalkgroupConfig {
+            filter: Filter::exclude(&[20]),
+            ..TalkgroupConfig::default()
+        };
+        c.preempt.insert(30);
+        c.prios.insert(10, 2.0);
+
+        ts.configure(c);
+
+        assert_eq!(&ts.cur[..], &[10, 30]);
+        assert_eq!(&ts.cur_preempt[..], &[30]);
+        assert_eq!(ts.feats.prios.get(&10), Some(&2.0));
+
+        ts.add_talkgroup(20, 200);
+        assert_eq!(&ts.cur[..], &[10, 30]);
+
+        ts.configure(TalkgroupConfig {
+            filter: Filter::include(&[10]),
+            ..TalkgroupConfig::default()
+        });
+
+        assert_eq!(&ts.cur[..], &[10]);
+        assert!(ts.cur_preempt.is_empty());
+    }
+
+    #[test]
+    fn test_hold() {
+        let mut ts = TalkgroupSelection::default();
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(20, 200);
+
+        ts.hold(Some(20));
+        assert_eq!(&ts.cur[..], &[20]);
+
+        ts.add_talkgroup(30, 300);
+        assert_eq!(ts.select_idle(), Some((20, 200)));
+
+        ts.add_talkgroup(10, 100);
+        assert_eq!(ts.select_idle(), None);
+
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(20, 200);
+        ts.hold(Some(20));
+        ts.hold(None);
+        ts.add_talkgroup(10, 100);
+        assert_eq!(&ts.cur[..], &[20, 10]);
+    }
+
+    #[test]
+    fn test_preferred() {
+        let mut ts = TalkgroupSelection::default();
+        ts.feats.prios.insert(10, 100.0);
+        ts.add_talkgroup(10, 100);
+        ts.add_preferred(20, 200);
+        ts.add_preferred(20, 200);
+        ts.add_talkgroup(30, 300);
+
+        assert_eq!(&ts.cur[..], &[10, 20, 30]);
+        assert_eq!(ts.select_idle(), Some((20, 200)));
+        assert!(ts.preferred.is_empty());
+
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(30, 300);
+        assert_eq!(ts.select_idle(), Some((10, 100)));
+    }
+
+    #[test]
+    fn test_strategy() {
+        let candidates = |ts: &mut TalkgroupSelection| {
+            for &tg in &[30, 10, 20] {
+                ts.add_talkgroup(tg, tg as u32 * 10);
+                ts.record_elapsed(10);
+            }
+        };
+
+        let mut ts = TalkgroupSelection::default();
+        ts.feats.prios.insert(10, 2.0);
+        ts.feats.prios.insert(20, 2.0);
+        ts.feats.weights.age = 0.0;
+        ts.feats.weights.recent = 0.0;
+
+        ts.strategy = Strategy::Priority;
+        candidates(&mut ts);
+        assert_eq!(ts.select_idle(), Some((20, 200)));
+
+        ts.strategy = Strategy::Recent;
+        candidates(&mut ts);
+        assert_eq!(ts.select_idle(), Some((20, 200)));
+
+        ts.strategy = Strategy::Waiting;
+        candidates(&mut ts);
+        assert_eq!(ts.select_idle(), Some((30, 300)));
+        candidates(&mut ts);
+        assert_eq!(ts.select_idle(), Some((10, 100)));
+
+        ts.strategy = Strategy::RoundRobin;
+        candidates(&mut ts);
+        assert_eq!(ts.select_idle(), Some((20, 200)));
+        candidates(&mut ts);
+        assert_eq!(ts.select_idle(), Some((30, 300)));
+        candidates(&mut ts);
+        assert_eq!(ts.select_idle(), Some((10, 100)));
+
+        assert_eq!(parse_strategy("round-robin"), Ok(Strategy::RoundRobin));
+        assert!(parse_strategy("random").is_err());
+    }
+
+    #[test]
+    fn test_decision() {
+        let mut ts = TalkgroupSelection::default();
+        ts.preempt.insert(20);
+        ts.feats.prios.insert(10, 2.0);
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(20, 200);
+
+        assert_eq!(ts.take_decision(), None);
+        assert_eq!(ts.select_preempt(), Some((20, 200)));
+
+        let d = ts.take_decision().unwrap();
+        assert_eq!(d.talkgroup, 20);
+        assert_eq!(d.rule, "preempt");
+        assert_eq!(d.candidates.len(), 2);
+        assert_eq!(d.candidates[0].talkgroup, 10);
+        assert_eq!(d.candidates[0].priority, 2.0);
+        assert!(!d.candidates[0].preempt);
+        assert!(d.candidates[1].preempt);
+        assert_eq!(ts.take_decision(), None);
+
+        ts.add_talkgroup(10, 100);
+        ts.add_preferred(30, 300);
+        ts.select_idle();
+
+        let d = ts.take_decision().unwrap();
+        assert_eq!(d.talkgroup, 30);
+        assert_eq!(d.rule, "preferred");
+        assert!(d.candidates[1].preferred);
+    }
+
+    #[test]
+    fn test_select_over() {
+        let mut ts = TalkgroupSelection::default();
+        ts.preempt.insert(30);
+        ts.feats.prios.insert(10, 2.0);
+        ts.feats.prios.insert(20, 3.0);
+
+        ts.add_talkgroup(10, 100);
+        assert_eq!(ts.select_over(10), None);
+        assert_eq!(ts.select_over(40), Some((10, 100)));
+        assert_eq!(ts.take_decision().unwrap().rule, "priority-sample");
+
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(20, 200);
+        assert_eq!(ts.select_over(20), None);
+        assert_eq!(ts.select_over(10), Some((20, 200)));
+
+        ts.add_talkgroup(10, 100);
+        ts.add_talkgroup(30, 300);
+        assert_eq!(ts.select_over(20), Some((30, 300)));
+    }
+
     #[test]
     fn test_age() {
         let mut ts = TalkgroupSelection::default();
@@ -0,0 +1,121 @@
+//! TCP listeners streaming live samples to any client that connects.
+//!
+//! Each client receives the raw samples written after it connects, with nothing sent in
+//! the other direction. Writes never block, so a client that can't keep up with the
+//! stream is disconnected rather than holding up decoding, and a partial write, which
+//! would misalign the samples that follow, also disconnects it.
+
+use std::{
+    io::{self, ErrorKind, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+};
+
+use crate::baseband::BasebandSink;
+
+/// Streams bytes to connected TCP clients.
+pub struct Tap {
+    /// Name of the stream for logging.
+    name: &'static str,
+    /// Listener accepting new clients.
+    listener: TcpListener,
+    /// Connected clients.
+    clients: Vec<(SocketAddr, TcpStream)>,
+}
+
+impl Tap {
+    /// Listen for clients of the stream with the given name at the given address.
+    pub fn bind(name: &'static str, addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Tap {
+            name,
+            listener,
+            clients: vec![],
+        })
+    }
+
+    /// Write the given bytes to each client, after accepting any new clients.
+    pub fn send(&mut self, buf: &[u8]) {
+        self.accept();
+
+        let name = self.name;
+
+        self.clients.retain_mut(|(addr, s)| match s.write(buf) {
+            Ok(n) if n == buf.len() => true,
+            Ok(_) => {
+                warn!("disconnecting {} tap client {}: fell behind", name, addr);
+                false
+            }
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                warn!("disconnecting {} tap client {}: fell behind", name, addr);
+                false
+            }
+            Err(e) => {
+                info!("{} tap client {} disconnected: {}", name, addr, e);
+                false
+            }
+        });
+    }
+
+    /// Accept all pending clients.
+    fn accept(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((s, addr)) => {
+                    if let Err(e) = s.set_nonblocking(true) {
+                        warn!("unable to accept {} tap client {}: {}", self.name, addr, e);
+                        continue;
+                    }
+
+                    info!("{} tap client {} connected", self.name, addr);
+                    self.clients.push((addr, s));
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    warn!("unable to accept {} tap client: {}", self.name, e);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Taps never fail, since clients that fail are dropped instead.
+impl BasebandSink for Tap {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.send(unsafe { slice_cast::cast(samples) });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_tap() {
+        let mut tap = Tap::bind("test", "127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = tap.listener.local_addr().unwrap();
+
+        // Nothing is buffered for clients that aren't connected.
+        tap.send(b"early");
+
+        let mut client = TcpStream::connect(addr).unwrap();
+
+        // The connection may not be ready to accept right away.
+        while tap.clients.is_empty() {
+            tap.send(b"");
+        }
+
+        tap.send(b"abc");
+        tap.send(b"def");
+
+        drop(tap);
+
+        let mut buf = vec![];
+        client.read_to_end(&mut buf).unwrap();
+        assert_eq!(&buf[..], b"abcdef");
+    }
+}
@@ -0,0 +1,48 @@
+//! Helpers shared by tests.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Number of temporary directories created, to keep those of concurrent tests apart.
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+/// Empty directory for the files of a test, removed with them when dropped, including
+/// when the test fails.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create a directory for the test with the given name.
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "p25rx-{}-{}-{}",
+            name,
+            std::process::id(),
+            CREATED.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        // Left behind by an earlier process with the same ID.
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir(&dir).expect("unable to create test directory");
+
+        TempDir(dir)
+    }
+
+    /// Get the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Get the path of the given file in the directory.
+    pub fn join<P: AsRef<Path>>(&self, file: P) -> PathBuf {
+        self.0.join(file)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
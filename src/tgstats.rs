@@ -0,0 +1,216 @@
+//! Activity statistics of each talkgroup heard.
+
+use std::collections::BTreeMap;
+
+use p25::trunking::{
+    fields::{self, TalkGroup},
+    tsbk::{self, TsbkFields, TsbkOpcode},
+};
+
+use crate::{
+    aliases::TalkgroupAliases,
+    calls::Call,
+    rollup::{RollupPage, RollupQuery, Rollups},
+};
+
+/// Shortest time (ms) between grants on a talkgroup that are counted separately, since
+/// each grant is usually repeated.
+const GRANT_REPEAT: u64 = 3000;
+
+/// Activity of a single talkgroup.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct GroupStats {
+    /// Number of voice grants seen.
+    grants: u64,
+    /// Number of calls followed.
+    calls: u64,
+    /// Total length (ms) of followed calls.
+    airtime: u64,
+    /// Time (ms since the Unix epoch) the talkgroup was last heard.
+    last_heard: u64,
+    /// Time (ms since the Unix epoch) a grant was last counted.
+    last_grant: u64,
+}
+
+/// Activity of a talkgroup, as reported to clients.
+#[derive(Serialize)]
+pub struct GroupSummary<'a> {
+    /// Talkgroup ID.
+    #[serde(rename = "talkGroup")]
+    talkgroup: u16,
+    /// Talkgroup name, if known.
+    alias: Option<&'a str>,
+    /// Number of voice grants seen.
+    grants: u64,
+    /// Number of calls followed.
+    calls: u64,
+    /// Total length (sec) of followed calls.
+    airtime: f32,
+    /// Time (ms since the Unix epoch) the talkgroup was last heard.
+    #[serde(rename = "lastHeard")]
+    last_heard: u64,
+}
+
+/// Talkgroups selected by a query, most recently heard first.
+#[derive(Serialize)]
+pub struct StatsPage<'a> {
+    /// Matching talkgroups.
+    talkgroups: Vec<GroupSummary<'a>>,
+}
+
+impl<'a> StatsPage<'a> {
+    /// Check if no talkgroups were selected.
+    pub fn is_empty(&self) -> bool {
+        self.talkgroups.is_empty()
+    }
+}
+
+/// Parse the earliest last-heard time (ms since the Unix epoch) from a query string like
+/// `since=1500000000000`.
+pub fn parse_query(query: &str) -> Result<Option<u64>, String> {
+    let mut since = None;
+
+    for param in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, val) = param.split_once('=').unwrap_or((param, ""));
+
+        if key == "since" {
+            since = Some(val.parse().map_err(|_| format!("invalid since {}", val))?);
+        }
+    }
+
+    Ok(since)
+}
+
+/// Accumulates activity statistics of each talkgroup.
+#[derive(Default)]
+pub struct TalkgroupStats {
+    /// Statistics of each talkgroup heard.
+    groups: BTreeMap<u16, GroupStats>,
+    /// Activity of each talkgroup by hour and day.
+    rollups: Rollups,
+}
+
+impl TalkgroupStats {
+    /// Record the talkgroup activity in the given trunking packet, received at the given
+    /// time (ms since the Unix epoch).
+    pub fn record_tsbk(&mut self, tsbk: TsbkFields, time: u64) {
+        match tsbk.opcode() {
+            Some(TsbkOpcode::GroupVoiceGrant) => {
+                if let TalkGroup::Other(tg) = tsbk::GroupVoiceGrant::new(tsbk).talkgroup() {
+                    self.record_grant(tg, time);
+                }
+            }
+            Some(TsbkOpcode::GroupVoiceUpdate) => {
+                for &(_, tg) in fields::GroupTrafficUpdate::new(tsbk.payload())
+                    .updates()
+                    .iter()
+                {
+                    if let TalkGroup::Other(tg) = tg {
+                        self.record_heard(tg, time);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Record a voice grant on the given talkgroup at the given time.
+    fn record_grant(&mut self, tg: u16, time: u64) {
+        let g = self.groups.entry(tg).or_default();
+
+        if g.grants == 0 || time.saturating_sub(g.last_grant) >= GRANT_REPEAT {
+            g.grants += 1;
+            g.last_grant = time;
+            self.rollups.record_grant(tg, time);
+        }
+
+        g.last_heard = g.last_heard.max(time);
+    }
+
+    /// Record that the given talkgroup was active at the given time.
+    fn record_heard(&mut self, tg: u16, time: u64) {
+        let g = self.groups.entry(tg).or_default();
+        g.last_heard = g.last_heard.max(time);
+    }
+
+    /// Record the given ended call.
+    pub fn record_call(&mut self, call: &Call) {
+        let g = self.groups.entry(call.talkgroup()).or_default();
+
+        g.calls += 1;
+        g.airtime += call.airtime();
+        g.last_heard = g.last_heard.max(call.end().unwrap_or(0));
+
+        self.rollups
+            .record_call(call.talkgroup(), call.start(), call.airtime());
+    }
+
+    /// Select the hourly or daily activity matching the given query.
+    pub fn rollup(&self, q: &RollupQuery) -> RollupPage {
+        self.rollups.query(q)
+    }
+
+    /// Select the talkgroups last heard at or after the given time (ms since the Unix
+    /// epoch), if any, most recently heard first.
+    pub fn query<'a>(&self, since: Option<u64>, aliases: &'a TalkgroupAliases) -> StatsPage<'a> {
+        let mut talkgroups = self
+            .groups
+            .iter()
+            .filter(|&(_, g)| since.map_or(true, |t| g.last_heard >= t))
+            .map(|(&tg, g)| GroupSummary {
+                talkgroup: tg,
+                alias: aliases.get(tg),
+                grants: g.grants,
+                calls: g.calls,
+                airtime: g.airtime as f32 / 1000.0,
+                last_heard: g.last_heard,
+            })
+            .collect::<Vec<_>>();
+
+        talkgroups.sort_by(|a, b| b.last_heard.cmp(&a.last_heard));
+
+        StatsPage {
+            talkgroups,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::calls::CallHistory;
+
+    #[test]
+    fn test_stats() {
+        let mut s = TalkgroupStats::default();
+        let aliases = crate::aliases::parse("101,Fire Dispatch").unwrap();
+
+        s.record_grant(101, 1000);
+        s.record_grant(101, 2000);
+        s.record_grant(101, 5000);
+        s.record_heard(102, 6000);
+
+        let mut h = CallHistory::default();
+        h.begin(101, 851000000);
+        s.record_call(&h.end().unwrap());
+
+        let g = &s.groups[&101];
+        assert_eq!(g.grants, 2);
+        assert_eq!(g.calls, 1);
+
+        let page = s.query(None, &aliases);
+        assert_eq!(page.talkgroups.len(), 2);
+        assert_eq!(page.talkgroups[0].talkgroup, 101);
+        assert_eq!(page.talkgroups[0].alias, Some("Fire Dispatch"));
+        assert_eq!(page.talkgroups[1].talkgroup, 102);
+        assert_eq!(page.talkgroups[1].alias, None);
+
+        let page = s.query(Some(5500), &aliases);
+        assert!(!page.is_empty());
+        assert!(page.talkgroups.iter().all(|g| g.last_heard >= 5500));
+
+        assert_eq!(parse_query("since=1000"), Ok(Some(1000)));
+        assert_eq!(parse_query(""), Ok(None));
+        assert!(parse_query("since=abc").is_err());
+    }
+}
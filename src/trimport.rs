@@ -0,0 +1,377 @@
+//! Conversion of trunk-recorder configurations into p25rx settings.
+//!
+//! A system from trunk-recorder's `config.json`, along with its talkgroups file, is
+//! converted into a settings file (see the `settings` module) and the options of the
+//! first source that p25rx can use directly.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{self, Map, Value};
+
+/// Parts of trunk-recorder's `config.json` used for conversion.
+#[derive(Deserialize)]
+struct TrConfig {
+    #[serde(default)]
+    sources: Vec<TrSource>,
+    #[serde(default)]
+    systems: Vec<TrSystem>,
+}
+
+/// SDR source of a trunk-recorder configuration.
+#[derive(Deserialize)]
+struct TrSource {
+    #[serde(default)]
+    device: Option<String>,
+    #[serde(default)]
+    gain: Option<f32>,
+    #[serde(default)]
+    ppm: Option<f32>,
+    /// Tuning error (Hz), which has no direct equivalent.
+    #[serde(default)]
+    error: Option<f32>,
+}
+
+/// Trunked system of a trunk-recorder configuration.
+#[derive(Deserialize)]
+struct TrSystem {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(rename = "shortName", default)]
+    short_name: String,
+    #[serde(default)]
+    control_channels: Vec<u32>,
+    #[serde(rename = "talkgroupsFile", default)]
+    talkgroups_file: Option<String>,
+    /// Whether talkgroups missing from the talkgroups file are recorded (default true).
+    #[serde(rename = "recordUnknown", default)]
+    record_unknown: Option<bool>,
+}
+
+/// Row of a trunk-recorder talkgroups file.
+#[derive(Clone, Debug, PartialEq)]
+struct TrTalkgroup {
+    /// Talkgroup ID.
+    talkgroup: u16,
+    /// Short name.
+    alpha_tag: String,
+    /// Recording priority, where 1 is highest and -1 means never record.
+    priority: Option<i32>,
+}
+
+/// Result of converting a trunk-recorder system.
+pub struct Conversion {
+    /// Short name of the converted system.
+    pub system: String,
+    /// Control channel frequencies (Hz), starting with the primary.
+    pub control_channels: Vec<u32>,
+    /// Device option of the first source, if it's an RTL-SDR.
+    pub device: Option<String>,
+    /// Tuner gain (tenths of dB) of the first source, if set.
+    pub gain: Option<i32>,
+    /// Frequency correction (ppm) of the first source, if set.
+    pub ppm: Option<i32>,
+    /// Whether the first source only had a correction in Hz, which isn't converted.
+    pub error_hz: bool,
+    /// Settings file contents.
+    pub settings: Value,
+    /// Talkgroups skipped because they aren't valid P25 talkgroups.
+    pub skipped: usize,
+}
+
+/// Convert the system with the given short name, or the first P25 system, of the
+/// trunk-recorder configuration at the given path.
+pub fn convert(path: &Path, system: Option<&str>) -> Result<Conversion> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("unable to read {}", path.display()))?;
+    let config: TrConfig = serde_json::from_str(&text)
+        .map_err(|e| anyhow!("invalid trunk-recorder config {}: {}", path.display(), e))?;
+
+    let sys = select_system(&config.systems, system).map_err(anyhow::Error::msg)?;
+
+    if sys.control_channels.is_empty() {
+        return Err(anyhow!("system {} has no control channels", sys.short_name));
+    }
+
+    let (talkgroups, skipped) = match sys.talkgroups_file {
+        Some(ref file) => {
+            // Relative paths are relative to the config file, as in trunk-recorder.
+            let file = path.parent().unwrap_or_else(|| Path::new("")).join(file);
+            let text = fs::read_to_string(&file)
+                .with_context(|| format!("unable to read {}", file.display()))?;
+
+            parse_talkgroups(&text)
+                .map_err(|e| anyhow!("invalid talkgroups file {}: {}", file.display(), e))?
+        }
+        None => (vec![], 0),
+    };
+
+    let source = config.sources.first();
+
+    Ok(Conversion {
+        system: sys.short_name.clone(),
+        control_channels: sys.control_channels.clone(),
+        device: source
+            .and_then(|s| s.device.as_deref())
+            .and_then(device_option),
+        // Gains are given in dB.
+        gain: source
+            .and_then(|s| s.gain)
+            .map(|g| (g * 10.0).round() as i32),
+        ppm: source.and_then(|s| s.ppm).map(|p| p.round() as i32),
+        error_hz: source.is_some_and(|s| s.ppm.is_none() && s.error.is_some()),
+        settings: settings(&talkgroups, sys.record_unknown.unwrap_or(true)),
+        skipped,
+    })
+}
+
+/// Find the P25 system with the given short name, or the first P25 system.
+fn select_system<'a>(systems: &'a [TrSystem], name: Option<&str>) -> Result<&'a TrSystem, String> {
+    let mut p25 = systems.iter().filter(|s| s.kind == "p25");
+
+    match name {
+        Some(name) => p25
+            .find(|s| s.short_name == name)
+            .ok_or_else(|| format!("no P25 system named {}", name)),
+        None => p25.next().ok_or_else(|| "no P25 systems".to_string()),
+    }
+}
+
+/// Convert the given talkgroups into settings file contents.
+///
+/// Alpha tags become aliases and priority -1 talkgroups are excluded, or, if unknown
+/// talkgroups aren't recorded, only the other listed talkgroups are included. Since
+/// lower trunk-recorder priorities are more important, they're inverted so the least
+/// important listed priority maps to the default priority of 1.
+fn settings(talkgroups: &[TrTalkgroup], record_unknown: bool) -> Value {
+    let mut aliases = BTreeMap::new();
+    let mut priorities = BTreeMap::new();
+    let mut listed = vec![];
+    let mut ignored = vec![];
+
+    let lowest = talkgroups
+        .iter()
+        .filter_map(|t| t.priority)
+        .filter(|&p| p > 0)
+        .max()
+        .unwrap_or(1);
+
+    for t in talkgroups {
+        if !t.alpha_tag.is_empty() {
+            aliases.insert(t.talkgroup.to_string(), t.alpha_tag.clone());
+        }
+
+        match t.priority {
+            Some(-1) => ignored.push(t.talkgroup),
+            Some(p) if p > 0 && p < lowest => {
+                priorities.insert(t.talkgroup.to_string(), (lowest - p + 1) as f32);
+                listed.push(t.talkgroup);
+            }
+            _ => listed.push(t.talkgroup),
+        }
+    }
+
+    let mut settings = Map::new();
+    settings.insert("aliases".to_string(), json!(aliases));
+
+    if !priorities.is_empty() {
+        settings.insert("priorities".to_string(), json!(priorities));
+    }
+
+    if !record_unknown {
+        settings.insert("include".to_string(), json!(listed));
+    }
+    else if !ignored.is_empty() {
+        settings.insert("exclude".to_string(), json!(ignored));
+    }
+
+    Value::Object(settings)
+}
+
+/// Parse a trunk-recorder talkgroups file, returning the talkgroups and the number of
+/// rows skipped for having IDs too large for P25.
+///
+/// Columns are `Decimal,Hex,Alpha Tag,Mode,Description,Tag,Category[,Priority]`, unless
+/// the file starts with a header row naming them in another order.
+fn parse_talkgroups(text: &str) -> Result<(Vec<TrTalkgroup>, usize), String> {
+    let mut cols = (0, 2, 7);
+    let mut talkgroups = vec![];
+    let mut skipped = 0;
+
+    for (num, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields = split_csv(line);
+
+        if num == 0 && fields[0].parse::<u32>().is_err() {
+            let find = |name: &str| fields.iter().position(|f| f.eq_ignore_ascii_case(name));
+
+            cols = (
+                find("Decimal").ok_or("header has no Decimal column")?,
+                find("Alpha Tag").unwrap_or(usize::MAX),
+                find("Priority").unwrap_or(usize::MAX),
+            );
+
+            continue;
+        }
+
+        let err = |what: &str| format!("line {}: invalid {}", num + 1, what);
+
+        let id: u32 = fields
+            .get(cols.0)
+            .and_then(|f| f.parse().ok())
+            .ok_or_else(|| err("talkgroup"))?;
+
+        let talkgroup = match u16::try_from(id) {
+            Ok(tg) => tg,
+            Err(_) => {
+                skipped += 1;
+                continue;
+            }
+        };
+
+        let priority = match fields.get(cols.2).map(|f| f.as_str()) {
+            None | Some("") => None,
+            Some(p) => Some(p.parse().map_err(|_| err("priority"))?),
+        };
+
+        talkgroups.push(TrTalkgroup {
+            talkgroup,
+            alpha_tag: fields.get(cols.1).cloned().unwrap_or_default(),
+            priority,
+        });
+    }
+
+    Ok((talkgroups, skipped))
+}
+
+/// Split the given CSV line into trimmed fields, handling quoted fields.
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut cur = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                cur.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut cur).trim().to_string()),
+            c => cur.push(c),
+        }
+    }
+
+    fields.push(cur.trim().to_string());
+
+    fields
+}
+
+/// Convert the given trunk-recorder device string, like `rtl=0` or `rtl=00000001`, into
+/// a `--device` option, if it's an RTL-SDR.
+fn device_option(dev: &str) -> Option<String> {
+    let id = dev.split(',').next()?.trim().strip_prefix("rtl=")?;
+
+    // Short numbers are indexes, while serials are usually zero-padded to 8 digits.
+    if id.len() < 3 && id.parse::<u32>().is_ok() {
+        Some(id.to_string())
+    }
+    else {
+        Some(format!("serial:{}", id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_talkgroups() {
+        let (tgs, skipped) = parse_talkgroups(
+            "101,065,Fire Disp,D,\"Fire, Dispatch\",Fire Dispatch,Fire,1\n\
+             102,066,Fire Tac,D,Fire Tactical,Fire-Tac,Fire,3\n\
+             103,067,Encrypted,E,Police,Law Dispatch,Police,-1\n\
+             \n\
+             70000,11170,Too Big,D,,,,\n\
+             104,068,No Prio,D,,,\n",
+        )
+        .unwrap();
+
+        assert_eq!(skipped, 1);
+        assert_eq!(tgs.len(), 4);
+        assert_eq!(
+            tgs[0],
+            TrTalkgroup {
+                talkgroup: 101,
+                alpha_tag: "Fire Disp".to_string(),
+                priority: Some(1),
+            }
+        );
+        assert_eq!(tgs[2].priority, Some(-1));
+        assert_eq!(tgs[3].priority, None);
+
+        let (tgs, _) = parse_talkgroups("Decimal,Priority,Alpha Tag\n4521,2,EMS\n").unwrap();
+        assert_eq!(
+            tgs,
+            vec![TrTalkgroup {
+                talkgroup: 4521,
+                alpha_tag: "EMS".to_string(),
+                priority: Some(2),
+            }]
+        );
+
+        assert_eq!(
+            parse_talkgroups("101,065,A\nabc,066,B\n").unwrap_err(),
+            "line 2: invalid talkgroup"
+        );
+        assert!(parse_talkgroups("Hex,Alpha Tag\n").is_err());
+    }
+
+    #[test]
+    fn test_settings() {
+        let (tgs, _) = parse_talkgroups(
+            "101,065,Fire Disp,D,,,,1\n102,066,Fire Tac,D,,,,3\n103,067,Law,E,,,,-1\n",
+        )
+        .unwrap();
+
+        let s = settings(&tgs, true);
+        let fields = s.as_object().unwrap();
+        assert_eq!(s["aliases"]["102"].as_str(), Some("Fire Tac"));
+        assert_eq!(s["priorities"]["101"].as_f64(), Some(3.0));
+        assert!(!s["priorities"].as_object().unwrap().contains_key("102"));
+        assert_eq!(s["exclude"], json!([103]));
+        assert!(!fields.contains_key("include"));
+
+        // The converted settings must be accepted by p25rx.
+        let parsed = crate::settings::parse(&serde_json::to_string(&s).unwrap()).unwrap();
+        assert_eq!(parsed.aliases.get(101), Some("Fire Disp"));
+
+        let s = settings(&tgs, false);
+        assert_eq!(s["include"], json!([101, 102]));
+        assert!(!s.as_object().unwrap().contains_key("exclude"));
+    }
+
+    #[test]
+    fn test_split_csv() {
+        assert_eq!(
+            split_csv(r#"a, b ,"c, d","e ""f""","#),
+            vec!["a", "b", "c, d", "e \"f\"", ""]
+        );
+    }
+
+    #[test]
+    fn test_device_option() {
+        assert_eq!(device_option("rtl=0"), Some("0".to_string()));
+        assert_eq!(
+            device_option("rtl=00000101,buflen=64"),
+            Some("serial:00000101".to_string())
+        );
+        assert_eq!(device_option("hackrf=0"), None);
+    }
+}
@@ -0,0 +1,439 @@
+//! Live view of receiver activity in the terminal.
+//!
+//! The view is only drawn when built with the `tui` feature.
+
+// Only the terminal reads the view.
+#![cfg_attr(not(feature = "tui"), allow(dead_code))]
+
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use p25::{
+    stats::{CodeStats, Stats},
+    voice::control::{self, LinkControlOpcode},
+};
+#[cfg(feature = "tui")]
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::{
+        event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+        execute,
+        terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Layout},
+    text::Line,
+    widgets::{Block, Borders, Gauge, Paragraph, Row, Table},
+    Frame, Terminal,
+};
+
+use crate::{aliases::TalkgroupAliases, hub::HubEvent};
+
+/// Number of calls kept in the recent call list.
+const RECENT_CALLS: usize = 100;
+/// Time between redraws.
+const REFRESH: Duration = Duration::from_millis(250);
+/// Signal power (dBm) shown as an empty meter.
+const METER_MIN: f32 = -10.0;
+/// Signal power (dBm) shown as a full meter.
+const METER_MAX: f32 = 30.0;
+
+/// Call shown in the recent call list.
+struct RecentCall {
+    /// Time the call was followed.
+    start: DateTime<Local>,
+    /// Instant the call was followed, for measuring its length.
+    began: Instant,
+    /// Length of the call, if it has ended.
+    len: Option<Duration>,
+    /// Talkgroup of the call.
+    talkgroup: u16,
+    /// Traffic channel frequency (Hz).
+    freq: u32,
+    /// Most recent source unit heard on the call, if any.
+    src_unit: Option<u32>,
+}
+
+impl RecentCall {
+    /// Get the length of the call so far.
+    fn len(&self) -> Duration {
+        self.len.unwrap_or_else(|| self.began.elapsed())
+    }
+}
+
+/// Error correction counters for a single code.
+struct CodeCounts {
+    /// Name of the code.
+    name: &'static str,
+    /// Total words decoded.
+    words: usize,
+    /// Words with errors.
+    errs: usize,
+    /// Symbols corrected.
+    fixed: usize,
+}
+
+impl CodeCounts {
+    fn new(name: &'static str, s: &CodeStats) -> Self {
+        CodeCounts {
+            name,
+            words: s.words,
+            errs: s.errs,
+            fixed: s.fixed,
+        }
+    }
+}
+
+/// Receiver state shown in the terminal, updated from hub events.
+pub struct LiveView {
+    /// Names shown alongside talkgroups.
+    aliases: TalkgroupAliases,
+    /// Current center frequency, if known.
+    curfreq: Option<u32>,
+    /// Latest signal power (dBm), if known.
+    power: Option<f32>,
+    /// Total SDR sample buffers dropped.
+    dropped: usize,
+    /// Recent calls, newest first.
+    calls: VecDeque<RecentCall>,
+    /// Latest error correction counters.
+    stats: Vec<CodeCounts>,
+}
+
+impl LiveView {
+    /// Create a new `LiveView` showing the given talkgroup names.
+    pub fn new(aliases: TalkgroupAliases) -> Self {
+        LiveView {
+            aliases,
+            curfreq: None,
+            power: None,
+            dropped: 0,
+            calls: VecDeque::with_capacity(RECENT_CALLS),
+            stats: vec![],
+        }
+    }
+
+    /// Show the given talkgroup names from now on.
+    pub fn set_aliases(&mut self, aliases: TalkgroupAliases) {
+        self.aliases = aliases;
+    }
+
+    /// Update the view based on the given event.
+    pub fn update(&mut self, e: &HubEvent) {
+        match *e {
+            HubEvent::UpdateCurFreq(f) => self.curfreq = Some(f),
+            HubEvent::UpdateTalkGroup(tg) => self.begin(tg),
+            HubEvent::BeginPrivateCall(ref p) => {
+                self.begin(0);
+
+                if let Some(call) = self.current() {
+                    call.src_unit = Some(p.src_unit);
+                }
+            }
+            HubEvent::UpdateSignalPower(p) => self.power = Some(p),
+            HubEvent::UpdateDroppedBuffers(n) => self.dropped = n,
+            HubEvent::UpdateStats(ref s) => self.stats = code_counts(s),
+            HubEvent::LinkControl(lc) => {
+                if let Some(LinkControlOpcode::GroupVoiceTraffic) = lc.opcode() {
+                    if let Some(call) = self.current() {
+                        call.src_unit = Some(control::GroupVoiceTraffic::new(lc).src_unit());
+                    }
+                }
+            }
+            HubEvent::EndTransmission => {
+                if let Some(call) = self.current() {
+                    call.len = Some(call.began.elapsed());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Record the start of a call on the given talkgroup, ending any current call.
+    fn begin(&mut self, talkgroup: u16) {
+        if let Some(call) = self.current() {
+            call.len = Some(call.began.elapsed());
+        }
+
+        if self.calls.len() == RECENT_CALLS {
+            self.calls.pop_back();
+        }
+
+        self.calls.push_front(RecentCall {
+            start: Local::now(),
+            began: Instant::now(),
+            len: None,
+            talkgroup,
+            freq: self.curfreq.unwrap_or(0),
+            src_unit: None,
+        });
+    }
+
+    /// Get the call currently being followed, if any.
+    fn current(&mut self) -> Option<&mut RecentCall> {
+        self.calls.front_mut().filter(|c| c.len.is_none())
+    }
+
+    /// Format the given talkgroup with its alias, if any.
+    fn talkgroup(&self, tg: u16) -> String {
+        match self.aliases.get(tg) {
+            Some(alias) => format!("{} \"{}\"", tg, alias),
+            None => tg.to_string(),
+        }
+    }
+}
+
+/// Collect the counters of each error correcting code.
+fn code_counts(s: &Stats) -> Vec<CodeCounts> {
+    vec![
+        CodeCounts::new("BCH", &s.bch),
+        CodeCounts::new("Cyclic", &s.cyclic),
+        CodeCounts::new("Golay (std)", &s.golay_std),
+        CodeCounts::new("Golay (ext)", &s.golay_ext),
+        CodeCounts::new("Golay (short)", &s.golay_short),
+        CodeCounts::new("Hamming (std)", &s.hamming_std),
+        CodeCounts::new("Hamming (short)", &s.hamming_short),
+        CodeCounts::new("RS (short)", &s.rs_short),
+        CodeCounts::new("RS (med)", &s.rs_med),
+        CodeCounts::new("RS (long)", &s.rs_long),
+        CodeCounts::new("Viterbi (dibit)", &s.viterbi_dibit),
+        CodeCounts::new("Viterbi (tribit)", &s.viterbi_tribit),
+    ]
+}
+
+/// Format the given frequency (Hz) in MHz.
+fn format_freq(freq: u32) -> String {
+    format!("{:.4} MHz", freq as f64 / 1e6)
+}
+
+/// Draws a `LiveView` in the terminal.
+#[cfg(feature = "tui")]
+pub struct TuiTask {
+    /// View to draw.
+    view: Arc<Mutex<LiveView>>,
+}
+
+#[cfg(feature = "tui")]
+impl TuiTask {
+    /// Create a new `TuiTask` drawing the given view.
+    pub fn new(view: Arc<Mutex<LiveView>>) -> Self {
+        TuiTask {
+            view,
+        }
+    }
+
+    /// Draw the view until the user quits, blocking the current thread.
+    pub fn run(&mut self) -> io::Result<()> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+
+        let res = self.draw_loop();
+
+        // Always give the terminal back, even if drawing failed.
+        execute!(io::stdout(), LeaveAlternateScreen)?;
+        terminal::disable_raw_mode()?;
+
+        res
+    }
+
+    fn draw_loop(&mut self) -> io::Result<()> {
+        let mut term = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+        loop {
+            term.draw(|f| draw(f, &self.view.lock().unwrap()))?;
+
+            if !event::poll(REFRESH)? {
+                continue;
+            }
+
+            if let Event::Key(key) = event::read()? {
+                if is_quit(&key) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Stands in for the terminal view when built without it, failing to draw.
+#[cfg(not(feature = "tui"))]
+pub struct TuiTask;
+
+#[cfg(not(feature = "tui"))]
+impl TuiTask {
+    pub fn new(_: Arc<Mutex<LiveView>>) -> Self {
+        TuiTask
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        Err(io::Error::other(NO_TUI))
+    }
+}
+
+/// Error for drawing the view when built without the terminal.
+pub const NO_TUI: &str = "p25rx was built without the terminal view (the tui feature)";
+
+/// Check if the given key press should quit the view.
+#[cfg(feature = "tui")]
+fn is_quit(key: &KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => true,
+        KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+        _ => false,
+    }
+}
+
+/// Draw the given view into the frame.
+#[cfg(feature = "tui")]
+fn draw(f: &mut Frame, v: &LiveView) {
+    let [status, meter, body] = Layout::vertical([
+        Constraint::Length(6),
+        Constraint::Length(3),
+        Constraint::Min(0),
+    ])
+    .areas(f.area());
+
+    let [calls, stats] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(body);
+
+    let cur = v.calls.front().filter(|c| c.len.is_none());
+
+    let lines = vec![
+        Line::from(format!(
+            "Frequency:   {}",
+            v.curfreq
+                .map(format_freq)
+                .unwrap_or_else(|| "-".to_string())
+        )),
+        Line::from(format!(
+            "Talkgroup:   {}",
+            cur.map(|c| v.talkgroup(c.talkgroup))
+                .unwrap_or_else(|| "-".to_string())
+        )),
+        Line::from(format!(
+            "Source unit: {}",
+            cur.and_then(|c| c.src_unit)
+                .map(|u| u.to_string())
+                .unwrap_or_else(|| "-".to_string())
+        )),
+        Line::from(format!("Dropped:     {} buffers", v.dropped)),
+    ];
+
+    f.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("p25rx")),
+        status,
+    );
+
+    let (ratio, label) = match v.power {
+        Some(p) => (
+            ((p - METER_MIN) / (METER_MAX - METER_MIN)).clamp(0.0, 1.0),
+            format!("{:.1} dBm", p),
+        ),
+        None => (0.0, "-".to_string()),
+    };
+
+    f.render_widget(
+        Gauge::default()
+            .block(Block::default().borders(Borders::ALL).title("Signal"))
+            .ratio(ratio as f64)
+            .label(label),
+        meter,
+    );
+
+    let rows = v.calls.iter().map(|c| {
+        Row::new(vec![
+            c.start.format("%H:%M:%S").to_string(),
+            v.talkgroup(c.talkgroup),
+            c.src_unit.map(|u| u.to_string()).unwrap_or_default(),
+            format!("{:.1}s", c.len().as_secs_f32()),
+            format_freq(c.freq),
+        ])
+    });
+
+    f.render_widget(
+        Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Min(10),
+                Constraint::Length(8),
+                Constraint::Length(7),
+                Constraint::Length(13),
+            ],
+        )
+        .header(Row::new(vec![
+            "Time",
+            "Talkgroup",
+            "Unit",
+            "Length",
+            "Frequency",
+        ]))
+        .block(Block::default().borders(Borders::ALL).title("Recent calls")),
+        calls,
+    );
+
+    let rows = v.stats.iter().map(|s| {
+        Row::new(vec![
+            s.name.to_string(),
+            s.words.to_string(),
+            s.errs.to_string(),
+            s.fixed.to_string(),
+        ])
+    });
+
+    f.render_widget(
+        Table::new(
+            rows,
+            [
+                Constraint::Min(16),
+                Constraint::Length(9),
+                Constraint::Length(9),
+                Constraint::Length(9),
+            ],
+        )
+        .header(Row::new(vec!["Code", "Words", "Errors", "Fixed"]))
+        .block(Block::default().borders(Borders::ALL).title("Decode stats")),
+        stats,
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::aliases;
+
+    #[test]
+    fn test_calls() {
+        let mut v = LiveView::new(aliases::parse("101,Fire Dispatch").unwrap());
+
+        v.update(&HubEvent::UpdateCurFreq(851000000));
+        v.update(&HubEvent::UpdateTalkGroup(101));
+        assert!(v.current().is_some());
+
+        v.update(&HubEvent::EndTransmission);
+        assert!(v.current().is_none());
+
+        v.update(&HubEvent::UpdateCurFreq(852000000));
+        v.update(&HubEvent::UpdateTalkGroup(102));
+
+        assert_eq!(v.calls.len(), 2);
+        assert_eq!(v.calls[0].talkgroup, 102);
+        assert_eq!(v.calls[0].freq, 852000000);
+        assert_eq!(v.calls[1].freq, 851000000);
+        assert!(v.calls[1].len.is_some());
+
+        assert_eq!(v.talkgroup(101), "101 \"Fire Dispatch\"");
+        assert_eq!(v.talkgroup(102), "102");
+
+        for tg in 0..RECENT_CALLS as u16 {
+            v.update(&HubEvent::UpdateTalkGroup(tg));
+        }
+
+        assert_eq!(v.calls.len(), RECENT_CALLS);
+        assert_eq!(v.calls.back().unwrap().talkgroup, 0);
+    }
+}
@@ -0,0 +1,440 @@
+//! Radio unit activity seen in trunking and link control messages.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use fnv::FnvBuildHasher;
+use p25::{
+    trunking::{
+        fields::TalkGroup,
+        tsbk::{self, TsbkFields, TsbkOpcode},
+    },
+    voice::control::{self, LinkControlFields, LinkControlOpcode},
+};
+use serde_json;
+
+use crate::auth::AuthCommand;
+
+/// Shortest time between repeated reports of the same activity by a watched unit.
+const REPEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Kind of activity by a unit.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum Activity {
+    /// Unit was granted a voice channel.
+    #[serde(rename = "grant")]
+    Grant,
+    /// Unit was heard talking on a voice channel.
+    #[serde(rename = "voice")]
+    Voice,
+    /// Unit registered with the system.
+    #[serde(rename = "registration")]
+    Registration,
+    /// Unit deregistered from the system.
+    #[serde(rename = "deregistration")]
+    Deregistration,
+    /// Unit registered its location with the site.
+    #[serde(rename = "location")]
+    Location,
+    /// Unit affiliated with a talkgroup.
+    #[serde(rename = "affiliation")]
+    Affiliation,
+    /// Unit was commanded to authenticate with the system.
+    #[serde(rename = "authentication")]
+    Authentication,
+}
+
+/// Activity by a single unit.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct Sighting {
+    /// Unit ID.
+    pub unit: u32,
+    /// What the unit did.
+    pub activity: Activity,
+    /// Talkgroup the activity was on, if any.
+    #[serde(rename = "talkGroup")]
+    pub talkgroup: Option<u16>,
+}
+
+impl Activity {
+    /// Get the name of the activity, as used in events.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Activity::Grant => "grant",
+            Activity::Voice => "voice",
+            Activity::Registration => "registration",
+            Activity::Deregistration => "deregistration",
+            Activity::Location => "location",
+            Activity::Affiliation => "affiliation",
+            Activity::Authentication => "authentication",
+        }
+    }
+}
+
+impl fmt::Display for Sighting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unit {} {}", self.unit, self.activity.name())?;
+
+        match self.talkgroup {
+            Some(tg) => write!(f, " on talkgroup {}", tg),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Sighting {
+    /// Find the unit activity in the given trunking packet, if any.
+    pub fn from_tsbk(tsbk: TsbkFields) -> Option<Self> {
+        let (unit, activity, talkgroup) = match tsbk.opcode()? {
+            TsbkOpcode::GroupVoiceGrant => {
+                let f = tsbk::GroupVoiceGrant::new(tsbk);
+                (f.src_unit(), Activity::Grant, group(f.talkgroup()))
+            }
+            TsbkOpcode::UnitRegResponse => {
+                let f = tsbk::UnitRegResponse::new(tsbk);
+                (f.src_addr(), Activity::Registration, None)
+            }
+            TsbkOpcode::UnitDeregAck => {
+                let f = tsbk::UnitDeregAck::new(tsbk);
+                (f.src_unit(), Activity::Deregistration, None)
+            }
+            TsbkOpcode::LocRegResponse => {
+                let f = tsbk::LocRegResponse::new(tsbk);
+                (f.dest_unit(), Activity::Location, None)
+            }
+            TsbkOpcode::GroupAffiliationResponse => {
+                let f = tsbk::GroupAffiliationResponse::new(tsbk);
+                (f.dest_unit(), Activity::Affiliation, group(f.talkgroup()))
+            }
+            TsbkOpcode::AuthCommand => {
+                let f = AuthCommand::new(tsbk.payload());
+                (f.unit, Activity::Authentication, None)
+            }
+            _ => return None,
+        };
+
+        Some(Sighting {
+            unit,
+            activity,
+            talkgroup,
+        })
+    }
+
+    /// Find the unit activity in the given link control word, if any.
+    pub fn from_lc(lc: LinkControlFields) -> Option<Self> {
+        match lc.opcode()? {
+            LinkControlOpcode::GroupVoiceTraffic => {
+                let f = control::GroupVoiceTraffic::new(lc);
+
+                Some(Sighting {
+                    unit: f.src_unit(),
+                    activity: Activity::Voice,
+                    talkgroup: group(f.talkgroup()),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Get the ID of the given talkgroup, if it's an ordinary talkgroup.
+fn group(tg: TalkGroup) -> Option<u16> {
+    match tg {
+        TalkGroup::Other(x) => Some(x),
+        _ => None,
+    }
+}
+
+/// Reports activity by a set of watched units.
+#[derive(Default)]
+pub struct UnitWatch {
+    /// Watched units.
+    units: HashSet<u32, FnvBuildHasher>,
+    /// Latest reported activity of each watched unit and when it was reported.
+    last: HashMap<u32, (Sighting, Instant), FnvBuildHasher>,
+}
+
+impl UnitWatch {
+    /// Create a new `UnitWatch` watching the given units.
+    pub fn new(units: &[u32]) -> Self {
+        UnitWatch {
+            units: units.iter().cloned().collect(),
+            last: HashMap::default(),
+        }
+    }
+
+    /// Check if the given sighting should be reported at the given time, which is when
+    /// it's by a watched unit and isn't a recent repeat of its last reported activity.
+    pub fn report(&mut self, s: Sighting, now: Instant) -> bool {
+        if !self.units.contains(&s.unit) {
+            return false;
+        }
+
+        if let Some(&(prev, at)) = self.last.get(&s.unit) {
+            if prev == s && now.duration_since(at) < REPEAT_INTERVAL {
+                return false;
+            }
+        }
+
+        self.last.insert(s.unit, (s, now));
+
+        true
+    }
+}
+
+/// Known state of a single unit.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UnitRecord {
+    /// Unit ID.
+    unit: u32,
+    /// Whether the unit is registered with the system, as of its last registration or
+    /// deregistration.
+    registered: bool,
+    /// Talkgroup the unit is affiliated with, if any.
+    #[serde(rename = "talkGroup")]
+    talkgroup: Option<u16>,
+    /// Time (ms since the Unix epoch) the unit affiliated with its talkgroup, if any.
+    affiliated: Option<u64>,
+    /// Time (ms since the Unix epoch) the unit was last seen.
+    #[serde(rename = "lastSeen")]
+    last_seen: u64,
+    /// Latest activity by the unit.
+    #[serde(rename = "lastActivity")]
+    last_activity: Activity,
+}
+
+/// Criteria for selecting units.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UnitQuery {
+    /// Only units affiliated with this talkgroup.
+    talkgroup: Option<u16>,
+    /// Only units seen at or after this time (ms since the Unix epoch).
+    since: Option<u64>,
+    /// Only registered units.
+    registered: bool,
+}
+
+/// Parse unit selection criteria from a query string like `tg=101&registered=true`.
+pub fn parse_query(query: &str) -> Result<UnitQuery, String> {
+    let mut q = UnitQuery::default();
+
+    for param in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, val) = param.split_once('=').unwrap_or((param, ""));
+        let err = || format!("invalid {} {}", key, val);
+
+        match key {
+            "tg" => q.talkgroup = Some(val.parse().map_err(|_| err())?),
+            "since" => q.since = Some(val.parse().map_err(|_| err())?),
+            "registered" => q.registered = val.parse().map_err(|_| err())?,
+            _ => {}
+        }
+    }
+
+    Ok(q)
+}
+
+/// Units selected by a query.
+#[derive(Serialize)]
+pub struct UnitPage<'a> {
+    /// Matching units, by ID.
+    units: Vec<&'a UnitRecord>,
+}
+
+/// Tracks the registration and affiliation of every unit seen.
+#[derive(Default)]
+pub struct UnitTable {
+    /// State of each unit, by ID.
+    units: BTreeMap<u32, UnitRecord>,
+    /// Whether the table changed since it was last saved.
+    dirty: bool,
+}
+
+impl UnitTable {
+    /// Load a table previously saved to the given file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("unable to read unit database {}", path.display()))?;
+
+        let records: Vec<UnitRecord> = serde_json::from_str(&text)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("invalid unit database {}", path.display()))?;
+
+        Ok(UnitTable {
+            units: records.into_iter().map(|r| (r.unit, r)).collect(),
+            dirty: false,
+        })
+    }
+
+    /// Save the table to the given file, if it changed since it was last saved.
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let records = self.units.values().collect::<Vec<_>>();
+        let text = serde_json::to_string(&records).expect("unable to serialize units");
+
+        // Write a separate file first so a crash never leaves a partial database.
+        let tmp = path.with_extension("tmp");
+
+        fs::write(&tmp, text)
+            .and_then(|_| fs::rename(&tmp, path))
+            .with_context(|| format!("unable to write unit database {}", path.display()))?;
+
+        self.dirty = false;
+
+        Ok(())
+    }
+
+    /// Record the given activity at the given time (ms since the Unix epoch).
+    pub fn record(&mut self, s: &Sighting, time: u64) {
+        let r = self.units.entry(s.unit).or_insert_with(|| UnitRecord {
+            unit: s.unit,
+            registered: false,
+            talkgroup: None,
+            affiliated: None,
+            last_seen: time,
+            last_activity: s.activity,
+        });
+
+        r.last_seen = time;
+        r.last_activity = s.activity;
+
+        match s.activity {
+            Activity::Registration => r.registered = true,
+            Activity::Deregistration => {
+                r.registered = false;
+                r.talkgroup = None;
+                r.affiliated = None;
+            }
+            Activity::Affiliation => {
+                r.registered = true;
+                r.talkgroup = s.talkgroup;
+                r.affiliated = Some(time);
+            }
+            Activity::Grant | Activity::Voice | Activity::Location | Activity::Authentication => {}
+        }
+
+        self.dirty = true;
+    }
+
+    /// Select the units matching the given query.
+    pub fn query(&self, q: &UnitQuery) -> UnitPage<'_> {
+        UnitPage {
+            units: self
+                .units
+                .values()
+                .filter(|r| q.talkgroup.map_or(true, |tg| r.talkgroup == Some(tg)))
+                .filter(|r| q.since.map_or(true, |t| r.last_seen >= t))
+                .filter(|r| !q.registered || r.registered)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_report() {
+        let mut w = UnitWatch::new(&[42]);
+        let now = Instant::now();
+
+        let grant = Sighting {
+            unit: 42,
+            activity: Activity::Grant,
+            talkgroup: Some(101),
+        };
+
+        let voice = Sighting {
+            activity: Activity::Voice,
+            ..grant
+        };
+
+        assert!(w.report(grant, now));
+        assert!(!w.report(grant, now + Duration::from_secs(1)));
+        assert!(w.report(voice, now + Duration::from_secs(2)));
+        assert!(w.report(grant, now + Duration::from_secs(3)));
+        assert!(w.report(grant, now + Duration::from_secs(40)));
+
+        assert_eq!(grant.to_string(), "unit 42 grant on talkgroup 101");
+
+        assert!(!w.report(
+            Sighting {
+                unit: 43,
+                ..grant
+            },
+            now
+        ));
+    }
+
+    #[test]
+    fn test_table() {
+        let mut t = UnitTable::default();
+
+        let sighting = |unit, activity, talkgroup| Sighting {
+            unit,
+            activity,
+            talkgroup,
+        };
+
+        t.record(&sighting(1, Activity::Registration, None), 1000);
+        t.record(&sighting(1, Activity::Affiliation, Some(101)), 2000);
+        t.record(&sighting(2, Activity::Affiliation, Some(102)), 3000);
+        t.record(&sighting(2, Activity::Voice, Some(102)), 4000);
+        t.record(&sighting(3, Activity::Grant, Some(101)), 5000);
+
+        let units = |q| {
+            t.query(&parse_query(q).unwrap())
+                .units
+                .iter()
+                .map(|r| r.unit)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(units(""), vec![1, 2, 3]);
+        assert_eq!(units("tg=101"), vec![1]);
+        assert_eq!(units("since=4000"), vec![2, 3]);
+        assert_eq!(units("registered=true"), vec![1, 2]);
+
+        let r = &t.units[&2];
+        assert_eq!(r.talkgroup, Some(102));
+        assert_eq!(r.affiliated, Some(3000));
+        assert_eq!(r.last_seen, 4000);
+        assert_eq!(r.last_activity, Activity::Voice);
+
+        t.record(&sighting(1, Activity::Deregistration, None), 6000);
+        assert!(!t.units[&1].registered);
+        assert_eq!(t.units[&1].talkgroup, None);
+
+        assert!(parse_query("tg=abc").is_err());
+    }
+
+    #[test]
+    fn test_save() {
+        let dir = TempDir::new("units");
+        let path = dir.join("units.json");
+
+        let mut t = UnitTable::default();
+        t.record(
+            &Sighting {
+                unit: 42,
+                activity: Activity::Affiliation,
+                talkgroup: Some(101),
+            },
+            1000,
+        );
+        t.save(&path).unwrap();
+        assert!(!t.dirty);
+
+        let loaded = UnitTable::load(&path).unwrap();
+        assert_eq!(loaded.units, t.units);
+    }
+}
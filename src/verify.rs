@@ -0,0 +1,107 @@
+//! Golden-output regression testing of the decoder.
+
+use std::{fs::File, io, path::Path};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{self, Value};
+
+use crate::{
+    audio::{AudioFormat, AudioOutput},
+    consts::AUDIO_SAMPLE_RATE,
+    replay::ReplayReceiver,
+};
+
+/// Number of matching events to show around the first difference.
+const DIFF_CONTEXT: usize = 3;
+
+/// Replay the given baseband file and compare the decoded events against the events
+/// stored in the given expected-events file.
+///
+/// If `update` is set, the expected-events file is instead overwritten with the
+/// decoded events.
+pub fn verify(replay: &Path, expected: &Path, update: bool) -> Result<()> {
+    let mut stream = File::open(replay).context("unable to open replay file")?;
+    let audio = AudioOutput::new(io::sink(), AudioFormat::F32le, AUDIO_SAMPLE_RATE);
+    let mut recv = ReplayReceiver::new(audio).capture_events();
+
+    recv.replay(&mut stream)?;
+
+    let actual = recv.take_events();
+
+    if update {
+        let out = File::create(expected).context("unable to create expected events file")?;
+        serde_json::to_writer_pretty(out, &actual)?;
+
+        info!("wrote {} events to {}", actual.len(), expected.display());
+
+        return Ok(());
+    }
+
+    let file = File::open(expected).context("unable to open expected events file")?;
+    let expected: Vec<Value> = serde_json::from_reader(file)?;
+
+    match diff(&expected, &actual) {
+        None => {
+            info!("all {} events match", actual.len());
+            Ok(())
+        }
+        Some(diff) => {
+            eprint!("{}", diff);
+            bail!("decoded events differ from expected events")
+        }
+    }
+}
+
+/// Render a diff around the first differing event, or return `None` if the event
+/// streams are equal.
+fn diff(expected: &[Value], actual: &[Value]) -> Option<String> {
+    let first = match expected.iter().zip(actual.iter()).position(|(e, a)| e != a) {
+        Some(idx) => idx,
+        None if expected.len() == actual.len() => return None,
+        None => expected.len().min(actual.len()),
+    };
+
+    let start = first.saturating_sub(DIFF_CONTEXT);
+    let mut out = format!(
+        "first difference at event {} ({} expected, {} decoded)\n",
+        first,
+        expected.len(),
+        actual.len()
+    );
+
+    for (idx, e) in expected.iter().enumerate().take(first).skip(start) {
+        out += &format!(" {:6} {}\n", idx, e);
+    }
+
+    for (idx, e) in expected.iter().enumerate().skip(first).take(DIFF_CONTEXT) {
+        out += &format!("-{:6} {}\n", idx, e);
+    }
+
+    for (idx, e) in actual.iter().enumerate().skip(first).take(DIFF_CONTEXT) {
+        out += &format!("+{:6} {}\n", idx, e);
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let a = vec![json!({"event": "a"}), json!({"event": "b"})];
+        let b = vec![json!({"event": "a"}), json!({"event": "c"})];
+
+        assert!(diff(&a, &a).is_none());
+        assert!(diff(&a, &b)
+            .unwrap()
+            .starts_with("first difference at event 1"));
+        assert!(diff(&a, &a[..1])
+            .unwrap()
+            .starts_with("first difference at event 1"));
+        assert!(diff(&[], &a)
+            .unwrap()
+            .starts_with("first difference at event 0"));
+    }
+}
@@ -0,0 +1,409 @@
+//! Voice frame decoder backends.
+//!
+//! Frames are decoded either in software or by a DVSI AMBE-3000/3003 vocoder chip (as in
+//! the USB-3000 and USB-3012 dongles) over its serial packet protocol.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, ErrorKind, Read, Write},
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+};
+
+use imbe::{consts::SAMPLES_PER_FRAME, decode::ImbeDecoder, frame::ReceivedFrame};
+use p25::voice::frame::VoiceFrame;
+
+use crate::export;
+
+/// First byte of every packet.
+const START_BYTE: u8 = 0x61;
+/// Packet type for configuration and status.
+const PKT_CONTROL: u8 = 0x00;
+/// Packet type for compressed voice data.
+const PKT_CHANNEL: u8 = 0x01;
+/// Packet type for speech samples.
+const PKT_SPEECH: u8 = 0x02;
+/// Field selecting the rate table entry.
+const FIELD_RATET: u8 = 0x09;
+/// Field carrying compressed voice bits.
+const FIELD_CHAND: u8 = 0x01;
+/// Field carrying speech samples.
+const FIELD_SPCHD: u8 = 0x00;
+/// Field carrying the packet parity byte.
+const FIELD_PARITY: u8 = 0x2F;
+/// Field requesting a chip reset.
+const FIELD_RESET: u8 = 0x33;
+/// Field sent by the chip once it's ready after a reset.
+const FIELD_READY: u8 = 0x39;
+/// Field selecting the first channel of a multi-channel chip.
+const FIELD_CHANNEL0: u8 = 0x40;
+/// Rate table entry for P25 full rate voice without FEC, as the receiver has already
+/// corrected the frame.
+const RATE_P25_NO_FEC: u8 = 34;
+/// Number of voice bits in each frame.
+const FRAME_BITS: u8 = 88;
+/// Default serial baud rate of the USB-3000.
+const DEFAULT_BAUD: u32 = 460800;
+
+/// Decodes voice frames into audio samples.
+pub trait Vocoder {
+    /// Decode the given frame into samples at the decoded audio rate, in the same
+    /// range as a 16-bit signed integer.
+    fn decode(&mut self, frame: &VoiceFrame, out: &mut [f32; SAMPLES_PER_FRAME]) -> io::Result<()>;
+
+    /// Reinitialize decoder state for a new transmission.
+    fn reset(&mut self);
+}
+
+/// Decodes voice frames with the built-in IMBE decoder.
+pub struct SoftwareVocoder(pub ImbeDecoder);
+
+impl Vocoder for SoftwareVocoder {
+    fn decode(&mut self, frame: &VoiceFrame, out: &mut [f32; SAMPLES_PER_FRAME]) -> io::Result<()> {
+        let frame = ReceivedFrame::new(frame.chunks, frame.errors);
+        self.0.decode(frame, out);
+
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.0 = ImbeDecoder::new();
+    }
+}
+
+/// Vocoder backend selected on the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VocoderSpec {
+    /// Built-in software decoder.
+    Software,
+    /// DVSI dongle at the given serial device.
+    Dvsi {
+        /// Serial device path.
+        path: String,
+        /// Serial baud rate.
+        baud: u32,
+        /// Channel of a multi-channel chip, if any.
+        channel: Option<u8>,
+    },
+}
+
+impl VocoderSpec {
+    /// Create the described vocoder.
+    pub fn open(&self) -> io::Result<Box<dyn Vocoder + Send>> {
+        Ok(match *self {
+            VocoderSpec::Software => Box::new(SoftwareVocoder(ImbeDecoder::new())),
+            VocoderSpec::Dvsi {
+                ref path,
+                baud,
+                channel,
+            } => Box::new(DvsiVocoder::open(path, baud, channel)?),
+        })
+    }
+}
+
+/// Parse a vocoder description, either `software` or `dvsi:PATH[,baud=N][,channel=N]`.
+pub fn parse_spec(s: &str) -> Result<VocoderSpec, String> {
+    if s == "software" {
+        return Ok(VocoderSpec::Software);
+    }
+
+    let mut parts = match s.strip_prefix("dvsi:") {
+        Some(rest) => rest.split(','),
+        None => return Err(format!("unknown vocoder {}", s)),
+    };
+
+    let path = match parts.next() {
+        Some(p) if !p.is_empty() => p.to_string(),
+        _ => return Err("missing DVSI serial device path".to_string()),
+    };
+
+    let mut baud = DEFAULT_BAUD;
+    let mut channel = None;
+
+    for opt in parts {
+        match opt.split_once('=') {
+            Some(("baud", v)) => baud = v.parse().map_err(|_| format!("invalid baud {}", v))?,
+            Some(("channel", v)) => {
+                channel = Some(v.parse().map_err(|_| format!("invalid channel {}", v))?)
+            }
+            _ => return Err(format!("unknown DVSI option {}", opt)),
+        }
+    }
+
+    if baud_const(baud).is_none() {
+        return Err(format!("unsupported baud {}", baud));
+    }
+
+    Ok(VocoderSpec::Dvsi {
+        path,
+        baud,
+        channel,
+    })
+}
+
+/// Termios speed constant for the given baud rate.
+fn baud_const(baud: u32) -> Option<libc::speed_t> {
+    match baud {
+        115200 => Some(libc::B115200),
+        230400 => Some(libc::B230400),
+        460800 => Some(libc::B460800),
+        921600 => Some(libc::B921600),
+        _ => None,
+    }
+}
+
+/// Build a packet of the given type containing the given fields, followed by a parity
+/// field.
+fn build_packet(kind: u8, fields: &[u8]) -> Vec<u8> {
+    let len = fields.len() as u16 + 2;
+    let mut pkt = vec![START_BYTE];
+
+    pkt.extend_from_slice(&len.to_be_bytes());
+    pkt.push(kind);
+    pkt.extend_from_slice(fields);
+    pkt.push(FIELD_PARITY);
+
+    // Parity covers every byte after the start byte.
+    let parity = pkt[1..].iter().fold(0, |p, &b| p ^ b);
+    pkt.push(parity);
+
+    pkt
+}
+
+/// Extract the speech samples from the fields of a speech packet.
+fn parse_speech(fields: &[u8], out: &mut [f32; SAMPLES_PER_FRAME]) -> io::Result<()> {
+    // Skip any channel select field.
+    let fields = match fields.first() {
+        Some(&f) if f >= FIELD_CHANNEL0 => &fields[1..],
+        _ => fields,
+    };
+
+    match fields {
+        [FIELD_SPCHD, count, samples @ ..]
+            if *count as usize == SAMPLES_PER_FRAME && samples.len() >= 2 * SAMPLES_PER_FRAME =>
+        {
+            for (s, b) in out.iter_mut().zip(samples.chunks(2)) {
+                *s = i16::from_be_bytes([b[0], b[1]]) as f32;
+            }
+
+            Ok(())
+        }
+        _ => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "malformed speech packet",
+        )),
+    }
+}
+
+/// Decodes voice frames with a DVSI vocoder dongle over a serial port.
+pub struct DvsiVocoder {
+    /// Serial port.
+    port: File,
+    /// Channel select field for multi-channel chips.
+    channel: Option<u8>,
+}
+
+impl DvsiVocoder {
+    /// Open and initialize the dongle at the given serial device.
+    pub fn open(path: &str, baud: u32, channel: Option<u8>) -> io::Result<Self> {
+        let port = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(path)?;
+
+        configure_port(&port, baud)?;
+
+        let mut v = DvsiVocoder {
+            port,
+            channel: channel.map(|c| FIELD_CHANNEL0 + c),
+        };
+
+        v.init()?;
+
+        Ok(v)
+    }
+
+    /// Reset the chip and select the P25 rate.
+    fn init(&mut self) -> io::Result<()> {
+        self.send(PKT_CONTROL, &[FIELD_RESET])?;
+
+        match self.recv()? {
+            (PKT_CONTROL, ref f) if f.first() == Some(&FIELD_READY) => {}
+            _ => return Err(io::Error::new(ErrorKind::InvalidData, "dongle not ready")),
+        }
+
+        self.send_channel(PKT_CONTROL, &[FIELD_RATET, RATE_P25_NO_FEC])?;
+
+        match self.recv()? {
+            (PKT_CONTROL, ref f) if f.ends_with(&[FIELD_RATET, 0]) => Ok(()),
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "unable to set P25 rate",
+            )),
+        }
+    }
+
+    /// Send a packet with the given type and fields.
+    fn send(&mut self, kind: u8, fields: &[u8]) -> io::Result<()> {
+        self.port.write_all(&build_packet(kind, fields)[..])
+    }
+
+    /// Send a packet with the given type and fields addressed to the configured channel.
+    fn send_channel(&mut self, kind: u8, fields: &[u8]) -> io::Result<()> {
+        match self.channel {
+            Some(ch) => {
+                let mut f = vec![ch];
+                f.extend_from_slice(fields);
+                self.send(kind, &f[..])
+            }
+            None => self.send(kind, fields),
+        }
+    }
+
+    /// Receive a packet, returning its type and fields with any parity field removed.
+    fn recv(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let mut head = [0; 4];
+        self.read_exact(&mut head)?;
+
+        if head[0] != START_BYTE {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "invalid packet start",
+            ));
+        }
+
+        let mut fields = vec![0; u16::from_be_bytes([head[1], head[2]]) as usize];
+        self.read_exact(&mut fields[..])?;
+
+        let len = fields.len();
+
+        if len >= 2 && fields[len - 2] == FIELD_PARITY {
+            fields.truncate(len - 2);
+        }
+
+        Ok((head[3], fields))
+    }
+
+    /// Fill the given buffer from the port, failing if the dongle stops responding.
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        let mut pos = 0;
+
+        while pos < buf.len() {
+            match self.port.read(&mut buf[pos..])? {
+                0 => return Err(ErrorKind::TimedOut.into()),
+                n => pos += n,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Vocoder for DvsiVocoder {
+    fn decode(&mut self, frame: &VoiceFrame, out: &mut [f32; SAMPLES_PER_FRAME]) -> io::Result<()> {
+        let mut fields = vec![FIELD_CHAND, FRAME_BITS];
+        fields.extend_from_slice(&export::pack_frame(&frame.chunks));
+
+        self.send_channel(PKT_CHANNEL, &fields[..])?;
+
+        match self.recv()? {
+            (PKT_SPEECH, ref f) => parse_speech(f, out),
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "expected speech packet",
+            )),
+        }
+    }
+
+    // The chip keeps no state between transmissions that needs clearing.
+    fn reset(&mut self) {}
+}
+
+/// Configure the given serial port for raw I/O at the given baud rate, with reads
+/// timing out after one second.
+fn configure_port(port: &File, baud: u32) -> io::Result<()> {
+    let speed = baud_const(baud).ok_or(ErrorKind::InvalidInput)?;
+
+    unsafe {
+        let mut t: libc::termios = std::mem::zeroed();
+
+        if libc::tcgetattr(port.as_raw_fd(), &mut t) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        libc::cfmakeraw(&mut t);
+        libc::cfsetspeed(&mut t, speed);
+
+        t.c_cflag |= libc::CLOCAL | libc::CREAD;
+        t.c_cc[libc::VMIN] = 0;
+        t.c_cc[libc::VTIME] = 10;
+
+        if libc::tcsetattr(port.as_raw_fd(), libc::TCSANOW, &t) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        libc::tcflush(port.as_raw_fd(), libc::TCIOFLUSH);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(parse_spec("software"), Ok(VocoderSpec::Software));
+        assert_eq!(
+            parse_spec("dvsi:/dev/ttyUSB0"),
+            Ok(VocoderSpec::Dvsi {
+                path: "/dev/ttyUSB0".to_string(),
+                baud: 460800,
+                channel: None,
+            })
+        );
+        assert_eq!(
+            parse_spec("dvsi:/dev/ttyUSB1,baud=921600,channel=2"),
+            Ok(VocoderSpec::Dvsi {
+                path: "/dev/ttyUSB1".to_string(),
+                baud: 921600,
+                channel: Some(2),
+            })
+        );
+        assert!(parse_spec("dvsi:").is_err());
+        assert!(parse_spec("dvsi:/dev/ttyUSB0,baud=1234").is_err());
+        assert!(parse_spec("dvsi:/dev/ttyUSB0,foo=1").is_err());
+        assert!(parse_spec("hardware").is_err());
+    }
+
+    #[test]
+    fn test_build_packet() {
+        assert_eq!(
+            build_packet(PKT_CONTROL, &[FIELD_RESET]),
+            &[0x61, 0x00, 0x03, 0x00, 0x33, 0x2F, 0x03 ^ 0x33 ^ 0x2F]
+        );
+    }
+
+    #[test]
+    fn test_parse_speech() {
+        let mut fields = vec![FIELD_SPCHD, SAMPLES_PER_FRAME as u8];
+
+        for i in 0..SAMPLES_PER_FRAME as i16 {
+            fields.extend_from_slice(&(i * 100 - 8000).to_be_bytes());
+        }
+
+        let mut out = [0.0; SAMPLES_PER_FRAME];
+        parse_speech(&fields[..], &mut out).unwrap();
+        assert_eq!(out[0], -8000.0);
+        assert_eq!(out[159], 7900.0);
+
+        // Channel select field is skipped.
+        fields.insert(0, FIELD_CHANNEL0);
+        let mut out = [0.0; SAMPLES_PER_FRAME];
+        parse_speech(&fields[..], &mut out).unwrap();
+        assert_eq!(out[1], -7900.0);
+
+        assert!(parse_speech(&fields[..10], &mut out).is_err());
+    }
+}
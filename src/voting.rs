@@ -0,0 +1,104 @@
+//! Voting between the trunking packets of two receivers on the same control channel.
+//!
+//! With diversity antennas, a packet lost to fading at one antenna is often received
+//! cleanly at the other. Each packet that passes its CRC is accepted from whichever
+//! receiver decodes it first, and the copy from the other receiver is dropped, so the
+//! packet stream has the fewest errors of either.
+
+use std::collections::VecDeque;
+
+/// Receiver a packet was decoded by.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Branch {
+    /// Receiver that also follows calls.
+    Primary,
+    /// Diversity receiver.
+    Diversity,
+}
+
+/// Chooses between the packets decoded by two receivers.
+pub struct Voter {
+    /// Time (baseband samples) within which identical packets are considered copies of
+    /// the same transmission.
+    window: usize,
+    /// Recently accepted packets, oldest first, with the time (baseband samples) and
+    /// receiver each was accepted from.
+    recent: VecDeque<(String, usize, Branch)>,
+    /// Packets only decoded by each receiver, indexed by branch.
+    only: [u64; 2],
+}
+
+impl Voter {
+    /// Create a new `Voter` matching copies received within the given time (baseband
+    /// samples) of each other.
+    pub fn new(window: usize) -> Self {
+        Voter {
+            window,
+            recent: VecDeque::new(),
+            only: [0; 2],
+        }
+    }
+
+    /// Check if the packet with the given description, decoded by the given receiver at
+    /// the given time (baseband samples), is new rather than a copy of one already
+    /// accepted from the other receiver, recording it if so.
+    pub fn accept(&mut self, desc: String, time: usize, branch: Branch) -> bool {
+        while let Some(&(_, t, _)) = self.recent.front() {
+            if time.saturating_sub(t) <= self.window {
+                break;
+            }
+
+            // Nothing matched the packet while it was recent, so only its receiver
+            // decoded it.
+            let (_, _, b) = self.recent.pop_front().unwrap();
+            self.only[b as usize] += 1;
+        }
+
+        let pos = self
+            .recent
+            .iter()
+            .position(|&(ref d, _, b)| b != branch && *d == desc);
+
+        match pos {
+            Some(idx) => {
+                // Each copy only matches once, so repeats of a packet are still kept.
+                self.recent.remove(idx);
+                false
+            }
+            None => {
+                self.recent.push_back((desc, time, branch));
+                true
+            }
+        }
+    }
+
+    /// Get the number of packets decoded only by the primary and diversity receivers.
+    pub fn only(&self) -> (u64, u64) {
+        (self.only[0], self.only[1])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use self::Branch::*;
+    use super::*;
+
+    #[test]
+    fn test_voter() {
+        let mut v = Voter::new(100);
+
+        assert!(v.accept("a".to_string(), 0, Primary));
+        assert!(!v.accept("a".to_string(), 10, Diversity));
+
+        // Repeats from the same receiver are separate transmissions.
+        assert!(v.accept("b".to_string(), 20, Diversity));
+        assert!(v.accept("b".to_string(), 30, Diversity));
+        assert!(!v.accept("b".to_string(), 40, Primary));
+
+        // Copies outside the window are separate transmissions.
+        assert!(v.accept("c".to_string(), 50, Primary));
+        assert!(v.accept("c".to_string(), 200, Diversity));
+
+        assert_eq!(v.only(), (1, 1));
+    }
+}
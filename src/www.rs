@@ -0,0 +1,102 @@
+//! Static file serving for custom web interfaces.
+
+use std::path::{Component, Path, PathBuf};
+
+/// File served for directory requests.
+const INDEX: &str = "index.html";
+
+/// Map the given request path to a file under the given root directory.
+///
+/// Paths that would escape the root, such as those containing `..`, are rejected.
+pub fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let rel = Path::new(path.trim_start_matches('/'));
+
+    if !rel
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return None;
+    }
+
+    let file = root.join(rel);
+
+    if file.is_dir() {
+        Some(file.join(INDEX))
+    }
+    else {
+        Some(file)
+    }
+}
+
+/// Guess the MIME type of the given file from its extension.
+pub fn content_type(file: &Path) -> &'static str {
+    let ext = match file.extension().and_then(|e| e.to_str()) {
+        Some(ext) => ext.to_ascii_lowercase(),
+        None => return "application/octet-stream",
+    };
+
+    match &ext[..] {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "ico" => "image/x-icon",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let root = Path::new("/nonexistent/www");
+
+        assert_eq!(
+            resolve(root, "/app.js"),
+            Some(PathBuf::from("/nonexistent/www/app.js"))
+        );
+        assert_eq!(
+            resolve(root, "/css/style.css"),
+            Some(PathBuf::from("/nonexistent/www/css/style.css"))
+        );
+        assert_eq!(resolve(root, "/../etc/passwd"), None);
+        assert_eq!(resolve(root, "/css/../../etc/passwd"), None);
+        assert_eq!(
+            resolve(root, "/./app.js"),
+            Some(PathBuf::from("/nonexistent/www/app.js"))
+        );
+
+        // Directories are served by their index.
+        let root = std::env::temp_dir();
+        assert_eq!(resolve(&root, "/"), Some(root.join(INDEX)));
+    }
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(
+            content_type(Path::new("index.html")),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(
+            content_type(Path::new("a/b.JS")),
+            "text/javascript; charset=utf-8"
+        );
+        assert_eq!(content_type(Path::new("logo.png")), "image/png");
+        assert_eq!(
+            content_type(Path::new("data.bin")),
+            "application/octet-stream"
+        );
+        assert_eq!(
+            content_type(Path::new("README")),
+            "application/octet-stream"
+        );
+    }
+}